@@ -0,0 +1,94 @@
+//! Разница сигнатур на 256 ячейках: прежний поэлементный цикл против текущего `Signature::distance`,
+//! и число сравнений `compare_signatures` в секунду.
+//! `cargo bench --bench distance`, для `std::simd` - `cargo +nightly bench --bench distance --features simd`
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use imgalg::{compare_signatures, ComparerOptions, Signature};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Считает выделения памяти, чтобы проверить, что сравнение обходится без них
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+const CELLS: usize = 256;
+const VALUE_LIMIT: i32 = 255 * 255;
+
+/// Псевдослучайные ячейки в диапазоне значений сигнатуры
+fn random_cells(seed: u64) -> Vec<[i32; 3]> {
+    let mut state = seed;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % (2 * VALUE_LIMIT as u64 + 1)) as i32 - VALUE_LIMIT
+    };
+    (0..CELLS).map(|_| [next(), next(), next()]).collect()
+}
+
+fn to_signature(cells: &[[i32; 3]]) -> Signature {
+    let hex: String = cells.iter().flatten().map(|value| format!("{:05x}", value + VALUE_LIMIT)).collect();
+    format!("v2:{hex}").parse().expect("valid signature")
+}
+
+/// Цикл `_get_diff` до перехода на плоскости каналов
+fn interleaved_distance(a: &[[i32; 3]], b: &[[i32; 3]]) -> f32 {
+    let mut diff = 0.0;
+    for i in 0..std::cmp::min(a.len(), b.len()) {
+        diff += ((a[i][0] - b[i][0]) as f32).abs().sqrt();
+        diff += ((a[i][1] - b[i][1]) as f32).abs().sqrt();
+        diff += ((a[i][2] - b[i][2]) as f32).abs().sqrt();
+    }
+    diff
+}
+
+fn distance(c: &mut Criterion) {
+    let (a, b) = (random_cells(0x9e37_79b9_7f4a_7c15), random_cells(0xd1b5_4a32_d192_ed03));
+    let (sig_a, sig_b) = (to_signature(&a), to_signature(&b));
+
+    // Первое сравнение заводит общие настройки по умолчанию, дальше выделений нет
+    black_box(sig_a.similarity(&sig_b).expect("compatible signatures"));
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(sig_a.distance(&sig_b).expect("compatible signatures"));
+    black_box(sig_a.similarity(&sig_b).expect("compatible signatures"));
+    assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before, "Signature::distance or Signature::similarity allocated");
+
+    let mut group = c.benchmark_group("distance_256");
+    group.throughput(Throughput::Elements(CELLS as u64));
+    group.bench_function("interleaved", |bench| bench.iter(|| interleaved_distance(black_box(&a), black_box(&b))));
+    group.bench_function("signature", |bench| bench.iter(|| black_box(&sig_a).distance(black_box(&sig_b))));
+    group.finish();
+}
+
+fn compare(c: &mut Criterion) {
+    let (a, b) = (to_signature(&random_cells(0x9e37_79b9_7f4a_7c15)), to_signature(&random_cells(0xd1b5_4a32_d192_ed03)));
+    let weighted = ComparerOptions::new().channel_weights([0.2, 0.6, 0.2]).expect("valid weights");
+
+    let mut group = c.benchmark_group("compare_signatures");
+    group.throughput(Throughput::Elements(1));
+    for (name, options) in [("default", ComparerOptions::default()), ("weighted", weighted)] {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        black_box(compare_signatures(&a, &b, &options).expect("compatible signatures"));
+        assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before, "compare_signatures allocated with {name} options");
+        group.bench_function(name, |bench| bench.iter(|| compare_signatures(black_box(&a), black_box(&b), black_box(&options))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, distance, compare);
+criterion_main!(benches);
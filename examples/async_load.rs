@@ -0,0 +1,21 @@
+//! Асинхронная загрузка изображений: `cargo run --example async_load --features async -- a.png b.png`
+use imgalg::ImagesComparer;
+
+#[tokio::main]
+async fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+
+    let (comparer, errors) = ImagesComparer::new_async(&paths).await;
+    for error in &errors {
+        match error.path() {
+            Some(path) => eprintln!("Не удалось загрузить {}: {}", path.display(), error),
+            None => eprintln!("Не удалось загрузить: {}", error),
+        }
+    }
+
+    if comparer.len() < 2 {
+        eprintln!("Нет изображений для сравнения!");
+        return;
+    }
+    println!("Процент схожести: {:.2}%", comparer.similarity_percentage());
+}
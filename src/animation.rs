@@ -0,0 +1,108 @@
+//! Анимации GIF, WebP и APNG: сравнение неподвижного изображения с каждым кадром, например
+//! чтобы узнать, из какого кадра сделана миниатюра. Кадры декодируются по одному и сразу
+//! сворачиваются в сигнатуру, так что память не зависит от длины анимации
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, DynamicImage, Frames, ImageError, ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::crop::{self, Rect};
+use crate::signature::{self, Signature};
+use crate::{ComparerOptions, ImgAlgError, Result, Warning};
+
+/// Кадр анимации, больше всего похожий на изображение
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BestFrame {
+    /// Номер кадра с нуля
+    pub frame: usize,
+    /// Начало кадра от начала анимации по задержкам кадров; `None`, если задержки не заданы
+    pub time_ms: Option<u64>,
+    pub similarity: f32,
+    /// Сколько кадров в анимации
+    pub frames: usize,
+}
+
+/// Анимация ли файл: GIF больше чем из одного кадра, анимированный WebP или APNG.
+/// Нечитаемый файл анимацией не считается, ошибку сообщит обычная загрузка
+pub fn is_animated<P: AsRef<Path>>(path: P) -> bool {
+    match frames(path.as_ref()) {
+        Ok(Some(frames)) => frames.take(2).count() == 2,
+        _ => false,
+    }
+}
+
+/// Кадры файла по одному; `None` - формат без анимации
+fn frames(path: &Path) -> Result<Option<Frames<'static>>> {
+    let format = ImageReader::open(path).and_then(ImageReader::with_guessed_format).map_err(|e| ImgAlgError::io(path, e))?.format();
+    let reader = || File::open(path).map(BufReader::new).map_err(|e| ImgAlgError::io(path, e));
+    let open = |e| ImgAlgError::open(path, e);
+    let frames = match format {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader()?).map_err(open)?.into_frames(),
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader()?).map_err(open)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader()?).map_err(open)?;
+            if !decoder.is_apng().map_err(open)? {
+                return Ok(None);
+            }
+            decoder.apng().map_err(open)?.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames))
+}
+
+/// Кадр анимации `path`, больше всего похожий на сигнатуру `target`, и его сигнатура.
+/// Сигнатуры кадров считаются с сетками, преобразованием и вписыванием из `options`
+/// по области `crop` каждого кадра. Если кадр после первого не декодируется, выбирается
+/// лучший из прочитанных, с предупреждением `TruncatedAnimation`
+pub(crate) fn best_frame(path: &Path, crop: Option<Rect>, target: &Signature, options: &ComparerOptions) -> Result<(BestFrame, Signature, Option<Warning>)> {
+    options.check_letterbox()?;
+    let Some(frames) = frames(path)? else {
+        let reason = DecodingError::new(ImageFormatHint::PathExtension(path.to_path_buf()), "not an animated GIF, WebP or PNG");
+        return Err(ImgAlgError::open(path, ImageError::Decoding(reason)));
+    };
+    let mut best: Option<(BestFrame, Signature)> = None;
+    let mut elapsed_ms = 0.0;
+    let mut count = 0;
+    let mut warning = None;
+    for (pos, frame) in frames.enumerate() {
+        options.check_cancelled()?;
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) if pos > 0 => {
+                warning = Some(Warning::TruncatedAnimation { path: path.to_path_buf(), frames: pos, reason: e.to_string() });
+                break;
+            }
+            Err(e) => return Err(ImgAlgError::open(path, e)),
+        };
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let start_ms = elapsed_ms;
+        elapsed_ms += f64::from(numer) / f64::from(denom.max(1));
+        let image = crop::apply(path, DynamicImage::ImageRgba8(frame.into_buffer()), crop)?;
+        let signature = Signature::from_image_ensemble(image, options.grid_sizes(), options.transform(), options.is_letterbox(), options.is_ensemble())?;
+        signature.check_compatible(target)?;
+        let similarity = signature::measure(&signature, target, options).1;
+        count += 1;
+        if best.as_ref().is_none_or(|(best, _)| similarity > best.similarity) {
+            let time_ms = (pos == 0 || start_ms > 0.0).then(|| start_ms.round() as u64);
+            best = Some((BestFrame { frame: pos, time_ms, similarity, frames: 0 }, signature));
+        }
+    }
+    let Some((mut best, signature)) = best else {
+        let reason = DecodingError::new(ImageFormatHint::PathExtension(path.to_path_buf()), "the animation has no frames");
+        return Err(ImgAlgError::open(path, ImageError::Decoding(reason)));
+    };
+    best.frames = count;
+    Ok((best, signature, warning))
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "zip")]
+use crate::{ImgAlgError, Result};
+
+/// Разделитель пути архива и пути внутри него: `album.zip!/IMG_0042.jpg`
+pub const SEPARATOR: &str = "!/";
+
+/// Архив ли это, который можно просматривать: `.zip` или `.cbz`
+pub fn is_archive<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip") || extension.eq_ignore_ascii_case("cbz"))
+}
+
+/// Путь к файлу внутри архива
+pub fn member_path<P: AsRef<Path>>(archive: P, name: &str) -> PathBuf {
+    let mut path = archive.as_ref().as_os_str().to_owned();
+    path.push(SEPARATOR);
+    path.push(name);
+    PathBuf::from(path)
+}
+
+/// Путь архива и имя файла в нем, если путь указывает внутрь архива
+pub fn split<P: AsRef<Path>>(path: P) -> Option<(PathBuf, String)> {
+    // Имя внутри ZIP всегда строка, а путь к самому архиву может быть не в UTF-8
+    crate::paths::splits(path.as_ref().as_os_str(), SEPARATOR)
+        .find(|(archive, name)| is_archive(archive) && !name.is_empty())
+        .and_then(|(archive, name)| Some((PathBuf::from(archive), name.to_str()?.to_string())))
+}
+
+/// Указывает ли путь внутрь архива. Такие файлы нельзя переместить или удалить по отдельности
+pub fn is_member<P: AsRef<Path>>(path: P) -> bool {
+    split(path).is_some()
+}
+
+/// Файлы архива, которые просматриваются как изображения, и вложенные архивы,
+/// которые не просматриваются
+#[cfg(feature = "zip")]
+#[derive(Debug, Default)]
+pub struct ArchiveMembers {
+    /// Пути вида `архив!/имя`, в порядке записи в архиве
+    pub images: Vec<PathBuf>,
+    pub nested: Vec<PathBuf>,
+}
+
+/// Перечисляет файлы архива по его оглавлению, не распаковывая их.
+/// Изображения узнаются по расширению имени
+#[cfg(feature = "zip")]
+pub fn members<P: AsRef<Path>>(archive_path: P) -> Result<ArchiveMembers> {
+    let archive_path = archive_path.as_ref();
+    let archive = open(archive_path)?;
+    let mut members = ArchiveMembers::default();
+    for name in archive.file_names() {
+        let name = name.map_err(|e| zip_error(archive_path, e))?;
+        if name.ends_with('/') {
+            continue; // Каталог
+        }
+        if is_archive(name.as_ref()) {
+            members.nested.push(member_path(archive_path, &name));
+        } else if image::ImageFormat::from_path(name.as_ref()).is_ok() {
+            members.images.push(member_path(archive_path, &name));
+        }
+    }
+    Ok(members)
+}
+
+/// Читает в память один файл архива по пути `архив!/имя`; остальные файлы не распаковываются.
+/// Зашифрованный файл - ошибка чтения
+#[cfg(feature = "zip")]
+pub(crate) fn read_member(path: &Path, archive_path: &Path, name: &str) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut archive = open(archive_path)?;
+    let mut file = archive.by_name(name).map_err(|e| zip_error(path, e))?;
+    let mut bytes = Vec::with_capacity(file.size().min(1 << 26) as usize);
+    file.read_to_end(&mut bytes).map_err(|e| ImgAlgError::io(path, e))?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "zip")]
+fn open(archive_path: &Path) -> Result<zip::ZipArchive<std::io::BufReader<std::fs::File>>> {
+    let file = std::fs::File::open(archive_path).map_err(|e| ImgAlgError::io(archive_path, e))?;
+    zip::ZipArchive::new(std::io::BufReader::new(file)).map_err(|e| zip_error(archive_path, e))
+}
+
+/// Ошибки архива сводятся к ошибке чтения файла
+#[cfg(feature = "zip")]
+fn zip_error(path: &Path, error: zip::result::ZipError) -> ImgAlgError {
+    let source = match error {
+        zip::result::ZipError::Io(e) => e,
+        zip::result::ZipError::UnsupportedArchive(zip::result::ZipError::PASSWORD_REQUIRED) | zip::result::ZipError::InvalidPassword => {
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, "the archive entry is password-protected")
+        }
+        zip::result::ZipError::FileNotFound => std::io::Error::new(std::io::ErrorKind::NotFound, "no such file in the archive"),
+        e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+    };
+    ImgAlgError::io(path, source)
+}
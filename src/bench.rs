@@ -0,0 +1,230 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::signature::{GRID_SIZE, MULTI_SCALE_GRIDS};
+use crate::{info, ComparerOptions, Fingerprint, FingerprintMode, ImagesComparer, Signature, Transform};
+
+/// Измеряемый способ сравнения
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Сигнатура 16x16, как `ImagesComparer` по умолчанию
+    Signature,
+    /// Сигнатура на сетках 8x8, 16x16 и 32x32
+    MultiScale,
+    /// 64-битный pHash, `FingerprintMode::Native`
+    Fingerprint,
+    /// pHash в режиме совместимости с `img_hash`
+    ImgHashFingerprint,
+}
+
+impl Algorithm {
+    pub const ALL: [Self; 4] = [Self::Signature, Self::MultiScale, Self::Fingerprint, Self::ImgHashFingerprint];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signature => "signature",
+            Self::MultiScale => "multi-scale",
+            Self::Fingerprint => "phash",
+            Self::ImgHashFingerprint => "phash-img-hash",
+        }
+    }
+}
+
+/// Параметры замера
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// Сколько изображений случайно выбрать из переданных
+    pub sample: usize,
+    /// Сколько случайных пар разных изображений сравнить
+    pub pairs: usize,
+    /// Начальное значение генератора: одинаковое значение дает одинаковую выборку
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self { sample: 100, pairs: 1000, seed: 0 }
+    }
+}
+
+/// Минимум, медиана и 95-й процентиль процентов схожести
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreStats {
+    pub min: f32,
+    pub median: f32,
+    pub p95: f32,
+}
+
+/// Результат замера одного алгоритма
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlgorithmBench {
+    pub algorithm: Algorithm,
+    /// Сколько изображений загружено и сколько не удалось загрузить
+    pub loaded: usize,
+    pub failed: usize,
+    /// Время декодирования и вычисления сигнатур всех изображений выборки
+    pub load_time: Duration,
+    /// Сколько пар сравнено и за какое время
+    pub pairs: usize,
+    pub compare_time: Duration,
+    /// Распределение схожести случайных пар, `None`, если пар нет
+    pub scores: Option<ScoreStats>,
+}
+
+impl AlgorithmBench {
+    /// Пропускная способность сравнения, пар в секунду
+    pub fn pairs_per_second(&self) -> f64 {
+        let seconds = self.compare_time.as_secs_f64();
+        if seconds > 0.0 { self.pairs as f64 / seconds } else { 0.0 }
+    }
+}
+
+/// Сигнатуры одного алгоритма для выборки
+enum Loaded {
+    Signatures(Vec<Signature>, ComparerOptions),
+    Fingerprints(Vec<Fingerprint>),
+}
+
+impl Loaded {
+    fn similarity(&self, i: usize, j: usize) -> f32 {
+        match self {
+            Self::Signatures(signatures, options) => options.similarity(options.distance(&signatures[i], &signatures[j])),
+            Self::Fingerprints(fingerprints) => 100.0 * (1.0 - fingerprints[i].distance(&fingerprints[j]) as f32 / 64.0),
+        }
+    }
+}
+
+/// Замеряет все алгоритмы на случайной выборке из `paths`.
+///
+/// Пары берутся только из разных файлов с разным содержимым, так что распределение
+/// схожести описывает несвязанные изображения. Файлы, которые не загрузились
+/// хоть одним алгоритмом, в парах не участвуют
+pub fn run<P: AsRef<Path>>(paths: &[P], options: &BenchOptions) -> Vec<AlgorithmBench> {
+    let mut rng = SplitMix64(options.seed);
+    let mut sample: Vec<PathBuf> = paths.iter().map(|path| path.as_ref().to_path_buf()).collect();
+    sample.sort(); // Выборка не зависит от порядка путей
+    shuffle(&mut sample, &mut rng);
+    sample.truncate(options.sample);
+    let contents: Vec<Option<u64>> = sample.iter().map(|path| content_hash(path)).collect();
+
+    let loads: Vec<(Algorithm, Duration, Vec<Option<usize>>, Loaded)> =
+        Algorithm::ALL.iter().map(|&algorithm| load(algorithm, &sample)).collect();
+
+    // Общие для всех алгоритмов пары, чтобы распределения были сравнимы
+    let usable: Vec<usize> = (0..sample.len())
+        .filter(|&i| contents[i].is_some() && loads.iter().all(|(_, _, positions, _)| positions[i].is_some()))
+        .collect();
+    let mut pairs = vec![];
+    if usable.len() >= 2 {
+        let mut attempts = 0;
+        while pairs.len() < options.pairs && attempts < options.pairs * 10 {
+            attempts += 1;
+            let a = usable[rng.below(usable.len())];
+            let b = usable[rng.below(usable.len())];
+            if a != b && contents[a] != contents[b] {
+                pairs.push((a, b));
+            }
+        }
+    }
+
+    loads
+        .into_iter()
+        .map(|(algorithm, load_time, positions, loaded)| {
+            let started = Instant::now();
+            let mut scores: Vec<f32> = pairs
+                .iter()
+                .map(|&(a, b)| loaded.similarity(positions[a].unwrap_or(0), positions[b].unwrap_or(0)))
+                .collect();
+            let compare_time = started.elapsed();
+            let failed = positions.iter().filter(|position| position.is_none()).count();
+            AlgorithmBench {
+                algorithm,
+                loaded: positions.len() - failed,
+                failed,
+                load_time,
+                pairs: scores.len(),
+                compare_time,
+                scores: score_stats(&mut scores),
+            }
+        })
+        .collect()
+}
+
+/// Загружает выборку одним алгоритмом; для каждого пути - позиция в `Loaded`, если загрузился
+fn load(algorithm: Algorithm, sample: &[PathBuf]) -> (Algorithm, Duration, Vec<Option<usize>>, Loaded) {
+    let started = Instant::now();
+    let mut positions = vec![];
+    let loaded = match algorithm {
+        Algorithm::Signature | Algorithm::MultiScale => {
+            let (grid_sizes, options): (&[u32], _) = match algorithm {
+                Algorithm::MultiScale => (&MULTI_SCALE_GRIDS, ComparerOptions::new().multi_scale(true)),
+                _ => (&[GRID_SIZE], ComparerOptions::new()),
+            };
+            let mut signatures = vec![];
+            for path in sample {
+                let signature = ImagesComparer::_get_pixels_diff(path, grid_sizes, None, Transform::Square, false, false).ok().map(|(signature, _)| signature);
+                positions.push(signature.map(|signature| {
+                    signatures.push(signature);
+                    signatures.len() - 1
+                }));
+            }
+            Loaded::Signatures(signatures, options)
+        }
+        Algorithm::Fingerprint | Algorithm::ImgHashFingerprint => {
+            let mode = if algorithm == Algorithm::Fingerprint { FingerprintMode::Native } else { FingerprintMode::ImgHash };
+            let mut fingerprints = vec![];
+            for path in sample {
+                let image = info::open(path).ok().map(|(image, _)| image);
+                positions.push(image.map(|image| {
+                    fingerprints.push(Fingerprint::compute_from_image_with(&image, mode));
+                    fingerprints.len() - 1
+                }));
+            }
+            Loaded::Fingerprints(fingerprints)
+        }
+    };
+    (algorithm, started.elapsed(), positions, loaded)
+}
+
+/// Сортирует оценки и берет из них минимум, медиану и 95-й процентиль (по ближайшему рангу)
+fn score_stats(scores: &mut [f32]) -> Option<ScoreStats> {
+    if scores.is_empty() {
+        return None;
+    }
+    scores.sort_by(f32::total_cmp);
+    let rank = |percent: usize| scores[(scores.len() * percent).div_ceil(100).max(1) - 1];
+    Some(ScoreStats { min: scores[0], median: rank(50), p95: rank(95) })
+}
+
+fn content_hash(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut SplitMix64) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i + 1));
+    }
+}
+
+/// Маленький генератор с фиксированным алгоритмом, чтобы выборка не менялась между версиями
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Число в `0..bound`
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
@@ -0,0 +1,235 @@
+use rayon::prelude::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::index::{self, IndexReadError};
+use crate::{signature, ComparerOptions, ImageInfo, ImagesComparer, ImgAlgError, Result, Signature, Transform};
+
+/// Сигнатуры большого набора изображений с ограничением занимаемой памяти.
+///
+/// Пока сигнатуры помещаются в `max_memory` байт, они хранятся в памяти и результаты
+/// совпадают с `ImagesComparer`. Иначе они выгружаются во временный файл в формате индекса,
+/// а сравнение идет блоками: в памяти одновременно не больше двух блоков сигнатур
+/// (и строки матрицы для одного блока). Превышение бюджета не прерывает загрузку
+pub struct BoundedComparer {
+    pub options: ComparerOptions,
+    infos: Vec<ImageInfo>,
+    store: Store,
+}
+
+enum Store {
+    Memory(Vec<Signature>),
+    Spilled(SpillFile),
+}
+
+/// Временный файл с сигнатурами
+struct SpillFile {
+    path: TempPath,
+    file: File,
+    offsets: Vec<u64>,
+    block_len: usize,
+    /// Преобразование значений и вписывание сигнатур: в записи они не входят
+    transform: Transform,
+    letterbox: bool,
+}
+
+impl BoundedComparer {
+    /// Загружает изображения, которые удалось прочитать, и возвращает ошибки по остальным.
+    /// Ошибка верхнего уровня - только сбой записи временного файла. Отмена работает
+    /// как в `ImagesComparer::new_lossy_with`
+    pub fn load<P: AsRef<Path> + Sync>(images: &[P], options: ComparerOptions, max_memory: usize) -> Result<(Self, Vec<ImgAlgError>)> {
+        let mut infos = vec![];
+        let mut errors = vec![];
+        let mut signatures = vec![];
+        let mut resident = 0;
+        let mut spill: Option<SpillWriter> = None;
+        // Файлы декодируются параллельно пачками по числу потоков, сигнатуры сохраняются по порядку
+        let pool = options.thread_pool()?;
+        'files: for chunk in images.chunks(pool.current_num_threads().max(1)) {
+            let loads: Vec<Result<(Signature, ImageInfo)>> = pool.install(|| {
+                chunk.par_iter().map(|path| options.check_cancelled().and_then(|()| ImagesComparer::_load_image(path, &options))).collect()
+            });
+            for load in loads {
+                let (signature, info) = match load {
+                    Ok(loaded) => loaded,
+                    Err(ImgAlgError::Cancelled) => {
+                        errors.push(ImgAlgError::Cancelled);
+                        break 'files;
+                    }
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
+                infos.push(info);
+                if let Some(spill) = &mut spill {
+                    spill.push(&signature)?;
+                    continue;
+                }
+                resident += signature.memory_size();
+                signatures.push(signature);
+                if resident > max_memory {
+                    // Бюджет превышен: все накопленное уходит на диск, дальше пишем сразу туда
+                    let mut writer = SpillWriter::create()?;
+                    for signature in signatures.drain(..) {
+                        writer.push(&signature)?;
+                    }
+                    spill = Some(writer);
+                }
+            }
+        }
+
+        let store = match spill {
+            None => Store::Memory(signatures),
+            Some(writer) => Store::Spilled(writer.finish(max_memory, options.transform(), options.is_letterbox())?),
+        };
+        Ok((Self { options, infos, store }, errors))
+    }
+
+    /// Количество загруженных изображений
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    pub fn image_info(&self, index: usize) -> Option<&ImageInfo> {
+        self.infos.get(index)
+    }
+
+    /// Выгружены ли сигнатуры на диск
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.store, Store::Spilled(_))
+    }
+
+    /// На сколько блоков разбиты сигнатуры при сравнении; без выгрузки блок один
+    pub fn blocks(&self) -> usize {
+        match &self.store {
+            Store::Memory(_) => 1,
+            Store::Spilled(spill) => self.len().div_ceil(spill.block_len),
+        }
+    }
+
+    /// Передает строки полной матрицы процентов схожести по порядку, на диагонали 100.0.
+    /// Значения те же, что у `ImagesComparer::similarity_matrix`. Отмена проверяется перед
+    /// каждой строкой, а после выгрузки на диск - перед каждым блоком
+    pub fn for_each_row<E: From<ImgAlgError>>(&self, mut on_row: impl FnMut(usize, &[f32]) -> std::result::Result<(), E>) -> std::result::Result<(), E> {
+        let n = self.len();
+        let pool = self.options.thread_pool()?;
+        match &self.store {
+            Store::Memory(signatures) => {
+                let mut row = vec![100.0; n];
+                for (i, a) in signatures.iter().enumerate() {
+                    self.options.check_cancelled()?;
+                    pool.install(|| {
+                        row.par_iter_mut().zip(signatures).enumerate().for_each(|(j, (value, b))| {
+                            *value = if i == j { 100.0 } else { self.similarity(a, b) };
+                        })
+                    });
+                    on_row(i, &row)?;
+                }
+            }
+            Store::Spilled(spill) => {
+                let block_len = spill.block_len;
+                for rows_start in (0..n).step_by(block_len) {
+                    let rows_block = spill.read_block(rows_start, block_len)?;
+                    let mut rows = vec![vec![100.0; n]; rows_block.len()];
+                    for columns_start in (0..n).step_by(block_len) {
+                        self.options.check_cancelled()?;
+                        let loaded;
+                        let columns_block = if columns_start == rows_start {
+                            &rows_block
+                        } else {
+                            loaded = spill.read_block(columns_start, block_len)?;
+                            &loaded
+                        };
+                        pool.install(|| {
+                            rows.par_iter_mut().zip(&rows_block).enumerate().for_each(|(di, (row, a))| {
+                                for (dj, b) in columns_block.iter().enumerate() {
+                                    if rows_start + di != columns_start + dj {
+                                        row[columns_start + dj] = self.similarity(a, b);
+                                    }
+                                }
+                            })
+                        });
+                    }
+                    for (di, row) in rows.iter().enumerate() {
+                        on_row(rows_start + di, row)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn similarity(&self, a: &Signature, b: &Signature) -> f32 {
+        signature::measure(a, b, &self.options).1
+    }
+}
+
+/// Запись сигнатур во временный файл по мере загрузки
+struct SpillWriter {
+    path: TempPath,
+    writer: BufWriter<File>,
+    offsets: Vec<u64>,
+    position: u64,
+}
+
+impl SpillWriter {
+    fn create() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("imgalg-spill-{}-{}.bin", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path).map_err(|e| ImgAlgError::io(&path, e))?;
+        Ok(Self { path: TempPath(path), writer: BufWriter::new(file), offsets: vec![], position: 0 })
+    }
+
+    fn push(&mut self, signature: &Signature) -> Result<()> {
+        let mut record = vec![];
+        index::write_signature(&mut record, signature).map_err(|e| ImgAlgError::io(&self.path.0, e))?;
+        self.writer.write_all(&record).map_err(|e| ImgAlgError::io(&self.path.0, e))?;
+        self.offsets.push(self.position);
+        self.position += record.len() as u64;
+        Ok(())
+    }
+
+    /// Размер блока выбирается так, чтобы два блока средних сигнатур помещались в бюджет
+    fn finish(self, max_memory: usize, transform: Transform, letterbox: bool) -> Result<SpillFile> {
+        let path = self.path;
+        let file = self.writer.into_inner().map_err(|e| ImgAlgError::io(&path.0, e.into_error()))?;
+        let average = (self.position as usize / self.offsets.len().max(1)).max(1);
+        let block_len = (max_memory / 2 / average).max(1);
+        Ok(SpillFile { path, file, offsets: self.offsets, block_len, transform, letterbox })
+    }
+}
+
+impl SpillFile {
+    /// До `len` сигнатур подряд, начиная с `start`
+    fn read_block(&self, start: usize, len: usize) -> Result<Vec<Signature>> {
+        let count = len.min(self.offsets.len() - start);
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(self.offsets[start])).map_err(|e| ImgAlgError::io(&self.path.0, e))?;
+        let mut block = Vec::with_capacity(count);
+        for _ in 0..count {
+            let signature = index::read_signature(&mut reader, index::INDEX_VERSION, self.transform, self.letterbox).map_err(|e| match e {
+                IndexReadError::Io(e) => ImgAlgError::io(&self.path.0, e),
+                IndexReadError::Format(reason) => ImgAlgError::corrupt_index(&self.path.0, reason),
+            })?;
+            block.push(signature);
+        }
+        Ok(block)
+    }
+}
+
+/// Путь временного файла, который удаляется вместе с владельцем, в том числе при ошибке загрузки
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
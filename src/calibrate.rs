@@ -0,0 +1,184 @@
+//! Распределение схожести несвязанных пар для выбора порога
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::bench::SplitMix64;
+use crate::{signature, ComparerOptions, ImagesComparer, ImgAlgError, Result, Signature, SimilarityThreshold};
+
+/// Ширина столбца гистограммы в процентах схожести
+pub const HISTOGRAM_STEP: f32 = 5.0;
+
+/// Параметры калибровки
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrateOptions {
+    /// Сколько случайных пар разных изображений сравнить; если всех пар меньше - все
+    pub samples: usize,
+    /// Начальное значение генератора: одинаковое значение дает одинаковые пары
+    pub seed: u64,
+    /// Запас над 99-м процентилем фона в предлагаемом пороге, в процентах схожести
+    pub margin: f32,
+}
+
+impl Default for CalibrateOptions {
+    fn default() -> Self {
+        Self { samples: 2000, seed: 0, margin: 1.0 }
+    }
+}
+
+/// Порог `SignatureIndex::scan`, выводимый из фона просмотренных файлов: процентиль
+/// схожести случайных пар плюс запас `calibrate.margin`. В разнородных коллекциях
+/// фон разный (у снимков экрана он выше, чем у фотографий), и порог следует за ним
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveThreshold {
+    percentile: f32,
+    pub calibrate: CalibrateOptions,
+}
+
+impl AdaptiveThreshold {
+    /// Процентиль фона от 0 (не включая) до 100
+    pub fn new(percentile: f32, calibrate: CalibrateOptions) -> Result<Self> {
+        if !(percentile > 0.0 && percentile <= 100.0) {
+            return Err(ImgAlgError::InvalidOptions(format!("percentile {percentile} must be in (0, 100]")));
+        }
+        if !calibrate.margin.is_finite() {
+            return Err(ImgAlgError::InvalidOptions("margin must be a finite number".to_string()));
+        }
+        Ok(Self { percentile, calibrate })
+    }
+
+    pub fn percentile(&self) -> f32 {
+        self.percentile
+    }
+
+    /// Порог по распределению фона, не выше 100; `None`, если пар нет
+    pub fn derive(&self, background: &Distribution) -> Option<SimilarityThreshold> {
+        suggest(background, self.percentile, self.calibrate.margin)
+    }
+}
+
+/// Отсортированные по возрастанию оценки схожести
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Distribution {
+    pub scores: Vec<f32>,
+}
+
+impl Distribution {
+    fn new(mut scores: Vec<f32>) -> Self {
+        scores.sort_by(f32::total_cmp);
+        Self { scores }
+    }
+
+    /// Процентиль по ближайшему рангу, `None` у пустого распределения
+    pub fn percentile(&self, percent: f32) -> Option<f32> {
+        let rank = (self.scores.len() as f64 * percent as f64 / 100.0).ceil().max(1.0) as usize;
+        self.scores.get(rank.min(self.scores.len()).checked_sub(1)?).copied()
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        self.scores.last().copied()
+    }
+
+    /// Число оценок в столбцах `[0, 5), [5, 10), ... [95, 100]`
+    pub fn histogram(&self) -> Vec<usize> {
+        let bins = (100.0 / HISTOGRAM_STEP) as usize;
+        let mut histogram = vec![0; bins];
+        for &score in &self.scores {
+            histogram[((score / HISTOGRAM_STEP) as usize).min(bins - 1)] += 1;
+        }
+        histogram
+    }
+}
+
+/// Итог `calibrate`
+#[derive(Debug, Default)]
+pub struct Calibration {
+    /// Сколько изображений загружено
+    pub loaded: usize,
+    /// Схожесть случайных пар разных изображений
+    pub background: Distribution,
+    /// Схожесть заведомых дубликатов, если они переданы
+    pub duplicates: Option<Distribution>,
+    /// 99-й процентиль фона плюс запас, не выше 100; `None`, если пар нет
+    pub suggested: Option<SimilarityThreshold>,
+    /// Файлы, которые не удалось загрузить
+    pub errors: Vec<ImgAlgError>,
+}
+
+impl Calibration {
+    /// Сколько заведомых дубликатов оказалось бы ниже предложенного порога
+    pub fn duplicates_below(&self) -> usize {
+        match (&self.duplicates, self.suggested) {
+            (Some(duplicates), Some(threshold)) => duplicates.scores.iter().filter(|&&score| !threshold.is_met_by(score)).count(),
+            _ => 0,
+        }
+    }
+}
+
+/// Сравнивает случайные пары изображений из `paths` и, если переданы, пары заведомых
+/// дубликатов `duplicates`, с настройками сравнения `options`. Изображения загружаются
+/// параллельно, как в `ImagesComparer::new_lossy_with`. Пары выбираются генератором
+/// с начальным значением `calibrate.seed` из путей, отсортированных по имени, так что
+/// результат не зависит от порядка путей и числа потоков.
+///
+/// Фон описывает несвязанные изображения, только если дубликатов в каталоге мало:
+/// каждая пара дубликатов, попавшая в выборку, завышает верхние процентили
+pub fn calibrate<P: AsRef<Path> + Sync>(paths: &[P], duplicates: &[(P, P)], options: &ComparerOptions, calibrate: &CalibrateOptions) -> Calibration {
+    let mut calibration = Calibration::default();
+    let mut paths: Vec<PathBuf> = paths.iter().map(|path| path.as_ref().to_path_buf()).collect();
+    paths.sort();
+    paths.dedup();
+    let mut signatures: Vec<Signature> = vec![];
+    for load in ImagesComparer::_load_all(&paths, options) {
+        match load {
+            Ok((signature, _)) => signatures.push(signature),
+            Err(e) => calibration.errors.push(e),
+        }
+    }
+    calibration.loaded = signatures.len();
+    let similarity = |a: &Signature, b: &Signature| signature::measure(a, b, options).1;
+    let signature_refs: Vec<&Signature> = signatures.iter().collect();
+    calibration.background = background(&signature_refs, options, calibrate);
+
+    if !duplicates.is_empty() {
+        let flat: Vec<&Path> = duplicates.iter().flat_map(|(a, b)| [a.as_ref(), b.as_ref()]).collect();
+        let mut loads = ImagesComparer::_load_all(&flat, options).into_iter();
+        let mut scores = vec![];
+        while let (Some(a), Some(b)) = (loads.next(), loads.next()) {
+            match (a, b) {
+                (Ok((a, _)), Ok((b, _))) => scores.push(similarity(&a, &b)),
+                (a, b) => calibration.errors.extend([a.err(), b.err()].into_iter().flatten()),
+            }
+        }
+        calibration.duplicates = Some(Distribution::new(scores));
+    }
+
+    calibration.suggested = suggest(&calibration.background, 99.0, calibrate.margin);
+    calibration
+}
+
+/// Схожесть случайных пар разных сигнатур из `signatures`: `calibrate.samples` пар
+/// генератором с начальным значением `calibrate.seed` или все пары, если их меньше
+pub(crate) fn background(signatures: &[&Signature], options: &ComparerOptions, calibrate: &CalibrateOptions) -> Distribution {
+    let mut rng = SplitMix64(calibrate.seed);
+    let n = signatures.len();
+    let all_pairs = n * n.saturating_sub(1) / 2;
+    let mut pairs: Vec<(usize, usize)> = vec![];
+    if all_pairs <= calibrate.samples {
+        pairs.extend((0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b))));
+    } else {
+        let mut seen = HashSet::new();
+        while pairs.len() < calibrate.samples {
+            let (a, b) = (rng.below(n), rng.below(n));
+            if a != b && seen.insert((a.min(b), a.max(b))) {
+                pairs.push((a.min(b), a.max(b)));
+            }
+        }
+    }
+    Distribution::new(pairs.iter().map(|&(a, b)| signature::measure(signatures[a], signatures[b], options).1).collect())
+}
+
+/// Процентиль `percentile` фона плюс запас `margin`, не выше 100
+fn suggest(background: &Distribution, percentile: f32, margin: f32) -> Option<SimilarityThreshold> {
+    background.percentile(percentile).and_then(|score| SimilarityThreshold::new((score + margin).min(100.0)).ok())
+}
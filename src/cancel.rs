@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{ImgAlgError, Result};
+
+/// Флаг отмены долгой загрузки или сравнения, общий для всех своих копий.
+///
+/// Передается через `ComparerOptions::cancel_token`; `cancel` можно вызвать из любого потока.
+/// Флаг проверяется между файлами при загрузке и между строками при сравнении,
+/// так что уже начатое декодирование одного файла дорабатывает
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Cancelled`, если отмена уже запрошена
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() { Err(ImgAlgError::Cancelled) } else { Ok(()) }
+    }
+}
+
+/// Копии одного флага равны между собой
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::bench::{self, AlgorithmBench, BenchOptions};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::exclude::Excludes;
+use super::lang::{self, tr, Lang};
+use super::output::Output;
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Каталог с изображениями, просматривается рекурсивно
+    pub dir: PathBuf,
+
+    /// Сколько изображений случайно выбрать из каталога
+    #[arg(long, default_value_t = BenchOptions::default().sample)]
+    pub sample: usize,
+
+    /// Сколько случайных пар разных изображений сравнить
+    #[arg(long, default_value_t = BenchOptions::default().pairs)]
+    pub pairs: usize,
+
+    /// Начальное значение генератора случайных чисел: с тем же значением выборка и пары те же
+    #[arg(long, default_value_t = BenchOptions::default().seed)]
+    pub seed: u64,
+}
+
+#[derive(Serialize)]
+struct BenchReport<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    dir: &'a Path,
+    seed: u64,
+    /// Сколько изображений найдено в каталоге
+    found: usize,
+    algorithms: Vec<AlgorithmReport>,
+}
+
+#[derive(Serialize)]
+struct AlgorithmReport {
+    algorithm: &'static str,
+    loaded: usize,
+    failed: usize,
+    load_ms: f64,
+    pairs: usize,
+    pairs_per_second: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<ScoreReport>,
+}
+
+#[derive(Serialize)]
+struct ScoreReport {
+    min: f32,
+    median: f32,
+    p95: f32,
+}
+
+impl From<&AlgorithmBench> for AlgorithmReport {
+    fn from(result: &AlgorithmBench) -> Self {
+        Self {
+            algorithm: result.algorithm.as_str(),
+            loaded: result.loaded,
+            failed: result.failed,
+            load_ms: result.load_time.as_secs_f64() * 1000.0,
+            pairs: result.pairs,
+            pairs_per_second: result.pairs_per_second(),
+            similarity: result.scores.map(|scores| ScoreReport { min: scores.min, median: scores.median, p95: scores.p95 }),
+        }
+    }
+}
+
+pub fn run(args: &BenchArgs, json: bool, output: &Output) -> Result<()> {
+    if !args.dir.is_dir() {
+        return Err(CliError::new(ErrorCode::Args, format!("{} is not a directory", args.dir.display())).into());
+    }
+    let mut paths = vec![];
+    super::collect_images(&args.dir, &Excludes::none(), &mut paths)?;
+    let options = BenchOptions { sample: args.sample, pairs: args.pairs, seed: args.seed };
+    let results = bench::run(&paths, &options);
+
+    let sampled = results.first().map_or(0, |result| result.loaded + result.failed);
+    let summary = tr!("Images in the directory: {}, sampled: {}, seed: {}", "Изображений в каталоге: {}, в выборке: {}, seed: {}", paths.len(), sampled, args.seed);
+    if json {
+        let report = BenchReport { dir: &args.dir, seed: args.seed, found: paths.len(), algorithms: results.iter().map(AlgorithmReport::from).collect() };
+        return output.emit_json(&report, &summary);
+    }
+
+    let columns = match lang::current() {
+        Lang::En => ["algorithm", "loaded", "errors", "load,ms", "pairs", "pairs/s", "min,%", "median", "p95,%"],
+        Lang::Ru => ["алгоритм", "загружено", "ошибок", "загрузка,мс", "пар", "пар/с", "мин,%", "медиана", "p95,%"],
+    };
+    let mut report = format!("{:<16} {:>9} {:>7} {:>11} {:>7} {:>12} {:>7} {:>8} {:>7}\n", columns[0], columns[1], columns[2], columns[3], columns[4], columns[5], columns[6], columns[7], columns[8]);
+    for result in &results {
+        let (min, median, p95) = match result.scores {
+            Some(scores) => (format!("{:.2}", scores.min), format!("{:.2}", scores.median), format!("{:.2}", scores.p95)),
+            None => ("-".to_string(), "-".to_string(), "-".to_string()),
+        };
+        writeln!(
+            report,
+            "{:<16} {:>9} {:>7} {:>11.1} {:>7} {:>12.0} {:>7} {:>8} {:>7}",
+            result.algorithm.as_str(),
+            result.loaded,
+            result.failed,
+            result.load_time.as_secs_f64() * 1000.0,
+            result.pairs,
+            result.pairs_per_second(),
+            min,
+            median,
+            p95
+        )?;
+    }
+    output.emit(&report, &summary)
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use imgalg::index::{EntryState, IndexStats, SignatureIndex};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::lang::{self, tr};
+use super::output::Output;
+
+#[derive(Args)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Показать число записей, размер файла и сколько записей устарело
+    Stats {
+        /// Файл индекса (`scan --index`, `watch --index`)
+        index: PathBuf,
+    },
+    /// Удалить записи файлов, которых больше нет или которые изменились с последнего `scan`
+    Prune {
+        /// Файл индекса (`scan --index`, `watch --index`)
+        index: PathBuf,
+    },
+    /// Перезаписать файл индекса в текущем формате
+    Compact {
+        /// Файл индекса (`scan --index`, `watch --index`)
+        index: PathBuf,
+    },
+}
+
+#[derive(Serialize)]
+struct StatsJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    index: &'a Path,
+    file_size: u64,
+    entries: usize,
+    current: usize,
+    modified: usize,
+    missing: usize,
+    unstamped: usize,
+    pairs: usize,
+}
+
+#[derive(Serialize)]
+struct PruneJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    index: &'a Path,
+    removed: Vec<RemovedEntry<'a>>,
+    remaining: usize,
+    size_before: u64,
+    size_after: u64,
+}
+
+#[derive(Serialize)]
+struct RemovedEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    /// `missing` или `modified`
+    reason: &'static str,
+}
+
+#[derive(Serialize)]
+struct CompactJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    index: &'a Path,
+    size_before: u64,
+    size_after: u64,
+}
+
+pub fn run(args: &CacheArgs, json: bool, output: &Output) -> Result<()> {
+    match &args.action {
+        CacheAction::Stats { index } => stats(index, json, output),
+        CacheAction::Prune { index } => prune(index, json, output),
+        CacheAction::Compact { index } => compact(index, json, output),
+    }
+}
+
+fn stats(path: &Path, json: bool, output: &Output) -> Result<()> {
+    let index = load(path)?;
+    let file_size = file_size(path)?;
+    let IndexStats { entries, current, modified, missing, unstamped, pairs } = index.stats();
+    let summary = tr!("Entries: {}, out of date: {}, file size: {} bytes", "Записей: {}, устарело: {}, размер файла: {} байт", entries, modified + missing, file_size);
+    if json {
+        let report = StatsJson { index: path, file_size, entries, current, modified, missing, unstamped, pairs };
+        return output.emit_json(&report, &summary);
+    }
+    let mut text = String::new();
+    writeln!(text, "{}", tr!("Index {}", "Индекс {}", path.display()))?;
+    writeln!(text, "{}", tr!("Entries: {}, duplicate pairs: {}, file size: {} bytes", "Записей: {}, пар дубликатов: {}, размер файла: {} байт", entries, pairs, file_size))?;
+    writeln!(text, "{}", tr!("Current: {}, file changed: {}, file missing: {}, unstamped: {}", "Актуальных: {}, файл изменился: {}, файла нет: {}, без отметки: {}", current, modified, missing, unstamped))?;
+    output.emit(&text, &summary)
+}
+
+fn prune(path: &Path, json: bool, output: &Output) -> Result<()> {
+    let mut index = load(path)?;
+    let size_before = file_size(path)?;
+    let removed = index.prune();
+    if !removed.is_empty() {
+        index.set_command_line(std::env::args_os());
+        index.save(path).with_context(|| format!("Failed to save the index {}", path.display()))?;
+    }
+    let size_after = file_size(path)?;
+    let missing = removed.iter().filter(|(_, state)| *state == EntryState::Missing).count();
+    let summary = tr!(
+        "Entries removed: {} (file missing: {}, file changed: {}), remaining: {}, file size: {} -> {} bytes",
+        "Удалено записей: {} (файла нет: {}, файл изменился: {}), осталось: {}, размер файла: {} -> {} байт",
+        removed.len(),
+        missing,
+        removed.len() - missing,
+        index.len(),
+        size_before,
+        size_after
+    );
+    if json {
+        let removed = removed.iter().map(|(path, state)| RemovedEntry { path, reason: reason(*state) }).collect();
+        let report = PruneJson { index: path, removed, remaining: index.len(), size_before, size_after };
+        return output.emit_json(&report, &summary);
+    }
+    let mut text = String::new();
+    for (path, state) in &removed {
+        let reason = if *state == EntryState::Missing { lang::text("file missing", "файла нет") } else { lang::text("file changed", "файл изменился") };
+        writeln!(text, "{}", tr!("Removed: {} ({})", "Удалено: {} ({})", path.display(), reason))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)
+}
+
+fn compact(path: &Path, json: bool, output: &Output) -> Result<()> {
+    require(path)?;
+    let report = SignatureIndex::compact(path).map_err(|e| CliError::from_lib(&e))?;
+    let summary = tr!("Index rewritten, file size: {} -> {} bytes", "Индекс перезаписан, размер файла: {} -> {} байт", report.size_before, report.size_after);
+    if json {
+        let report = CompactJson { index: path, size_before: report.size_before, size_after: report.size_after };
+        return output.emit_json(&report, &summary);
+    }
+    output.emit(&(summary.clone() + "\n"), &summary)
+}
+
+fn load(path: &Path) -> Result<SignatureIndex> {
+    require(path)?;
+    Ok(SignatureIndex::load(path).map_err(|e| CliError::from_lib(&e))?)
+}
+
+/// Индекс должен уже существовать: в отличие от `scan`, пустой индекс не создается
+fn require(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Err(CliError::new(ErrorCode::Args, format!("Index file {} does not exist", path.display())).into());
+    }
+    Ok(())
+}
+
+fn reason(state: EntryState) -> &'static str {
+    match state {
+        EntryState::Missing => "missing",
+        _ => "modified",
+    }
+}
+
+fn file_size(path: &Path) -> Result<u64> {
+    Ok(fs::metadata(path).with_context(|| format!("Failed to read the index {}", path.display()))?.len())
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::calibrate::{self, CalibrateOptions, Distribution, HISTOGRAM_STEP};
+use imgalg::{ComparerOptions, ImgAlgError};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::exclude::Excludes;
+use super::lang::{self, tr};
+use super::output::Output;
+
+/// Ширина самого длинного столбца гистограммы в символах
+const BAR_WIDTH: usize = 40;
+
+#[derive(Args)]
+pub struct CalibrateArgs {
+    /// Каталог с изображениями, просматривается рекурсивно
+    pub dir: PathBuf,
+
+    /// Сколько случайных пар разных изображений сравнить
+    #[arg(long, default_value_t = CalibrateOptions::default().samples)]
+    pub samples: usize,
+
+    /// Начальное значение генератора случайных чисел: с тем же значением пары те же
+    #[arg(long, default_value_t = CalibrateOptions::default().seed)]
+    pub seed: u64,
+
+    /// Запас над 99-м процентилем фона в предлагаемом пороге, в процентах схожести
+    #[arg(long, default_value_t = CalibrateOptions::default().margin)]
+    pub margin: f32,
+
+    /// Файл с парами заведомых дубликатов в формате `--pairs`, чтобы показать и их распределение
+    #[arg(long, value_name = "FILE")]
+    pub duplicates: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct CalibrateJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    dir: &'a Path,
+    seed: u64,
+    loaded: usize,
+    background: DistributionJson,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicates: Option<DistributionJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_threshold: Option<f32>,
+    /// Сколько заведомых дубликатов ниже предложенного порога
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicates_below: Option<usize>,
+    errors: Vec<CliError>,
+}
+
+#[derive(Serialize)]
+struct DistributionJson {
+    pairs: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p50: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p90: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p99: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<f32>,
+    /// Число пар в столбцах по 5%: `[0, 5)`, `[5, 10)`, ... `[95, 100]`
+    histogram: Vec<usize>,
+}
+
+impl From<&Distribution> for DistributionJson {
+    fn from(distribution: &Distribution) -> Self {
+        Self {
+            pairs: distribution.scores.len(),
+            p50: distribution.percentile(50.0),
+            p90: distribution.percentile(90.0),
+            p99: distribution.percentile(99.0),
+            max: distribution.max(),
+            histogram: distribution.histogram(),
+        }
+    }
+}
+
+pub fn run(args: &CalibrateArgs, options: &ComparerOptions, json: bool, output: &Output) -> Result<()> {
+    if !args.dir.is_dir() {
+        return Err(CliError::new(ErrorCode::Args, format!("{} is not a directory", args.dir.display())).into());
+    }
+    let mut paths = vec![];
+    super::collect_images(&args.dir, &Excludes::none(), &mut paths)?;
+    let duplicates: Vec<(PathBuf, PathBuf)> = match &args.duplicates {
+        Some(path) => super::pairs::read_pairs(path)?.into_iter().map(|pair| (pair.a, pair.b)).collect(),
+        None => vec![],
+    };
+    let calibrate_options = CalibrateOptions { samples: args.samples, seed: args.seed, margin: args.margin };
+    let calibration = calibrate::calibrate(&paths, &duplicates, options, &calibrate_options);
+    if let Some(e) = calibration.errors.iter().find(|e| matches!(e, ImgAlgError::Cancelled)) {
+        return Err(CliError::from_lib(e).into());
+    }
+
+    let errors: Vec<CliError> = calibration.errors.iter().map(CliError::from_lib).collect();
+    let suggested = calibration.suggested.map(f32::from);
+    let summary = match suggested {
+        Some(threshold) => tr!("Images: {}, pairs: {}, suggested threshold: {:.2}%", "Изображений: {}, пар: {}, предлагаемый порог: {:.2}%", calibration.loaded, calibration.background.scores.len(), threshold),
+        None => tr!("Images: {}, no pairs to estimate from", "Изображений: {}, пар для оценки нет", calibration.loaded),
+    };
+    if json {
+        let report = CalibrateJson {
+            dir: &args.dir,
+            seed: args.seed,
+            loaded: calibration.loaded,
+            background: (&calibration.background).into(),
+            duplicates: calibration.duplicates.as_ref().map(DistributionJson::from),
+            suggested_threshold: suggested,
+            duplicates_below: calibration.duplicates.as_ref().map(|_| calibration.duplicates_below()),
+            errors,
+        };
+        return output.emit_json(&report, &summary);
+    }
+
+    let mut text = String::new();
+    write_distribution(&mut text, lang::text("Unrelated pairs", "Несвязанные пары"), &calibration.background)?;
+    if let Some(duplicates) = &calibration.duplicates {
+        write_distribution(&mut text, lang::text("Known duplicates", "Заведомые дубликаты"), duplicates)?;
+    }
+    if let Some(threshold) = suggested {
+        writeln!(text, "{}", tr!("Suggested threshold: {:.2}% (99th percentile of unrelated pairs + {})", "Предлагаемый порог: {:.2}% (99-й процентиль несвязанных пар + {})", threshold, args.margin))?;
+        if calibration.duplicates.is_some() {
+            writeln!(text, "{}", tr!("Known duplicates below the threshold: {}", "Заведомых дубликатов ниже порога: {}", calibration.duplicates_below()))?;
+        }
+    }
+    for error in &errors {
+        writeln!(text, "{}", tr!("Failed to process: {}", "Не удалось обработать: {}", error))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)
+}
+
+/// Процентили и гистограмма; пустые столбцы в начале не выводятся
+fn write_distribution(text: &mut String, title: &str, distribution: &Distribution) -> std::fmt::Result {
+    writeln!(text, "{}", tr!("{}, pairs: {}", "{}, пар: {}", title, distribution.scores.len()))?;
+    let value = |value: Option<f32>| value.map_or("-".to_string(), |value| format!("{value:.2}%"));
+    writeln!(
+        text,
+        "  p50 {}, p90 {}, p99 {}, max {}",
+        value(distribution.percentile(50.0)),
+        value(distribution.percentile(90.0)),
+        value(distribution.percentile(99.0)),
+        value(distribution.max())
+    )?;
+    let histogram = distribution.histogram();
+    let largest = histogram.iter().copied().max().unwrap_or(0).max(1);
+    let first = histogram.iter().position(|&count| count > 0).unwrap_or(histogram.len());
+    for (bin, &count) in histogram.iter().enumerate().skip(first) {
+        let from = bin as f32 * HISTOGRAM_STEP;
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest));
+        writeln!(text, "  {:>5.1}-{:<5.1} {:>7} {}", from, from + HISTOGRAM_STEP, count, bar)?;
+    }
+    writeln!(text)
+}
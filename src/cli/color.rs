@@ -0,0 +1,85 @@
+//! Цвета ANSI в текстовом выводе. Раскраска накладывается на уже готовые строки: без цвета
+//! `Palette` возвращает текст как есть, так что вывод в файл или канал не меняется
+
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Значения `--color`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Только в терминал и если не задана переменная `NO_COLOR` (по умолчанию)
+    #[default]
+    Auto,
+    /// Всегда, даже в канал и при `NO_COLOR`
+    Always,
+    Never,
+}
+
+static CHOICE: OnceLock<ColorChoice> = OnceLock::new();
+
+/// Запоминает `--color` на весь запуск; до вызова действует `auto`
+pub fn init(choice: ColorChoice) {
+    let _ = CHOICE.set(choice);
+}
+
+/// Раскраска для одного потока вывода
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    /// Без цвета, например для отчета в файл
+    pub const PLAIN: Self = Self { enabled: false };
+
+    pub fn stdout() -> Self {
+        Self::for_stream(std::io::stdout().is_terminal())
+    }
+
+    pub fn stderr() -> Self {
+        Self::for_stream(std::io::stderr().is_terminal())
+    }
+
+    fn for_stream(terminal: bool) -> Self {
+        let enabled = match CHOICE.get().copied().unwrap_or_default() {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        };
+        Self { enabled }
+    }
+
+    fn paint(self, code: &str, text: impl Display) -> String {
+        if self.enabled { format!("\x1b[{code}m{text}\x1b[0m") } else { text.to_string() }
+    }
+
+    /// `text` цветом по проценту схожести: от 99% - зеленым, от 95% - желтым, ниже - красным
+    pub fn similarity(self, similarity: f32, text: impl Display) -> String {
+        let code = match similarity {
+            s if s >= 99.0 => "32",
+            s if s >= 95.0 => "33",
+            _ => "31",
+        };
+        self.paint(code, text)
+    }
+
+    /// Процент схожести `{:.2}%` в цвете по его значению. `100.00%` - только у равных
+    /// сигнатур: почти полная схожесть не округляется до 100
+    pub fn percent(self, similarity: f32) -> String {
+        let shown = if similarity < 100.0 { similarity.min(99.99) } else { similarity };
+        self.similarity(similarity, format!("{shown:.2}%"))
+    }
+
+    /// Заголовок, например группы дубликатов, - жирным
+    pub fn header(self, text: impl Display) -> String {
+        self.paint("1", text)
+    }
+
+    /// Сообщение об ошибке - красным
+    pub fn error(self, text: impl Display) -> String {
+        self.paint("31", text)
+    }
+}
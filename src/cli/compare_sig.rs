@@ -0,0 +1,129 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::{compare_signatures, ComparerOptions, Cutoffs, ImagesComparer, Signature, SimilarityThreshold, Verdict};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode, Outcome};
+use super::lang::{self, tr};
+use super::output::Output;
+
+#[derive(Args)]
+pub struct CompareSigArgs {
+    /// Изображение, сигнатура которого сравнивается с сохраненными
+    pub image: PathBuf,
+
+    /// Сохраненная сигнатура в текстовой форме, например `v2:...`
+    #[arg(required_unless_present = "sigs")]
+    pub signature: Option<String>,
+
+    /// Еще одна сохраненная сигнатура; можно указать несколько раз
+    #[arg(long = "sig", value_name = "SIGNATURE")]
+    pub sigs: Vec<String>,
+
+    /// Минимальный процент схожести: если хотя бы одна сигнатура ниже порога, процесс
+    /// завершается с кодом 1
+    #[arg(long)]
+    pub threshold: Option<SimilarityThreshold>,
+
+    /// Сравнивать старые сигнатуры 16x16 с многомасштабной сигнатурой изображения
+    /// (`--multi-scale`), уменьшив ее до 16x16. Точность такого сравнения ниже, в выводе оно
+    /// отмечено; без флага сигнатуры с другими сетками - ошибка
+    #[arg(long)]
+    pub allow_downsample: bool,
+}
+
+#[derive(Serialize)]
+struct CompareSigJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    image: &'a Path,
+    /// Сигнатура изображения в той же текстовой форме, чтобы ее можно было сохранить
+    signature: String,
+    /// В порядке сигнатур в командной строке: сначала позиционная, затем `--sig`
+    matches: Vec<SigMatch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CliError>,
+}
+
+#[derive(Serialize)]
+struct SigMatch {
+    /// Номер сигнатуры, с единицы
+    signature: usize,
+    similarity: f32,
+    #[serde(serialize_with = "super::serialize_verdict")]
+    verdict: Option<Verdict>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passed: Option<bool>,
+    /// Сторона сетки, до которой уменьшена сигнатура изображения (`--allow-downsample`):
+    /// схожесть посчитана с пониженной точностью
+    #[serde(skip_serializing_if = "Option::is_none")]
+    downsampled_to: Option<u32>,
+}
+
+/// Сравнивает изображение с сигнатурами без исходных файлов. Сигнатуры разбираются и
+/// сверяются с настройками до декодирования изображения
+pub fn run(args: &CompareSigArgs, options: &ComparerOptions, cutoffs: Cutoffs, json: bool, output: &Output) -> Result<Outcome> {
+    let mut stored = vec![];
+    for (pos, text) in args.signature.iter().chain(&args.sigs).enumerate() {
+        let signature = text.parse::<Signature>().and_then(|signature| check(signature, options, args.allow_downsample));
+        match signature {
+            Ok(signature) => stored.push(signature),
+            Err(e) => return Err(CliError::new(ErrorCode::Args, format!("Signature {}: {}", pos + 1, e)).into()),
+        }
+    }
+    let (comparer, errors) = ImagesComparer::new_lossy_with(&[&args.image], options.clone());
+    if let Some(e) = errors.first() {
+        return Err(CliError::from_lib(e).into());
+    }
+    let upload = comparer.signature(0).expect("the only image was loaded");
+
+    let mut matches = vec![];
+    for (pos, (signature, downsampled_to)) in stored.iter().enumerate() {
+        let similarity = match downsampled_to {
+            Some(grid) => upload.downsample_to(*grid).and_then(|reduced| compare_signatures(&reduced, signature, options)),
+            None => compare_signatures(upload, signature, options),
+        };
+        let similarity = similarity.map_err(|e| CliError::from_lib(&e))?;
+        let passed = args.threshold.map(|threshold| threshold.is_met_by(similarity));
+        matches.push(SigMatch { signature: pos + 1, similarity, verdict: Some(Verdict::from_similarity(similarity, &cutoffs)), passed, downsampled_to: *downsampled_to });
+    }
+    let passed = matches.iter().all(|m| m.passed != Some(false));
+    let outcome = if passed { Outcome::Passed } else { Outcome::BelowThreshold };
+    let best = matches.iter().map(|m| m.similarity).fold(0.0, f32::max);
+    let summary = tr!("Signatures: {}, best similarity: {:.2}%", "Сигнатур: {}, наибольшая схожесть: {:.2}%", matches.len(), best);
+    if json {
+        let error = (!passed).then(|| CliError::new(ErrorCode::Threshold, "Similarity is below the threshold"));
+        let report = CompareSigJson { image: &args.image, signature: upload.to_string(), matches, threshold: args.threshold.map(f32::from), error };
+        output.emit_json(&report, &summary)?;
+        return Ok(outcome);
+    }
+
+    let palette = output.palette();
+    let mut text = String::new();
+    for m in &matches {
+        let mark = if m.passed == Some(false) { lang::text(", below the threshold", ", ниже порога") } else { "" };
+        let reduced = m.downsampled_to.map(|grid| tr!(", reduced precision: grid {0}x{0}", ", пониженная точность: сетка {0}x{0}", grid)).unwrap_or_default();
+        writeln!(text, "{}", tr!("Signature {}: {} ({}{}{})", "Сигнатура {}: {} ({}{}{})", m.signature, palette.percent(m.similarity), m.verdict.map_or("", super::verdict_word), mark, reduced))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)?;
+    Ok(outcome)
+}
+
+/// Сохраненная сигнатура, годная для сравнения, и сетка, до которой для этого нужно уменьшить
+/// сигнатуру изображения. Уменьшение допускается только для обычной сигнатуры, посчитанной
+/// с теми же настройками без `--multi-scale`
+fn check(signature: Signature, options: &ComparerOptions, allow_downsample: bool) -> imgalg::Result<(Signature, Option<u32>)> {
+    let error = match signature.check_options(options) {
+        Ok(()) => return Ok((signature, None)),
+        Err(e) => e,
+    };
+    if allow_downsample && options.is_multi_scale() && signature.check_options(&options.clone().multi_scale(false)).is_ok() {
+        let grid = signature.grid_sizes()[0];
+        return Ok((signature, Some(grid)));
+    }
+    Err(error)
+}
@@ -0,0 +1,305 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use imgalg::{ComparerOptions, Cutoffs, SimilarityThreshold, Transform};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::color::ColorChoice;
+use super::lang::{self, tr, LangChoice};
+use super::{ChannelArg, Cli};
+
+/// Значения по умолчанию для параметров командной строки, с теми же именами, что у флагов
+/// (`jobs = 4` вместо `--jobs 4`). Приоритет: флаги командной строки, затем файл настроек,
+/// затем встроенные значения. Флаг-переключатель, включенный в файле, из командной строки
+/// не выключается
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Минимальный процент схожести для совпадения
+    #[serde(with = "threshold_value", skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<SimilarityThreshold>,
+    /// Интервал сохранения индекса в режиме наблюдения и промежуточного индекса `scan --index`, в секундах
+    pub flush_interval: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<ChannelArg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weights: Option<[f32; 3]>,
+    pub ignore_hue: bool,
+    pub multi_scale: bool,
+    pub letterbox: bool,
+    pub strict_aspect: bool,
+    pub ensemble: bool,
+    #[serde(with = "text_value", skip_serializing_if = "Option::is_none")]
+    pub value_transform: Option<Transform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_worst: Option<usize>,
+    /// В секундах, можно дробное
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_timeout: Option<f64>,
+    pub retries: u32,
+    /// В миллисекундах
+    pub retry_delay: u64,
+    #[serde(with = "text_value", skip_serializing_if = "Option::is_none")]
+    pub bands: Option<Cutoffs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_size: Option<u32>,
+    pub color: ColorChoice,
+    pub lang: LangChoice,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            flush_interval: 30,
+            jobs: None,
+            channel: None,
+            weights: None,
+            ignore_hue: false,
+            multi_scale: false,
+            letterbox: false,
+            strict_aspect: false,
+            ensemble: false,
+            value_transform: None,
+            ignore_worst: None,
+            decode_timeout: None,
+            retries: 0,
+            retry_delay: 100,
+            bands: None,
+            icon_size: None,
+            color: ColorChoice::Auto,
+            lang: LangChoice::En,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Вывести итоговые настройки: файл настроек вместе с флагами командной строки
+    #[arg(long)]
+    pub show: bool,
+}
+
+impl Config {
+    /// Загружает настройки из указанного файла или из файла по умолчанию.
+    /// Отсутствие файла по умолчанию не ошибка, отсутствие явно указанного файла - ошибка
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let path = match explicit {
+            Some(path) => {
+                if !path.is_file() {
+                    bail!("Config file {} does not exist", path.display());
+                }
+                path.to_path_buf()
+            }
+            None => match default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the config {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text).map_err(|e| anyhow::anyhow!("{}", e.to_string().trim_end()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Те же проверки, что clap делает для флагов
+    fn validate(&self) -> Result<()> {
+        if self.jobs == Some(0) {
+            bail!("jobs must be at least 1");
+        }
+        if let Some(weights) = self.weights {
+            ComparerOptions::new().channel_weights(weights).context("invalid weights")?;
+        }
+        if let Some(seconds) = self.decode_timeout {
+            super::timeout_from_secs(seconds).map_err(|e| anyhow::anyhow!("invalid decode_timeout: {e}"))?;
+        }
+        if self.icon_size.is_some_and(|size| !(1..=256).contains(&size)) {
+            bail!("icon_size must be between 1 and 256");
+        }
+        Ok(())
+    }
+
+    /// Дополняет флаги значениями из файла там, где флаг не задан, и возвращает итоговые
+    /// настройки, которые выводит `config --show`
+    pub fn apply(&self, cli: &mut Cli) -> Self {
+        cli.threshold = cli.threshold.or(self.threshold);
+        cli.jobs = cli.jobs.or(self.jobs);
+        cli.channel = cli.channel.or(self.channel);
+        cli.weights = cli.weights.or(self.weights);
+        cli.ignore_hue |= self.ignore_hue;
+        cli.multi_scale |= self.multi_scale;
+        cli.letterbox |= self.letterbox;
+        cli.strict_aspect |= self.strict_aspect;
+        cli.ensemble |= self.ensemble;
+        cli.value_transform = cli.value_transform.or(self.value_transform);
+        cli.ignore_worst = cli.ignore_worst.or(self.ignore_worst);
+        cli.decode_timeout = cli.decode_timeout.or(self.decode_timeout.map(Duration::from_secs_f64));
+        cli.retries = cli.retries.or(Some(self.retries));
+        cli.retry_delay = cli.retry_delay.or(Some(self.retry_delay));
+        cli.bands = cli.bands.or(self.bands);
+        cli.icon_size = cli.icon_size.or(self.icon_size);
+        cli.color = cli.color.or(Some(self.color));
+        cli.lang = cli.lang.or(Some(self.lang));
+        Self {
+            threshold: Some(cli.threshold.unwrap_or(SimilarityThreshold::DEFAULT)),
+            flush_interval: self.flush_interval,
+            jobs: cli.jobs,
+            channel: cli.channel,
+            weights: cli.weights,
+            ignore_hue: cli.ignore_hue,
+            multi_scale: cli.multi_scale,
+            letterbox: cli.letterbox,
+            strict_aspect: cli.strict_aspect,
+            ensemble: cli.ensemble,
+            value_transform: Some(cli.value_transform.unwrap_or_default()),
+            ignore_worst: cli.ignore_worst,
+            decode_timeout: cli.decode_timeout.map(|timeout| timeout.as_secs_f64()),
+            retries: cli.retries.unwrap_or_default(),
+            retry_delay: cli.retry_delay.unwrap_or_default(),
+            bands: Some(cli.bands.unwrap_or_default()),
+            icon_size: cli.icon_size,
+            color: cli.color.unwrap_or_default(),
+            lang: cli.lang.unwrap_or_default(),
+        }
+    }
+
+    /// Порог подкоманд, у которых нет своего `--threshold`
+    pub fn threshold(&self) -> SimilarityThreshold {
+        self.threshold.unwrap_or(SimilarityThreshold::DEFAULT)
+    }
+}
+
+/// В файле настроек порог хранится обычным числом
+mod threshold_value {
+    use imgalg::SimilarityThreshold;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(threshold: &Option<SimilarityThreshold>, serializer: S) -> Result<S::Ok, S::Error> {
+        match threshold {
+            Some(threshold) => serializer.serialize_f32(threshold.value()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<SimilarityThreshold>, D::Error> {
+        SimilarityThreshold::new(f32::deserialize(deserializer)?).map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Значения, у которых есть запись строкой, как у флага: `value_transform = "gamma=2.2"`,
+/// `bands = "99.5,97,90"`
+mod text_value {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: FromStr<Err: Display>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+        String::deserialize(deserializer)?.parse().map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `~/.config/imgalg/config.toml` (или `$XDG_CONFIG_HOME/imgalg/config.toml`)
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("imgalg").join("config.toml"))
+}
+
+pub fn run(args: &ConfigArgs, config: &Config, explicit: Option<&Path>) -> Result<()> {
+    let source = explicit.map(Path::to_path_buf).or_else(default_path);
+    match &source {
+        Some(path) if path.is_file() => println!("{}", tr!("# Config file: {}", "# Файл настроек: {}", path.display())),
+        Some(path) => println!("{}", tr!("# Config file not found: {}", "# Файл настроек не найден: {}", path.display())),
+        None => println!("{}", lang::text("# Config file not found", "# Файл настроек не найден")),
+    }
+    if args.show {
+        print!("{}", toml::to_string(config).context("Failed to serialize the config")?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("imgalg").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn command_line_overrides_file_and_file_overrides_defaults() {
+        let config = Config::parse("threshold = 93\njobs = 4\nignore_hue = true").unwrap();
+        let mut cli = cli(&["--jobs", "2", "a.png", "b.png"]);
+        let effective = config.apply(&mut cli);
+        assert_eq!(cli.jobs, Some(2));
+        assert_eq!(cli.threshold.map(|t| t.value()), Some(93.0));
+        assert!(cli.ignore_hue);
+        assert_eq!(effective.jobs, Some(2));
+        assert_eq!(effective.retry_delay, 100);
+        assert_eq!(effective.flush_interval, 30);
+        assert_eq!(effective.decode_timeout, None);
+        assert_eq!(effective.bands, Some(Cutoffs::default()));
+    }
+
+    #[test]
+    fn built_in_threshold_without_file_or_flag() {
+        let effective = Config::default().apply(&mut cli(&[]));
+        assert_eq!(effective.threshold(), SimilarityThreshold::DEFAULT);
+        let effective = Config::parse("threshold = 90").unwrap().apply(&mut cli(&["--threshold", "97"]));
+        assert_eq!(effective.threshold().value(), 97.0);
+    }
+
+    #[test]
+    fn shown_config_parses_back() {
+        let config = Config::parse("threshold = 90.5\nweights = [0.2, 0.6, 0.2]\nchannel = \"g\"\ndecode_timeout = 2.5\nvalue_transform = \"gamma=2.2\"\nbands = \"99.9,98,92\"\nicon_size = 16\ncolor = \"always\"\nlang = \"ru\"").unwrap();
+        let effective = config.apply(&mut cli(&[]));
+        let shown = toml::to_string(&effective).unwrap();
+        let parsed = Config::parse(&shown).unwrap();
+        assert_eq!(parsed.threshold.map(|t| t.value()), Some(90.5));
+        assert_eq!(parsed.weights, Some([0.2, 0.6, 0.2]));
+        assert!(matches!(parsed.channel, Some(ChannelArg::G)));
+        assert_eq!(parsed.decode_timeout, Some(2.5));
+        assert_eq!(parsed.value_transform, Some(Transform::Gamma(2.2)));
+        assert_eq!(parsed.bands, Some(Cutoffs::new(99.9, 98.0, 92.0).unwrap()));
+        assert_eq!(parsed.icon_size, Some(16));
+        assert_eq!(parsed.color, ColorChoice::Always);
+        assert_eq!(parsed.lang, LangChoice::Ru);
+    }
+
+    #[test]
+    fn unknown_key_is_named() {
+        let error = Config::parse("treshold = 93").unwrap_err().to_string();
+        assert!(error.contains("unknown field `treshold`"), "{error}");
+    }
+
+    #[test]
+    fn malformed_file_is_an_error() {
+        for text in ["threshold = ", "jobs = \"four\"", "threshold = 150", "jobs = 0", "weights = [0, 0, 0]", "channel = \"x\"", "decode_timeout = 0", "value_transform = \"gamma=0\"", "bands = \"90,95,99\"", "icon_size = 0", "icon_size = 512", "color = \"rainbow\"", "lang = \"fr\""] {
+            assert!(Config::parse(text).is_err(), "{text}");
+        }
+    }
+}
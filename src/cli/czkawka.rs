@@ -0,0 +1,71 @@
+use anyhow::Result;
+use imgalg::{ComparerOptions, ImageInfo, ImagesComparer, SimilarityThreshold};
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::lang::tr;
+use super::output;
+use super::verbosity;
+
+/// Пишет группы дубликатов в текстовом формате, который сохраняет czkawka для похожих изображений:
+/// заголовок с каталогами, число групп, затем каждая группа с числом изображений и строками
+/// `"путь" - ШxВ - размер - схожесть`. Первое изображение группы - образец, схожесть указана с ним.
+/// Размеры и вес файла берутся из сведений, собранных при загрузке
+pub fn run(images: &[PathBuf], options: &ComparerOptions, threshold: Option<SimilarityThreshold>, path: &Path) -> Result<()> {
+    if images.len() < 2 {
+        return Err(CliError::new(ErrorCode::Args, "At least two images are required").into());
+    }
+    let (comparer, errors) = ImagesComparer::new_lossy_with(images, options.clone());
+    if let Some(error) = errors.first() {
+        return Err(CliError::from_lib(error).into());
+    }
+    let groups = comparer.duplicate_groups(threshold.unwrap_or_default());
+
+    output::write_atomic(path, |writer| {
+        let mut directories: Vec<&Path> = images.iter().map(|image| directory(image)).collect();
+        directories.sort();
+        directories.dedup();
+        writeln!(writer, "Results of searching {:?} with excluded directories [] and excluded items []", directories)?;
+        if groups.is_empty() {
+            write!(writer, "Not found any similar images.")?;
+            return Ok(());
+        }
+        write!(writer, "{} images which have similar friends\n\n", groups.len())?;
+        for group in &groups {
+            writeln!(writer, "Found {} images which have similar friends", group.len())?;
+            for &idx in group {
+                let Some(info) = comparer.image_info(idx) else { continue };
+                let similarity = comparer.similarity_percentage_between(group[0], idx)?;
+                writeln!(writer, "{}", entry_line(&images[idx], info, similarity))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    })?;
+    verbosity::info(tr!("Duplicate groups: {}, list written to {}", "Групп дубликатов: {}, список записан в {}", groups.len(), path.display()));
+    Ok(())
+}
+
+/// Каталог файла; у пути без каталога - текущий
+fn directory(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn entry_line(path: &Path, info: &ImageInfo, similarity: f32) -> String {
+    format!("{:?} - {}x{} - {} - {:.2}%", path, info.width, info.height, binary_size(info.file_size), similarity)
+}
+
+/// Размер в двоичных единицах, как его выводит czkawka: `895 B`, `1.50 KiB`, `2 MiB`
+fn binary_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value.fract() == 0.0 { format!("{} {}", value, UNITS[unit]) } else { format!("{:.2} {}", value, UNITS[unit]) }
+}
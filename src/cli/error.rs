@@ -0,0 +1,286 @@
+use imgalg::{ImgAlgError, Warning};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::lang::tr;
+use super::verbosity;
+
+/// Стабильные коды ошибок командной строки. Коды и соответствующие им коды
+/// завершения процесса - часть интерфейса, их нельзя менять между версиями:
+///
+/// | код                | exit | причина                                   |
+/// |--------------------|------|-------------------------------------------|
+/// | `E_THRESHOLD`      | 1    | схожесть ниже порога `--threshold`        |
+/// | `E_ARGS`           | 2    | неверные аргументы или файл настроек      |
+/// | `E_IO`             | 3    | файл не найден или не читается            |
+/// | `E_DECODE`         | 4    | файл поврежден или не является картинкой  |
+/// | `E_UNSUPPORTED`    | 5    | формат или цветовая модель не поддержаны  |
+/// | `E_TIMEOUT`        | 6    | декодирование дольше `--decode-timeout`   |
+/// | `E_INDEX_MISMATCH` | 7    | индекс построен другой версией алгоритма  |
+/// | `E_CANCELLED`      | 130  | прервано по Ctrl-C                        |
+/// | `E_INTERNAL`       | 10   | прочие ошибки                             |
+///
+/// Исключение - `check`: он завершается с кодом 1 при любой ошибке, см. `exit_code`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorCode {
+    #[serde(rename = "E_THRESHOLD")]
+    Threshold,
+    #[serde(rename = "E_ARGS")]
+    Args,
+    #[serde(rename = "E_IO")]
+    Io,
+    #[serde(rename = "E_DECODE")]
+    Decode,
+    #[serde(rename = "E_UNSUPPORTED")]
+    Unsupported,
+    #[serde(rename = "E_TIMEOUT")]
+    Timeout,
+    #[serde(rename = "E_INDEX_MISMATCH")]
+    IndexMismatch,
+    #[serde(rename = "E_CANCELLED")]
+    Cancelled,
+    #[serde(rename = "E_INTERNAL")]
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Threshold => "E_THRESHOLD",
+            Self::Args => "E_ARGS",
+            Self::Io => "E_IO",
+            Self::Decode => "E_DECODE",
+            Self::Unsupported => "E_UNSUPPORTED",
+            Self::Timeout => "E_TIMEOUT",
+            Self::IndexMismatch => "E_INDEX_MISMATCH",
+            Self::Cancelled => "E_CANCELLED",
+            Self::Internal => "E_INTERNAL",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Threshold => 1,
+            Self::Args => 2,
+            Self::Io => 3,
+            Self::Decode => 4,
+            Self::Unsupported => 5,
+            Self::Timeout => 6,
+            Self::IndexMismatch => 7,
+            Self::Cancelled => 130,
+            Self::Internal => 10,
+        }
+    }
+
+    /// Определяет код по цепочке причин ошибки
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(cli_error) = cause.downcast_ref::<CliError>() {
+                return cli_error.code;
+            }
+            if let Some(lib_error) = cause.downcast_ref::<ImgAlgError>() {
+                return Self::of(lib_error);
+            }
+            if let Some(image_error) = cause.downcast_ref::<image::ImageError>() {
+                return Self::of_image(image_error);
+            }
+            if cause.downcast_ref::<std::io::Error>().is_some() {
+                return Self::Io;
+            }
+        }
+        Self::Internal
+    }
+
+    pub fn of(error: &ImgAlgError) -> Self {
+        match error {
+            ImgAlgError::Io { .. } => Self::Io,
+            ImgAlgError::Decode { source, .. } => Self::of_image(source),
+            ImgAlgError::UnsupportedColorType(_) => Self::Unsupported,
+            ImgAlgError::Timeout { .. } => Self::Timeout,
+            ImgAlgError::CorruptIndex { .. } => Self::Decode,
+            ImgAlgError::IndexMismatch { .. } => Self::IndexMismatch,
+            ImgAlgError::InvalidOptions(_) => Self::Args,
+            ImgAlgError::InvalidSignature(_) => Self::Args,
+            ImgAlgError::CropOutOfBounds { .. } => Self::Args,
+            ImgAlgError::Video { .. } => Self::Decode,
+            ImgAlgError::IconSizeMissing { .. } => Self::Args,
+            ImgAlgError::ToleranceMapSize { .. } => Self::Args,
+            ImgAlgError::EmptyImage { .. } => Self::Decode,
+            ImgAlgError::ExtremeAspect { .. } => Self::Unsupported,
+            ImgAlgError::Journal { .. } => Self::Args,
+            ImgAlgError::Cancelled => Self::Cancelled,
+            ImgAlgError::Retried { source, .. } => Self::of(source),
+            _ => Self::Internal,
+        }
+    }
+
+    fn of_image(error: &image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(_) => Self::Io,
+            image::ImageError::Unsupported(_) => Self::Unsupported,
+            _ => Self::Decode,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Ошибка с кодом и, если известно, путем к файлу, из-за которого она возникла
+#[derive(Debug, Serialize)]
+pub struct CliError {
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "super::serialize_optional_path")]
+    pub path: Option<PathBuf>,
+    pub message: String,
+    /// Сколько раз файл перечитывался, прежде чем ошибка признана окончательной (`--retries`)
+    #[serde(skip_serializing_if = "is_zero")]
+    pub retries: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl CliError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, path: None, message: message.into(), retries: 0 }
+    }
+
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Ошибка библиотеки с кодом, определенным по ее варианту
+    pub fn from_lib(error: &ImgAlgError) -> Self {
+        let cli_error = Self { retries: error.retries(), ..Self::new(ErrorCode::of(error), error.to_string()) };
+        match error.path() {
+            Some(path) => cli_error.with_path(path),
+            None => cli_error,
+        }
+    }
+
+    /// Приводит произвольную ошибку к `CliError`, сохраняя уже назначенный код
+    pub fn from_anyhow(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<CliError>() {
+            Ok(cli_error) => return cli_error,
+            Err(error) => error,
+        };
+        match error.downcast_ref::<ImgAlgError>() {
+            Some(lib_error) => Self::from_lib(lib_error),
+            None => Self::new(ErrorCode::classify(&error), render(&error)),
+        }
+    }
+
+    /// Объект `{ "error": { ... } }` для вывода в режиме `--json`, а в `--format ndjson` -
+    /// строка `{"type": "error", ...}`, как и у остальных ошибок отчета
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            error: &'a CliError,
+        }
+        #[derive(Serialize)]
+        struct Line<'a> {
+            #[serde(rename = "type")]
+            kind: &'static str,
+            #[serde(flatten)]
+            error: &'a CliError,
+        }
+        if LINES.get().copied().unwrap_or_default() {
+            return serde_json::to_string(&Line { kind: "error", error: self }).unwrap_or_default();
+        }
+        serde_json::to_string_pretty(&Envelope { error: self }).unwrap_or_default()
+    }
+}
+
+static LINES: OnceLock<bool> = OnceLock::new();
+
+/// Запоминает на весь запуск, что отчет выводится строками NDJSON
+pub fn init_lines(lines: bool) {
+    let _ = LINES.set(lines);
+}
+
+static CHECK: OnceLock<bool> = OnceLock::new();
+
+/// Код завершения `check`, если изображение совпало с записью набора
+pub const CHECK_MATCHED_EXIT: i32 = 2;
+
+/// Запоминает на весь запуск, что выполняется `check`
+pub fn init_check(check: bool) {
+    let _ = CHECK.set(check);
+}
+
+/// Код завершения процесса при ошибке. Хукам модерации после `check` нужны три исхода:
+/// 0 - совпадений нет, 2 - есть (`CHECK_MATCHED_EXIT`), 1 - проверка не удалась
+pub fn exit_code(code: ErrorCode) -> i32 {
+    if CHECK.get().copied().unwrap_or_default() { 1 } else { code.exit_code() }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Цепочка причин через двоеточие. Ошибки библиотеки уже включают свою причину
+/// в текст, поэтому повторяющиеся звенья пропускаются
+pub fn render(error: &anyhow::Error) -> String {
+    let mut message = String::new();
+    for cause in error.chain() {
+        let text = cause.to_string();
+        if message.contains(&text) {
+            continue;
+        }
+        if !message.is_empty() {
+            message.push_str(": ");
+        }
+        message.push_str(&text);
+    }
+    message
+}
+
+/// Итог успешно отработавшей команды
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Сравнение выполнено, но схожесть ниже порога: отчет уже выведен, нужен код `E_THRESHOLD`
+    BelowThreshold,
+    /// Прервано по Ctrl-C: отчет по уже сделанному выведен, нужен код `E_CANCELLED`
+    Cancelled,
+    /// `check` нашел совпадение: отчет выведен, нужен код `CHECK_MATCHED_EXIT`
+    Matched,
+}
+
+/// Предупреждение библиотеки в JSON-отчете, массив `warnings`
+#[derive(Debug, Serialize)]
+pub struct CliWarning {
+    /// Вид предупреждения, например `stale_entry`
+    pub kind: &'static str,
+    #[serde(serialize_with = "super::serialize_path")]
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl CliWarning {
+    pub fn from_lib(warning: &Warning) -> Self {
+        Self { kind: warning.as_str(), path: warning.path().to_path_buf(), message: warning.to_string() }
+    }
+}
+
+/// Предупреждения текстового отчета выводятся в stderr, как и сводка
+pub fn print_warnings<'a>(warnings: impl IntoIterator<Item = &'a Warning>) {
+    for warning in warnings {
+        verbosity::info(tr!("Warning: {}: {}", "Предупреждение: {}: {}", warning.path().display(), warning));
+    }
+}
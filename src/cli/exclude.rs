@@ -0,0 +1,189 @@
+//! Исключения при обходе каталога в синтаксисе `.gitignore`: `--exclude`, `--exclude-from`
+//! и встроенные исключения служебных каталогов. Исключенный каталог не читается вовсе,
+//! так что его файлы не декодируются и даже не перечисляются
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Встроенные исключения: миниатюры freedesktop и каталоги миниатюр Synology
+const DEFAULT_EXCLUDES: [&str; 2] = [".thumbnails/", "@eaDir/"];
+/// Скрытые файлы и каталоги, пока не передан `--hidden`
+const HIDDEN: &str = ".*";
+
+#[derive(Args)]
+pub struct ExcludeArgs {
+    /// Не просматривать файлы и каталоги, подходящие под шаблон `.gitignore`: `*.tmp`,
+    /// `cache/` (только каталоги), `/raw/**/*.png` (от корня просмотра), `!keep.png`
+    /// (вернуть исключенное раньше). Можно указать несколько раз
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Файл с шаблонами, по одному в строке, как `.gitignore`; они применяются до `--exclude`
+    #[arg(long, value_name = "FILE")]
+    pub exclude_from: Vec<PathBuf>,
+
+    /// Просматривать скрытые файлы и каталоги (имя начинается с точки)
+    #[arg(long)]
+    pub hidden: bool,
+
+    /// Не применять встроенные исключения `.thumbnails/`, `@eaDir/` и скрытых файлов
+    #[arg(long)]
+    pub no_default_excludes: bool,
+}
+
+/// Сколько записей пропущено при обходе
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Excluded {
+    pub files: usize,
+    /// Каталоги пропускаются целиком, их содержимое не считается
+    pub directories: usize,
+}
+
+impl Excluded {
+    pub fn is_empty(&self) -> bool {
+        self.files == 0 && self.directories == 0
+    }
+}
+
+/// Шаблоны в порядке применения: решает последний подошедший
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: Vec<char>,
+    /// `!` в начале: подошедший путь возвращается
+    negated: bool,
+    /// `/` в конце: только каталоги
+    directory: bool,
+    /// `/` в начале или середине: сравнивается весь путь от корня, а не только имя
+    anchored: bool,
+}
+
+impl Excludes {
+    /// Без исключений, как обход до появления `--exclude`
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn from_args(args: &ExcludeArgs) -> Result<Self> {
+        let mut excludes = Self::none();
+        if !args.no_default_excludes {
+            excludes.patterns.extend(DEFAULT_EXCLUDES.iter().filter_map(|line| Pattern::parse(line)));
+            if !args.hidden {
+                excludes.patterns.extend(Pattern::parse(HIDDEN));
+            }
+        }
+        for path in &args.exclude_from {
+            let text = fs::read_to_string(path).with_context(|| format!("Failed to read the exclude file {}", path.display()))?;
+            excludes.patterns.extend(text.lines().filter_map(Pattern::parse));
+        }
+        excludes.patterns.extend(args.exclude.iter().filter_map(|line| Pattern::parse(line)));
+        Ok(excludes)
+    }
+
+    /// Исключен ли путь `relative` от корня просмотра
+    pub fn is_excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let path: Vec<char> = relative.to_string_lossy().replace('\\', "/").chars().collect();
+        let name_start = path.iter().rposition(|&c| c == '/').map_or(0, |pos| pos + 1);
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            if pattern.negated != excluded || (pattern.directory && !is_dir) {
+                continue; // Шаблон ничего не изменил бы
+            }
+            let target = if pattern.anchored { &path[..] } else { &path[name_start..] };
+            if glob_match(&pattern.glob, target) {
+                excluded = !pattern.negated;
+            }
+        }
+        excluded
+    }
+}
+
+impl Pattern {
+    /// Строка `.gitignore`; пустые строки и комментарии - `None`
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim_start_matches('\u{feff}');
+        // Пробелы в конце отбрасываются, если не экранированы
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix("\\!").map_or(line, |_| &line[1..])),
+        };
+        let line = line.strip_prefix("\\#").map_or(line, |_| &line[1..]);
+        let (directory, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Self { glob: line.chars().collect(), negated, directory, anchored })
+    }
+}
+
+/// Сопоставление с шаблоном `.gitignore`: `*` и `?` не переходят через `/`, `**/` - любое
+/// число каталогов (и ни одного), `**` в конце - все внутри, `[a-z]`, `[!a-z]` - классы,
+/// `\` экранирует следующий символ
+fn glob_match(glob: &[char], path: &[char]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        ['*', '*'] => true,
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, path) || path.iter().enumerate().any(|(pos, &c)| c == '/' && glob_match(rest, &path[pos + 1..]))
+        }
+        ['*', rest @ ..] => {
+            let limit = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=limit).any(|skip| glob_match(rest, &path[skip..]))
+        }
+        ['?', rest @ ..] => matches!(path, [c, ..] if *c != '/') && glob_match(rest, &path[1..]),
+        ['[', class @ ..] => match (path.first(), class_match(class, path.first().copied())) {
+            (Some(_), Some((true, rest))) => glob_match(rest, &path[1..]),
+            (_, Some((false, _))) | (None, Some(_)) => false,
+            (_, None) => matches!(path, ['[', ..]) && glob_match(class, &path[1..]), // Незакрытая скобка - сама по себе
+        },
+        ['\\', c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+    }
+}
+
+/// Класс символов после `[`: подходит ли `c` и остаток шаблона после `]`;
+/// `None`, если закрывающей скобки нет
+fn class_match(class: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match class {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [']', tail @ ..] if !first => return Some((found != negated && c != Some('/'), tail)),
+            [low, '-', high, tail @ ..] if *high != ']' => {
+                found |= c.is_some_and(|c| (*low..=*high).contains(&c));
+                rest = tail;
+            }
+            [single, tail @ ..] => {
+                found |= c == Some(*single);
+                rest = tail;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::{Fingerprint, FingerprintMode};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::CliError;
+use super::lang::tr;
+use super::output::Output;
+
+#[derive(Args)]
+pub struct FingerprintArgs {
+    /// Изображения
+    #[arg(required = true)]
+    pub images: Vec<PathBuf>,
+
+    /// Режим совместимости с `img_hash` 3.x (`HashAlg::Mean` и `preproc_dct()`): отпечатки
+    /// выводятся в base64, как `ImageHash::to_base64()`, и совпадают с уже посчитанными им.
+    /// Без флага - классический pHash шестнадцатеричным числом
+    #[arg(long)]
+    pub img_hash: bool,
+}
+
+#[derive(Serialize)]
+struct FingerprintsJson<'a> {
+    /// `native` или `img_hash`
+    mode: &'static str,
+    fingerprints: Vec<FingerprintRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct FingerprintRow<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CliError>,
+}
+
+pub fn run(args: &FingerprintArgs, json: bool, output: &Output) -> Result<()> {
+    let mode = if args.img_hash { FingerprintMode::ImgHash } else { FingerprintMode::Native };
+    let rows: Vec<FingerprintRow> = args.images.iter().map(|path| match Fingerprint::compute_with(path, mode) {
+        Ok(fingerprint) => {
+            let text = if args.img_hash { fingerprint.to_img_hash_base64() } else { fingerprint.to_string() };
+            FingerprintRow { path, fingerprint: Some(text), error: None }
+        }
+        Err(e) => FingerprintRow { path, fingerprint: None, error: Some(CliError::from_lib(&e)) },
+    }).collect();
+    let failed = rows.iter().filter(|row| row.error.is_some()).count();
+    let summary = tr!("Fingerprints: {}, errors: {}", "Отпечатков: {}, ошибок: {}", rows.len() - failed, failed);
+    if json {
+        let mode = if args.img_hash { "img_hash" } else { "native" };
+        return output.emit_json(&FingerprintsJson { mode, fingerprints: rows }, &summary);
+    }
+    let mut text = String::new();
+    for row in &rows {
+        match (&row.fingerprint, &row.error) {
+            (Some(fingerprint), _) => writeln!(text, "{}  {}", fingerprint, row.path.display())?,
+            (None, Some(error)) => writeln!(text, "{}", output.palette().error(tr!("Error: {}", "Ошибка: {}", error)))?,
+            (None, None) => {}
+        }
+    }
+    writeln!(text, "{summary}")?;
+    output.emit(&text, &summary)
+}
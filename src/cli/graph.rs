@@ -0,0 +1,33 @@
+use anyhow::Result;
+use imgalg::graph::DuplicateGraph;
+use imgalg::{ComparerOptions, ImagesComparer, SimilarityThreshold};
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::lang::tr;
+use super::output;
+use super::verbosity;
+
+/// Пишет пары не ниже `--threshold` (по умолчанию 95%) графом: в GraphML, если у файла
+/// расширение `.graphml`, иначе в формате DOT для Graphviz
+pub fn run(images: &[PathBuf], options: &ComparerOptions, threshold: Option<SimilarityThreshold>, path: &Path) -> Result<()> {
+    if images.len() < 2 {
+        return Err(CliError::new(ErrorCode::Args, "At least two images are required").into());
+    }
+    let (comparer, errors) = ImagesComparer::new_lossy_with(images, options.clone());
+    if let Some(error) = errors.first() {
+        return Err(CliError::from_lib(error).into());
+    }
+    let graph = DuplicateGraph::new(&comparer, images, threshold.unwrap_or_default());
+    let graphml = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("graphml"));
+    output::write_atomic(path, |writer| {
+        if graphml {
+            graph.write_graphml(writer)?;
+        } else {
+            graph.write_dot(writer)?;
+        }
+        Ok(())
+    })?;
+    verbosity::info(tr!("Nodes: {}, edges: {}, graph written to {}", "Вершин: {}, ребер: {}, граф записан в {}", graph.nodes.len(), graph.edges.len(), path.display()));
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Допустимые пары для `--pairs`: `--ignore-pairs` читает их, `--write-ignores` дописывает
+//! пары ниже порога. Файл того же вида, что и список пар: строки `a<TAB>b` или CSV
+
+use anyhow::{bail, Context, Result};
+use imgalg::paths;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::lang::tr;
+use super::pairs;
+
+/// Пары, чей результат не считается провалом; порядок путей в паре не важен
+#[derive(Debug, Default)]
+pub struct IgnoredPairs {
+    pairs: HashSet<(PathBuf, PathBuf)>,
+}
+
+impl IgnoredPairs {
+    pub fn open(path: &Path) -> Result<Self> {
+        let pairs = pairs::read_pairs(path)?.iter().map(|pair| key(&pair.a, &pair.b)).collect();
+        Ok(Self { pairs })
+    }
+
+    pub fn contains(&self, a: &Path, b: &Path) -> bool {
+        !self.pairs.is_empty() && self.pairs.contains(&key(a, b))
+    }
+}
+
+/// Одна и та же пара при любом написании путей: `./a.png` и `a.png`, ссылки, порядок
+fn key(a: &Path, b: &Path) -> (PathBuf, PathBuf) {
+    let (a, b) = (normalize(a), normalize(b));
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+/// Настоящий путь файла; для исчезнувшего - путь без `.`
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.components().filter(|component| *component != Component::CurDir).collect())
+}
+
+/// Дописывает пары в `path` в его же формате, спросив подтверждение, если не передан `--yes`.
+/// Возвращает, сколько пар записано
+pub fn append(path: &Path, pairs: &[(&Path, &Path)], yes: bool) -> Result<usize> {
+    if pairs.is_empty() || !(yes || confirm(path, pairs.len())?) {
+        return Ok(0);
+    }
+    let existing = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let csv = pairs::is_csv(&existing);
+    let mut text = vec![];
+    if !existing.is_empty() && !existing.ends_with(b"\n") {
+        text.push(b'\n');
+    }
+    for &(a, b) in pairs {
+        let (a, b) = (paths::to_bytes(a), paths::to_bytes(b));
+        if csv {
+            text.extend(csv_field(&a));
+            text.push(b',');
+            text.extend(csv_field(&b));
+        } else {
+            if [&a, &b].iter().any(|path| path.contains(&b'\t') || path.contains(&b'\n')) {
+                bail!("Cannot write a path with a tab or a line break to {}", path.display());
+            }
+            text.extend_from_slice(&a);
+            text.push(b'\t');
+            text.extend_from_slice(&b);
+        }
+        text.push(b'\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(&text).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(pairs.len())
+}
+
+/// Можно ли будет спросить подтверждение: проверяется до сравнения, чтобы не терять на него время
+pub fn check_confirmation(yes: bool) -> Result<()> {
+    if !yes && !std::io::stdin().is_terminal() {
+        return Err(CliError::new(ErrorCode::Args, "--write-ignores needs confirmation: pass --yes when stdin is not a terminal").into());
+    }
+    Ok(())
+}
+
+/// Вопрос в stderr, ответ из stdin; без терминала спросить некого
+fn confirm(path: &Path, count: usize) -> Result<bool> {
+    check_confirmation(false)?;
+    eprint!("{}", tr!("Add the pairs below the threshold ({}) to {}? [y/N] ", "Добавить пары ниже порога ({}) в {}? [y/N] ", count, path.display()));
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes" | "д" | "да"))
+}
+
+fn csv_field(field: &[u8]) -> Vec<u8> {
+    if !field.iter().any(|byte| matches!(byte, b',' | b'"' | b'\n' | b'\r')) {
+        return field.to_vec();
+    }
+    let mut quoted = vec![b'"'];
+    for &byte in field {
+        if byte == b'"' {
+            quoted.push(b'"');
+        }
+        quoted.push(byte);
+    }
+    quoted.push(b'"');
+    quoted
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use imgalg::index::SignatureIndex;
+use imgalg::provenance::parse_utc;
+use imgalg::{paths, Provenance};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::lang::{self, tr};
+use super::output::{Output, ProvenanceJson};
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Файл индекса (`scan --index`) или JSON-отчет (`--json`)
+    pub file: PathBuf,
+}
+
+#[derive(Serialize)]
+struct InspectJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    file: &'a Path,
+    /// `index` или `report`
+    kind: &'static str,
+    provenance: ProvenanceJson<'a>,
+}
+
+pub fn run(args: &InspectArgs, json: bool, output: &Output) -> Result<()> {
+    let path = args.file.as_path();
+    let (kind, provenance) = if SignatureIndex::is_index_file(path) {
+        ("index", SignatureIndex::read_provenance(path).map_err(|e| CliError::from_lib(&e))?)
+    } else {
+        ("report", read_report(path)?)
+    };
+    let summary = tr!("{}: imgalg {}, format {}", "{}: imgalg {}, формат {}", path.display(), provenance.version.as_deref().unwrap_or("?"), provenance.format_version);
+    if json {
+        let report = InspectJson { file: path, kind, provenance: ProvenanceJson::new(&provenance) };
+        return output.emit(&(serde_json::to_string_pretty(&report)? + "\n"), &summary);
+    }
+    let mut text = String::new();
+    writeln!(text, "{} ({})", path.display(), if kind == "index" { lang::text("index", "индекс") } else { lang::text("report", "отчет") })?;
+    writeln!(text, "{}", tr!("imgalg version: {}", "Версия imgalg: {}", provenance.version.as_deref().unwrap_or(lang::text("unknown", "неизвестна"))))?;
+    writeln!(text, "{}", tr!("Format version: {}", "Версия формата: {}", provenance.format_version))?;
+    writeln!(text, "{}", tr!("Pipeline: {}", "Вычисление: {}", provenance.pipeline))?;
+    writeln!(text, "{}", tr!("Algorithm: {}", "Алгоритм: {}", provenance.algorithm_id()))?;
+    writeln!(text, "{}", tr!("Written: {}", "Записан: {}", provenance.created_utc().as_deref().unwrap_or(lang::text("unknown", "неизвестно"))))?;
+    match &provenance.command_line {
+        Some(args) => {
+            let args: Vec<_> = args.iter().map(|arg| paths::encode(Path::new(arg)).into_owned()).collect();
+            writeln!(text, "{}", tr!("Command: {}", "Команда: {}", args.join(" ")))?;
+        }
+        None => writeln!(text, "{}", lang::text("Command: unknown", "Команда: неизвестна"))?,
+    }
+    output.emit(&text, &summary)
+}
+
+/// Сведения из поля `provenance` JSON-отчета
+fn read_report(path: &Path) -> Result<Provenance> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let invalid = || CliError::new(ErrorCode::Args, format!("{} is neither an index nor a JSON report", path.display()));
+    let report: Value = serde_json::from_str(&text).map_err(|_| invalid())?;
+    let Some(provenance) = report.get("provenance") else {
+        return Err(CliError::new(ErrorCode::Args, format!("Report {} has no provenance", path.display())).into());
+    };
+    let string = |key: &str| provenance.get(key).and_then(Value::as_str);
+    let format_version = provenance.get("format_version").and_then(Value::as_u64).and_then(|version| u32::try_from(version).ok());
+    let (Some(format_version), Some(pipeline)) = (format_version, string("pipeline")) else {
+        return Err(invalid().into());
+    };
+    let command_line = provenance.get("command_line").and_then(Value::as_array).map(|args| {
+        args.iter().filter_map(Value::as_str).map(|arg| paths::decode(arg).into_os_string()).collect()
+    });
+    Ok(Provenance {
+        version: string("version").map(str::to_string),
+        format_version,
+        pipeline: pipeline.to_string(),
+        created: string("created").and_then(parse_utc),
+        command_line,
+    })
+}
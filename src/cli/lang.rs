@@ -0,0 +1,72 @@
+//! Язык текстового вывода: `--lang en|ru|auto`, по умолчанию английский. Переводится только
+//! то, что читает человек: текстовые отчеты, сводка, сообщения в stderr. Имена полей JSON,
+//! CSV и NDJSON, коды ошибок и сообщения библиотеки от языка не зависят.
+//!
+//! Обе формы строки стоят рядом в месте вывода: `tr!("Pairs: {}", "Пар: {}", count)`,
+//! для строк без подстановок - `text("Results:", "Результаты:")`
+
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+/// Значения `--lang`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LangChoice {
+    #[default]
+    En,
+    Ru,
+    /// По переменным окружения `LC_ALL`, `LC_MESSAGES` и `LANG`; если ни одна не русская - английский
+    Auto,
+}
+
+/// Язык вывода после разбора `--lang`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    Ru,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Запоминает язык на весь запуск; до вызова выводится по-английски
+pub fn init(choice: LangChoice) {
+    let lang = match choice {
+        LangChoice::En => Lang::En,
+        LangChoice::Ru => Lang::Ru,
+        LangChoice::Auto => from_env(),
+    };
+    let _ = LANG.set(lang);
+}
+
+pub fn current() -> Lang {
+    LANG.get().copied().unwrap_or_default()
+}
+
+/// Строка без подстановок на выбранном языке
+pub fn text(en: &'static str, ru: &'static str) -> &'static str {
+    match current() {
+        Lang::En => en,
+        Lang::Ru => ru,
+    }
+}
+
+/// Первая непустая переменная в порядке POSIX решает, например `ru_RU.UTF-8`
+fn from_env() -> Lang {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter().filter_map(|name| std::env::var(name).ok()).find(|value| !value.is_empty());
+    match locale {
+        Some(locale) if locale.starts_with("ru") => Lang::Ru,
+        _ => Lang::En,
+    }
+}
+
+/// `format!` на выбранном языке: сначала английская строка, затем русская, затем аргументы
+macro_rules! tr {
+    ($en:literal, $ru:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::cli::lang::current() {
+            $crate::cli::lang::Lang::En => format!($en $(, $arg)*),
+            $crate::cli::lang::Lang::Ru => format!($ru $(, $arg)*),
+        }
+    };
+}
+pub(crate) use tr;
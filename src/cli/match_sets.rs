@@ -0,0 +1,118 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::{Assignment, ComparerOptions, SimilarityThreshold};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::exclude::Excludes;
+use super::lang::{self, tr};
+use super::output::Output;
+
+#[derive(Args)]
+pub struct MatchArgs {
+    /// Первый каталог, просматривается рекурсивно
+    pub dir_a: PathBuf,
+
+    /// Второй каталог: каждому изображению первого подбирается свое изображение из него
+    pub dir_b: PathBuf,
+
+    /// Отметить пары со схожестью ниже этого процента
+    #[arg(long)]
+    pub min_similarity: Option<SimilarityThreshold>,
+
+    /// Подобрать пары с наибольшей суммарной схожестью (венгерский алгоритм) вместо
+    /// жадного подбора по убыванию схожести; медленнее на больших наборах
+    #[arg(long)]
+    pub exact: bool,
+}
+
+#[derive(Serialize)]
+struct MatchJson<'a> {
+    pairs: Vec<PairEntry<'a>>,
+    #[serde(serialize_with = "super::serialize_paths")]
+    unmatched_a: Vec<&'a Path>,
+    #[serde(serialize_with = "super::serialize_paths")]
+    unmatched_b: Vec<&'a Path>,
+    /// Сколько пар ниже `--min-similarity`
+    below_min_similarity: usize,
+    errors: Vec<CliError>,
+}
+
+#[derive(Serialize)]
+struct PairEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    a: &'a Path,
+    #[serde(serialize_with = "super::serialize_path")]
+    b: &'a Path,
+    similarity: f32,
+    /// Схожесть ниже `--min-similarity`
+    below_min_similarity: bool,
+}
+
+pub fn run(args: &MatchArgs, options: &ComparerOptions, json: bool, output: &Output) -> Result<()> {
+    let mut sets = [vec![], vec![]];
+    for (dir, files) in [&args.dir_a, &args.dir_b].into_iter().zip(&mut sets) {
+        if !dir.is_dir() {
+            return Err(CliError::new(ErrorCode::Args, format!("{} is not a directory", dir.display())).into());
+        }
+        super::collect_images(dir, &Excludes::none(), files)?;
+        files.sort();
+    }
+    let [a, b] = &sets;
+    let assignment = if args.exact { Assignment::Optimal } else { Assignment::Greedy };
+    let (matching, errors) = imgalg::match_sets(a, b, options, assignment);
+    if let Some(e) = errors.iter().find(|e| matches!(e, imgalg::ImgAlgError::Cancelled)) {
+        return Err(CliError::from_lib(e).into());
+    }
+
+    let errors: Vec<CliError> = errors.iter().map(CliError::from_lib).collect();
+    let below = |similarity: f32| args.min_similarity.is_some_and(|floor| !floor.is_met_by(similarity));
+    let below_count = matching.pairs.iter().filter(|pair| below(pair.similarity)).count();
+    let summary = tr!(
+        "Pairs: {}, unmatched in the first directory: {}, in the second: {}, below the threshold: {}",
+        "Пар: {}, без пары в первом каталоге: {}, во втором: {}, ниже порога: {}",
+        matching.pairs.len(),
+        matching.unmatched_a.len(),
+        matching.unmatched_b.len(),
+        below_count
+    );
+    if json {
+        let report = MatchJson {
+            pairs: matching
+                .pairs
+                .iter()
+                .map(|pair| PairEntry { a: &a[pair.a], b: &b[pair.b], similarity: pair.similarity, below_min_similarity: below(pair.similarity) })
+                .collect(),
+            unmatched_a: matching.unmatched_a.iter().map(|&idx| a[idx].as_path()).collect(),
+            unmatched_b: matching.unmatched_b.iter().map(|&idx| b[idx].as_path()).collect(),
+            below_min_similarity: below_count,
+            errors,
+        };
+        return output.emit_json(&report, &summary);
+    }
+
+    let palette = output.palette();
+    let mut text = String::new();
+    let width = matching.pairs.iter().map(|pair| a[pair.a].display().to_string().chars().count()).max().unwrap_or(0);
+    for pair in &matching.pairs {
+        let mark = if below(pair.similarity) { "!" } else { " " };
+        let path_a = a[pair.a].display().to_string();
+        writeln!(text, "{} {:<width$}  {}  {}", mark, path_a, b[pair.b].display(), palette.percent(pair.similarity))?;
+    }
+    if below_count > 0 {
+        writeln!(text, "{}", lang::text("! - similarity below the threshold", "! - схожесть ниже порога"))?;
+    }
+    for &idx in &matching.unmatched_a {
+        writeln!(text, "{}", tr!("Unmatched: {}", "Без пары: {}", a[idx].display()))?;
+    }
+    for &idx in &matching.unmatched_b {
+        writeln!(text, "{}", tr!("Unmatched: {}", "Без пары: {}", b[idx].display()))?;
+    }
+    for error in &errors {
+        writeln!(text, "{}", palette.error(tr!("Failed to process: {}", "Не удалось обработать: {}", error)))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)
+}
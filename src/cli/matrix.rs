@@ -0,0 +1,138 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use imgalg::{algorithm_id, paths, BoundedComparer, ComparerOptions, ImageInfo, ImagesComparer};
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::error::{CliError, ErrorCode};
+use super::lang::tr;
+use super::output::Output;
+use super::report::ImageEntry;
+
+/// Формат вывода матрицы схожести
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MatrixFormat {
+    /// Объект с массивом `paths` и матрицей `matrix` (массив массивов)
+    Json,
+    /// Таблица с путями в заголовке и в первом столбце; в последнем столбце `algorithm` -
+    /// идентификатор вычисления (`imgalg::algorithm_id`)
+    Csv,
+}
+
+#[derive(Serialize)]
+struct MatrixReport<'a> {
+    #[serde(serialize_with = "super::serialize_paths")]
+    paths: &'a [PathBuf],
+    images: Vec<ImageEntry<'a>>,
+    matrix: Vec<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<MemoryStats>,
+}
+
+/// Как сработало ограничение `--max-memory`
+#[derive(Serialize)]
+struct MemoryStats {
+    max_memory_mb: u64,
+    /// Сигнатуры не поместились в бюджет и были выгружены во временный файл
+    spilled: bool,
+    blocks: usize,
+}
+
+/// Изображения, загруженные целиком или с ограничением памяти
+enum Loaded {
+    Full(ImagesComparer),
+    Bounded(BoundedComparer),
+}
+
+impl Loaded {
+    fn image_info(&self, index: usize) -> Option<&ImageInfo> {
+        match self {
+            Self::Full(comparer) => comparer.image_info(index),
+            Self::Bounded(comparer) => comparer.image_info(index),
+        }
+    }
+
+    fn for_each_row(&self, on_row: impl FnMut(usize, &[f32]) -> Result<()>) -> Result<()> {
+        match self {
+            Self::Full(comparer) => comparer.for_each_row(on_row),
+            Self::Bounded(comparer) => comparer.for_each_row(on_row),
+        }
+    }
+}
+
+pub fn run(images: &[PathBuf], options: &ComparerOptions, max_memory: Option<u64>, format: MatrixFormat, output: &Output) -> Result<()> {
+    if images.len() < 2 {
+        return Err(CliError::new(ErrorCode::Args, "At least two images are required").into());
+    }
+    let (loaded, errors) = match max_memory {
+        None => {
+            let (comparer, errors) = ImagesComparer::new_lossy_with(images, options.clone());
+            (Loaded::Full(comparer), errors)
+        }
+        Some(megabytes) => {
+            let budget = usize::try_from(megabytes.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX);
+            let (comparer, errors) = BoundedComparer::load(images, options.clone(), budget).map_err(|e| CliError::from_lib(&e))?;
+            (Loaded::Bounded(comparer), errors)
+        }
+    };
+    if let Some(error) = errors.first() {
+        return Err(CliError::from_lib(error).into());
+    }
+
+    let memory = match (&loaded, max_memory) {
+        (Loaded::Bounded(comparer), Some(max_memory_mb)) => {
+            Some(MemoryStats { max_memory_mb, spilled: comparer.is_spilled(), blocks: comparer.blocks() })
+        }
+        _ => None,
+    };
+    let mut summary = tr!("Similarity matrix {}x{}", "Матрица схожести {}x{}", images.len(), images.len());
+    if let Some(memory) = memory.as_ref().filter(|memory| memory.spilled) {
+        summary += &tr!(", signatures spilled to disk, blocks: {}", ", сигнатуры выгружены на диск, блоков: {}", memory.blocks);
+    }
+    match format {
+        MatrixFormat::Json => {
+            let entries = images
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, path)| loaded.image_info(idx).map(|info| ImageEntry::new(path, info)))
+                .collect();
+            let mut matrix = Vec::with_capacity(images.len());
+            loaded.for_each_row(|_, row| {
+                matrix.push(row.to_vec());
+                Ok(())
+            })?;
+            let report = MatrixReport { paths: images, images: entries, matrix, memory };
+            output.emit_json(&report, &summary)
+        }
+        MatrixFormat::Csv => output.emit_with(&summary, |writer| write_csv(writer, images, &loaded, &algorithm_id(options))),
+    }
+}
+
+/// Строки CSV пишутся сразу в вывод, без сборки всей таблицы в строку.
+/// Пути не в UTF-8 записываются так же, как в JSON: `imgalg::paths::encode`
+fn write_csv(writer: &mut dyn Write, paths: &[PathBuf], loaded: &Loaded, algorithm: &str) -> Result<()> {
+    write!(writer, "path")?;
+    for path in paths {
+        write!(writer, ",{}", csv_field(&paths::encode(path)))?;
+    }
+    writeln!(writer, ",algorithm")?;
+    let algorithm = csv_field(algorithm);
+    loaded.for_each_row(|i, row| {
+        write!(writer, "{}", csv_field(&paths::encode(&paths[i])))?;
+        for value in row {
+            write!(writer, ",{}", value)?;
+        }
+        writeln!(writer, ",{}", algorithm)?;
+        Ok(())
+    })
+}
+
+/// Поля с запятыми, кавычками или переводами строк берутся в кавычки
+pub(super) fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
@@ -0,0 +1,452 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use imgalg::{Channel, ChannelSelect, ComparerOptions, CopyKind, Cutoffs, FileId, Rect, SimilarityThreshold, Transform, Verdict};
+use anyhow::Context;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use exclude::{Excluded, Excludes};
+
+pub mod bench;
+pub mod cache;
+pub mod calibrate;
+pub mod color;
+pub mod compare_sig;
+pub mod config;
+pub mod czkawka;
+pub mod error;
+pub mod exclude;
+pub mod fingerprint;
+pub mod graph;
+pub mod ignores;
+pub mod inspect;
+pub mod lang;
+pub mod match_sets;
+pub mod matrix;
+pub mod outliers;
+pub mod output;
+pub mod pairs;
+pub mod report;
+pub mod scan;
+pub mod set;
+pub mod sheets;
+pub mod verbosity;
+pub mod watch;
+
+/// Сравнение изображений по уменьшенной цветовой сигнатуре
+#[derive(Parser)]
+#[command(name = "imgalg", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Файл настроек вместо ~/.config/imgalg/config.toml
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Изображения для сравнения: два, или сколько угодно с `--matrix`
+    pub images: Vec<PathBuf>,
+
+    /// Файл со списком пар для сравнения: строки `a<TAB>b` или CSV с заголовком
+    #[arg(long, conflicts_with = "images")]
+    pub pairs: Option<PathBuf>,
+
+    /// Файл допустимых пар того же вида, что и `--pairs`: такие пары ниже `--threshold` попадают
+    /// в отчет с отметкой `ignored` и не считаются провалом. Пути сравниваются после
+    /// приведения к настоящим, так что `./a.png` и `a.png` - одна пара
+    #[arg(long, value_name = "FILE", requires = "pairs")]
+    pub ignore_pairs: Option<PathBuf>,
+
+    /// Дописать пары ниже `--threshold` в файл допустимых пар, спросив подтверждение
+    #[arg(long, value_name = "FILE", requires_all = ["pairs", "threshold"])]
+    pub write_ignores: Option<PathBuf>,
+
+    /// Не спрашивать подтверждение для `--write-ignores`
+    #[arg(long, requires = "write_ignores")]
+    pub yes: bool,
+
+    /// Вывести полную матрицу схожести всех переданных изображений
+    #[arg(long, value_enum, conflicts_with = "pairs")]
+    pub matrix: Option<matrix::MatrixFormat>,
+
+    /// Записать группы дубликатов среди переданных изображений в файл в формате czkawka.
+    /// Дубликаты - пары не ниже `--threshold` (по умолчанию 95%)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["pairs", "matrix", "explain"])]
+    pub export_czkawka: Option<PathBuf>,
+
+    /// Записать пары не ниже `--threshold` (по умолчанию 95%) среди переданных изображений
+    /// графом: вершины - файлы, ребра - пары со схожестью. Формат DOT для Graphviz,
+    /// с расширением `.graphml` - GraphML
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["pairs", "matrix", "explain", "export_czkawka"])]
+    pub export_graph: Option<PathBuf>,
+
+    /// Ограничение памяти под сигнатуры для `--matrix`, в мегабайтах: сверх него сигнатуры
+    /// выгружаются во временный файл и сравниваются блоками
+    #[arg(long, value_name = "MB", requires = "matrix")]
+    pub max_memory: Option<u64>,
+
+    /// Веса каналов R,G,B в разнице сигнатур, например `0.2,0.6,0.2`
+    #[arg(long, value_name = "R,G,B", value_parser = parse_weights)]
+    pub weights: Option<[f32; 3]>,
+
+    /// Сравнивать только по выбранным каналам
+    #[arg(long, value_enum)]
+    pub channel: Option<ChannelArg>,
+
+    /// Не учитывать тон: сравнивать по насыщенности и яркости
+    #[arg(long)]
+    pub ignore_hue: bool,
+
+    /// Сравнивать на сетках 8x8, 16x16 и 32x32 и усреднять результат
+    #[arg(long)]
+    pub multi_scale: bool,
+
+    /// Во что переводить значения каналов: `square` (по умолчанию) выделяет светлые участки,
+    /// `identity` весит все уровни одинаково, `sqrt` выделяет темные, `gamma=<показатель>` -
+    /// степень. `scan --index` записывает его в индекс и пересчитывает индекс с другим;
+    /// `watch` берет его из индекса
+    #[arg(long, value_name = "TRANSFORM")]
+    pub value_transform: Option<Transform>,
+
+    /// Вписывать изображение в квадрат с полями его среднего цвета перед уменьшением, чтобы
+    /// обрезка с другим соотношением сторон (3:2 и 4:5) не растягивалась иначе, чем исходное.
+    /// `scan --index` записывает его в индекс и пересчитывает индекс, посчитанный иначе;
+    /// `watch` берет его из индекса
+    #[arg(long, conflicts_with = "tolerance_map")]
+    pub letterbox: bool,
+
+    /// Не загружать изображения, у которых одна сторона длиннее другой больше чем в 50 раз
+    /// (полосы вроде 1x2000): без флага они сравниваются с предупреждением `extreme_aspect`,
+    /// с флагом - пропускаются с ошибкой `E_UNSUPPORTED`
+    #[arg(long)]
+    pub strict_aspect: bool,
+
+    /// Оценивать неуверенность схожести: считать сигнатуры еще с двумя фильтрами уменьшения
+    /// (Triangle, CatmullRom) и выводить схожесть средним с разбросом, `97.30% ±0.80`. Пары,
+    /// у которых порог лежит между наименьшей и наибольшей оценкой, помечаются неуверенными.
+    /// Примерно вдвое дольше загрузка; не сочетается с `--letterbox` и не действует на `scan`
+    /// и `watch`
+    #[arg(long, conflicts_with = "letterbox")]
+    pub ensemble: bool,
+
+    /// Не учитывать N ячеек с наибольшим вкладом в разницу пары (не больше 8), чтобы битые
+    /// пиксели, пыль и мелкие надписи вроде даты не мешали найти дубликат
+    #[arg(long, value_name = "N")]
+    pub ignore_worst: Option<usize>,
+
+    /// Карта допусков: полутоновое изображение тех же размеров, что и сравниваемые. Чем светлее
+    /// пиксель, тем большая разница допускается в этом месте; белое не сравнивается совсем
+    #[arg(long, value_name = "FILE", conflicts_with = "max_memory")]
+    pub tolerance_map: Option<PathBuf>,
+
+    /// Ограничение времени декодирования одного файла, в секундах: файл, который не успел
+    /// декодироваться, пропускается с ошибкой `E_TIMEOUT`
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    pub decode_timeout: Option<Duration>,
+
+    /// Сколько раз перечитывать файл после временной ошибки чтения (прерванное чтение, тайм-аут,
+    /// EIO, например на сетевом диске); между попытками пауза, каждый раз вдвое длиннее. По умолчанию 0
+    #[arg(long, value_name = "N")]
+    pub retries: Option<u32>,
+
+    /// Пауза перед первым повтором `--retries`, в миллисекундах, по умолчанию 100
+    #[arg(long, value_name = "MS")]
+    pub retry_delay: Option<u64>,
+
+    /// Число потоков для декодирования и сравнения, по умолчанию по числу ядер.
+    /// С `--jobs 1` все выполняется по очереди
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Сравнивать только область `x,y,ширина,высота` в пикселях, одну и ту же у всех
+    /// изображений, например `100,200,400,300`
+    #[arg(long, value_name = "X,Y,W,H")]
+    pub crop: Option<Rect>,
+
+    /// Область первого изображения пары, если у изображений разная раскладка; вместо `--crop`
+    #[arg(long, value_name = "X,Y,W,H", conflicts_with_all = ["pairs", "matrix", "export_czkawka"])]
+    pub crop_a: Option<Rect>,
+
+    /// Область второго изображения пары; вместо `--crop`
+    #[arg(long, value_name = "X,Y,W,H", conflicts_with_all = ["pairs", "matrix", "export_czkawka"])]
+    pub crop_b: Option<Rect>,
+
+    /// Какой размер брать из многоразмерных значков `.ico` среди переданных изображений,
+    /// по умолчанию - самый большой
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..=256))]
+    pub icon_size: Option<u32>,
+
+    /// Выводить также ненормированную разницу сигнатур с полной точностью
+    #[arg(long)]
+    pub raw: bool,
+
+    /// Показать ячейки уменьшенной копии, которые больше всего повлияли на разницу пары
+    #[arg(long, conflicts_with_all = ["pairs", "matrix"])]
+    pub explain: bool,
+
+    /// Для совпавших пар (`одинаковые` и `почти дубликаты`) подсказать по размерам, формату,
+    /// качеству JPEG и резкости, пересжатая это копия, другого размера или измененная.
+    /// Берутся сведения, собранные при загрузке, пиксели повторно не декодируются
+    #[arg(long, conflicts_with = "matrix")]
+    pub classify_matches: bool,
+
+    /// Нижние границы оценок `одинаковые`, `почти дубликаты` и `похожие` в процентах,
+    /// строго по убыванию, например `99.9,98,92`. По умолчанию `99.5,97,90`. Оценки выводятся
+    /// у сравнения, у `--pairs` и у копий в группах `scan`
+    #[arg(long, value_name = "IDENTICAL,NEAR,SIMILAR")]
+    pub bands: Option<Cutoffs>,
+
+    /// Минимальный процент схожести: если пара ниже порога, процесс завершается с кодом 1
+    #[arg(long)]
+    pub threshold: Option<SimilarityThreshold>,
+
+    /// Выводить результат в формате JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Формат отчета: `json` - то же, что `--json`, `ndjson` - объект JSON в каждой строке,
+    /// выводимый по мере получения результатов (`scan` и `--pairs`), последней идет сводка
+    #[arg(long, value_enum, global = true, value_name = "FORMAT")]
+    pub format: Option<output::ReportFormat>,
+
+    /// Записать отчет в файл вместо stdout
+    #[arg(long, global = true)]
+    pub output: Option<PathBuf>,
+
+    /// Выделять цветом проценты схожести, заголовки групп и ошибки. В `auto` цвета только
+    /// в терминале и без переменной `NO_COLOR`; JSON, CSV и отчеты в файл не раскрашиваются.
+    /// По умолчанию `auto`
+    #[arg(long, value_enum, global = true)]
+    pub color: Option<color::ColorChoice>,
+
+    /// Язык текстового вывода и сообщений; `auto` - по `LC_ALL`, `LC_MESSAGES` и `LANG`.
+    /// Имена полей JSON, CSV и NDJSON и коды ошибок от языка не зависят. По умолчанию `en`
+    #[arg(long, value_enum, global = true)]
+    pub lang: Option<lang::LangChoice>,
+
+    /// Выводить только результат: без сводки и предупреждений, ошибки остаются
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Подробнее: `-v` - строка на каждый обработанный файл, `-vv` - и время этапов.
+    /// Все это, как и сводка, выводится в stderr, в stdout остается только результат
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Следить за каталогом и проверять новые изображения по индексу.
+    ///
+    /// Как и `scan`, сравнивает с настройками по умолчанию: `--weights`, `--channel`,
+    /// `--ignore-hue`, `--ignore-worst`, `--multi-scale`, `--crop` и `--tolerance-map` с ним - ошибка
+    Watch(watch::WatchArgs),
+    /// Вывести 64-битные перцептивные хеши (pHash) изображений, в том числе в форме `img_hash`
+    Fingerprint(fingerprint::FingerprintArgs),
+    /// Показать действующие настройки
+    Config(config::ConfigArgs),
+    /// Замерить скорость и разброс оценок всех алгоритмов на выборке из каталога
+    Bench(bench::BenchArgs),
+    /// Найти группы дубликатов среди всех изображений каталога.
+    ///
+    /// Сигнатуры индекса сравниваются с настройками по умолчанию: `--weights`, `--channel`,
+    /// `--ignore-hue`, `--ignore-worst`, `--multi-scale`, `--crop` и `--tolerance-map` с ним - ошибка
+    Scan(scan::ScanArgs),
+    /// Подобрать каждому изображению одного каталога самое похожее изображение другого,
+    /// без повторов
+    Match(match_sets::MatchArgs),
+    /// Показать распределение схожести случайных пар каталога, чтобы выбрать порог
+    Calibrate(calibrate::CalibrateArgs),
+    /// Найти изображения, которые выбиваются из набора, например неудачные кадры серии
+    Outliers(outliers::OutliersArgs),
+    /// Обслуживание файла индекса сигнатур: статистика, удаление устаревших записей, перезапись
+    Cache(cache::CacheArgs),
+    /// Показать, какой версией и с какими настройками записан индекс или JSON-отчет
+    Inspect(inspect::InspectArgs),
+    /// Сравнить изображение с сохраненными сигнатурами в текстовой форме, без исходных файлов
+    CompareSig(compare_sig::CompareSigArgs),
+    /// Вести набор эталонных изображений с метками, например список запрещенных картинок
+    Set(set::SetArgs),
+    /// Проверить изображение по набору: код 2 - есть совпадение, 0 - нет, 1 - ошибка
+    Check(set::CheckArgs),
+}
+
+/// Значения `--channel`
+#[derive(Debug, Clone, Copy, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelArg {
+    /// Цвет и прозрачность
+    All,
+    /// Только цвет (по умолчанию)
+    Rgb,
+    R,
+    G,
+    B,
+    /// Только прозрачность
+    A,
+}
+
+impl From<ChannelArg> for ChannelSelect {
+    fn from(arg: ChannelArg) -> Self {
+        match arg {
+            ChannelArg::All => Self::All,
+            ChannelArg::Rgb => Self::Rgb,
+            ChannelArg::R => Self::Single(Channel::R),
+            ChannelArg::G => Self::Single(Channel::G),
+            ChannelArg::B => Self::Single(Channel::B),
+            ChannelArg::A => Self::Single(Channel::A),
+        }
+    }
+}
+
+/// Оценка схожести для вывода пользователю
+pub fn verdict_word(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Identical => lang::text("identical", "одинаковые"),
+        Verdict::NearDuplicate => lang::text("near duplicates", "почти дубликаты"),
+        Verdict::Similar => lang::text("similar", "похожие"),
+        Verdict::Different => lang::text("different", "разные"),
+    }
+}
+
+/// Вид копии для вывода пользователю
+pub fn copy_kind_word(kind: CopyKind) -> &'static str {
+    match kind {
+        CopyKind::ExactIsh => lang::text("near-exact copy", "почти точная копия"),
+        CopyKind::Recompressed => lang::text("recompressed copy", "пересжатая копия"),
+        CopyKind::Resized => lang::text("resized copy", "копия другого размера"),
+        CopyKind::Edited => lang::text("edited copy", "измененная копия"),
+    }
+}
+
+/// Цвет ячейки в виде `#rrggbb`, с прозрачностью - `#rrggbbaa`
+pub fn hex_color([r, g, b, a]: [u8; 4]) -> String {
+    if a == u8::MAX { format!("#{r:02x}{g:02x}{b:02x}") } else { format!("#{r:02x}{g:02x}{b:02x}{a:02x}") }
+}
+
+/// В JSON оценка выводится машиночитаемым именем
+pub fn serialize_verdict<S: serde::Serializer>(verdict: &Option<Verdict>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(verdict.map_or("", |verdict| verdict.as_str()))
+}
+
+/// В JSON вид копии выводится машиночитаемым именем
+pub fn serialize_copy_kind<S: serde::Serializer>(kind: &Option<CopyKind>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(kind.map_or("", |kind| kind.as_str()))
+}
+
+/// Путь в JSON строкой, без потерь и для путей не в UTF-8: `imgalg::paths::encode`
+pub fn serialize_path<P: AsRef<Path>, S: serde::Serializer>(path: &P, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&imgalg::paths::encode(path.as_ref()))
+}
+
+pub fn serialize_optional_path<P: AsRef<Path>, S: serde::Serializer>(path: &Option<P>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn serialize_paths<P: AsRef<Path>, S: serde::Serializer>(paths: &[P], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| imgalg::paths::encode(path.as_ref())))
+}
+
+/// Файлы изображений в каталоге и подкаталогах, в порядке обхода, кроме исключенных `excludes`.
+/// С функцией `zip` в список попадают и изображения внутри архивов `.zip` и `.cbz`, путями
+/// `архив!/имя`; архивы внутри архивов пропускаются с предупреждением
+pub fn collect_images(dir: &Path, excludes: &Excludes, paths: &mut Vec<PathBuf>) -> anyhow::Result<Excluded> {
+    let mut excluded = Excluded::default();
+    walk(dir, excludes, &mut excluded, &mut |path| {
+        if image::ImageFormat::from_path(&path).is_ok() {
+            paths.push(path);
+        } else if imgalg::archive::is_archive(&path) {
+            collect_archive(&path, paths);
+        }
+    })?;
+    Ok(excluded)
+}
+
+/// Обход дерева каталогов от корня просмотра `root`: исключенные каталоги не читаются.
+/// Ссылки на каталоги раскрываются, но каждый каталог читается один раз, так что ссылка
+/// на родительский каталог не зацикливает обход
+fn walk(root: &Path, excludes: &Excludes, excluded: &mut Excluded, visit: &mut dyn FnMut(PathBuf)) -> anyhow::Result<()> {
+    let mut visited = HashSet::new();
+    visited.extend(FileId::of(root).ok());
+    walk_dir(root, root, excludes, excluded, &mut visited, visit)
+}
+
+fn walk_dir(root: &Path, dir: &Path, excludes: &Excludes, excluded: &mut Excluded, visited: &mut HashSet<FileId>, visit: &mut dyn FnMut(PathBuf)) -> anyhow::Result<()> {
+    let read_error = || format!("Failed to read the directory {}", dir.display());
+    for entry in fs::read_dir(dir).with_context(read_error)? {
+        let entry = entry.with_context(read_error)?;
+        let path = entry.path();
+        let file_type = entry.file_type().with_context(read_error)?;
+        // Битая ссылка остается файлом: ошибку покажет его загрузка
+        let is_dir = file_type.is_dir() || file_type.is_symlink() && fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+        if excludes.is_excluded(path.strip_prefix(root).unwrap_or(&path), is_dir) {
+            if is_dir { excluded.directories += 1 } else { excluded.files += 1 }
+        } else if is_dir {
+            // Каталог, который не опознать, читается: ошибку чтения покажет `read_dir`
+            if FileId::of(&path).ok().is_none_or(|id| visited.insert(id)) {
+                walk_dir(root, &path, excludes, excluded, visited, visit)?;
+            }
+        } else {
+            visit(path);
+        }
+    }
+    Ok(())
+}
+
+/// Изображения архива по его оглавлению. Нечитаемый архив не прерывает обход
+#[cfg(feature = "zip")]
+fn collect_archive(archive: &Path, paths: &mut Vec<PathBuf>) {
+    match imgalg::archive::members(archive) {
+        Ok(members) => {
+            for nested in &members.nested {
+                verbosity::info(lang::tr!("Nested archive skipped: {}", "Вложенный архив пропущен: {}", nested.display()));
+            }
+            paths.extend(members.images);
+        }
+        Err(e) => eprintln!("{}", color::Palette::stderr().error(lang::tr!("Failed to read the archive: {}", "Не удалось прочитать архив: {}", error::CliError::from_lib(&e)))),
+    }
+}
+
+/// Без функции `zip` архивы не просматриваются
+#[cfg(not(feature = "zip"))]
+fn collect_archive(_archive: &Path, _paths: &mut Vec<PathBuf>) {}
+
+/// Ролики в каталоге и подкаталогах, в порядке обхода, кроме исключенных `excludes`
+#[cfg(feature = "video")]
+pub fn collect_videos(dir: &Path, excludes: &Excludes, paths: &mut Vec<PathBuf>) -> anyhow::Result<Excluded> {
+    let mut excluded = Excluded::default();
+    walk(dir, excludes, &mut excluded, &mut |path| {
+        if imgalg::video::is_video(&path) {
+            paths.push(path);
+        }
+    })?;
+    Ok(excluded)
+}
+
+/// Разбирает `--decode-timeout`: положительное число секунд, можно дробное
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.trim().parse().map_err(|_| format!("'{}' is not a number", value.trim()))?;
+    timeout_from_secs(seconds)
+}
+
+/// Тайм-аут из `--decode-timeout` или файла настроек
+fn timeout_from_secs(seconds: f64) -> Result<Duration, String> {
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err("the timeout must be a positive number of seconds".to_string());
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+fn parse_weights(value: &str) -> Result<[f32; 3], String> {
+    let weights: Vec<f32> = value
+        .split(',')
+        .map(|w| w.trim().parse::<f32>().map_err(|_| format!("'{}' is not a number", w.trim())))
+        .collect::<Result<_, _>>()?;
+    let weights: [f32; 3] = weights.try_into().map_err(|_| "expected three comma-separated weights R,G,B".to_string())?;
+    ComparerOptions::new().channel_weights(weights).map_err(|e| e.to_string())?;
+    Ok(weights)
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use clap::Args;
+use imgalg::{ComparerOptions, ImagesComparer, ImgAlgError, SimilarityThreshold};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, ErrorCode};
+use super::exclude::Excludes;
+use super::lang::tr;
+use super::output::Output;
+
+/// Порог по умолчанию: кадры одной серии обычно похожи друг на друга больше чем на 90%
+const DEFAULT_THRESHOLD: f32 = 90.0;
+
+#[derive(Args)]
+pub struct OutliersArgs {
+    /// Изображения или каталоги (просматриваются рекурсивно, файлы по имени)
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Выбивается изображение, средняя схожесть которого с остальными ниже порога
+    #[arg(long, default_value_t = SimilarityThreshold::new(DEFAULT_THRESHOLD).expect("the default is within 0..=100"))]
+    pub threshold: SimilarityThreshold,
+}
+
+#[derive(Serialize)]
+struct OutliersJson<'a> {
+    loaded: usize,
+    threshold: f32,
+    /// От самой низкой средней схожести
+    outliers: Vec<OutlierEntry<'a>>,
+    errors: Vec<CliError>,
+}
+
+#[derive(Serialize)]
+struct OutlierEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    /// Средняя схожесть со всеми остальными изображениями
+    average: f32,
+}
+
+pub fn run(args: &OutliersArgs, options: &ComparerOptions, json: bool, output: &Output) -> Result<()> {
+    let mut paths = vec![];
+    for input in &args.inputs {
+        if input.is_dir() {
+            let mut files = vec![];
+            super::collect_images(input, &Excludes::none(), &mut files)?;
+            files.sort();
+            paths.extend(files);
+        } else {
+            paths.push(input.clone());
+        }
+    }
+    let (comparer, errors) = ImagesComparer::new_lossy_with(&paths, options.clone());
+    if let Some(e) = errors.iter().find(|e| matches!(e, ImgAlgError::Cancelled)) {
+        return Err(CliError::from_lib(e).into());
+    }
+    if comparer.len() < 2 {
+        return Err(CliError::new(ErrorCode::Args, "At least two images are required").into());
+    }
+    // Сравнитель хранит загруженные изображения подряд, в порядке путей
+    let failed: HashSet<&Path> = errors.iter().filter_map(ImgAlgError::path).collect();
+    let loaded: Vec<&Path> = paths.iter().map(PathBuf::as_path).filter(|path| !failed.contains(path)).collect();
+
+    let outliers = comparer.outliers(args.threshold);
+    let errors: Vec<CliError> = errors.iter().map(CliError::from_lib).collect();
+    let summary = tr!("Images: {}, outliers: {}", "Изображений: {}, выбиваются из набора: {}", comparer.len(), outliers.len());
+    if json {
+        let outliers = outliers.iter().map(|&(index, average)| OutlierEntry { path: loaded[index], average }).collect();
+        let report = OutliersJson { loaded: comparer.len(), threshold: args.threshold.value(), outliers, errors };
+        return output.emit_json(&report, &summary);
+    }
+
+    let palette = output.palette();
+    let mut text = String::new();
+    if !outliers.is_empty() {
+        writeln!(text, "{}", palette.header(tr!("Average similarity to the others below {:.2}%:", "Средняя схожесть с остальными ниже {:.2}%:", args.threshold.value())))?;
+    }
+    for &(index, average) in &outliers {
+        writeln!(text, "{} ({})", loaded[index].display(), palette.percent(average))?;
+    }
+    for error in &errors {
+        writeln!(text, "{}", palette.error(tr!("Failed to process: {}", "Не удалось обработать: {}", error)))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)
+}
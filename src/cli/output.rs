@@ -0,0 +1,238 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use imgalg::{paths, Provenance};
+use serde::Serialize;
+
+use super::color::Palette;
+use super::lang::tr;
+use super::verbosity::{self, Verbosity};
+
+/// Значения `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Один документ JSON, как `--json`
+    Json,
+    /// Объект JSON в каждой строке, по мере получения результатов
+    Ndjson,
+}
+
+/// Куда выводится отчет: в stdout или в файл, записываемый атомарно
+pub struct Output {
+    path: Option<PathBuf>,
+    started: Instant,
+    provenance: Option<Provenance>,
+    /// `--format ndjson`
+    ndjson: bool,
+}
+
+impl Output {
+    /// Проверяет путь заранее, чтобы не терять время на сравнение ради отчета, который некуда записать
+    pub fn new(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            let parent = parent_dir(path);
+            if !parent.is_dir() {
+                bail!("Directory {} for the report {} does not exist", parent.display(), path.display());
+            }
+        }
+        Ok(Self { path: path.map(Path::to_path_buf), started: Instant::now(), provenance: None, ndjson: false })
+    }
+
+    /// Сведения о запуске, которые `emit_json` добавляет в отчет полем `provenance`
+    pub fn set_provenance(&mut self, provenance: Provenance) {
+        self.provenance = Some(provenance);
+    }
+
+    /// Раскраска текстового отчета: в файл отчет пишется без цвета
+    pub fn palette(&self) -> Palette {
+        if self.path.is_some() { Palette::PLAIN } else { Palette::stdout() }
+    }
+
+    /// Выводит отчет. При записи в файл в stderr остается только краткая сводка. С `-q`
+    /// сводка, которой заканчивается текстовый отчет в stdout, отбрасывается
+    pub fn emit(&self, report: &str, summary: &str) -> Result<()> {
+        let quiet = self.path.is_none() && verbosity::level() == Verbosity::Quiet;
+        let report = match report.strip_suffix('\n').and_then(|report| report.strip_suffix(summary)) {
+            Some(rest) if quiet && (rest.is_empty() || rest.ends_with('\n')) => rest,
+            _ => report,
+        };
+        self.emit_with(summary, |writer| Ok(writer.write_all(report.as_bytes())?))
+    }
+
+    /// Выводить отчет строками NDJSON (`lines`)
+    pub fn set_ndjson(&mut self) {
+        self.ndjson = true;
+    }
+
+    /// Выводит JSON-отчет с полем `provenance`: какая версия и с какими настройками его создала
+    pub fn emit_json<T: Serialize>(&self, report: &T, summary: &str) -> Result<()> {
+        let report = WithProvenance { report, provenance: self.provenance.as_ref().map(ProvenanceJson::new) };
+        self.emit(&(serde_json::to_string_pretty(&report)? + "\n"), summary)
+    }
+
+    /// Отчет `--format ndjson`, если он выбран: строки пишутся сразу, а не целиком в конце.
+    /// Файл отчета пишется напрямую, без временного, чтобы после сбоя в нем остались уже
+    /// записанные строки
+    pub fn lines(&self) -> Result<Option<Lines<'_>>> {
+        if !self.ndjson {
+            return Ok(None);
+        }
+        let writer: Box<dyn Write + Send> = match &self.path {
+            None => Box::new(std::io::stdout()),
+            Some(path) => Box::new(std::io::BufWriter::new(fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?)),
+        };
+        Ok(Some(Lines { output: self, writer: Mutex::new(writer), failed: Mutex::new(None) }))
+    }
+
+    /// Как `emit`, но отчет пишется по частям, не собираясь целиком в памяти
+    pub fn emit_with(&self, summary: &str, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+        match &self.path {
+            None => {
+                let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+                write(&mut stdout)?;
+                stdout.flush()?;
+            }
+            Some(path) => {
+                write_atomic(path, write)?;
+                self.report_written(path, summary);
+            }
+        }
+        Ok(())
+    }
+
+    fn report_written(&self, path: &Path, summary: &str) {
+        verbosity::info(format_args!("{}", summary));
+        verbosity::info(tr!("Time: {:.2?}, report written to {}", "Время: {:.2?}, отчет записан в {}", self.started.elapsed(), path.display()));
+    }
+}
+
+#[derive(Serialize)]
+struct WithProvenance<'a, T> {
+    #[serde(flatten)]
+    report: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<ProvenanceJson<'a>>,
+}
+
+/// `Provenance` в JSON; его же читает `imgalg inspect`
+#[derive(Serialize)]
+pub struct ProvenanceJson<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'a str>,
+    pub format_version: u32,
+    pub pipeline: &'a str,
+    /// Канонический идентификатор вычисления, `imgalg::algorithm_id`
+    pub algorithm_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<Vec<String>>,
+}
+
+impl<'a> ProvenanceJson<'a> {
+    pub fn new(provenance: &'a Provenance) -> Self {
+        Self {
+            version: provenance.version.as_deref(),
+            format_version: provenance.format_version,
+            pipeline: &provenance.pipeline,
+            algorithm_id: provenance.algorithm_id(),
+            algorithm: provenance.setting("algorithm"),
+            grid: provenance.setting("grid"),
+            filter: provenance.setting("filter"),
+            color: provenance.setting("color"),
+            created: provenance.created_utc(),
+            command_line: provenance.command_line.as_ref().map(|args| args.iter().map(|arg| paths::encode(Path::new(arg)).into_owned()).collect()),
+        }
+    }
+}
+
+/// Строки NDJSON: `{"type": "...", ...}` с теми же полями, что и в отчете `--json`.
+/// Писать можно из нескольких потоков; каждая строка сбрасывается сразу после записи
+pub struct Lines<'a> {
+    output: &'a Output,
+    writer: Mutex<Box<dyn Write + Send>>,
+    /// Первая ошибка записи: строки пишутся из обработчиков, которые ошибку не вернут
+    failed: Mutex<Option<std::io::Error>>,
+}
+
+#[derive(Serialize)]
+struct Line<'a, T> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    #[serde(flatten)]
+    value: &'a T,
+}
+
+impl Lines<'_> {
+    /// Строка вида `kind`, например `pair` или `error`; после ошибки записи строки пропускаются
+    pub fn line<T: Serialize>(&self, kind: &str, value: &T) {
+        let mut failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+        if failed.is_some() {
+            return;
+        }
+        let mut text = serde_json::to_string(&Line { kind, value }).unwrap_or_default();
+        text.push('\n');
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writer.write_all(text.as_bytes()).and_then(|()| writer.flush()) {
+            *failed = Some(e);
+        }
+    }
+
+    /// Последняя строка `summary` со сведениями `provenance`. При записи в файл в stderr,
+    /// как у `emit`, выводится краткая сводка
+    pub fn finish<T: Serialize>(self, totals: &T, summary: &str) -> Result<()> {
+        let provenance = self.output.provenance.as_ref().map(ProvenanceJson::new);
+        self.line("summary", &WithProvenance { report: totals, provenance });
+        if let Some(e) = self.failed.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            bail!("Failed to write the report: {e}");
+        }
+        if let Some(path) = &self.output.path {
+            self.output.report_written(path, summary);
+        }
+        Ok(())
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Пишет во временный файл рядом с целевым и переименовывает его,
+/// так что прерванный запуск не оставляет недописанный отчет
+pub fn write_atomic(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let file_name = path.file_name().with_context(|| format!("{} is not a file path", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = parent_dir(path).join(tmp_name);
+
+    let result = (|| -> Result<()> {
+        let file = fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        let mut writer = std::io::BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, path).with_context(|| format!("Failed to write {}", path.display()))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
@@ -0,0 +1,342 @@
+use anyhow::{bail, Context, Result};
+use imgalg::{paths, ComparerOptions, CopyKind, Cutoffs, ImagesComparer, SimilarityThreshold, Verdict, Warning};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::{CliError, CliWarning, ErrorCode, Outcome};
+use super::ignores::{self, IgnoredPairs};
+use super::lang::{text, tr};
+use super::output::Output;
+use super::report::{self, BandCounts, ImageEntry};
+use super::verbosity;
+
+/// Что выводить для каждой пары сверх схожести и оценки
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PairColumns {
+    /// Ненормированная разница, `--raw`
+    pub raw: bool,
+    /// Вид копии у совпавших пар, `--classify-matches`
+    pub classify: bool,
+}
+
+/// Порог и допустимые пары: что считается провалом
+#[derive(Debug, Default)]
+pub struct PairChecks {
+    /// Пары ниже порога проваливают проверку, `--threshold`
+    pub threshold: Option<SimilarityThreshold>,
+    /// Файл пар, которые не проваливают проверку, даже если ниже порога, `--ignore-pairs`
+    pub ignore_pairs: Option<PathBuf>,
+    /// Куда дописать пары ниже порога, `--write-ignores`
+    pub write_ignores: Option<PathBuf>,
+    /// Дописывать без подтверждения, `--yes`
+    pub yes: bool,
+}
+
+/// Пара путей из входного файла с номером строки
+pub struct PairLine {
+    pub line: usize,
+    pub a: PathBuf,
+    pub b: PathBuf,
+}
+
+#[derive(Serialize)]
+struct PairRow<'a> {
+    line: usize,
+    #[serde(serialize_with = "super::serialize_path")]
+    a: &'a Path,
+    #[serde(serialize_with = "super::serialize_path")]
+    b: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<f32>,
+    /// Разброс схожести по фильтрам уменьшения, `--ensemble`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spread: Option<f32>,
+    /// В пределах разброса схожесть бывает по обе стороны порога
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    uncertain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_diff: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "super::serialize_verdict")]
+    verdict: Option<Verdict>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "super::serialize_copy_kind")]
+    copy_kind: Option<CopyKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passed: Option<bool>,
+    /// Пара из `--ignore-pairs`: ниже порога она не считается провалом
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    ignored: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a CliError>,
+}
+
+#[derive(Serialize)]
+struct PairsStats {
+    pairs: usize,
+    /// Сколько файлов было декодировано: каждый файл читается один раз,
+    /// даже если на него ведут разные пути или символические ссылки
+    decoded: usize,
+    /// Сколько упоминаний файлов обошлось без декодирования
+    decodes_saved: usize,
+    failed: usize,
+    /// Без допустимых пар
+    #[serde(skip_serializing_if = "Option::is_none")]
+    below_threshold: Option<usize>,
+    /// Пары из `--ignore-pairs`
+    ignored: usize,
+    /// Неуверенные пары, с `--ensemble` и `--threshold`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uncertain: Option<usize>,
+    /// Сколько всего повторных чтений понадобилось (`--retries`)
+    retries: u32,
+    /// Сколько пар получили каждую оценку
+    bands: BandCounts,
+}
+
+#[derive(Serialize)]
+struct PairsReport<'a> {
+    pairs: Vec<PairRow<'a>>,
+    /// Успешно загруженные файлы
+    images: Vec<ImageEntry<'a>>,
+    /// Ошибки загрузки по каждому файлу
+    errors: Vec<&'a CliError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<CliWarning>,
+    stats: PairsStats,
+}
+
+/// Строка `summary` в `--format ndjson`
+#[derive(Serialize)]
+struct PairsTotals {
+    stats: PairsStats,
+}
+
+/// Читает список пар: строки `a<TAB>b` или CSV с заголовком. Файл разбирается по байтам,
+/// так что пути в нем могут быть и не в UTF-8
+pub fn read_pairs(path: &Path) -> Result<Vec<PairLine>> {
+    let text = fs::read(path)
+        .with_context(|| format!("Failed to read the pairs file {}", path.display()))?;
+    let mut lines = text
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.strip_suffix(b"\r").unwrap_or(line)));
+
+    let is_csv = is_csv(&text);
+    if is_csv {
+        lines.next(); // Заголовок CSV
+    }
+
+    let mut pairs = vec![];
+    for (line, content) in lines {
+        if content.trim_ascii().is_empty() || content.starts_with(b"#") {
+            continue;
+        }
+        let fields = if is_csv { split_csv_line(content) } else { content.split(|&byte| byte == b'\t').map(<[u8]>::to_vec).collect() };
+        match <[Vec<u8>; 2]>::try_from(fields) {
+            Ok([a, b]) => pairs.push(PairLine { line, a: paths::from_bytes(a), b: paths::from_bytes(b) }),
+            Err(fields) => bail!("{}:{}: expected two paths, got {} fields", path.display(), line, fields.len()),
+        }
+    }
+    Ok(pairs)
+}
+
+/// CSV ли список пар: первая строка - заголовок с запятой и без табуляции
+pub fn is_csv(text: &[u8]) -> bool {
+    text.split(|&byte| byte == b'\n').next().is_some_and(|first| !first.contains(&b'\t') && first.contains(&b','))
+}
+
+/// Разбивает строку CSV на поля с учетом кавычек
+fn split_csv_line(line: &[u8]) -> Vec<Vec<u8>> {
+    let mut fields = vec![];
+    let mut field = vec![];
+    let mut quoted = false;
+    let mut bytes = line.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'"' if quoted && bytes.peek() == Some(&b'"') => {
+                field.push(b'"');
+                bytes.next();
+            }
+            b'"' => quoted = !quoted,
+            b',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(byte),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Сравнивает ровно перечисленные пары, декодируя каждый файл один раз
+pub fn run(pairs_path: &Path, options: &ComparerOptions, cutoffs: Cutoffs, checks: &PairChecks, columns: PairColumns, json: bool, output: &Output) -> Result<Outcome> {
+    let pairs = read_pairs(pairs_path)?;
+    let threshold = checks.threshold;
+    if checks.write_ignores.is_some() {
+        ignores::check_confirmation(checks.yes)?;
+    }
+    let ignored_pairs = match &checks.ignore_pairs {
+        Some(path) => IgnoredPairs::open(path)?,
+        None => IgnoredPairs::default(),
+    };
+
+    // Уникальные пути в порядке первого упоминания
+    let mut unique: Vec<&Path> = vec![];
+    let mut positions: HashMap<&Path, usize> = HashMap::new();
+    for pair in &pairs {
+        for path in [pair.a.as_path(), pair.b.as_path()] {
+            positions.entry(path).or_insert_with(|| {
+                unique.push(path);
+                unique.len() - 1
+            });
+        }
+    }
+
+    let (mut comparer, errors) = ImagesComparer::new_lossy_with(&unique, options.clone());
+    comparer.cutoffs = cutoffs;
+    let file_errors: Vec<CliError> = errors.iter().map(CliError::from_lib).collect();
+    let mut failures: HashMap<&Path, &CliError> = HashMap::new();
+    for file_error in &file_errors {
+        if let Some(path) = &file_error.path {
+            failures.insert(path.as_path(), file_error);
+        }
+    }
+
+    // После Ctrl-C файлы дальше не загружаются: такие пары попадают в отчет с ошибкой отмены
+    let cancelled = file_errors.iter().find(|file_error| file_error.code == ErrorCode::Cancelled);
+
+    // Индексы в сравнителе сдвигаются на число неудачно загруженных файлов перед ними
+    let mut loaded: HashMap<&Path, usize> = HashMap::new();
+    for path in &unique {
+        if !failures.contains_key(path) && loaded.len() < comparer.len() {
+            let next = loaded.len();
+            loaded.insert(path, next);
+        }
+    }
+
+    // В `--format ndjson` пары выводятся по мере сравнения после загрузки всех файлов
+    let lines = output.lines()?;
+    if let Some(lines) = &lines {
+        let loaded_paths: Vec<&Path> = unique.iter().copied().filter(|path| loaded.contains_key(path)).collect();
+        for image in report::image_entries(&comparer, &loaded_paths) {
+            lines.line("image", &image);
+        }
+        for file_error in &file_errors {
+            lines.line("error", file_error);
+        }
+        for warning in comparer.warnings() {
+            lines.line("warning", &CliWarning::from_lib(warning));
+        }
+    }
+
+    let mut rows = vec![];
+    for pair in &pairs {
+        let (result, raw_diff, verdict, copy_kind, error) = match (loaded.get(pair.a.as_path()), loaded.get(pair.b.as_path())) {
+            (Some(&i), Some(&j)) => {
+                let raw_diff = columns.raw.then(|| comparer.raw_diff(i, j)).transpose()?;
+                let verdict = comparer.verdict(i, j)?;
+                let copy_kind = (columns.classify && verdict.is_duplicate()).then(|| comparer.copy_kind(i, j)).transpose()?;
+                (Some(comparer.compare_pair(i, j)?), raw_diff, Some(verdict), copy_kind, None)
+            }
+            _ => {
+                let failed = if loaded.contains_key(pair.a.as_path()) { &pair.b } else { &pair.a };
+                (None, None, None, None, failures.get(failed.as_path()).copied().or(cancelled))
+            }
+        };
+        let similarity = result.as_ref().map(|result| result.similarity);
+        let spread = result.as_ref().map(|result| result.spread).filter(|_| comparer.options.is_ensemble());
+        let uncertain = threshold.zip(result.as_ref()).is_some_and(|(threshold, result)| result.is_uncertain(threshold));
+        let passed = threshold.zip(similarity).map(|(threshold, similarity)| threshold.is_met_by(similarity));
+        let ignored = ignored_pairs.contains(&pair.a, &pair.b);
+        let row = PairRow { line: pair.line, a: &pair.a, b: &pair.b, similarity, spread, uncertain, raw_diff, verdict, copy_kind, passed, ignored, error };
+        if let Some(lines) = &lines {
+            lines.line("pair", &row);
+        }
+        rows.push(row);
+    }
+
+    // Упоминания файлов, до которых дошла загрузка: все, если ее не прервали
+    let attempted = comparer.len() + file_errors.len() - usize::from(cancelled.is_some());
+    let mentions = pairs.iter().flat_map(|pair| [&pair.a, &pair.b]).filter(|path| positions[path.as_path()] < attempted).count();
+    let retried = comparer.warnings().map(|warning| match warning {
+        Warning::Retried { retries, .. } => *retries,
+        _ => 0,
+    });
+    let stats = PairsStats {
+        pairs: rows.len(),
+        decoded: comparer.decoded_count(),
+        decodes_saved: mentions - comparer.decoded_count(),
+        failed: rows.iter().filter(|row| row.error.is_some()).count(),
+        below_threshold: threshold.map(|_| rows.iter().filter(|row| row.passed == Some(false) && !row.ignored).count()),
+        ignored: rows.iter().filter(|row| row.ignored).count(),
+        uncertain: threshold.filter(|_| comparer.options.is_ensemble()).map(|_| rows.iter().filter(|row| row.uncertain).count()),
+        retries: retried.chain(file_errors.iter().map(|file_error| file_error.retries)).sum(),
+        bands: BandCounts::count(rows.iter().filter_map(|row| row.verdict)),
+    };
+    let outcome = match stats.below_threshold {
+        _ if cancelled.is_some() => Outcome::Cancelled,
+        Some(below) if below > 0 => Outcome::BelowThreshold,
+        _ => Outcome::Passed,
+    };
+
+    if let Some(path) = &checks.write_ignores {
+        let failing: Vec<(&Path, &Path)> = rows.iter().filter(|row| row.passed == Some(false) && !row.ignored).map(|row| (row.a, row.b)).collect();
+        let written = ignores::append(path, &failing, checks.yes)?;
+        if written > 0 {
+            verbosity::info(tr!("Pairs added to {}: {}, they no longer count as failures from the next run", "Пар добавлено в {}: {}, со следующего запуска они не считаются провалом", path.display(), written));
+        }
+    }
+
+    let mut extra = if stats.ignored > 0 { tr!(", accepted: {}", ", допустимых: {}", stats.ignored) } else { String::new() };
+    if let Some(uncertain) = stats.uncertain.filter(|&uncertain| uncertain > 0) {
+        extra += &tr!(", uncertain: {}", ", неуверенных: {}", uncertain);
+    }
+    if stats.retries > 0 {
+        extra += &tr!(", read retries: {}", ", повторов чтения: {}", stats.retries);
+    }
+    let summary = tr!(
+        "Pairs: {}, files decoded: {} (repeats without decoding: {}), errors: {}{}\n{}",
+        "Пар: {}, декодировано файлов: {} (повторов без декодирования: {}), ошибок: {}{}\n{}",
+        stats.pairs, stats.decoded, stats.decodes_saved, stats.failed, extra, stats.bands.describe(),
+    );
+    if let Some(lines) = lines {
+        lines.finish(&PairsTotals { stats }, &summary)?;
+        return Ok(outcome);
+    }
+    if json {
+        let loaded_paths: Vec<&Path> = unique.iter().copied().filter(|path| loaded.contains_key(path)).collect();
+        let images = report::image_entries(&comparer, &loaded_paths);
+        let report = PairsReport { pairs: rows, images, errors: file_errors.iter().collect(), warnings: comparer.warnings().map(CliWarning::from_lib).collect(), stats };
+        output.emit_json(&report, &summary)?;
+        return Ok(outcome);
+    }
+    super::error::print_warnings(comparer.warnings());
+
+    let palette = output.palette();
+    let mut report = String::new();
+    for row in &rows {
+        match (&row.similarity, &row.error) {
+            (Some(similarity), _) => {
+                let verdict = row.verdict.map_or("", super::verdict_word);
+                let mark = match (row.passed, row.ignored) {
+                    (_, true) => text(", accepted pair", ", допустимая пара"),
+                    (Some(false), false) => text(", below the threshold", ", ниже порога"),
+                    _ => "",
+                };
+                let uncertain = if row.uncertain { text(", uncertain", ", неуверенно") } else { "" };
+                let spread = row.spread.map(|spread| format!(" ±{spread:.2}")).unwrap_or_default();
+                let kind = row.copy_kind.map(|kind| format!(", {}", super::copy_kind_word(kind))).unwrap_or_default();
+                let raw_diff = row.raw_diff.map(|raw_diff| tr!(", difference {}", ", разница {}", raw_diff)).unwrap_or_default();
+                writeln!(report, "{}: {} ~ {}: {}{} ({}{}{}{}){}", row.line, row.a.display(), row.b.display(), palette.percent(*similarity), spread, verdict, kind, mark, uncertain, raw_diff)?
+            }
+            (None, Some(error)) => {
+                writeln!(report, "{}: {} ~ {}: {}", row.line, row.a.display(), row.b.display(), palette.error(tr!("error [{}]: {}", "ошибка [{}]: {}", error.code, error)))?
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    writeln!(report, "{}", summary)?;
+    output.emit(&report, &summary)?;
+    Ok(outcome)
+}
+
@@ -0,0 +1,89 @@
+use imgalg::{ImageInfo, ImagesComparer, Verdict};
+use serde::Serialize;
+use std::path::Path;
+
+use super::lang::tr;
+
+/// Сколько пар получили каждую оценку (`--bands`)
+#[derive(Serialize, Default)]
+pub struct BandCounts {
+    pub identical: usize,
+    pub near_duplicate: usize,
+    pub similar: usize,
+    pub different: usize,
+}
+
+impl BandCounts {
+    pub fn count(verdicts: impl IntoIterator<Item = Verdict>) -> Self {
+        let mut counts = Self::default();
+        for verdict in verdicts {
+            match verdict {
+                Verdict::Identical => counts.identical += 1,
+                Verdict::NearDuplicate => counts.near_duplicate += 1,
+                Verdict::Similar => counts.similar += 1,
+                Verdict::Different => counts.different += 1,
+            }
+        }
+        counts
+    }
+
+    /// Строка сводки: `By verdict: identical 2, near duplicates 1, similar 0, different 0`
+    pub fn describe(&self) -> String {
+        tr!(
+            "By verdict: {} {}, {} {}, {} {}, {} {}",
+            "По оценкам: {} {}, {} {}, {} {}, {} {}",
+            super::verdict_word(Verdict::Identical), self.identical,
+            super::verdict_word(Verdict::NearDuplicate), self.near_duplicate,
+            super::verdict_word(Verdict::Similar), self.similar,
+            super::verdict_word(Verdict::Different), self.different,
+        )
+    }
+}
+
+/// Сведения об изображении в JSON-отчетах
+#[derive(Serialize)]
+pub struct ImageEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    pub path: &'a Path,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<&'static str>,
+    pub file_size: u64,
+    /// Область, по которой сравнивалось изображение
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crop: Option<CropEntry>,
+}
+
+#[derive(Serialize)]
+pub struct CropEntry {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> ImageEntry<'a> {
+    pub fn new(path: &'a Path, info: &ImageInfo) -> Self {
+        let crop = info.crop.map(|crop| CropEntry { x: crop.x, y: crop.y, width: crop.width, height: crop.height });
+        Self { path, width: info.width, height: info.height, format: info.format_name(), file_size: info.file_size, crop }
+    }
+}
+
+/// Записи для всех загруженных изображений; `paths` идут в порядке загрузки
+pub fn image_entries<'a>(comparer: &ImagesComparer, paths: &[&'a Path]) -> Vec<ImageEntry<'a>> {
+    paths
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, path)| comparer.image_info(idx).map(|info| ImageEntry::new(path, info)))
+        .collect()
+}
+
+/// Строка таблицы: `64x48, png, 1234 байт`, при сравнении по области - `..., область 0,0,32,24`
+pub fn describe(info: &ImageInfo) -> String {
+    let description = tr!("{}x{}, {}, {} bytes", "{}x{}, {}, {} байт", info.width, info.height, info.format_name().unwrap_or("?"), info.file_size);
+    match info.crop {
+        Some(crop) => tr!("{}, region {}", "{}, область {}", description, crop),
+        None => description,
+    }
+}
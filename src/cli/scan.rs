@@ -0,0 +1,694 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use imgalg::calibrate::{AdaptiveThreshold, CalibrateOptions};
+use imgalg::index::{IndexMatch, ScanEvent, ScanReport, ScanThreshold, SignatureIndex};
+use imgalg::journal::ScanJournal;
+use imgalg::{paths, ComparerOptions, CopyKind, FileId, ImgAlgError, SimilarityThreshold, Verdict};
+use serde::Serialize;
+use std::fmt::Write;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::config::Config;
+use super::error::{CliError, CliWarning, ErrorCode};
+use super::exclude::{ExcludeArgs, Excluded, Excludes};
+use super::output::Output;
+use super::report::BandCounts;
+use super::lang::{self, tr, Lang};
+use super::verbosity;
+
+#[derive(Args)]
+pub struct ScanArgs {
+    /// Каталог с изображениями, просматривается рекурсивно
+    pub dir: PathBuf,
+
+    /// Файл индекса: сигнатуры и найденные пары сохраняются в нем для следующего просмотра
+    #[arg(long)]
+    pub index: Option<PathBuf>,
+
+    /// Декодировать только новые и измененные файлы и сравнивать только пары с ними,
+    /// остальное взять из индекса
+    #[arg(long, requires = "index")]
+    pub incremental: bool,
+
+    /// Журнал просмотра: после каждого файла и каждой части сравнения в него дописывается
+    /// сделанное, и та же команда после обрыва продолжит с этого места. Журнал другого
+    /// каталога или с другими настройками - ошибка; после успешного просмотра файл удаляется
+    #[arg(long, value_name = "FILE")]
+    pub journal: Option<PathBuf>,
+
+    /// Минимальный процент схожести для дубликатов
+    #[arg(long)]
+    pub threshold: Option<SimilarityThreshold>,
+
+    /// Выводить порог из фона каталога вместо `--threshold`: процентиль схожести случайных
+    /// пар файлов плюс `--margin`. Снимкам экрана нужен порог выше, чем фотографиям
+    #[arg(long, value_name = "PERCENTILE", conflicts_with = "threshold")]
+    pub adaptive_threshold: Option<f32>,
+
+    /// Сколько случайных пар сравнить для `--adaptive-threshold`
+    #[arg(long, requires = "adaptive_threshold", default_value_t = CalibrateOptions::default().samples)]
+    pub samples: usize,
+
+    /// Начальное значение генератора для `--adaptive-threshold`: с тем же значением пары те же
+    #[arg(long, requires = "adaptive_threshold", default_value_t = CalibrateOptions::default().seed)]
+    pub seed: u64,
+
+    /// Запас над процентилем фона для `--adaptive-threshold`, в процентах схожести
+    #[arg(long, requires = "adaptive_threshold", default_value_t = CalibrateOptions::default().margin, allow_negative_numbers = true)]
+    pub margin: f32,
+
+    /// Перед группами вывести сводку по подкаталогам верхнего уровня
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Какое изображение группы оставить; остальные считаются лишними копиями
+    #[arg(long, value_enum, default_value_t = KeepPolicy::First)]
+    pub keep: KeepPolicy,
+
+    /// Сравнивать каждый размер многоразмерного значка `.ico` как отдельное изображение
+    /// с путем вида `app.ico#256`; по умолчанию берется самый большой
+    #[arg(long)]
+    pub split_icons: bool,
+
+    /// Подсказать для каждой копии в группе, чем она отличается от первого изображения:
+    /// пересжата, другого размера или изменена. По сведениям из индекса, без декодирования
+    #[arg(long)]
+    pub classify_matches: bool,
+
+    /// Записать в каталог по листу миниатюр на группу, `group_0001.jpg` и так далее: первые
+    /// 8 изображений группы высотой 160 пикселей в ряд, с номерами в группе
+    #[arg(long, value_name = "DIR")]
+    pub contact_sheets: Option<PathBuf>,
+
+    /// Включать в группы все пути к одному и тому же файлу (символьные ссылки, повторно
+    /// подключенные каталоги), а не только первый. Такие изображения помечены, и путь
+    /// к остающемуся файлу тоже остается: это не копия, и удаление удалит и «оригинал»
+    #[arg(long)]
+    pub include_same_file: bool,
+
+    /// Вывести группы таблицей CSV: `group,path,similarity,band,keep`, по строке на изображение; с `--contact-sheets` - еще столбец `contact_sheet`
+    #[arg(long)]
+    pub csv: bool,
+
+    #[command(flatten)]
+    pub excludes: ExcludeArgs,
+
+    /// Сколько кадров брать из каждого ролика
+    #[cfg(feature = "video")]
+    #[arg(long, default_value_t = imgalg::video::DEFAULT_FRAMES as u16, value_parser = clap::value_parser!(u16).range(1..))]
+    pub frames: u16,
+}
+
+/// Значения `--keep`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeepPolicy {
+    /// Первое по пути, образец группы
+    First,
+    /// С лучшей оценкой качества (резкость с поправкой на сжатие JPEG),
+    /// при близких оценках - с большим разрешением
+    BestQuality,
+}
+
+#[derive(Serialize)]
+struct ScanJson<'a> {
+    #[serde(flatten)]
+    totals: ScanTotals<'a>,
+    groups: Vec<Vec<GroupEntry<'a>>>,
+    errors: Vec<CliError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<CliWarning>,
+    /// Листы миниатюр `--contact-sheets` групп, у которых они есть
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    contact_sheets: Vec<SheetJson<'a>>,
+    /// Пары путей к одному и тому же файлу
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    same_file: Vec<SameFileJson<'a>>,
+}
+
+/// Два пути к одному файлу: это не дубликаты
+#[derive(Serialize)]
+struct SameFileJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    a: &'a Path,
+    #[serde(serialize_with = "super::serialize_path")]
+    b: &'a Path,
+}
+
+/// Лист миниатюр группы: ее номер с единицы и имя файла в каталоге `--contact-sheets`
+#[derive(Serialize)]
+struct SheetJson<'a> {
+    group: usize,
+    file: &'a str,
+}
+
+/// Итоги просмотра; в `--format ndjson` - строка `summary`
+#[derive(Serialize)]
+struct ScanTotals<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    dir: &'a Path,
+    files: usize,
+    videos: usize,
+    reused: usize,
+    recomputed: usize,
+    /// Сколько сигнатур взято из журнала `--journal` прерванного просмотра
+    #[serde(skip_serializing_if = "is_zero_count")]
+    resumed: usize,
+    removed: usize,
+    compared: usize,
+    /// Сколько всего повторных чтений понадобилось (`--retries`)
+    #[serde(skip_serializing_if = "is_zero")]
+    retries: u32,
+    /// Пропущено по `--exclude` и встроенным исключениям
+    excluded: Excluded,
+    /// Порог, с которым искались дубликаты
+    threshold: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adaptive: Option<AdaptiveJson>,
+    /// Границы оценок `--bands` и сколько копий в группах получили каждую
+    cutoffs: CutoffsJson,
+    bands: BandCounts,
+    summary: ScanSummary,
+}
+
+#[derive(Serialize)]
+struct CutoffsJson {
+    identical: f32,
+    near_duplicate: f32,
+    similar: f32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_zero_count(value: &usize) -> bool {
+    *value == 0
+}
+
+/// Как выведен порог `--adaptive-threshold`
+#[derive(Serialize)]
+struct AdaptiveJson {
+    percentile: f32,
+    margin: f32,
+    seed: u64,
+    /// Сколько пар сравнено для фона
+    pairs: usize,
+    /// Схожесть фона на процентиле, до запаса
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<f32>,
+}
+
+/// Строка `pair` в `--format ndjson`: пара не ниже порога, найденная при просмотре
+#[derive(Serialize)]
+struct PairLine<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    a: &'a Path,
+    #[serde(serialize_with = "super::serialize_path")]
+    b: &'a Path,
+    similarity: f32,
+    #[serde(serialize_with = "super::serialize_verdict")]
+    band: Option<Verdict>,
+    /// Оба пути ведут к одному файлу
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    same_file: bool,
+}
+
+/// Строка `group` в `--format ndjson`: одна группа из `groups`
+#[derive(Serialize)]
+struct GroupLine<'a> {
+    entries: Vec<GroupEntry<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contact_sheet: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ScanSummary {
+    directories: Vec<DirectorySummary>,
+    /// Пары дубликатов из разных подкаталогов, всего
+    cross_directory_pairs: usize,
+}
+
+/// Сводка по подкаталогу верхнего уровня; файлы в самом каталоге просмотра относятся к `.`
+#[derive(Serialize, Default)]
+struct DirectorySummary {
+    directory: String,
+    images: usize,
+    /// Сколько изображений входит в группы дубликатов
+    duplicates: usize,
+    /// Сколько байт освободится, если оставить только первое изображение каждой группы
+    reclaimable_bytes: u64,
+    /// Пары с изображением из другого подкаталога
+    cross_directory_pairs: usize,
+}
+
+#[derive(Serialize)]
+struct GroupEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    similarity: f32,
+    /// Оценка схожести с первым изображением группы; у самого первого ее нет
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "super::serialize_verdict")]
+    band: Option<Verdict>,
+    /// Оценка `Quality::score`, относительная: сравнима только внутри группы
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<f32>,
+    /// Длительность ролика в секундах; у изображений ее нет
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    /// Чем копия отличается от первого изображения группы, `--classify-matches`
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "super::serialize_copy_kind")]
+    copy_kind: Option<CopyKind>,
+    /// Тот же файл, что и другое изображение группы (`--include-same-file`); путь
+    /// к остающемуся файлу тоже остается
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    same_file: bool,
+    keep: bool,
+}
+
+/// Ролики каталога: длительности, группы похожих и ошибки чтения
+#[derive(Default)]
+struct Videos {
+    durations: HashMap<PathBuf, Duration>,
+    /// Размеры роликов на время просмотра, для освобождаемого места
+    sizes: HashMap<PathBuf, u64>,
+    groups: Vec<Vec<IndexMatch>>,
+    errors: Vec<ImgAlgError>,
+}
+
+pub fn run(args: &ScanArgs, config: &Config, options: &ComparerOptions, json: bool, output: &Output) -> Result<()> {
+    if !args.dir.is_dir() {
+        return Err(CliError::new(ErrorCode::Args, format!("{} is not a directory", args.dir.display())).into());
+    }
+    if args.csv && json {
+        return Err(CliError::new(ErrorCode::Args, "--csv cannot be combined with --json or --format").into());
+    }
+    let cutoffs = config.bands.unwrap_or_default();
+    let threshold: ScanThreshold = match args.adaptive_threshold {
+        Some(percentile) => {
+            let calibrate = CalibrateOptions { samples: args.samples, seed: args.seed, margin: args.margin };
+            AdaptiveThreshold::new(percentile, calibrate).map_err(|e| CliError::from_lib(&e))?.into()
+        }
+        None => args.threshold.unwrap_or(config.threshold()).into(),
+    };
+    let excludes = Excludes::from_args(&args.excludes).map_err(|e| CliError::new(ErrorCode::Args, format!("{e:#}")))?;
+    let mut files = vec![];
+    let walk_started = Instant::now();
+    let excluded = super::collect_images(&args.dir, &excludes, &mut files)?;
+    let journal = args.journal.as_ref().map(|path| ScanJournal::open(path, &args.dir, &journal_settings(args, &threshold, options), options)).transpose().map_err(|e| CliError::from_lib(&e))?;
+    if let Some(journal) = journal.as_ref().filter(|journal| !journal.is_empty()) {
+        verbosity::info(tr!("Resuming from the journal {}: signatures in it: {}", "Продолжение по журналу {}: сигнатур в нем: {}", journal.path().display(), journal.len()));
+    }
+    verbosity::timing(tr!("Directory walk: {:.2?}, files: {}", "Обход каталога: {:.2?}, файлов: {}", walk_started.elapsed(), files.len()));
+    if args.split_icons {
+        files = split_icons(files);
+    }
+
+    let mut index = match &args.index {
+        Some(path) => open_index(path, options)?,
+        None => SignatureIndex::new(),
+    };
+    // В `--format ndjson` ошибки чтения и пары выводятся, не дожидаясь конца просмотра
+    let lines = output.lines()?;
+    // С `-v` - строка в stderr на каждый файл
+    let on_event = |event: ScanEvent<'_>| {
+        match event {
+            ScanEvent::Signed { path, reused: false } => verbosity::progress(tr!("Processed: {}", "Обработан: {}", path.display())),
+            ScanEvent::Signed { path, reused: true } => verbosity::progress(tr!("From the index: {}", "Из индекса: {}", path.display())),
+            ScanEvent::Error(e) => verbosity::progress(tr!("Failed to process: {}", "Не удалось обработать: {}", CliError::from_lib(e))),
+            ScanEvent::Pair { .. } => {}
+        }
+        let Some(lines) = &lines else { return };
+        match event {
+            ScanEvent::Error(e) => lines.line("error", &CliError::from_lib(e)),
+            ScanEvent::Pair { a, b, similarity, same_file } => lines.line("pair", &PairLine { a, b, similarity, band: Some(Verdict::from_similarity(similarity, &cutoffs)), same_file }),
+            ScanEvent::Signed { .. } => {}
+        }
+    };
+    // Долгий просмотр сохраняет посчитанное по ходу, чтобы после обрыва `--incremental` продолжил с него
+    if let Some(path) = &args.index {
+        index.set_command_line(std::env::args_os());
+        index.set_checkpoint(Some(path.clone()), Duration::from_secs(config.flush_interval));
+    }
+    index.set_journal(journal);
+    index.set_include_same_file(args.include_same_file);
+    let scan_started = Instant::now();
+    let report = index.scan_with(&files, threshold, options, args.incremental, &on_event).map_err(|e| CliError::from_lib(&e))?;
+    verbosity::timing(tr!("Signatures and comparison: {:.2?}", "Сигнатуры и сравнение: {:.2?}", scan_started.elapsed()));
+    if let Some(path) = &args.index {
+        index.save(path).with_context(|| format!("Failed to save the index {}", path.display()))?;
+    }
+    let adaptive = args.adaptive_threshold.zip(report.background.as_ref()).map(|(percentile, background)| AdaptiveJson {
+        percentile,
+        margin: args.margin,
+        seed: args.seed,
+        pairs: background.scores.len(),
+        background: background.percentile(percentile),
+    });
+    if let Some(adaptive) = &adaptive {
+        let background = adaptive.background.map_or(lang::text("no pairs", "нет пар").to_string(), |score| format!("{score:.2}%"));
+        verbosity::info(tr!(
+            "Background threshold: {:.2}% (percentile {} {} + margin {:.2}, pairs in the sample: {})",
+            "Порог по фону: {:.2}% ({}-й процентиль {} + запас {:.2}, пар в выборке: {})",
+            report.threshold.value(),
+            adaptive.percentile,
+            background,
+            adaptive.margin,
+            adaptive.pairs
+        ));
+    }
+    if !report.same_file.is_empty() && !args.include_same_file {
+        verbosity::info(tr!(
+            "Paths to the same file: {} pairs, groups list each file once (see --include-same-file)",
+            "Путей к одному файлу: {} пар, в группах каждый файл один раз (см. --include-same-file)",
+            report.same_file.len()
+        ));
+    }
+    let videos = scan_videos(args, &excludes, report.threshold, options)?;
+
+    // Группы роликов идут после групп изображений
+    let groups: Vec<&Vec<IndexMatch>> = report.groups.iter().chain(&videos.groups).collect();
+    let errors: Vec<CliError> = report.errors.iter().chain(&videos.errors).map(CliError::from_lib).collect();
+    let kept: Vec<usize> = groups.iter().map(|group| keep(group, args.keep, &index)).collect();
+    let quality = |path: &Path| index.image_info(path).map(|info| info.quality.score());
+    let duration = |path: &Path| videos.durations.get(path).copied();
+    let copy_kind = |group: &[IndexMatch], idx: usize| {
+        let (first, entry) = (&group[0], &group[idx]);
+        let infos = index.image_info(&first.path).zip(index.image_info(&entry.path));
+        infos.filter(|_| args.classify_matches && idx > 0).map(|(a, b)| CopyKind::classify(a, b, entry.similarity))
+    };
+    let band = |group: &[IndexMatch], idx: usize| (idx > 0).then(|| Verdict::from_similarity(group[idx].similarity, &cutoffs));
+    let bands = BandCounts::count(groups.iter().flat_map(|group| (1..group.len()).filter_map(|idx| band(group, idx))));
+    let sheets = match &args.contact_sheets {
+        Some(dir) => {
+            let members: Vec<Vec<&Path>> = groups.iter().map(|group| group.iter().map(|m| m.path.as_path()).collect()).collect();
+            let sheets = super::sheets::write_sheets(dir, &members, options.thread_count())?;
+            let written = sheets.iter().flatten().count();
+            verbosity::info(tr!("Contact sheets: {}, written to {}", "Листов миниатюр: {}, записаны в {}", written, dir.display()));
+            sheets
+        }
+        None => vec![None; groups.len()],
+    };
+    let mut scanned = files.clone();
+    scanned.extend(videos.durations.keys().cloned());
+    let file_size = |path: &Path| index.image_info(path).map(|info| info.file_size).or_else(|| videos.sizes.get(path).copied());
+    let directories = summarize(&args.dir, &scanned, &groups, &kept, &file_size);
+    let summary = summary(&report, files.len(), excluded, &videos, &bands);
+    if json {
+        let groups = groups.iter().zip(&kept).map(|(group, &kept)| {
+            group
+                .iter()
+                .enumerate()
+                .map(|(idx, m)| GroupEntry {
+                    path: &m.path,
+                    similarity: m.similarity,
+                    band: band(group, idx),
+                    quality: quality(&m.path),
+                    duration: duration(&m.path).map(|duration| duration.as_secs_f64()),
+                    copy_kind: copy_kind(group, idx),
+                    same_file: m.same_file,
+                    keep: is_kept(group, kept, idx),
+                })
+                .collect()
+        });
+        let totals = ScanTotals {
+            dir: &args.dir,
+            files: files.len(),
+            videos: videos.durations.len(),
+            reused: report.reused,
+            recomputed: report.computed,
+            resumed: report.resumed,
+            removed: report.removed,
+            compared: report.compared,
+            retries: report.retries,
+            excluded,
+            threshold: report.threshold.value(),
+            adaptive,
+            cutoffs: CutoffsJson { identical: cutoffs.identical, near_duplicate: cutoffs.near_duplicate, similar: cutoffs.similar },
+            bands,
+            summary: directories,
+        };
+        if let Some(lines) = lines {
+            // Ошибки изображений уже выведены во время просмотра, остались ошибки роликов
+            for (entries, sheet) in groups.zip(&sheets) {
+                lines.line("group", &GroupLine { entries, contact_sheet: sheet.as_deref() });
+            }
+            for error in &errors[report.errors.len()..] {
+                lines.line("error", error);
+            }
+            for warning in &report.warnings {
+                lines.line("warning", &CliWarning::from_lib(warning));
+            }
+            return lines.finish(&totals, &summary);
+        }
+        let warnings = report.warnings.iter().map(CliWarning::from_lib).collect();
+        let contact_sheets = sheets.iter().enumerate().filter_map(|(pos, sheet)| Some(SheetJson { group: pos + 1, file: sheet.as_deref()? })).collect();
+        let same_file = report.same_file.iter().map(|(a, b)| SameFileJson { a, b }).collect();
+        let scan = ScanJson { totals, groups: groups.collect(), errors, warnings, contact_sheets, same_file };
+        return output.emit_json(&scan, &summary);
+    }
+    super::error::print_warnings(&report.warnings);
+    if args.csv {
+        for error in &errors {
+            eprintln!("{}", super::color::Palette::stderr().error(tr!("Failed to process: {}", "Не удалось обработать: {}", error)));
+        }
+        return output.emit_with(&summary, |writer| write_csv(writer, &groups, &kept, &band, args.contact_sheets.is_some().then_some(&sheets[..])));
+    }
+
+    let palette = output.palette();
+    let mut text = String::new();
+    if args.summary {
+        write_summary(&mut text, &directories)?;
+    }
+    for (number, (group, &kept)) in groups.iter().zip(&kept).enumerate() {
+        match duration(&group[0].path) {
+            Some(_) => writeln!(text, "{}", palette.header(tr!("Group {}, videos: {}", "Группа {}, роликов: {}", number + 1, group.len())))?,
+            None => writeln!(text, "{}", palette.header(tr!("Group {}, images: {}", "Группа {}, изображений: {}", number + 1, group.len())))?,
+        }
+        if let (Some(dir), Some(sheet)) = (&args.contact_sheets, &sheets[number]) {
+            writeln!(text, "{}", tr!("  Contact sheet: {}", "  Лист миниатюр: {}", dir.join(sheet).display()))?;
+        }
+        for (idx, m) in group.iter().enumerate() {
+            let mark = match (idx == kept, is_kept(group, kept, idx)) {
+                (true, _) => "*",
+                (false, true) => "=",
+                (false, false) => " ",
+            };
+            let quality = quality(&m.path).filter(|_| args.keep == KeepPolicy::BestQuality);
+            let band = band(group, idx).map(|band| format!(", {}", super::verdict_word(band))).unwrap_or_default();
+            let kind = band + &copy_kind(group, idx).map(|kind| format!(", {}", super::copy_kind_word(kind))).unwrap_or_default();
+            match (duration(&m.path), quality) {
+                (Some(duration), _) => writeln!(text, "{}", tr!("{} {} ({}, video {}{})", "{} {} ({}, видео {}{})", mark, m.path.display(), palette.percent(m.similarity), format_duration(duration), kind))?,
+                (None, Some(quality)) => writeln!(text, "{}", tr!("{} {} ({}, quality {:.1}{})", "{} {} ({}, качество {:.1}{})", mark, m.path.display(), palette.percent(m.similarity), quality, kind))?,
+                (None, None) => writeln!(text, "{} {} ({}{})", mark, m.path.display(), palette.percent(m.similarity), kind)?,
+            }
+        }
+    }
+    if !groups.is_empty() {
+        writeln!(text, "{}", lang::text("* - the image that is kept", "* - изображение, которое остается"))?;
+        if groups.iter().zip(&kept).any(|(group, &kept)| (0..group.len()).any(|idx| idx != kept && is_kept(group, kept, idx))) {
+            writeln!(text, "{}", lang::text("= - the same file as the kept image under another path, also kept", "= - тот же файл, что и остающееся изображение, под другим путем, тоже остается"))?;
+        }
+    }
+    for error in &errors {
+        writeln!(text, "{}", palette.error(tr!("Failed to process: {}", "Не удалось обработать: {}", error)))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)
+}
+
+fn summary(report: &ScanReport, files: usize, excluded: Excluded, videos: &Videos, bands: &BandCounts) -> String {
+    let summary = tr!(
+        "Files: {}, taken from the index: {}, recomputed: {}, removed from the index: {}, pairs compared: {}, duplicate groups: {}",
+        "Файлов: {}, взято из индекса: {}, пересчитано: {}, удалено из индекса: {}, сравнено пар: {}, групп дубликатов: {}",
+        files,
+        report.reused,
+        report.computed,
+        report.removed,
+        report.compared,
+        report.groups.len()
+    );
+    let summary = if report.resumed == 0 { summary } else { tr!("{}, taken from the journal: {}", "{}, взято из журнала: {}", summary, report.resumed) };
+    let summary = if report.retries == 0 { summary } else { tr!("{}, read retries: {}", "{}, повторов чтения: {}", summary, report.retries) };
+    let summary = if excluded.is_empty() { summary } else { tr!("{}, files excluded: {}, directories: {}", "{}, исключено файлов: {}, каталогов: {}", summary, excluded.files, excluded.directories) };
+    let summary = if videos.durations.is_empty() && videos.errors.is_empty() {
+        summary
+    } else {
+        tr!("{}, videos: {}, video groups: {}", "{}, роликов: {}, групп роликов: {}", summary, videos.durations.len(), videos.groups.len())
+    };
+    format!("{summary}\n{}", bands.describe())
+}
+
+/// Настройки, с которыми журнал `--journal` годится для продолжения: все, от чего зависят
+/// просмотренные файлы, их сигнатуры и найденные пары
+fn journal_settings(args: &ScanArgs, threshold: &ScanThreshold, options: &ComparerOptions) -> String {
+    let threshold = match threshold {
+        ScanThreshold::Fixed(threshold) => threshold.value().to_string(),
+        ScanThreshold::Adaptive(_) => format!("adaptive={}/{}/{}/{}", args.adaptive_threshold.unwrap_or_default(), args.samples, args.seed, args.margin),
+    };
+    let excludes = &args.excludes;
+    let index = args.index.as_ref().map(|index| index.display().to_string()).unwrap_or_default();
+    format!(
+        "threshold={threshold};index={index};incremental={};split_icons={};exclude={:?};exclude_from={:?};hidden={};default_excludes={};strict_aspect={}",
+        args.incremental,
+        args.split_icons,
+        excludes.exclude,
+        excludes.exclude_from,
+        excludes.hidden,
+        !excludes.no_default_excludes,
+        options.is_strict_aspect()
+    )
+}
+
+/// Заменяет каждый значок изображениями всех его размеров. Значок, который не удалось
+/// прочитать или разобрать, остается как есть, и ошибку сообщит загрузка
+fn split_icons(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut split = Vec::with_capacity(files.len());
+    for file in files {
+        let sizes = if imgalg::icon::is_icon(&file) { fs::read(&file).map(|bytes| imgalg::icon::sizes(&bytes)).unwrap_or_default() } else { vec![] };
+        if sizes.is_empty() {
+            split.push(file);
+        } else {
+            split.extend(sizes.iter().map(|&size| imgalg::icon::size_path(&file, size)));
+        }
+    }
+    split
+}
+
+/// Ролики каталога просматриваются отдельно от изображений: кадры сравниваются только
+/// с кадрами, и в индекс ролики не записываются
+#[cfg(feature = "video")]
+fn scan_videos(args: &ScanArgs, excludes: &Excludes, threshold: SimilarityThreshold, options: &ComparerOptions) -> Result<Videos> {
+    let mut paths = vec![];
+    super::collect_videos(&args.dir, excludes, &mut paths)?;
+    let scan = imgalg::video::scan_videos(&paths, args.frames as usize, threshold, options).map_err(|e| CliError::from_lib(&e))?;
+    let sizes = scan.videos.iter().filter_map(|(path, _)| Some((path.clone(), fs::metadata(path).ok()?.len()))).collect();
+    Ok(Videos { durations: scan.videos.into_iter().collect(), sizes, groups: scan.groups, errors: scan.errors })
+}
+
+/// Без функции `video` ролики не просматриваются
+#[cfg(not(feature = "video"))]
+fn scan_videos(_args: &ScanArgs, _excludes: &Excludes, _threshold: SimilarityThreshold, _options: &ComparerOptions) -> Result<Videos> {
+    Ok(Videos::default())
+}
+
+/// `1:05.3` - минуты и секунды с десятыми
+fn format_duration(duration: Duration) -> String {
+    let tenths = (duration.as_secs_f64() * 10.0).round() as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+/// Группы строками CSV, по строке на изображение: номер группы с единицы, путь, схожесть
+/// с первым изображением группы, оценка (у первого пустая) и остается ли файл; с `--contact-sheets` -
+/// еще имя листа миниатюр группы (пустое, если листа нет)
+fn write_csv(writer: &mut dyn std::io::Write, groups: &[&Vec<IndexMatch>], kept: &[usize], band: &dyn Fn(&[IndexMatch], usize) -> Option<Verdict>, sheets: Option<&[Option<String>]>) -> Result<()> {
+    writeln!(writer, "group,path,similarity,band,keep{}", if sheets.is_some() { ",contact_sheet" } else { "" })?;
+    for (number, (group, &kept)) in groups.iter().zip(kept).enumerate() {
+        let sheet = sheets.map(|sheets| format!(",{}", sheets[number].as_deref().unwrap_or(""))).unwrap_or_default();
+        for (idx, m) in group.iter().enumerate() {
+            let band = band(group, idx).map_or("", |band| band.as_str());
+            writeln!(writer, "{},{},{},{},{}{sheet}", number + 1, super::matrix::csv_field(&paths::encode(&m.path)), m.similarity, band, is_kept(group, kept, idx))?;
+        }
+    }
+    Ok(())
+}
+
+/// Позиция в группе изображения, которое остается по `policy`; при равенстве - первое
+fn keep(group: &[IndexMatch], policy: KeepPolicy, index: &SignatureIndex) -> usize {
+    match policy {
+        KeepPolicy::First => 0,
+        KeepPolicy::BestQuality => {
+            let mut best = 0;
+            for (idx, m) in group.iter().enumerate().skip(1) {
+                if let (Some(info), Some(best_info)) = (index.image_info(&m.path), index.image_info(&group[best].path))
+                    && info.cmp_quality(best_info) == Ordering::Greater
+                {
+                    best = idx;
+                }
+            }
+            best
+        }
+    }
+}
+
+/// Остается ли изображение группы: само остающееся и путь к тому же файлу, что и оно,
+/// - удалить его значило бы удалить и остающееся
+fn is_kept(group: &[IndexMatch], kept: usize, idx: usize) -> bool {
+    idx == kept || (group[idx].same_file && FileId::is_same_file(&group[idx].path, &group[kept].path))
+}
+
+/// Раскладывает файлы и группы по подкаталогам верхнего уровня. Освобождаемое место
+/// считается по всем изображениям группы, кроме остающихся (`is_kept`), файл под несколькими
+/// путями - один раз, и относится к их подкаталогам. Размер файла (`file_size`) - тот, что
+/// был при просмотре: у изображения из индекса, у файла внутри архива - распакованный.
+/// Пара из разных подкаталогов - образец группы и изображение из другого подкаталога
+fn summarize(root: &Path, files: &[PathBuf], groups: &[&Vec<IndexMatch>], kept: &[usize], file_size: &dyn Fn(&Path) -> Option<u64>) -> ScanSummary {
+    let mut directories: BTreeMap<String, DirectorySummary> = BTreeMap::new();
+    for file in files {
+        directory(&mut directories, root, file).images += 1;
+    }
+    let mut cross_directory_pairs = 0;
+    for (group, &kept) in groups.iter().zip(kept) {
+        let reference = top_directory(root, &group[0].path);
+        for (idx, m) in group.iter().enumerate() {
+            let summary = directory(&mut directories, root, &m.path);
+            summary.duplicates += 1;
+            let counted = m.same_file && group[..idx].iter().any(|other| other.same_file && FileId::is_same_file(&m.path, &other.path));
+            if !is_kept(group, kept, idx) && !counted {
+                summary.reclaimable_bytes += file_size(&m.path).unwrap_or(0);
+            }
+            if idx > 0 && summary.directory != reference {
+                summary.cross_directory_pairs += 1;
+                directory(&mut directories, root, &group[0].path).cross_directory_pairs += 1;
+                cross_directory_pairs += 1;
+            }
+        }
+    }
+    ScanSummary { directories: directories.into_values().collect(), cross_directory_pairs }
+}
+
+fn directory<'a>(directories: &'a mut BTreeMap<String, DirectorySummary>, root: &Path, path: &Path) -> &'a mut DirectorySummary {
+    let name = top_directory(root, path);
+    directories.entry(name.clone()).or_insert_with(|| DirectorySummary { directory: name, ..DirectorySummary::default() })
+}
+
+/// Первый компонент пути относительно каталога просмотра, `.` - для файлов в нем самом
+fn top_directory(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    match relative.components().next() {
+        Some(Component::Normal(name)) if relative.components().count() > 1 => imgalg::paths::encode(Path::new(name)).into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+fn write_summary(text: &mut String, summary: &ScanSummary) -> std::fmt::Result {
+    let columns = match lang::current() {
+        Lang::En => ["directory", "images", "duplicates", "reclaimable,B", "cross-dir"],
+        Lang::Ru => ["каталог", "изображений", "дубликатов", "освободится,Б", "между папками"],
+    };
+    writeln!(text, "{:<24} {:>11} {:>10} {:>14} {:>14}", columns[0], columns[1], columns[2], columns[3], columns[4])?;
+    for directory in &summary.directories {
+        writeln!(
+            text,
+            "{:<24} {:>11} {:>10} {:>14} {:>14}",
+            directory.directory, directory.images, directory.duplicates, directory.reclaimable_bytes, directory.cross_directory_pairs
+        )?;
+    }
+    writeln!(text, "{}", tr!("Duplicate pairs across subdirectories: {}", "Пар дубликатов из разных подкаталогов: {}", summary.cross_directory_pairs))?;
+    writeln!(text)
+}
+
+/// Индекс с другими настройками сигнатур не мешает: просмотр все равно пересчитывает
+/// все файлы каталога, поэтому такой индекс просто заменяется
+fn open_index(path: &Path, options: &ComparerOptions) -> Result<SignatureIndex> {
+    match SignatureIndex::open_or_create_with(path, options) {
+        Err(e @ ImgAlgError::IndexMismatch { .. }) => {
+            verbosity::info(tr!("Warning: {}, recomputing the signatures", "Предупреждение: {}, сигнатуры пересчитываются", e));
+            Ok(SignatureIndex::new())
+        }
+        result => Ok(result?),
+    }
+}
@@ -0,0 +1,231 @@
+//! Наборы эталонных изображений (`imgalg set`) и проверка изображения по набору (`imgalg check`),
+//! например загрузок по списку запрещенных картинок
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use imgalg::set::{SetCheck, SignatureSet};
+use imgalg::SimilarityThreshold;
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::config::Config;
+use super::error::{CliError, ErrorCode, Outcome};
+use super::lang::tr;
+use super::output::Output;
+
+#[derive(Args)]
+pub struct SetArgs {
+    #[command(subcommand)]
+    pub action: SetAction,
+}
+
+#[derive(Subcommand)]
+pub enum SetAction {
+    /// Создать пустой набор
+    Create {
+        /// Файл набора; существующий файл не перезаписывается
+        set: PathBuf,
+    },
+    /// Добавить изображения в набор или обновить их записи. Набор сохраняется, только если
+    /// прочитались все файлы
+    Add {
+        set: PathBuf,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Метка записей, например причина блокировки; выводится при совпадении
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Удалить записи изображений из набора
+    Remove {
+        set: PathBuf,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Показать записи набора с метками
+    List { set: PathBuf },
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Проверяемое изображение
+    pub image: PathBuf,
+
+    /// Файл набора (`set create`); подходит и индекс `scan --index`
+    #[arg(long)]
+    pub set: PathBuf,
+
+    /// Минимальный процент схожести для совпадения, по умолчанию из файла настроек
+    #[arg(long)]
+    pub threshold: Option<SimilarityThreshold>,
+
+    /// Сравнивать по сигнатуре все записи, без предварительного отбора по отпечатку
+    #[arg(long)]
+    pub no_prefilter: bool,
+}
+
+#[derive(Serialize)]
+struct SetEntry<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SetJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    set: &'a Path,
+    /// Записи, которые добавлены, удалены или показаны
+    entries: Vec<SetEntry<'a>>,
+    /// Пути `set remove`, которых в наборе не было
+    #[serde(skip_serializing_if = "Vec::is_empty", serialize_with = "super::serialize_paths")]
+    missing: Vec<&'a Path>,
+    /// Сколько записей в наборе теперь
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct CheckJson<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    image: &'a Path,
+    #[serde(serialize_with = "super::serialize_path")]
+    set: &'a Path,
+    threshold: f32,
+    matched: bool,
+    matches: Vec<CheckMatch<'a>>,
+    /// Сколько записей сравнено по сигнатуре и сколько отброшено по отпечатку
+    compared: usize,
+    skipped: usize,
+}
+
+#[derive(Serialize)]
+struct CheckMatch<'a> {
+    #[serde(serialize_with = "super::serialize_path")]
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    similarity: f32,
+}
+
+pub fn run(args: &SetArgs, json: bool, output: &Output) -> Result<()> {
+    match &args.action {
+        SetAction::Create { set } => create(set, json, output),
+        SetAction::Add { set, files, label } => add(set, files, label.as_deref(), json, output),
+        SetAction::Remove { set, files } => remove(set, files, json, output),
+        SetAction::List { set } => list(set, json, output),
+    }
+}
+
+fn create(path: &Path, json: bool, output: &Output) -> Result<()> {
+    if path.exists() {
+        return Err(CliError::new(ErrorCode::Args, format!("{} already exists", path.display())).into());
+    }
+    save(&mut SignatureSet::new(), path)?;
+    let summary = tr!("Set {} created", "Набор {} создан", path.display());
+    if json {
+        return output.emit_json(&SetJson { set: path, entries: vec![], missing: vec![], total: 0 }, &summary);
+    }
+    output.emit(&(summary.clone() + "\n"), &summary)
+}
+
+fn add(path: &Path, files: &[PathBuf], label: Option<&str>, json: bool, output: &Output) -> Result<()> {
+    let mut set = open(path)?;
+    for file in files {
+        set.add(file, label).map_err(|e| CliError::from_lib(&e))?;
+    }
+    save(&mut set, path)?;
+    let summary = tr!("Entries added: {}, in the set: {}", "Добавлено записей: {}, в наборе: {}", files.len(), set.len());
+    let entries = files.iter().map(|file| SetEntry { path: file, label }).collect();
+    report(path, entries, vec![], set.len(), &summary, json, output)
+}
+
+fn remove(path: &Path, files: &[PathBuf], json: bool, output: &Output) -> Result<()> {
+    let mut set = open(path)?;
+    let (mut removed, mut missing) = (vec![], vec![]);
+    for file in files {
+        if set.remove(file) {
+            removed.push(SetEntry { path: file, label: None });
+        } else {
+            missing.push(file.as_path());
+        }
+    }
+    if !removed.is_empty() {
+        save(&mut set, path)?;
+    }
+    let summary = tr!("Entries removed: {}, not in the set: {}, remaining: {}", "Удалено записей: {}, не было в наборе: {}, осталось: {}", removed.len(), missing.len(), set.len());
+    report(path, removed, missing, set.len(), &summary, json, output)
+}
+
+fn list(path: &Path, json: bool, output: &Output) -> Result<()> {
+    let set = open(path)?;
+    let entries = set.entries().map(|(path, label)| SetEntry { path, label }).collect();
+    let summary = tr!("Entries in the set: {}", "Записей в наборе: {}", set.len());
+    report(path, entries, vec![], set.len(), &summary, json, output)
+}
+
+fn report(path: &Path, entries: Vec<SetEntry<'_>>, missing: Vec<&Path>, total: usize, summary: &str, json: bool, output: &Output) -> Result<()> {
+    if json {
+        return output.emit_json(&SetJson { set: path, entries, missing, total }, summary);
+    }
+    let mut text = String::new();
+    for entry in &entries {
+        match entry.label {
+            Some(label) => writeln!(text, "{}\t{}", entry.path.display(), label)?,
+            None => writeln!(text, "{}", entry.path.display())?,
+        }
+    }
+    for path in &missing {
+        writeln!(text, "{}", tr!("Not in the set: {}", "Нет в наборе: {}", path.display()))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, summary)
+}
+
+/// Проверяет изображение по набору. Совпадение - не ошибка, а отдельный исход `Matched`
+pub fn check(args: &CheckArgs, config: &Config, json: bool, output: &Output) -> Result<Outcome> {
+    let set = open(&args.set)?;
+    let set = if args.no_prefilter { set.prefilter(None) } else { set };
+    let threshold = args.threshold.unwrap_or(config.threshold());
+    let SetCheck { matches, compared, skipped } = set.check(&args.image, threshold).map_err(|e| CliError::from_lib(&e))?;
+    let outcome = if matches.is_empty() { Outcome::Passed } else { Outcome::Matched };
+    let summary = tr!(
+        "Matches: {}, entries compared: {}, skipped by fingerprint: {}",
+        "Совпадений: {}, сравнено записей: {}, отброшено по отпечатку: {}",
+        matches.len(),
+        compared,
+        skipped
+    );
+    if json {
+        let matches = matches.iter().map(|m| CheckMatch { path: &m.path, label: m.label.as_deref(), similarity: m.similarity }).collect();
+        let report = CheckJson { image: &args.image, set: &args.set, threshold: threshold.value(), matched: outcome == Outcome::Matched, matches, compared, skipped };
+        output.emit_json(&report, &summary)?;
+        return Ok(outcome);
+    }
+    let palette = output.palette();
+    let mut text = String::new();
+    if matches.is_empty() {
+        writeln!(text, "{}", tr!("No matches in {}", "Совпадений в {} нет", args.set.display()))?;
+    }
+    for m in &matches {
+        let label = m.label.as_deref().map(|label| format!(" [{label}]")).unwrap_or_default();
+        writeln!(text, "{}", tr!("Match: {}{} ({})", "Совпадение: {}{} ({})", m.path.display(), label, palette.percent(m.similarity)))?;
+    }
+    writeln!(text, "{}", summary)?;
+    output.emit(&text, &summary)?;
+    Ok(outcome)
+}
+
+/// Набор должен уже существовать: его создает `set create`
+fn open(path: &Path) -> Result<SignatureSet> {
+    if !path.is_file() {
+        return Err(CliError::new(ErrorCode::Args, format!("Set file {} does not exist", path.display())).into());
+    }
+    Ok(SignatureSet::open(path).map_err(|e| CliError::from_lib(&e))?)
+}
+
+fn save(set: &mut SignatureSet, path: &Path) -> Result<()> {
+    set.set_command_line(std::env::args_os());
+    Ok(set.save(path).map_err(|e| CliError::from_lib(&e))?)
+}
@@ -0,0 +1,116 @@
+//! Листы миниатюр групп дубликатов (`scan --contact-sheets`): по JPEG на группу, миниатюры
+//! первых изображений группы в ряд, каждая с номером в группе
+
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+use rayon::prelude::*;
+use std::fs;
+use std::path::Path;
+
+use super::output;
+
+/// Сколько изображений группы попадает на лист
+pub const MAX_MEMBERS: usize = 8;
+/// Высота миниатюры; ширина - по пропорциям, но не больше `MAX_THUMB_WIDTH`
+const THUMB_HEIGHT: u32 = 160;
+const MAX_THUMB_WIDTH: u32 = 4 * THUMB_HEIGHT;
+/// Промежуток между миниатюрами и поле вокруг них
+const GAP: u32 = 4;
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+/// Качество JPEG листа
+const QUALITY: u8 = 85;
+/// Цифры 3x5 пикселей для номеров, построчно, старший из трех битов - левый пиксель
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+/// Во сколько раз цифры увеличены на листе
+const DIGIT_SCALE: u32 = 3;
+
+/// Имя листа группы с номером `number` (с единицы, как в выводе): `group_0001.jpg`
+pub fn file_name(number: usize) -> String {
+    format!("group_{number:04}.jpg")
+}
+
+/// Пишет листы групп в `dir` (каталог создается) на `threads` потоках, 0 - по числу ядер.
+/// Возвращает имя листа каждой группы; у группы, ни одно изображение которой не декодировалось
+/// (например, группы роликов), листа нет
+pub fn write_sheets(dir: &Path, groups: &[Vec<&Path>], threads: usize) -> Result<Vec<Option<String>>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().context("Failed to start the worker threads")?;
+    pool.install(|| {
+        groups
+            .par_iter()
+            .enumerate()
+            .map(|(pos, members)| {
+                let Some(sheet) = sheet(members) else { return Ok(None) };
+                let name = file_name(pos + 1);
+                output::write_atomic(&dir.join(&name), |writer| Ok(JpegEncoder::new_with_quality(writer, QUALITY).encode_image(&sheet)?))?;
+                Ok(Some(name))
+            })
+            .collect()
+    })
+}
+
+/// Лист из первых `MAX_MEMBERS` изображений; не декодировавшиеся пропускаются, но номера
+/// остальных остаются их номерами в группе
+fn sheet(members: &[&Path]) -> Option<RgbImage> {
+    let thumbs: Vec<(usize, RgbImage)> = members
+        .iter()
+        .take(MAX_MEMBERS)
+        .enumerate()
+        .filter_map(|(pos, path)| Some((pos + 1, imgalg::image::open(path).ok()?.resize(MAX_THUMB_WIDTH, THUMB_HEIGHT, FilterType::Triangle).to_rgb8())))
+        .collect();
+    if thumbs.is_empty() {
+        return None;
+    }
+    let width = GAP + thumbs.iter().map(|(_, thumb)| thumb.width() + GAP).sum::<u32>();
+    let mut sheet = RgbImage::from_pixel(width, THUMB_HEIGHT + 2 * GAP, BACKGROUND);
+    let mut x = GAP;
+    for (number, thumb) in &thumbs {
+        // Слишком широкое изображение ниже миниатюры, оно выравнивается по середине
+        let y = GAP + (THUMB_HEIGHT - thumb.height()) / 2;
+        imageops::replace(&mut sheet, thumb, x as i64, y as i64);
+        label(&mut sheet, x, GAP, *number);
+        x += thumb.width() + GAP;
+    }
+    Some(sheet)
+}
+
+/// Белый номер на черной плашке в левом верхнем углу миниатюры
+fn label(sheet: &mut RgbImage, left: u32, top: u32, number: usize) {
+    let digits: Vec<usize> = number.to_string().bytes().map(|digit| (digit - b'0') as usize).collect();
+    let pad = DIGIT_SCALE;
+    let step = 4 * DIGIT_SCALE;
+    let (width, height) = (digits.len() as u32 * step - DIGIT_SCALE + 2 * pad, 5 * DIGIT_SCALE + 2 * pad);
+    for y in top..(top + height).min(sheet.height()) {
+        for x in left..(left + width).min(sheet.width()) {
+            sheet.put_pixel(x, y, Rgb([0, 0, 0]));
+        }
+    }
+    for (pos, &digit) in digits.iter().enumerate() {
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let (x0, y0) = (left + pad + pos as u32 * step + column * DIGIT_SCALE, top + pad + row as u32 * DIGIT_SCALE);
+                for y in y0..(y0 + DIGIT_SCALE).min(sheet.height()) {
+                    for x in x0..(x0 + DIGIT_SCALE).min(sheet.width()) {
+                        sheet.put_pixel(x, y, Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
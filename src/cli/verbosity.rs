@@ -0,0 +1,58 @@
+//! Подробность вывода: `-q`, по умолчанию, `-v`, `-vv`. В stdout идет только результат,
+//! сводка, ход работы и замеры времени - в stderr, так что вывод можно передавать по каналу
+//! при любой подробности и в любом формате
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Уровни по возрастанию: каждый выводит все, что и предыдущие
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// `-q`: только результат, без сводки и предупреждений; ошибки выводятся всегда
+    Quiet,
+    /// Результат и сводка в одну строку
+    #[default]
+    Normal,
+    /// `-v`: и строка на каждый обработанный файл
+    Progress,
+    /// `-vv`: и время этапов работы
+    Timing,
+}
+
+static LEVEL: OnceLock<Verbosity> = OnceLock::new();
+
+/// Запоминает `-q` и число `-v` на весь запуск; до вызова действует обычная подробность
+pub fn init(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Progress,
+        (false, _) => Verbosity::Timing,
+    };
+    let _ = LEVEL.set(level);
+}
+
+pub fn level() -> Verbosity {
+    LEVEL.get().copied().unwrap_or_default()
+}
+
+/// Сводка, предупреждения и прочее, что не входит в результат; молчит при `-q`
+pub fn info(message: impl Display) {
+    print_at(Verbosity::Normal, message);
+}
+
+/// Строка о ходе работы, например об одном файле; с `-v`
+pub fn progress(message: impl Display) {
+    print_at(Verbosity::Progress, message);
+}
+
+/// Замер времени; с `-vv`
+pub fn timing(message: impl Display) {
+    print_at(Verbosity::Timing, message);
+}
+
+fn print_at(level: Verbosity, message: impl Display) {
+    if self::level() >= level {
+        eprintln!("{}", message);
+    }
+}
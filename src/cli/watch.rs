@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use imgalg::index::SignatureIndex;
+use imgalg::{CancelToken, ImgAlgError, SimilarityThreshold};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::color::Palette;
+use super::config::Config;
+use super::error::{CliError, ErrorCode};
+use super::lang::{text, tr};
+use super::verbosity;
+
+/// Пауза после последнего события, прежде чем читать файл
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// Задержка перед повторной попыткой декодирования недописанного файла
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Сколько раз пробуем декодировать файл, прежде чем сдаться
+const MAX_ATTEMPTS: u32 = 5;
+/// Как часто проверяем очередь и флаг прерывания
+const TICK: Duration = Duration::from_millis(200);
+
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Каталог, за которым нужно следить
+    pub dir: PathBuf,
+
+    /// Файл индекса с сигнатурами уже известных изображений
+    #[arg(long)]
+    pub index: PathBuf,
+
+    /// Минимальный процент схожести, при котором изображение считается совпадением
+    #[arg(long)]
+    pub threshold: Option<SimilarityThreshold>,
+
+    /// Интервал сохранения индекса на диск, в секундах
+    #[arg(long)]
+    pub flush_interval: Option<u64>,
+
+    /// Если индекс построен с другими настройками вычисления сигнатур,
+    /// пересчитать их по файлам вместо отказа
+    #[arg(long)]
+    pub migrate: bool,
+}
+
+/// Файл, ожидающий обработки
+struct Pending {
+    due: Instant,
+    attempts: u32,
+}
+
+/// Наблюдает до отмены `cancel` (Ctrl-C), после чего сохраняет индекс
+pub fn run(args: &WatchArgs, config: &Config, cancel: &CancelToken) -> Result<()> {
+    let threshold = args.threshold.unwrap_or(config.threshold());
+    let flush_interval = Duration::from_secs(args.flush_interval.unwrap_or(config.flush_interval));
+    let dir = args.dir.canonicalize()
+        .with_context(|| format!("Failed to open the directory {}", args.dir.display()))?;
+    let mut index = open_index(&args.index, args.migrate)?;
+    index.set_command_line(std::env::args_os());
+    verbosity::info(tr!("Loaded the index {}: {} images", "Загружен индекс {}: {} изображений", args.index.display(), index.len()));
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to create the watcher")?;
+    watcher.watch(&dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    verbosity::info(tr!("Watching {}", "Наблюдение за {}", dir.display()));
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    let mut dirty = false;
+    let mut last_flush = Instant::now();
+
+    while !cancel.is_cancelled() {
+        match rx.recv_timeout(TICK) {
+            Ok(Ok(event)) => dirty |= handle_event(event, &mut index, &mut pending),
+            Ok(Err(e)) => eprintln!("{}", Palette::stderr().error(tr!("Watch error: {}", "Ошибка наблюдения: {}", e))),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+        let mut due: Vec<PathBuf> = pending.iter()
+            .filter(|(_, p)| p.due <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        // Порядок HashMap случаен, а от порядка зависит, какой из двух похожих файлов окажется новым
+        due.sort();
+        for path in due {
+            dirty |= process(&path, &mut index, &mut pending, threshold);
+        }
+
+        if dirty && last_flush.elapsed() >= flush_interval {
+            flush(&index, &args.index)?;
+            dirty = false;
+            last_flush = Instant::now();
+        }
+    }
+
+    if dirty {
+        flush(&index, &args.index)?;
+    }
+    verbosity::info(text("Watching stopped", "Наблюдение остановлено"));
+    Ok(())
+}
+
+/// Разбирает событие файловой системы, возвращает `true`, если индекс изменился
+fn handle_event(event: Event, index: &mut SignatureIndex, pending: &mut HashMap<PathBuf, Pending>) -> bool {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_)) | EventKind::Modify(ModifyKind::Any) => {
+            for path in &event.paths {
+                schedule(path, pending);
+            }
+            false
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            let (from, to) = (&event.paths[0], &event.paths[1]);
+            pending.remove(from);
+            if to.is_dir() {
+                // Записи файлов каталога переезжают вместе с ним, отложенные ставятся заново
+                let moved: Vec<PathBuf> = pending.keys().filter(|path| path.starts_with(from)).cloned().collect();
+                for path in moved {
+                    pending.remove(&path);
+                    schedule(&to.join(path.strip_prefix(from).unwrap_or(&path)), pending);
+                }
+                let renamed = index.rename_dir(from, to);
+                if renamed > 0 {
+                    verbosity::info(tr!("Directory renamed: {} -> {}, images: {}", "Каталог переименован: {} -> {}, изображений: {}", from.display(), to.display(), renamed));
+                }
+                renamed > 0
+            } else if is_image(to) && index.rename(from, to) {
+                pending.remove(to);
+                verbosity::info(tr!("Renamed: {} -> {}", "Переименовано: {} -> {}", from.display(), to.display()));
+                true
+            } else {
+                // Файла не было в индексе: обрабатываем как новый
+                let removed = forget(from, index, pending);
+                schedule(to, pending);
+                removed
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(_)) => {
+            // Половинки переименования откладываем: если следом придет пара путей,
+            // запись просто переедет, иначе исчезнувший файл удалится при обработке.
+            // Каталог, перенесенный из-под наблюдения, забывается сразу, а перенесенный сюда
+            // просматривается: событий о его файлах не будет
+            let mut changed = false;
+            for path in &event.paths {
+                if path.is_dir() {
+                    schedule_dir(path, pending);
+                } else if !path.exists() {
+                    changed |= forget_dir(path, index, pending);
+                }
+                schedule(path, pending);
+            }
+            changed
+        }
+        EventKind::Remove(_) => {
+            let mut changed = false;
+            for path in &event.paths {
+                changed |= forget(path, index, pending) | forget_dir(path, index, pending);
+            }
+            changed
+        }
+        _ => false,
+    }
+}
+
+/// Ставит файл в очередь, откладывая обработку до окончания записи
+fn schedule(path: &Path, pending: &mut HashMap<PathBuf, Pending>) {
+    if !is_image(path) {
+        return;
+    }
+    let entry = pending.entry(path.to_path_buf()).or_insert(Pending { due: Instant::now(), attempts: 0 });
+    entry.due = Instant::now() + DEBOUNCE;
+}
+
+/// Ставит в очередь изображения каталога на любой глубине
+fn schedule_dir(dir: &Path, pending: &mut HashMap<PathBuf, Pending>) {
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            schedule_dir(&path, pending);
+        } else {
+            schedule(&path, pending);
+        }
+    }
+}
+
+/// Удаляет из индекса и очереди файлы исчезнувшего каталога, возвращает `true`, если они были
+fn forget_dir(dir: &Path, index: &mut SignatureIndex, pending: &mut HashMap<PathBuf, Pending>) -> bool {
+    pending.retain(|path, _| !path.starts_with(dir));
+    let removed = index.remove_dir(dir);
+    if removed > 0 {
+        verbosity::info(tr!("Directory removed from the index: {}, images: {}", "Каталог удален из индекса: {}, изображений: {}", dir.display(), removed));
+    }
+    removed > 0
+}
+
+fn forget(path: &Path, index: &mut SignatureIndex, pending: &mut HashMap<PathBuf, Pending>) -> bool {
+    pending.remove(path);
+    let removed = index.remove(path);
+    if removed {
+        verbosity::info(tr!("Removed from the index: {}", "Удалено из индекса: {}", path.display()));
+    }
+    removed
+}
+
+/// Проверяет файл по индексу и добавляет его, возвращает `true`, если индекс изменился
+fn process(path: &Path, index: &mut SignatureIndex, pending: &mut HashMap<PathBuf, Pending>, threshold: SimilarityThreshold) -> bool {
+    if !path.is_file() {
+        return forget(path, index, pending);
+    }
+    match index.check_and_insert(path, threshold) {
+        Ok(matches) => {
+            pending.remove(path);
+            if matches.is_empty() {
+                println!("{}", tr!("New image: {}", "Новое изображение: {}", path.display()));
+            }
+            for m in matches {
+                println!("{}", tr!("Match: {} ~ {} ({})", "Совпадение: {} ~ {} ({})", path.display(), m.path.display(), Palette::stdout().percent(m.similarity)));
+            }
+            true
+        }
+        Err(e) => {
+            // Файл мог быть еще не дописан: пробуем позже
+            if let Some(entry) = pending.get_mut(path) {
+                entry.attempts += 1;
+                if entry.attempts < MAX_ATTEMPTS {
+                    entry.due = Instant::now() + RETRY_DELAY;
+                } else {
+                    eprintln!("{}", Palette::stderr().error(tr!("Failed to process {}: {}", "Не удалось обработать {}: {}", path.display(), e)));
+                    pending.remove(path);
+                }
+            }
+            false
+        }
+    }
+}
+
+/// Открывает индекс; устаревший пересчитывается только с `--migrate`
+fn open_index(index_path: &Path, migrate: bool) -> Result<SignatureIndex> {
+    match SignatureIndex::open_or_create(index_path) {
+        Err(e @ ImgAlgError::IndexMismatch { .. }) if migrate => {
+            verbosity::info(tr!("Warning: {}, recomputing the signatures", "Предупреждение: {}, сигнатуры пересчитываются", e));
+            let (index, errors) = SignatureIndex::migrate(index_path)?;
+            for error in &errors {
+                eprintln!("{}", Palette::stderr().error(tr!("Failed to recompute, the entry was removed: {}", "Не удалось пересчитать, запись удалена: {}", CliError::from_lib(error))));
+            }
+            flush(&index, index_path)?;
+            Ok(index)
+        }
+        Err(e @ ImgAlgError::IndexMismatch { .. }) => {
+            let message = format!("{e}; rerun with --migrate to recompute the signatures");
+            Err(CliError::new(ErrorCode::IndexMismatch, message).with_path(index_path).into())
+        }
+        result => Ok(result?),
+    }
+}
+
+fn flush(index: &SignatureIndex, index_path: &Path) -> Result<()> {
+    index.save(index_path)?;
+    verbosity::progress(tr!("Index saved: {} images", "Индекс сохранен: {} изображений", index.len()));
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, RemoveKind};
+
+    fn save(path: &Path, seed: u8) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8 ^ seed, (y * 8) as u8, seed])).save(path).unwrap();
+    }
+
+    /// Индекс с тремя изображениями: два в каталоге `album` (одно во вложенном) и одно рядом
+    fn indexed(root: &Path) -> SignatureIndex {
+        let mut index = SignatureIndex::new();
+        for (name, seed) in [("album/a.png", 1), ("album/deep/b.png", 2), ("album-other.png", 3)] {
+            save(&root.join(name), seed);
+            index.check_and_insert(root.join(name), SimilarityThreshold::DEFAULT).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn renamed_directory_moves_its_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut index = indexed(root);
+        let mut pending = HashMap::new();
+        std::fs::rename(root.join("album"), root.join("photos")).unwrap();
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both))).add_path(root.join("album")).add_path(root.join("photos"));
+        assert!(handle_event(event, &mut index, &mut pending));
+        for name in ["photos/a.png", "photos/deep/b.png", "album-other.png"] {
+            assert!(index.contains(root.join(name)), "{name}");
+        }
+        assert!(!index.contains(root.join("album/a.png")));
+        assert_eq!(index.len(), 3);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn removed_or_moved_out_directory_forgets_its_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for kind in [EventKind::Remove(RemoveKind::Folder), EventKind::Modify(ModifyKind::Name(RenameMode::From))] {
+            let mut index = indexed(root);
+            let mut pending = HashMap::new();
+            std::fs::remove_dir_all(root.join("album")).unwrap();
+            assert!(handle_event(Event::new(kind).add_path(root.join("album")), &mut index, &mut pending));
+            assert_eq!(index.len(), 1, "{kind:?}");
+            assert!(index.contains(root.join("album-other.png")));
+        }
+    }
+
+    #[test]
+    fn directory_moved_in_schedules_its_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut index = SignatureIndex::new();
+        let mut pending = HashMap::new();
+        save(&root.join("new/a.png"), 1);
+        save(&root.join("new/deep/b.png"), 2);
+        assert!(!handle_event(Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To))).add_path(root.join("new")), &mut index, &mut pending));
+        let mut scheduled: Vec<_> = pending.keys().cloned().collect();
+        scheduled.sort();
+        assert_eq!(scheduled, [root.join("new/a.png"), root.join("new/deep/b.png")]);
+        // Создание файла по-прежнему только откладывает его
+        assert!(!handle_event(Event::new(EventKind::Create(CreateKind::File)).add_path(root.join("c.png")), &mut index, &mut pending));
+        assert_eq!(pending.len(), 3);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::ImageInfo;
+
+/// Соотношения сторон, различающиеся меньше чем на эту долю, считаются одинаковыми:
+/// при уменьшении стороны округляются до целых пикселей
+const ASPECT_TOLERANCE: f32 = 0.01;
+/// На сколько могут различаться оценки качества JPEG одного и того же сжатия
+const JPEG_QUALITY_TOLERANCE: u8 = 2;
+/// Резкость, различающаяся меньше чем на эту долю, считается одинаковой
+const SHARPNESS_TOLERANCE: f32 = 0.1;
+/// Схожесть, начиная с которой копия без других отличий считается точной
+const EXACT_ISH_SIMILARITY: f32 = 99.0;
+
+/// Чем совпавшая пара отличается, кроме самих пикселей: пересохраненная копия, уменьшенная
+/// копия или правка. Определяется только по уже собранным `ImageInfo` и схожести, без
+/// повторного декодирования, поэтому это подсказка, а не доказательство. Правила по порядку:
+///
+/// 1. Соотношения сторон сравниваемых областей различаются больше чем на 1% - `Edited`
+///    (обрезка, поля или растяжение).
+/// 2. Стороны различаются при том же соотношении - `Resized`.
+/// 3. Стороны одинаковы, но формат другой или оценки качества JPEG различаются больше
+///    чем на 2 - `Recompressed`.
+/// 4. Схожесть ниже 99% - `Edited`: та же геометрия и то же сжатие, но другое содержимое.
+///    Резкость здесь не сравнивается: правка и сама меняет перепады яркости.
+/// 5. Резкость различается больше чем на 10% - `Recompressed` (пересохранение с размытием
+///    или повышением резкости), иначе - `ExactIsh`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyKind {
+    ExactIsh,
+    Recompressed,
+    Resized,
+    Edited,
+}
+
+impl CopyKind {
+    /// Вид копии для пары со схожестью `similarity`; порядок `a` и `b` не важен
+    pub fn classify(a: &ImageInfo, b: &ImageInfo, similarity: f32) -> Self {
+        let size = |info: &ImageInfo| info.crop.map_or((info.width, info.height), |crop| (crop.width, crop.height));
+        let ((wa, ha), (wb, hb)) = (size(a), size(b));
+        let aspect = |w: u32, h: u32| w as f32 / h.max(1) as f32;
+        let (aspect_a, aspect_b) = (aspect(wa, ha), aspect(wb, hb));
+        if (aspect_a - aspect_b).abs() > ASPECT_TOLERANCE * aspect_a.max(aspect_b) {
+            return Self::Edited;
+        }
+        if (wa, ha) != (wb, hb) {
+            return Self::Resized;
+        }
+        let quality_differs = match (a.quality.jpeg_quality, b.quality.jpeg_quality) {
+            (Some(qa), Some(qb)) => qa.abs_diff(qb) > JPEG_QUALITY_TOLERANCE,
+            (qa, qb) => qa.is_some() != qb.is_some(),
+        };
+        let (sa, sb) = (a.quality.sharpness, b.quality.sharpness);
+        if a.format != b.format || quality_differs {
+            Self::Recompressed
+        } else if similarity < EXACT_ISH_SIMILARITY {
+            Self::Edited
+        } else if (sa - sb).abs() > SHARPNESS_TOLERANCE * sa.max(sb) {
+            Self::Recompressed
+        } else {
+            Self::ExactIsh
+        }
+    }
+
+    /// Машиночитаемое имя
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ExactIsh => "exact-ish",
+            Self::Recompressed => "recompressed",
+            Self::Resized => "resized",
+            Self::Edited => "edited",
+        }
+    }
+}
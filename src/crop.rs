@@ -0,0 +1,93 @@
+use image::DynamicImage;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{ImgAlgError, Result};
+
+/// Прямоугольник в пикселях исходного изображения: левый верхний угол и размеры
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Лежит ли прямоугольник целиком внутри изображения `width`x`height`
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height)
+    }
+}
+
+/// `x,y,ширина,высота`, как в `--crop`
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Rect {
+    type Err = ImgAlgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>().map_err(|_| ImgAlgError::InvalidOptions(format!("'{}' is not a pixel coordinate", value.trim()))))
+            .collect::<Result<Vec<_>>>()?;
+        match values.as_slice() {
+            &[_, _, 0, _] | &[_, _, _, 0] => Err(ImgAlgError::InvalidOptions("the crop rectangle must not be empty".to_string())),
+            &[x, y, width, height] => Ok(Self { x, y, width, height }),
+            _ => Err(ImgAlgError::InvalidOptions(format!("expected four comma-separated values x,y,width,height, got {}", values.len()))),
+        }
+    }
+}
+
+/// Вырезает прямоугольник из декодированного изображения. Прямоугольник, который
+/// не помещается в изображение, - ошибка `CropOutOfBounds` с размерами изображения
+pub(crate) fn apply(image_path: &Path, image: DynamicImage, rect: Option<Rect>) -> Result<DynamicImage> {
+    let Some(rect) = rect else {
+        return Ok(image);
+    };
+    if !rect.fits(image.width(), image.height()) {
+        return Err(ImgAlgError::CropOutOfBounds { path: image_path.to_path_buf(), crop: rect, width: image.width(), height: image.height() });
+    }
+    Ok(image.crop_imm(rect.x, rect.y, rect.width, rect.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn parses_and_reports_bad_rectangles() {
+        assert_eq!(" 1, 2,3 ,4".parse::<Rect>().unwrap(), Rect::new(1, 2, 3, 4));
+        assert_eq!(Rect::new(1, 2, 3, 4).to_string(), "1,2,3,4");
+        for (input, message) in [("1,2,3", "got 3"), ("1,2,0,4", "must not be empty"), ("1,-2,3,4", "'-2' is not")] {
+            let error = input.parse::<Rect>().unwrap_err().to_string();
+            assert!(error.contains(message), "{input}: {error}");
+        }
+    }
+
+    #[test]
+    fn rectangle_must_lie_inside_the_image() {
+        assert!(Rect::new(0, 0, 10, 10).fits(10, 10));
+        assert!(!Rect::new(1, 0, 10, 10).fits(10, 10));
+        assert!(!Rect::new(u32::MAX, 0, 2, 1).fits(10, 10));
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(10, 8));
+        let cropped = apply(Path::new("a.png"), image.clone(), Some(Rect::new(2, 3, 4, 5))).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (4, 5));
+        let error = apply(Path::new("a.png"), image, Some(Rect::new(2, 4, 4, 5))).unwrap_err();
+        assert!(matches!(error, ImgAlgError::CropOutOfBounds { width: 10, height: 8, .. }), "{error}");
+    }
+}
@@ -0,0 +1,97 @@
+/// Число независимых сумм в `sqrt_diff_sum`.
+///
+/// Значение с индексом `i` всегда попадает в сумму `i % LANES`, а суммы складываются
+/// в одном порядке, поэтому векторный и поэлементный пути дают одинаковые до бита результаты
+pub(crate) const LANES: usize = 8;
+
+/// Сумма `sqrt(|a[i] - b[i]|)` по общей части двух последовательностей
+pub(crate) fn sqrt_diff_sum(a: &[i32], b: &[i32]) -> f64 {
+    let len = a.len().min(b.len());
+    let (a_chunks, a_tail) = a[..len].as_chunks::<LANES>();
+    let (b_chunks, b_tail) = b[..len].as_chunks::<LANES>();
+
+    let mut lanes = [0.0f64; LANES];
+    accumulate_chunks(a_chunks, b_chunks, &mut lanes);
+    // Остаток короче LANES считаем по одному значению, в те же суммы
+    for ((lane, x), y) in lanes.iter_mut().zip(a_tail).zip(b_tail) {
+        *lane += sqrt_diff(*x, *y);
+    }
+    lanes.iter().sum()
+}
+
+/// Сумма `sqrt(|x|)`: разница с последовательностью нулей
+pub(crate) fn sqrt_sum(values: &[i32]) -> f64 {
+    values.iter().map(|&x| sqrt_diff(x, 0)).sum()
+}
+
+/// Разность считается в `i64`, так что переполнения нет при любых `i32`
+pub(crate) fn sqrt_diff(x: i32, y: i32) -> f64 {
+    ((x as i64 - y as i64).abs() as f64).sqrt()
+}
+
+/// Переносимый путь: цикл по блокам фиксированной длины, который компилятор векторизует сам
+#[cfg(not(feature = "simd"))]
+fn accumulate_chunks(a: &[[i32; LANES]], b: &[[i32; LANES]], lanes: &mut [f64; LANES]) {
+    for (a, b) in a.iter().zip(b) {
+        for ((lane, x), y) in lanes.iter_mut().zip(a).zip(b) {
+            *lane += sqrt_diff(*x, *y);
+        }
+    }
+}
+
+/// `std::simd`, требует nightly
+#[cfg(feature = "simd")]
+fn accumulate_chunks(a: &[[i32; LANES]], b: &[[i32; LANES]], lanes: &mut [f64; LANES]) {
+    use std::simd::num::SimdInt;
+    use std::simd::{Simd, StdFloat};
+
+    let mut sums = Simd::from_array(*lanes);
+    for (a, b) in a.iter().zip(b) {
+        let diff = Simd::<i32, LANES>::from_array(*a).cast::<i64>() - Simd::from_array(*b).cast::<i64>();
+        sums += diff.abs().cast::<f64>().sqrt();
+    }
+    *lanes = sums.to_array();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Поэлементный путь без блоков: значение `i` в сумму `i % LANES`
+    fn scalar_sum(a: &[i32], b: &[i32]) -> f64 {
+        let mut lanes = [0.0f64; LANES];
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            lanes[i % LANES] += sqrt_diff(*x, *y);
+        }
+        lanes.iter().sum()
+    }
+
+    /// Псевдослучайные значения сигнатуры, одинаковые при одинаковом `seed`
+    fn random_values(seed: u64, len: usize, limit: i32) -> Vec<i32> {
+        let mut state = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                ((state >> 33) as i64 % (2 * limit as i64 + 1) - limit as i64) as i32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunked_sum_is_bit_identical_to_scalar() {
+        for seed in 0..200 {
+            // Длины с остатком и без, в том числе короче одного блока
+            let len = [0, 1, 7, 8, 9, 255, 256, 768, 1000][seed as usize % 9];
+            let a = random_values(seed, len, 255 * 255);
+            let b = random_values(seed + 1000, len, 255 * 255);
+            assert_eq!(sqrt_diff_sum(&a, &b).to_bits(), scalar_sum(&a, &b).to_bits(), "seed {seed}, len {len}");
+        }
+    }
+
+    #[test]
+    fn uses_only_the_common_prefix() {
+        let a = random_values(1, 20, 100);
+        let b = random_values(2, 13, 100);
+        assert_eq!(sqrt_diff_sum(&a, &b).to_bits(), scalar_sum(&a[..13], &b).to_bits());
+    }
+}
@@ -0,0 +1,160 @@
+use image::{ImageBuffer, Rgba};
+use std::ops::Deref;
+
+/// Уменьшает изображение до сетки `size`x`size` усреднением по площади. Часть формата
+/// сигнатуры `v2`: алгоритм нельзя менять, не меняя формат.
+///
+/// Исходное изображение `W`x`H` делится на `size` равных полос по каждой оси: ячейка `(cx, cy)`
+/// покрывает прямоугольник `[cx·W/size, (cx+1)·W/size) x [cy·H/size, (cy+1)·H/size)`.
+/// Пиксель входит в ячейку с весом, равным площади их пересечения. Если мерить ось в единицах
+/// `1/size` пикселя, границы целые: пиксель `x` занимает `[x·size, (x+1)·size)`, полоса
+/// `cx` - `[cx·W, (cx+1)·W)`, и вес - произведение длин пересечений по осям, а сумма весов
+/// ячейки - `W·H`. Каналы R, G, B и A усредняются независимо, без домножения на прозрачность.
+/// Среднее округляется до ближайшего целого, половина - вверх: `(сумма + W·H/2) / (W·H)`
+/// в целых `u64`, так что результат одинаков на любой платформе.
+///
+/// Пиксели ячеек возвращаются построчно. Буфер может быть и заимствованным срезом
+pub(crate) fn area_average<C: Deref<Target = [u8]>>(image: &ImageBuffer<Rgba<u8>, C>, size: u32) -> Vec<[u8; 4]> {
+    area_average_fine(image, size).into_iter().map(coarse).collect()
+}
+
+/// Дробных битов у средних `area_average_fine`
+pub(crate) const FINE_BITS: u32 = 8;
+
+/// Как `area_average`, но средние с `FINE_BITS` дробными битами: по ним ячейки можно усреднять
+/// дальше (`Signature::downsample_to`) без повторного округления. Дробная часть ограничена так,
+/// что `coarse` возвращает в точности результат `area_average`
+pub(crate) fn area_average_fine<C: Deref<Target = [u8]>>(image: &ImageBuffer<Rgba<u8>, C>, size: u32) -> Vec<[u16; 4]> {
+    let (width, height) = image.dimensions();
+    let columns = spans(width, size);
+    let rows = spans(height, size);
+    let area = width as u64 * height as u64;
+    if area == 0 {
+        return vec![[0, 0, 0, 255 << FINE_BITS]; (size * size) as usize]; // Пустое изображение - непрозрачный черный
+    }
+
+    let mut cells = Vec::with_capacity((size * size) as usize);
+    for row in &rows {
+        let mut sums = vec![[0u64; 4]; size as usize];
+        for &(y, weight_y) in row {
+            for (sum, column) in sums.iter_mut().zip(&columns) {
+                for &(x, weight_x) in column {
+                    let pixel = image.get_pixel(x, y);
+                    let weight = weight_x * weight_y;
+                    for (channel, value) in sum.iter_mut().zip(pixel.0) {
+                        *channel += value as u64 * weight;
+                    }
+                }
+            }
+        }
+        cells.extend(sums.iter().map(|sum| sum.map(|channel| fine(channel, area))));
+    }
+    cells
+}
+
+/// Среднее `sum / area` с `FINE_BITS` дробными битами, округленное так, чтобы целая часть после
+/// `coarse` совпадала с `(sum + area/2) / area`
+fn fine(sum: u64, area: u64) -> u16 {
+    let whole = ((sum + area / 2) / area) << FINE_BITS;
+    let scaled = ((sum << FINE_BITS) + area / 2) / area;
+    let half = 1i64 << (FINE_BITS - 1);
+    (whole as i64 + (scaled as i64 - whole as i64).clamp(-half, half - 1)) as u16
+}
+
+/// Целый цвет ячейки из среднего `area_average_fine`, половина - вверх
+pub(crate) fn coarse(pixel: [u16; 4]) -> [u8; 4] {
+    pixel.map(|channel| ((u32::from(channel) + (1 << (FINE_BITS - 1))) >> FINE_BITS) as u8)
+}
+
+/// Для каждой из `size` полос оси длины `len` - пиксели, которые она задевает, и длины
+/// пересечений в единицах `1/size` пикселя
+fn spans(len: u32, size: u32) -> Vec<Vec<(u32, u64)>> {
+    let (len, size) = (len as u64, size as u64);
+    (0..size)
+        .map(|cell| {
+            let (start, end) = (cell * len, (cell + 1) * len);
+            (start / size..end.div_ceil(size))
+                .map(|pixel| {
+                    let overlap = end.min((pixel + 1) * size) - start.max(pixel * size);
+                    (pixel as u32, overlap)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Как `area_average`, но изображение сначала вписывается по центру в квадрат со стороной
+/// `max(W, H)`, а поля заполняются средним цветом изображения (не черным: черные поля дали бы
+/// резкие разности на краях содержимого). Пропорции содержимого сохраняются, так что
+/// изображение и его обрезка с другим соотношением сторон не растягиваются по-разному.
+/// Холст не создается: пиксели полей подставляются при усреднении.
+///
+/// Вместе с ячейками возвращаются флаги ячеек, целиком попавших на поля
+pub(crate) fn letterbox_average<C: Deref<Target = [u8]>>(image: &ImageBuffer<Rgba<u8>, C>, size: u32) -> (Vec<[u8; 4]>, Vec<bool>) {
+    let (width, height) = image.dimensions();
+    if width == height || width == 0 || height == 0 {
+        return (area_average(image, size), vec![false; (size * size) as usize]);
+    }
+    let side = width.max(height);
+    let (left, top) = ((side - width) / 2, (side - height) / 2);
+    let fill = mean_color(image);
+    let spans = spans(side, size);
+    let area = side as u64 * side as u64;
+
+    let mut cells = Vec::with_capacity((size * size) as usize);
+    let mut padding = Vec::with_capacity((size * size) as usize);
+    for row in &spans {
+        let mut sums = vec![[0u64; 4]; size as usize];
+        let mut inside = vec![false; size as usize];
+        for &(y, weight_y) in row {
+            for ((sum, inside), column) in sums.iter_mut().zip(&mut inside).zip(&spans) {
+                for &(x, weight_x) in column {
+                    let pixel = match (x.checked_sub(left).filter(|&x| x < width), y.checked_sub(top).filter(|&y| y < height)) {
+                        (Some(x), Some(y)) => {
+                            *inside = true;
+                            image.get_pixel(x, y).0
+                        }
+                        _ => fill,
+                    };
+                    let weight = weight_x * weight_y;
+                    for (channel, value) in sum.iter_mut().zip(pixel) {
+                        *channel += value as u64 * weight;
+                    }
+                }
+            }
+        }
+        cells.extend(sums.iter().map(|sum| sum.map(|channel| ((channel + area / 2) / area) as u8)));
+        padding.extend(inside.iter().map(|inside| !inside));
+    }
+    (cells, padding)
+}
+
+/// Средний цвет RGBA всех пикселей, с тем же округлением, что у `area_average`
+fn mean_color<C: Deref<Target = [u8]>>(image: &ImageBuffer<Rgba<u8>, C>) -> [u8; 4] {
+    let mut sum = [0u64; 4];
+    for pixel in image.pixels() {
+        for (channel, value) in sum.iter_mut().zip(pixel.0) {
+            *channel += value as u64;
+        }
+    }
+    let count = image.width() as u64 * image.height() as u64;
+    sum.map(|channel| ((channel + count / 2) / count) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fine_averages_round_to_the_format_averages() {
+        // Нечетные стороны: ячейки делят пиксели, и дробные части средних разные
+        let image = ImageBuffer::from_fn(37, 23, |x, y| Rgba([(x * 7 + y * 3) as u8, (x * y) as u8, 255 - (x * 5) as u8, (128 + y) as u8]));
+        for size in [4, 8, 16, 32] {
+            let fine = area_average_fine(&image, size);
+            assert_eq!(fine.iter().copied().map(coarse).collect::<Vec<_>>(), area_average(&image, size));
+        }
+        // Половина - вверх, а дробная часть не переносит целый уровень
+        assert_eq!(coarse([fine(5, 2), fine(3, 2), fine(0, 1), fine(255, 1)]), [3, 2, 0, 255]);
+        assert_eq!(fine(5, 2), 2 << FINE_BITS | 1 << (FINE_BITS - 1));
+    }
+}
@@ -0,0 +1,162 @@
+use image::ColorType;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::crop::Rect;
+
+/// Ошибки библиотеки
+#[derive(Debug, thiserror::Error)]
+pub enum ImgAlgError {
+    /// Файл не найден или не читается
+    #[error("Failed to open the image: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// Файл прочитан, но не декодируется как изображение
+    #[error("Failed to open the image: {source}")]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+    /// Декодирование не уложилось в `ComparerOptions::decode_timeout`
+    #[error("Decoding took longer than {timeout:?}")]
+    Timeout { path: PathBuf, timeout: Duration },
+    /// Цветовая модель изображения не поддерживается
+    #[error("Unsupported image format: {0:?}")]
+    UnsupportedColorType(ColorType),
+    /// Строка не является текстовой формой сигнатуры
+    #[error("Invalid signature string: {0}")]
+    InvalidSignature(String),
+    /// Порог схожести вне допустимых пределов или не число
+    #[error("Invalid similarity threshold: {0}")]
+    InvalidThreshold(String),
+    /// Недопустимые настройки сравнения
+    #[error("Invalid comparer options: {0}")]
+    InvalidOptions(String),
+    /// Сигнатуры нельзя сравнивать между собой
+    #[error("Signatures were computed with incompatible settings")]
+    SignatureMismatch,
+    /// Обращение к изображению, которого нет в сравнителе
+    #[error("No image with index {0}")]
+    InvalidIndex(usize),
+    /// Файл индекса поврежден или имеет неизвестный формат
+    #[error("Invalid index file: {reason}")]
+    CorruptIndex { path: PathBuf, reason: String },
+    /// Сигнатуры в индексе посчитаны по-другому и несравнимы с новыми
+    #[error("Index was built with {option}={stored}, but signatures are now computed with {option}={current}")]
+    IndexMismatch { path: PathBuf, option: String, stored: String, current: String },
+    /// Область сравнения не помещается в изображение
+    #[error("Crop rectangle {crop} (x,y,width,height) does not fit into the {width}x{height} image")]
+    CropOutOfBounds { path: PathBuf, crop: Rect, width: u32, height: u32 },
+    /// Длина буфера пикселей не соответствует размерам изображения
+    #[error("RGBA buffer of {len} bytes does not match a {width}x{height} image: expected width * height * 4 bytes with no row padding")]
+    InvalidBuffer { len: usize, width: u32, height: u32 },
+    /// Ролик не удалось прочитать через `ffmpeg`, или `ffmpeg` не установлен
+    #[error("Failed to read the video: {reason}")]
+    Video { path: PathBuf, reason: String },
+    /// В значке нет изображения запрошенного размера
+    #[error("The icon has no {size}px image, available sizes: {}", format_sizes(available))]
+    IconSizeMissing { path: PathBuf, size: u32, available: Vec<u32> },
+    /// Размеры изображения или его области `crop` не совпадают с размерами карты допусков.
+    /// У буфера пикселей пути нет
+    #[error("The tolerance map is {}x{} but the image is {}x{}", map.0, map.1, image.0, image.1)]
+    ToleranceMapSize { path: Option<PathBuf>, map: (u32, u32), image: (u32, u32) },
+    /// У изображения нет пикселей: ширина или высота равна нулю. У буфера пикселей пути нет
+    #[error("The image is {width}x{height} and has no pixels")]
+    EmptyImage { path: Option<PathBuf>, width: u32, height: u32 },
+    /// Одна сторона изображения длиннее другой больше чем в `MAX_ASPECT_RATIO` раз,
+    /// а в настройках `ComparerOptions::strict_aspect`. У буфера пикселей пути нет
+    #[error("The image is {width}x{height}: its sides differ more than {}-fold", crate::info::MAX_ASPECT_RATIO)]
+    ExtremeAspect { path: Option<PathBuf>, width: u32, height: u32 },
+    /// Журнал просмотра не подходит к этому просмотру или не является журналом
+    #[error("Cannot resume the scan from the journal: {reason}")]
+    Journal { path: PathBuf, reason: String },
+    /// Временная ошибка чтения (`is_transient`) повторилась и после `ComparerOptions::retries`
+    /// попыток; код и путь - как у последней ошибки
+    #[error("{source} (still failing after {retries} retries)")]
+    Retried {
+        retries: u32,
+        #[source]
+        source: Box<ImgAlgError>,
+    },
+    /// Не удалось запустить потоки `ComparerOptions::threads`
+    #[error("Failed to start the worker threads: {0}")]
+    ThreadPool(String),
+    /// Операция была отменена
+    #[error("Operation was cancelled")]
+    Cancelled,
+}
+
+impl ImgAlgError {
+    /// Разделяет ошибку `image` на ошибку чтения файла и ошибку декодирования
+    pub(crate) fn open(path: &Path, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(source) => Self::Io { path: path.to_path_buf(), source },
+            source => Self::Decode { path: path.to_path_buf(), source },
+        }
+    }
+
+    pub(crate) fn io(path: &Path, source: std::io::Error) -> Self {
+        Self::Io { path: path.to_path_buf(), source }
+    }
+
+    pub(crate) fn corrupt_index(path: &Path, reason: impl Into<String>) -> Self {
+        Self::CorruptIndex { path: path.to_path_buf(), reason: reason.into() }
+    }
+
+    /// Путь к файлу, из-за которого возникла ошибка, если он известен
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Io { path, .. }
+            | Self::Decode { path, .. }
+            | Self::Timeout { path, .. }
+            | Self::CorruptIndex { path, .. }
+            | Self::IndexMismatch { path, .. }
+            | Self::CropOutOfBounds { path, .. }
+            | Self::Video { path, .. }
+            | Self::IconSizeMissing { path, .. }
+            | Self::Journal { path, .. } => Some(path),
+            Self::ToleranceMapSize { path, .. } | Self::EmptyImage { path, .. } | Self::ExtremeAspect { path, .. } => path.as_deref(),
+            Self::Retried { source, .. } => source.path(),
+            _ => None,
+        }
+    }
+
+    /// Может ли повторное чтение пройти успешно: `EIO`, `EAGAIN`, прерванное или не успевшее
+    /// чтение (например, с сетевого диска) и `Timeout`. Поврежденный файл не временная ошибка
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Io { source, .. } | Self::Decode { source: image::ImageError::IoError(source), .. } => is_transient_io(source),
+            Self::Timeout { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Сколько раз файл перечитывался перед этой ошибкой
+    pub fn retries(&self) -> u32 {
+        match self {
+            Self::Retried { retries, .. } => *retries,
+            _ => 0,
+        }
+    }
+}
+
+/// Код ошибки ввода-вывода `EIO`, одинаковый во всех Unix
+const EIO: i32 = 5;
+
+fn is_transient_io(error: &std::io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut) || error.raw_os_error() == Some(EIO)
+}
+
+fn format_sizes(sizes: &[u32]) -> String {
+    if sizes.is_empty() {
+        return "none, not a valid ICO file".to_string();
+    }
+    sizes.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+}
+
+pub type Result<T, E = ImgAlgError> = std::result::Result<T, E>;
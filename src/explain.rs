@@ -0,0 +1,65 @@
+use crate::{ComparerOptions, Signature};
+
+/// Вклад одной ячейки уменьшенной копии в разницу двух сигнатур
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellContribution {
+    /// Сторона сетки, к которой относится ячейка: 16, у многомасштабной сигнатуры 8, 16 или 32
+    pub grid: u32,
+    /// Столбец и строка ячейки, от 0
+    pub x: u32,
+    pub y: u32,
+    /// Цвета RGBA ячейки в уменьшенных копиях первого и второго изображения
+    pub a: [u8; 4],
+    pub b: [u8; 4],
+    /// Доля разницы от этой ячейки, в единицах `PairResult::distance`
+    pub distance: f64,
+    /// Та же доля в процентах от всей разницы
+    pub percent: f64,
+    /// Доля разницы ячейки, отброшенная `ComparerOptions::ignore_worst`, в тех же единицах;
+    /// в `distance` и в разницу пары она не входит
+    pub ignored: f64,
+}
+
+/// Раскладывает `options.distance(a, b)` по ячейкам, от наибольшего вклада к наименьшему.
+///
+/// Сигнатура хранит только разности соседних цветов (по строкам, с переходом на следующую),
+/// поэтому вклад разности приписывается ячейке, на которой она записана у `a`, и бывает большим
+/// даже при одинаковом цвете ячейки, если различаются предыдущие. Если у изображений
+/// повторяются соседние цвета, разности сравниваются со сдвигом, и ячейка указывает на место в `a`.
+/// Ячейки без вклада не выводятся: у одинаковых изображений список пуст.
+/// С `ignore_worst` в списке есть и отброшенные ячейки, с ненулевым `ignored`
+pub(crate) fn explain(options: &ComparerOptions, a: &Signature, b: &Signature) -> Vec<CellContribution> {
+    let planes: Vec<_> = options.planes().collect();
+    let mut contributions = vec![];
+    for ((a_grid, b_grid), factor) in a.grids().iter().zip(b.grids()).zip(options.grid_factors(a)) {
+        let size = a_grid.size();
+        let ignored_entries = options.ignored_entries(a_grid, b_grid);
+        let kept_factor = factor * options.kept_scale(a_grid);
+        let mut cells = vec![0.0; (size * size) as usize];
+        let mut ignored = vec![0.0; (size * size) as usize];
+        for &(plane, weight) in &planes {
+            for (entry, (cell, diff)) in a_grid.plane_terms(b_grid, plane).into_iter().enumerate() {
+                let diff = options.tolerated(size, cell, diff);
+                let (totals, factor) = if ignored_entries.contains(&entry) { (&mut ignored, factor) } else { (&mut cells, kept_factor) };
+                if let Some(total) = totals.get_mut(cell) {
+                    *total += diff * weight * factor;
+                }
+            }
+        }
+        for (cell, (distance, ignored)) in cells.into_iter().zip(ignored).enumerate() {
+            if distance > 0.0 || ignored > 0.0 {
+                let (a, b) = (a_grid.pixel(cell).unwrap_or_default(), b_grid.pixel(cell).unwrap_or_default());
+                let (x, y) = (cell as u32 % size, cell as u32 / size);
+                contributions.push(CellContribution { grid: size, x, y, a, b, distance, percent: 0.0, ignored });
+            }
+        }
+    }
+
+    let total: f64 = contributions.iter().map(|contribution| contribution.distance).sum();
+    for contribution in &mut contributions {
+        contribution.percent = if total > 0.0 { contribution.distance / total * 100.0 } else { 0.0 };
+    }
+    let key = |contribution: &CellContribution| contribution.distance + contribution.ignored;
+    contributions.sort_by(|l, r| key(r).total_cmp(&key(l)).then((l.grid, l.y, l.x).cmp(&(r.grid, r.y, r.x))));
+    contributions
+}
@@ -0,0 +1,61 @@
+//! Один и тот же файл под разными путями: символьные ссылки, жесткие ссылки, каталог,
+//! подключенный второй раз (`mount --bind`), имя в другом регистре на файловой системе
+//! без учета регистра. Такие пути - не дубликаты, а один файл, и удалять «копию» нельзя.
+//!
+//! На Unix файл узнается по устройству и номеру inode. На Windows - по окончательному пути,
+//! который `fs::canonicalize` получает из открытого дескриптора файла
+//! (`GetFinalPathNameByHandleW`): ссылки и регистр имени он раскрывает, а жесткие ссылки
+//! остаются разными файлами - номер файла из дескриптора в стабильной `std` недоступен
+
+use std::fs;
+use std::path::Path;
+
+use crate::{archive, icon, ImgAlgError, Result};
+
+/// Опознаватель файла, одинаковый у всех путей к нему
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileId {
+    file: FileKey,
+    /// Файл внутри архива и размер из значка: разные части одного файла - разные изображения
+    member: Option<String>,
+    size: Option<u32>,
+}
+
+#[cfg(unix)]
+type FileKey = (u64, u64);
+#[cfg(not(unix))]
+type FileKey = std::path::PathBuf;
+
+impl FileId {
+    /// Опознаватель файла по пути; у файла внутри архива и размера значка - опознаватель
+    /// архива или значка вместе с именем или размером. Ошибка - файл не открывается
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let (path, size) = match icon::split(path) {
+            Some((icon, size)) => (icon, Some(size)),
+            None => (path.to_path_buf(), None),
+        };
+        let (path, member) = match archive::split(&path) {
+            Some((archive, member)) => (archive, Some(member)),
+            None => (path, None),
+        };
+        Ok(Self { file: file_key(&path)?, member, size })
+    }
+
+    /// Ведут ли оба пути к одному файлу; путь, который не открывается, ни с чем не совпадает
+    pub fn is_same_file<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> bool {
+        matches!((Self::of(a), Self::of(b)), (Ok(a), Ok(b)) if a == b)
+    }
+}
+
+#[cfg(unix)]
+fn file_key(path: &Path) -> Result<FileKey> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(path).map_err(|e| ImgAlgError::io(path, e))?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_key(path: &Path) -> Result<FileKey> {
+    fs::canonicalize(path).map_err(|e| ImgAlgError::io(path, e))
+}
@@ -0,0 +1,262 @@
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::{ImgAlgError, Result};
+
+/// Сторона уменьшенной копии, от которой считается DCT
+const DCT_SIZE: usize = 32;
+/// Сторона уменьшенной копии в режиме совместимости с `img_hash` (8 · 2)
+const IMG_HASH_DCT_SIZE: usize = 16;
+/// Сторона блока низких частот, дающего 64 бита
+const HASH_SIZE: usize = 8;
+
+/// Способ вычисления отпечатка.
+///
+/// `Native` - классический pHash:
+/// 1. оттенки серого по Rec. 709 (`imageops::grayscale`, альфа-канал отбрасывается);
+/// 2. уменьшение до 32x32 фильтром `Triangle`;
+/// 3. двумерный DCT-II без нормировки, из него берется левый верхний блок 8x8;
+/// 4. бит равен 1, если коэффициент больше медианы коэффициентов без постоянной составляющей.
+///
+/// `ImgHash` повторяет `HasherConfig::new().hash_alg(HashAlg::Mean).preproc_dct()` из `img_hash` 3.x:
+/// те же оттенки серого, уменьшение до 16x16 фильтром `Lanczos3`, DCT-II без нормировки,
+/// блок 8x8 и сравнение `>=` со средним всех 64 коэффициентов.
+///
+/// В обоих режимах биты идут построчно: первый коэффициент - старший бит `to_u64`.
+/// `img_hash` укладывает те же биты в байты начиная с младшего, это учитывают
+/// `to_img_hash_base64`/`from_img_hash_base64`, поэтому расстояния между
+/// сконвертированными значениями совпадают с расстояниями `img_hash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FingerprintMode {
+    #[default]
+    Native,
+    ImgHash,
+}
+
+/// 64-битный перцептивный хеш (pHash) изображения.
+///
+/// Равные отпечатки не гарантируют одинаковых изображений: это лишь значит,
+/// что их низкие частоты совпали. Для оценки близости есть `distance`.
+/// Сравнивать имеет смысл только отпечатки, посчитанные в одном `FingerprintMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// Вычисляет отпечаток изображения из файла
+    pub fn compute<P: AsRef<Path>>(image_path: P) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let image = image::open(image_path).map_err(|e| ImgAlgError::open(image_path, e))?;
+        Ok(Self::compute_from_image(&image))
+    }
+
+    /// Вычисляет отпечаток уже декодированного изображения
+    pub fn compute_from_image(image: &DynamicImage) -> Self {
+        Self::compute_from_image_with(image, FingerprintMode::Native)
+    }
+
+    /// Вычисляет отпечаток файла в указанном режиме
+    pub fn compute_with<P: AsRef<Path>>(image_path: P, mode: FingerprintMode) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let image = image::open(image_path).map_err(|e| ImgAlgError::open(image_path, e))?;
+        Ok(Self::compute_from_image_with(&image, mode))
+    }
+
+    /// Вычисляет отпечаток декодированного изображения в указанном режиме
+    pub fn compute_from_image_with(image: &DynamicImage, mode: FingerprintMode) -> Self {
+        let (size, filter) = match mode {
+            FingerprintMode::Native => (DCT_SIZE, FilterType::Triangle),
+            FingerprintMode::ImgHash => (IMG_HASH_DCT_SIZE, FilterType::Lanczos3),
+        };
+        let gray = imageops::grayscale(image);
+        let small = imageops::resize(&gray, size as u32, size as u32, filter);
+        let pixels: Vec<f64> = small.pixels().map(|p| p.0[0] as f64).collect();
+        let coefficients = dct_low_frequencies(&pixels, size);
+
+        let bit_set: Vec<bool> = match mode {
+            FingerprintMode::Native => {
+                // Постоянную составляющую не учитываем при поиске медианы, она почти всегда выше остальных
+                let mut sorted = coefficients[1..].to_vec();
+                sorted.sort_by(f64::total_cmp);
+                let median = sorted[sorted.len() / 2];
+                coefficients.iter().map(|value| *value > median).collect()
+            }
+            FingerprintMode::ImgHash => {
+                let mean = coefficients.iter().sum::<f64>() / coefficients.len() as f64;
+                coefficients.iter().map(|value| *value >= mean).collect()
+            }
+        };
+
+        let mut bits = 0u64;
+        for (i, set) in bit_set.into_iter().enumerate() {
+            if set {
+                bits |= 1 << (63 - i); // Старший бит - левый верхний коэффициент
+            }
+        }
+        Self(bits)
+    }
+
+    /// Разбирает хеш в формате `ImageHash::to_base64()` из `img_hash` (8 байт в base64)
+    pub fn from_img_hash_base64(encoded: &str) -> Result<Self> {
+        let invalid = |reason: &str| ImgAlgError::InvalidSignature(format!("img_hash value: {reason}"));
+        let bytes = decode_base64(encoded.trim()).ok_or_else(|| invalid("not a base64 string"))?;
+        let bytes: [u8; 8] = bytes.try_into().map_err(|_| invalid("expected a 64-bit hash"))?;
+
+        let mut bits = 0u64;
+        for i in 0..64 {
+            if bytes[i / 8] >> (i % 8) & 1 == 1 {
+                bits |= 1 << (63 - i);
+            }
+        }
+        Ok(Self(bits))
+    }
+
+    /// Текстовая форма, совместимая с `ImageHash::from_base64()` из `img_hash`
+    pub fn to_img_hash_base64(&self) -> String {
+        let mut bytes = [0u8; 8];
+        for i in 0..64 {
+            if self.0 >> (63 - i) & 1 == 1 {
+                bytes[i / 8] |= 1 << (i % 8);
+            }
+        }
+        encode_base64(&bytes)
+    }
+
+    pub fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_u64(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Расстояние Хэмминга: число различающихся битов, от 0 до 64.
+    /// Это и есть ненормированная разница для отпечатков
+    pub fn distance(&self, other: &Fingerprint) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Группирует индексы отпечатков, совпадающих в точности.
+///
+/// Возвращаются только группы из двух и более индексов, в порядке первого появления
+pub fn dedup_exact(fingerprints: &[Fingerprint]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut group_of: HashMap<Fingerprint, usize> = HashMap::new();
+    for (idx, fingerprint) in fingerprints.iter().enumerate() {
+        match group_of.get(fingerprint) {
+            Some(&group) => groups[group].push(idx),
+            None => {
+                group_of.insert(*fingerprint, groups.len());
+                groups.push(vec![idx]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Двумерный DCT-II по строкам и столбцам квадрата `n`x`n`, возвращает левый верхний блок 8x8 построчно
+fn dct_low_frequencies(pixels: &[f64], n: usize) -> Vec<f64> {
+    let basis: Vec<f64> = (0..HASH_SIZE * n)
+        .map(|i| {
+            let (k, x) = (i / n, i % n);
+            (std::f64::consts::PI * k as f64 * (2 * x + 1) as f64 / (2 * n) as f64).cos()
+        })
+        .collect();
+
+    // Сначала строки: для каждой строки нужны только первые 8 частот
+    let mut rows = vec![0.0; n * HASH_SIZE];
+    for y in 0..n {
+        for k in 0..HASH_SIZE {
+            rows[y * HASH_SIZE + k] = (0..n).map(|x| pixels[y * n + x] * basis[k * n + x]).sum();
+        }
+    }
+    // Затем столбцы получившейся матрицы n x 8
+    let mut result = vec![0.0; HASH_SIZE * HASH_SIZE];
+    for k in 0..HASH_SIZE {
+        for u in 0..HASH_SIZE {
+            result[k * HASH_SIZE + u] = (0..n).map(|y| rows[y * HASH_SIZE + u] * basis[k * n + y]).sum();
+        }
+    }
+    result
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Стандартный base64 с дополнением `=`, как в `img_hash`
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        bytes.extend(group.to_be_bytes()[1..4 - padding].iter());
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    #[test]
+    fn groups_paths_in_a_hash_map() {
+        let files = [("a.png", 0xff00), ("b.png", 0x00ff), ("c.png", 0xff00)];
+        let mut by_fingerprint: HashMap<Fingerprint, Vec<PathBuf>> = HashMap::new();
+        for (path, bits) in files {
+            by_fingerprint.entry(Fingerprint::from_u64(bits)).or_default().push(PathBuf::from(path));
+        }
+        assert_eq!(by_fingerprint.len(), 2);
+        assert_eq!(by_fingerprint[&Fingerprint::from_u64(0xff00)], [PathBuf::from("a.png"), PathBuf::from("c.png")]);
+        assert_eq!(by_fingerprint[&Fingerprint::from_u64(0x00ff)], [PathBuf::from("b.png")]);
+    }
+
+    #[test]
+    fn orders_by_bits() {
+        let sorted: BTreeSet<Fingerprint> = [3, 1, 2, 1].map(Fingerprint::from_u64).into_iter().collect();
+        assert_eq!(sorted.into_iter().map(Fingerprint::to_u64).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(Fingerprint::from_u64(1) < Fingerprint::from_u64(u64::MAX));
+    }
+
+    #[test]
+    fn dedup_exact_keeps_first_appearance_order() {
+        let fingerprints = [5, 7, 5, 9, 7, 5].map(Fingerprint::from_u64);
+        assert_eq!(dedup_exact(&fingerprints), [vec![0, 2, 5], vec![1, 4]]);
+    }
+}
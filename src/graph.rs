@@ -0,0 +1,155 @@
+//! Пары дубликатов в виде графа для Graphviz (DOT) и других программ (GraphML).
+//!
+//! Граф показывает, как почти одинаковые изображения связываются в цепочки: A похоже на B,
+//! B на C, но A на C уже нет. Вершины - файлы, у которых есть хотя бы одна пара выше порога,
+//! ребра - такие пары со схожестью в процентах
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{paths, ImagesComparer, PairResult, SimilarityThreshold};
+
+/// Вершина графа: файл и размеры изображения
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    /// Индекс изображения в порядке загрузки, он же номер вершины
+    pub index: usize,
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Граф пар со схожестью не ниже порога
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DuplicateGraph {
+    /// Вершины по возрастанию индекса
+    pub nodes: Vec<GraphNode>,
+    /// Ребра в порядке `ImagesComparer::iter_pairs`
+    pub edges: Vec<PairResult>,
+}
+
+impl DuplicateGraph {
+    /// Граф по загруженным изображениям; `paths` - пути в порядке загрузки, как у `image_entries`
+    pub fn new<P: AsRef<Path>>(comparer: &ImagesComparer, paths: &[P], threshold: SimilarityThreshold) -> Self {
+        Self::from_pairs(comparer, paths, comparer.iter_pairs_above(threshold).collect())
+    }
+
+    /// Граф по уже найденным парам, например отобранным вручную
+    pub fn from_pairs<P: AsRef<Path>>(comparer: &ImagesComparer, paths: &[P], edges: Vec<PairResult>) -> Self {
+        let mut connected = vec![false; comparer.len()];
+        for edge in &edges {
+            connected[edge.a] = true;
+            connected[edge.b] = true;
+        }
+        let nodes = (0..comparer.len())
+            .filter(|&index| connected[index])
+            .filter_map(|index| {
+                let (path, info) = (paths.get(index)?, comparer.image_info(index)?);
+                Some(GraphNode { index, path: path.as_ref().to_path_buf(), width: info.width, height: info.height })
+            })
+            .collect();
+        Self { nodes, edges }
+    }
+
+    /// Неориентированный граф Graphviz: у вершины подпись `имя\nШxВ` и полный путь
+    /// во всплывающей подсказке, у ребра - подпись со схожестью и атрибут `similarity`
+    pub fn write_dot<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "graph duplicates {{")?;
+        writeln!(writer, "  node [shape=box];")?;
+        for node in &self.nodes {
+            let label = format!("{}\n{}x{}", file_name(&node.path), node.width, node.height);
+            writeln!(writer, "  n{} [label=\"{}\", tooltip=\"{}\"];", node.index, dot_escape(&label), dot_escape(&paths::encode(&node.path)))?;
+        }
+        for edge in &self.edges {
+            writeln!(writer, "  n{} -- n{} [label=\"{:.2}%\", similarity={:.2}];", edge.a, edge.b, edge.similarity, edge.similarity)?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// GraphML: у вершин данные `label`, `path`, `width` и `height`, у ребер - `similarity`
+    pub fn write_graphml<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(writer, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="path" for="node" attr.name="path" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="width" for="node" attr.name="width" attr.type="int"/>"#)?;
+        writeln!(writer, r#"  <key id="height" for="node" attr.name="height" attr.type="int"/>"#)?;
+        writeln!(writer, r#"  <key id="similarity" for="edge" attr.name="similarity" attr.type="double"/>"#)?;
+        writeln!(writer, r#"  <graph id="duplicates" edgedefault="undirected">"#)?;
+        for node in &self.nodes {
+            writeln!(writer, r#"    <node id="n{}">"#, node.index)?;
+            writeln!(writer, r#"      <data key="label">{}</data>"#, xml_escape(&file_name(&node.path)))?;
+            writeln!(writer, r#"      <data key="path">{}</data>"#, xml_escape(&paths::encode(&node.path)))?;
+            writeln!(writer, r#"      <data key="width">{}</data>"#, node.width)?;
+            writeln!(writer, r#"      <data key="height">{}</data>"#, node.height)?;
+            writeln!(writer, "    </node>")?;
+        }
+        for edge in &self.edges {
+            writeln!(writer, r#"    <edge source="n{}" target="n{}">"#, edge.a, edge.b)?;
+            writeln!(writer, r#"      <data key="similarity">{}</data>"#, edge.similarity)?;
+            writeln!(writer, "    </edge>")?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")
+    }
+}
+
+/// Имя файла без каталога; у пути без имени - весь путь
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| paths::encode(path), |name| paths::encode(Path::new(name))).into_owned()
+}
+
+/// Строка в кавычках DOT: экранируются кавычка и обратная косая черта (иначе `\n`, `\l`
+/// в имени файла стали бы переводами строк), переводы строк записываются как `\n`
+fn dot_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Текст XML. Управляющие символы, которых в XML 1.0 не бывает, заменяются на U+FFFD
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_strings_keep_quotes_and_backslashes_literal() {
+        assert_eq!(dot_escape("say \"hi\"\\l.png\r\n64x64"), "say \\\"hi\\\"\\\\l.png\\n64x64");
+    }
+
+    #[test]
+    fn xml_text_escapes_markup_and_drops_controls() {
+        assert_eq!(xml_escape("<a & 'b'>\u{1}\t"), "&lt;a &amp; &apos;b&apos;&gt;\u{fffd}\t");
+    }
+
+    #[test]
+    fn file_name_of_a_bare_root_is_the_whole_path() {
+        assert_eq!(file_name(Path::new("/photos/a.png")), "a.png");
+        assert_eq!(file_name(Path::new("/")), "/");
+    }
+}
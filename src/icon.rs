@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+
+use crate::{ImgAlgError, Result};
+
+/// Разделитель пути значка и размера вложенного изображения: `app.ico#256`
+pub const SEPARATOR: char = '#';
+
+/// Значок ли это Windows (`.ico`), по расширению
+pub fn is_icon<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extension.eq_ignore_ascii_case("ico"))
+}
+
+/// Путь к изображению одного размера внутри значка
+pub fn size_path<P: AsRef<Path>>(icon: P, size: u32) -> PathBuf {
+    let mut path = icon.as_ref().as_os_str().to_owned();
+    path.push(format!("{SEPARATOR}{size}"));
+    PathBuf::from(path)
+}
+
+/// Путь значка и размер, если путь указывает на одно из изображений значка
+pub fn split<P: AsRef<Path>>(path: P) -> Option<(PathBuf, u32)> {
+    let mut separator = [0; 4];
+    let (icon, size) = crate::paths::splits(path.as_ref().as_os_str(), SEPARATOR.encode_utf8(&mut separator)).last()?;
+    let size = size.to_str()?.parse().ok().filter(|&size| size > 0)?;
+    is_icon(icon).then(|| (PathBuf::from(icon), size))
+}
+
+/// Размеры вложенных изображений значка по его оглавлению, по возрастанию, без повторов
+pub fn sizes(bytes: &[u8]) -> Vec<u32> {
+    let mut sizes: Vec<u32> = entries(bytes).unwrap_or_default().iter().map(Entry::size).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+/// Запись оглавления ICO
+struct Entry {
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    /// Запись оглавления как есть
+    header: [u8; 16],
+    /// Положение данных изображения в файле
+    data: std::ops::Range<usize>,
+}
+
+impl Entry {
+    /// Сторона изображения; у неквадратных - большая
+    fn size(&self) -> u32 {
+        self.width.max(self.height)
+    }
+}
+
+/// Оглавление значка или `None`, если это не ICO или оглавление повреждено
+fn entries(bytes: &[u8]) -> Option<Vec<Entry>> {
+    let &[0, 0, 1, 0, low, high] = bytes.get(..6)? else {
+        return None;
+    };
+    let count = u16::from_le_bytes([low, high]) as usize;
+    (0..count)
+        .map(|idx| {
+            let header: [u8; 16] = bytes.get(6 + idx * 16..22 + idx * 16)?.try_into().ok()?;
+            let len = u32::from_le_bytes(header[8..12].try_into().ok()?) as usize;
+            let offset = u32::from_le_bytes(header[12..16].try_into().ok()?) as usize;
+            let data = offset..offset.checked_add(len).filter(|&end| end <= bytes.len())?;
+            // 0 в поле размера означает 256
+            let side = |value: u8| if value == 0 { 256 } else { value as u32 };
+            Some(Entry { width: side(header[0]), height: side(header[1]), bits_per_pixel: u16::from_le_bytes([header[6], header[7]]), header, data })
+        })
+        .collect()
+}
+
+/// Похоже ли содержимое на значок ICO: тогда его нужно пропустить через `select`
+pub(crate) fn is_icon_data(bytes: &[u8]) -> bool {
+    entries(bytes).is_some()
+}
+
+/// Оставляет в значке одно изображение: размера `size` или, без него, самое большое
+/// (при равных размерах - с большей глубиной цвета). Декодер `image` сам выбирает сначала
+/// по глубине цвета, так что 16x16 в 32 бита побеждает 256x256 в 8 бит.
+/// Не-ICO и значок из одного изображения без запрошенного размера возвращаются как есть.
+/// Запрошенного размера нет - ошибка `IconSizeMissing` со списком имеющихся
+pub(crate) fn select(path: &Path, bytes: Vec<u8>, size: Option<u32>) -> Result<Vec<u8>> {
+    let Some(entries) = entries(&bytes) else {
+        return match size {
+            Some(size) => Err(ImgAlgError::IconSizeMissing { path: path.to_path_buf(), size, available: vec![] }),
+            None => Ok(bytes),
+        };
+    };
+    let best = entries
+        .iter()
+        .filter(|entry| size.is_none_or(|size| entry.size() == size))
+        .max_by_key(|entry| (entry.width as u64 * entry.height as u64, entry.bits_per_pixel));
+    let Some(best) = best else {
+        return match size {
+            Some(size) => Err(ImgAlgError::IconSizeMissing { path: path.to_path_buf(), size, available: sizes(&bytes) }),
+            None => Ok(bytes), // Пустое оглавление, ошибку сообщит декодер
+        };
+    };
+    if entries.len() == 1 {
+        return Ok(bytes);
+    }
+    // Значок из одного выбранного изображения: запись оглавления с новым смещением и данные
+    let mut icon = Vec::with_capacity(22 + best.data.len());
+    icon.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    icon.extend_from_slice(&best.header[..12]);
+    icon.extend_from_slice(&22u32.to_le_bytes());
+    icon.extend_from_slice(&bytes[best.data.clone()]);
+    Ok(icon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_path_round_trips_through_split() {
+        let path = size_path("icons/app.ico", 256);
+        assert_eq!(path, Path::new("icons/app.ico#256"));
+        assert_eq!(split(&path), Some((PathBuf::from("icons/app.ico"), 256)));
+        assert_eq!(split("icons/app.png#256"), None);
+        assert_eq!(split("icons/app.ico#0"), None);
+        assert_eq!(split("icons/app.ico"), None);
+    }
+
+    #[test]
+    fn sizes_come_from_the_directory() {
+        let bytes = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/icons/app.ico")).unwrap();
+        assert!(is_icon_data(&bytes));
+        assert_eq!(sizes(&bytes), [16, 32, 256]);
+        assert!(sizes(b"not an icon").is_empty());
+    }
+}
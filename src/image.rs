@@ -0,0 +1,12 @@
+//! Типы крейта `image`, которые встречаются в API: принимать `DynamicImage` и читать
+//! `ImageInfo::format` можно без своей зависимости от `image` и без подбора совместимой версии.
+//!
+//! Смена старшей версии `image` здесь - несовместимое изменение и `imgalg`
+
+pub use ::image::{DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageReader, Rgb, RgbImage, Rgba, RgbaImage};
+
+/// Декодирует файл так же, как сравнитель: пути вида `архив.zip!/имя` и `значок.ico#256`
+/// тоже читаются
+pub fn open<P: AsRef<std::path::Path>>(path: P) -> crate::Result<DynamicImage> {
+    crate::info::open_image(path.as_ref())
+}
@@ -0,0 +1,1473 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ffi::OsString;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::calibrate::{self, AdaptiveThreshold, Distribution};
+use crate::journal::ScanJournal;
+use crate::{archive, icon, mapping, paths};
+use crate::signature::{self, Grid, Signature, GRID_SIZE};
+use crate::{ComparerOptions, FileId, Fingerprint, ImageInfo, Provenance, ImagesComparer, ImgAlgError, Quality, Result, SimilarityThreshold, Transform, Warning};
+
+/// Сигнатура файла индекса
+const INDEX_MAGIC: &[u8; 8] = b"IMGALGIX";
+/// Версия формата индекса. Во второй версии после ячеек записан альфа-канал,
+/// в третьей после него - насыщенность и яркость, в четвертой записей может быть
+/// несколько сеток, каждая со своей стороной, в пятой после версии записано
+/// описание вычисления сигнатур (`signature::PIPELINE`), в шестой у записей может быть
+/// размер и время изменения файла, а после записей - пары, найденные `scan`, в седьмой
+/// вместе с ними записаны сведения `ImageInfo`, в восьмой после описания вычисления
+/// записаны версия imgalg, время записи и командная строка (`Provenance`), в девятой
+/// после сигнатуры записи могут идти отпечаток и метка (`SignatureSet`)
+pub(crate) const INDEX_VERSION: u32 = 9;
+/// Флаги записи после сигнатуры: за ними идут отпечаток (`u64`) и метка (строка)
+const ENTRY_FINGERPRINT: u8 = 1;
+const ENTRY_LABEL: u8 = 2;
+/// Сколько файлов `scan` декодирует между проверками, не пора ли сохранить промежуточный индекс
+const CHECKPOINT_CHUNK: usize = 256;
+/// Сколько элементов выделяется заранее под длину, прочитанную из файла
+const MAX_PREALLOCATION: usize = 1024;
+
+/// Совпадение, найденное в индексе
+#[derive(Debug, Clone)]
+pub struct IndexMatch {
+    pub path: PathBuf,
+    pub similarity: f32,
+    /// Тот же файл (`FileId`), что и другое изображение группы, а не его копия:
+    /// такие изображения `scan` включает в группы только с `set_include_same_file`
+    pub same_file: bool,
+}
+
+/// Размер и время изменения файла, по которым `SignatureIndex::scan` узнает,
+/// что файл не менялся
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    /// Время изменения в наносекундах от начала эпохи Unix, 0 - неизвестно
+    pub modified: u64,
+}
+
+impl FileStamp {
+    /// Отметка файла; у файла внутри архива - отметка самого архива, у изображения
+    /// одного размера из значка - отметка значка
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let icon = icon::split(&path).map(|(icon, _)| icon);
+        let path = icon.as_deref().unwrap_or(path.as_ref());
+        let split = archive::split(path);
+        let path = split.as_ref().map_or(path, |(archive, _)| archive.as_path());
+        let metadata = fs::metadata(path).map_err(|e| ImgAlgError::io(path, e))?;
+        let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map_or(0, |since| since.as_nanos() as u64);
+        Ok(Self { size: metadata.len(), modified })
+    }
+}
+
+/// Итог `SignatureIndex::scan`
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Сколько сигнатур взято из индекса без декодирования
+    pub reused: usize,
+    /// Сколько файлов декодировано заново: новые, измененные и все при полном просмотре
+    pub computed: usize,
+    /// Сколько сигнатур взято из журнала прерванного просмотра (`set_journal`) без декодирования
+    pub resumed: usize,
+    /// Сколько записей удалено: файлы, которых больше нет среди просмотренных
+    pub removed: usize,
+    /// Сколько пар сравнено
+    pub compared: usize,
+    /// Файлы, которые не удалось прочитать; в индекс они не попадают
+    pub errors: Vec<ImgAlgError>,
+    /// Предупреждения загрузки и устаревшие записи индекса (`incremental`), в порядке файлов
+    pub warnings: Vec<Warning>,
+    /// Сколько всего повторных чтений понадобилось (`ComparerOptions::retries`), включая
+    /// файлы, которые так и не прочитались
+    pub retries: u32,
+    /// Группы дубликатов: первым идет образец со схожестью 100, остальные - со схожестью с ним
+    pub groups: Vec<Vec<IndexMatch>>,
+    /// Пары путей к одному и тому же файлу (`FileId`), по порядку путей. Без
+    /// `set_include_same_file` в группы попадает только один путь к файлу
+    pub same_file: Vec<(PathBuf, PathBuf)>,
+    /// Порог, с которым искались пары: заданный или выведенный из фона
+    pub threshold: SimilarityThreshold,
+    /// Фон просмотренных файлов, по которому выведен порог `ScanThreshold::Adaptive`
+    pub background: Option<Distribution>,
+}
+
+/// Порог `SignatureIndex::scan`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanThreshold {
+    Fixed(SimilarityThreshold),
+    /// Выводится из схожести случайных пар просмотренных файлов, когда все сигнатуры
+    /// уже посчитаны; если пар нет (файлов меньше двух), порог - 100
+    Adaptive(AdaptiveThreshold),
+}
+
+impl From<SimilarityThreshold> for ScanThreshold {
+    fn from(threshold: SimilarityThreshold) -> Self {
+        Self::Fixed(threshold)
+    }
+}
+
+impl From<AdaptiveThreshold> for ScanThreshold {
+    fn from(adaptive: AdaptiveThreshold) -> Self {
+        Self::Adaptive(adaptive)
+    }
+}
+
+/// Промежуточный результат `SignatureIndex::scan_with`, сообщаемый до конца просмотра
+#[derive(Debug, Clone, Copy)]
+pub enum ScanEvent<'a> {
+    /// Файл не удалось прочитать; сообщается сразу после попытки декодирования
+    Error(&'a ImgAlgError),
+    /// Сигнатура файла готова: вычислена (`reused == false`) или взята из индекса или журнала
+    Signed { path: &'a Path, reused: bool },
+    /// Пара не ниже порога: пары с прошлого просмотра - сразу, новые - по мере сравнения.
+    /// `same_file` - оба пути ведут к одному файлу
+    Pair { a: &'a Path, b: &'a Path, similarity: f32, same_file: bool },
+}
+
+/// Состояние записи индекса по сравнению с файлом на диске
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryState {
+    /// Размер и время изменения файла те же, что при просмотре
+    Current,
+    /// Файл есть, но размер или время изменения другие: сигнатура устарела
+    Modified,
+    /// Файла нет или он не читается
+    Missing,
+    /// Файл есть, но у записи нет отметки (запись добавлена не `scan`), так что
+    /// проверить, менялся ли он, нельзя
+    Unstamped,
+}
+
+/// Итог `SignatureIndex::stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub entries: usize,
+    pub current: usize,
+    pub modified: usize,
+    pub missing: usize,
+    pub unstamped: usize,
+    /// Пары дубликатов, сохраненные `scan`
+    pub pairs: usize,
+}
+
+/// Итог `SignatureIndex::compact`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Размер файла индекса до и после перезаписи, в байтах
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// Запись индекса. `stamp` и `info` есть только у файлов, просмотренных `scan`: по `stamp`
+/// узнается, что файл не менялся и найденные для него пары действительны. Отпечаток и метка
+/// есть у записей, добавленных в `SignatureSet`; отпечаток посчитан по тому же содержимому,
+/// что и сигнатура, а метка переживает пересчет
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub(crate) path: PathBuf,
+    pub(crate) signature: Signature,
+    stamp: Option<FileStamp>,
+    info: Option<ImageInfo>,
+    pub(crate) fingerprint: Option<Fingerprint>,
+    pub(crate) label: Option<String>,
+}
+
+impl Entry {
+    fn state(&self) -> EntryState {
+        match (FileStamp::of(&self.path), self.stamp) {
+            (Err(_), _) => EntryState::Missing,
+            (Ok(_), None) => EntryState::Unstamped,
+            (Ok(now), Some(stamp)) if now == stamp => EntryState::Current,
+            (Ok(_), Some(_)) => EntryState::Modified,
+        }
+    }
+}
+
+/// Пара записей по позициям и ее схожесть
+type Pair = (usize, usize, f32);
+
+/// Набор сигнатур изображений, сохраняемый на диск
+#[derive(Default)]
+pub struct SignatureIndex {
+    entries: Vec<Entry>,
+    /// Куда и как часто `scan` сохраняет промежуточный индекс
+    checkpoint: Option<(PathBuf, Duration)>,
+    /// Журнал следующего `scan`
+    journal: Option<ScanJournal>,
+    /// Строить ли группы из путей к одному файлу
+    include_same_file: bool,
+    /// Порог, с которым `scan` искал пары, и найденные пары со схожестью
+    scan_threshold: Option<SimilarityThreshold>,
+    pairs: Vec<(PathBuf, PathBuf, f32)>,
+    /// Преобразование значений и вписывание в квадрат у всех сигнатур индекса;
+    /// записываются в описание вычисления
+    transform: Transform,
+    letterbox: bool,
+    /// Сведения о записи загруженного файла
+    provenance: Option<Provenance>,
+    /// Командная строка для следующего `save`
+    command_line: Option<Vec<OsString>>,
+}
+
+impl SignatureIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Загружает индекс с диска, либо создает пустой, если файла еще нет
+    pub fn open_or_create<P: AsRef<Path>>(index_path: P) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        if index_path.exists() {
+            Self::load(index_path)
+        } else {
+            Ok(Self::new())
+        }
+    }
+
+    /// Как `open_or_create`, но индекс, сигнатуры которого посчитаны с другим
+    /// преобразованием значений или вписыванием, чем у `options`, - ошибка `IndexMismatch`,
+    /// см. `load_with`
+    pub fn open_or_create_with<P: AsRef<Path>>(index_path: P, options: &ComparerOptions) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        if index_path.exists() {
+            Self::load_with(index_path, options)
+        } else {
+            Ok(Self::new().with_format(options))
+        }
+    }
+
+    /// Загружает индекс с диска. Если сигнатуры в нем посчитаны не так, как сейчас,
+    /// возвращает `IndexMismatch` с первой различающейся настройкой, см. `migrate`.
+    /// Преобразование значений и вписывание берутся из файла: с ними и считаются новые сигнатуры
+    pub fn load<P: AsRef<Path>>(index_path: P) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        let (index, pipeline) = Self::read_file(index_path)?;
+        if let Some((option, stored, current)) = signature::pipeline_difference(&pipeline) {
+            return Err(ImgAlgError::IndexMismatch { path: index_path.to_path_buf(), option, stored, current });
+        }
+        Ok(index)
+    }
+
+    /// Как `load`, но и преобразование значений и вписывание должны совпадать с `options`;
+    /// иначе - `IndexMismatch` с `values` или `filter`
+    pub fn load_with<P: AsRef<Path>>(index_path: P, options: &ComparerOptions) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        let index = Self::load(index_path)?;
+        let (stored, current) = (signature::pipeline(index.transform, index.letterbox), signature::pipeline(options.transform(), options.is_letterbox()));
+        if let Some((option, stored, current)) = signature::pipeline_difference_from(&stored, &current) {
+            return Err(ImgAlgError::IndexMismatch { path: index_path.to_path_buf(), option, stored, current });
+        }
+        Ok(index)
+    }
+
+    /// Пустой индекс для сигнатур с преобразованием значений и вписыванием из `options`
+    fn with_format(mut self, options: &ComparerOptions) -> Self {
+        self.transform = options.transform();
+        self.letterbox = options.is_letterbox();
+        self
+    }
+
+    /// Загружает индекс и пересчитывает все сигнатуры по файлам, на которые он ссылается.
+    /// Метки записей, преобразование значений и вписывание сохраняются, а отпечатки, размер и время
+    /// изменения файла и сведения `ImageInfo` - у тех записей, у которых они были, -
+    /// пересчитываются вместе с сигнатурой.
+    /// Найденные пары не переносятся: следующий `scan` найдет их заново.
+    /// Файлы, которые больше не читаются, удаляются из индекса, ошибки по ним возвращаются.
+    /// Сам файл индекса не перезаписывается, для этого есть `save`
+    pub fn migrate<P: AsRef<Path>>(index_path: P) -> Result<(Self, Vec<ImgAlgError>)> {
+        let (stale, _) = Self::read_file(index_path.as_ref())?;
+        let (transform, letterbox) = (stale.transform, stale.letterbox);
+        let mut entries = Vec::with_capacity(stale.entries.len());
+        let mut errors = vec![];
+        for Entry { path, stamp, info, fingerprint, label, .. } in stale.entries {
+            // Отпечаток и сведения считаются заново по тому же декодированию, что и сигнатура
+            let recomputed = crate::info::open(&path).and_then(|(image, fresh)| {
+                let fingerprint = fingerprint.map(|_| Fingerprint::compute_from_image(&image));
+                Ok((Signature::from_image_scales(image, &[GRID_SIZE], transform, letterbox)?, fresh, fingerprint))
+            });
+            match recomputed {
+                Ok((signature, fresh, fingerprint)) => {
+                    let stamp = stamp.and_then(|_| FileStamp::of(&path).ok());
+                    entries.push(Entry { path, signature, stamp, info: info.map(|_| fresh), fingerprint, label });
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        Ok((Self { entries, transform, letterbox, ..Self::default() }, errors))
+    }
+
+    /// Начинается ли файл с заголовка индекса; остальное содержимое не проверяется
+    pub fn is_index_file<P: AsRef<Path>>(path: P) -> bool {
+        let mut magic = [0u8; 8];
+        fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == INDEX_MAGIC
+    }
+
+    /// Сведения о том, чем и когда записан индекс `index_path`; читается только заголовок
+    pub fn read_provenance<P: AsRef<Path>>(index_path: P) -> Result<Provenance> {
+        let index_path = index_path.as_ref();
+        let file = fs::File::open(index_path).map_err(|e| ImgAlgError::io(index_path, e))?;
+        let (_, provenance) = Self::read_header(&mut BufReader::new(file)).map_err(|e| e.at(index_path))?;
+        Ok(provenance)
+    }
+
+    /// Записи и описание вычисления, с которым они были сохранены
+    fn read_file(index_path: &Path) -> Result<(Self, String)> {
+        // Индекс сохраняется через переименование, так что отображенный файл не меняется
+        let bytes = mapping::read(index_path).map_err(|e| ImgAlgError::io(index_path, e))?;
+        Self::read_entries(&mut &bytes[..]).map_err(|e| e.at(index_path))
+    }
+
+    /// Версия формата и сведения о записи; у индексов до восьмой версии известны
+    /// только формат и описание вычисления
+    fn read_header<R: Read>(reader: &mut R) -> Result<(u32, Provenance), IndexReadError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC {
+            return Err(IndexReadError::Format("not an index file".to_string()));
+        }
+        let version = read_u32(reader)?;
+        if !(1..=INDEX_VERSION).contains(&version) {
+            return Err(IndexReadError::Format(format!("unsupported index version {version}")));
+        }
+        let pipeline = if version >= 5 { read_string(reader)? } else { signature::LEGACY_PIPELINE.to_string() }; // Версии 1-4 - гауссов фильтр
+        let mut provenance = Provenance { version: None, format_version: version, pipeline, created: None, command_line: None };
+        if version >= 8 {
+            provenance.version = Some(read_string(reader)?);
+            provenance.created = Some(read_u64(reader)?).filter(|&created| created > 0);
+            if read_u8(reader)? == 1 {
+                let count = read_u32(reader)? as usize;
+                let args = (0..count).map(|_| read_bytes(reader).map(|arg| paths::from_bytes(arg).into_os_string())).collect::<Result<_, _>>()?;
+                provenance.command_line = Some(args);
+            }
+        }
+        Ok((version, provenance))
+    }
+
+    fn read_entries<R: Read>(reader: &mut R) -> Result<(Self, String), IndexReadError> {
+        let (version, provenance) = Self::read_header(reader)?;
+        let pipeline = provenance.pipeline.clone();
+        // Незнакомое преобразование `load` сообщит как `IndexMismatch`
+        let transform = signature::pipeline_transform(&pipeline).unwrap_or_default();
+        let letterbox = signature::pipeline_letterbox(&pipeline);
+
+        let count = read_u32(reader)? as usize;
+        let mut entries = Vec::with_capacity(preallocation(count));
+        for _ in 0..count {
+            let path = paths::from_bytes(read_bytes(reader)?);
+            let (mut stamp, mut info) = (None, None);
+            if version >= 6 && read_u8(reader)? == 1 {
+                stamp = Some(FileStamp { size: read_u64(reader)?, modified: read_u64(reader)? });
+                if version >= 7 {
+                    info = Some(read_info(reader)?);
+                } else {
+                    stamp = None; // В шестой версии сведений нет: запись пересчитается при просмотре
+                }
+            }
+            let signature = read_signature(reader, version, transform, letterbox)?;
+            let (mut fingerprint, mut label) = (None, None);
+            if version >= 9 {
+                let flags = read_u8(reader)?;
+                if flags & ENTRY_FINGERPRINT != 0 {
+                    fingerprint = Some(Fingerprint::from_u64(read_u64(reader)?));
+                }
+                if flags & ENTRY_LABEL != 0 {
+                    label = Some(read_string(reader)?);
+                }
+            }
+            entries.push(Entry { path, signature, stamp, info, fingerprint, label });
+        }
+
+        let mut index = Self { entries, transform, letterbox, provenance: Some(provenance), ..Self::default() };
+        if version >= 6 && read_u8(reader)? == 1 {
+            let threshold = SimilarityThreshold::new(f32::from_bits(read_u32(reader)?)).map_err(|e| IndexReadError::Format(e.to_string()))?;
+            index.scan_threshold = Some(threshold);
+            let pairs_count = read_u32(reader)? as usize;
+            for _ in 0..pairs_count {
+                let (a, b) = (read_u32(reader)? as usize, read_u32(reader)? as usize);
+                let similarity = f32::from_bits(read_u32(reader)?);
+                match (index.entries.get(a), index.entries.get(b)) {
+                    (Some(a), Some(b)) => index.pairs.push((a.path.clone(), b.path.clone(), similarity)),
+                    _ => return Err(IndexReadError::Format("pair refers to a missing entry".to_string())),
+                }
+            }
+        }
+        Ok((index, pipeline))
+    }
+
+    /// Сохраняет индекс: пишем во временный файл и переименовываем,
+    /// чтобы прерванная запись не испортила существующий индекс
+    pub fn save<P: AsRef<Path>>(&self, index_path: P) -> Result<()> {
+        let index_path = index_path.as_ref();
+        let mut tmp_name = index_path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let mut provenance = Provenance::current();
+        provenance.command_line = self.command_line.clone();
+        self.write_entries(&tmp_path, &provenance).map_err(|e| ImgAlgError::io(&tmp_path, e))?;
+        fs::rename(&tmp_path, index_path).map_err(|e| ImgAlgError::io(index_path, e))
+    }
+
+    fn write_entries(&self, tmp_path: &Path, provenance: &Provenance) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(tmp_path)?);
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        write_bytes(&mut writer, signature::pipeline(self.transform, self.letterbox).as_bytes())?;
+        write_bytes(&mut writer, provenance.version.as_deref().unwrap_or_default().as_bytes())?;
+        writer.write_all(&provenance.created.unwrap_or(0).to_le_bytes())?;
+        match &provenance.command_line {
+            Some(args) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&(args.len() as u32).to_le_bytes())?;
+                for arg in args {
+                    write_bytes(&mut writer, &paths::to_bytes(Path::new(arg)))?;
+                }
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for entry in &self.entries {
+            write_bytes(&mut writer, &paths::to_bytes(&entry.path))?;
+            match (entry.stamp, &entry.info) {
+                (Some(stamp), Some(info)) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(&stamp.size.to_le_bytes())?;
+                    writer.write_all(&stamp.modified.to_le_bytes())?;
+                    write_info(&mut writer, info)?;
+                }
+                _ => writer.write_all(&[0])?,
+            }
+            write_signature(&mut writer, &entry.signature)?;
+            let flags = if entry.fingerprint.is_some() { ENTRY_FINGERPRINT } else { 0 } | if entry.label.is_some() { ENTRY_LABEL } else { 0 };
+            writer.write_all(&[flags])?;
+            if let Some(fingerprint) = entry.fingerprint {
+                writer.write_all(&fingerprint.to_u64().to_le_bytes())?;
+            }
+            if let Some(label) = &entry.label {
+                write_bytes(&mut writer, label.as_bytes())?;
+            }
+        }
+
+        match self.scan_threshold {
+            Some(threshold) => {
+                let positions: HashMap<&Path, usize> = self.entries.iter().enumerate().map(|(pos, entry)| (entry.path.as_path(), pos)).collect();
+                let pairs: Vec<Pair> = self
+                    .pairs
+                    .iter()
+                    .filter_map(|(a, b, similarity)| Some((*positions.get(a.as_path())?, *positions.get(b.as_path())?, *similarity)))
+                    .collect();
+                writer.write_all(&[1])?;
+                writer.write_all(&threshold.value().to_bits().to_le_bytes())?;
+                writer.write_all(&(pairs.len() as u32).to_le_bytes())?;
+                for (a, b, similarity) in pairs {
+                    writer.write_all(&(a as u32).to_le_bytes())?;
+                    writer.write_all(&(b as u32).to_le_bytes())?;
+                    writer.write_all(&similarity.to_bits().to_le_bytes())?;
+                }
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    }
+
+    /// Сведения о том, чем и когда записан загруженный индекс; у нового индекса их нет
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Во время `scan` сохранять в `path` уже посчитанные сигнатуры не чаще раза в `interval`,
+    /// чтобы обрыв долгого просмотра (например, сетевого диска) не терял сделанное: следующий
+    /// `scan` с `incremental` возьмет их из этого файла. Обычно `path` - сам файл индекса
+    pub fn set_checkpoint(&mut self, path: Option<PathBuf>, interval: Duration) {
+        self.checkpoint = path.map(|path| (path, interval));
+    }
+
+    /// Вести во время следующего `scan` журнал `journal`: файлы, сигнатуры которых в нем уже
+    /// есть, не декодируются заново (если не менялись), а уже сделанные части сравнения
+    /// не повторяются. После успешного просмотра файл журнала удаляется, после ошибки
+    /// или отмены остается, чтобы следующий просмотр продолжил с него
+    pub fn set_journal(&mut self, journal: Option<ScanJournal>) {
+        self.journal = journal;
+    }
+
+    /// Включать ли в группы `scan` пары путей к одному и тому же файлу (символьные ссылки,
+    /// повторно подключенные каталоги), помечая их `IndexMatch::same_file`. По умолчанию
+    /// такие пары только перечисляются в `ScanReport::same_file`, а в группе файл один раз
+    pub fn set_include_same_file(&mut self, include: bool) {
+        self.include_same_file = include;
+    }
+
+    /// Командная строка, которая запишется в сведения индекса при следующем `save`
+    pub fn set_command_line<I: IntoIterator<Item = OsString>>(&mut self, args: I) {
+        self.command_line = Some(args.into_iter().collect());
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.position(path.as_ref()).is_some()
+    }
+
+    /// Сведения о файле, собранные `scan`
+    pub fn image_info<P: AsRef<Path>>(&self, path: P) -> Option<&ImageInfo> {
+        self.position(path.as_ref()).and_then(|pos| self.entries[pos].info.as_ref())
+    }
+
+    /// Ищет в индексе изображения, похожие на указанный файл не меньше чем на `threshold` процентов.
+    /// Совпадения идут по убыванию схожести, при равной схожести - по пути
+    pub fn query_file<P: AsRef<Path>>(&self, image_path: P, threshold: SimilarityThreshold) -> Result<Vec<IndexMatch>> {
+        let signature = self.compute(image_path.as_ref())?;
+        Ok(self.query(&signature, threshold, Some(image_path.as_ref())))
+    }
+
+    /// Добавляет (или обновляет) файл в индексе и возвращает найденные до этого совпадения
+    pub fn check_and_insert<P: AsRef<Path>>(&mut self, image_path: P, threshold: SimilarityThreshold) -> Result<Vec<IndexMatch>> {
+        let image_path = image_path.as_ref();
+        let signature = self.compute(image_path)?;
+        let matches = self.query(&signature, threshold, Some(image_path));
+        let label = self.position(image_path).and_then(|pos| self.entries[pos].label.take());
+        self.insert(Entry { path: image_path.to_path_buf(), signature, stamp: None, info: None, fingerprint: None, label });
+        Ok(matches)
+    }
+
+    /// Сигнатура файла в формате индекса
+    fn compute(&self, image_path: &Path) -> Result<Signature> {
+        self.signature_of(crate::info::open_image(image_path)?)
+    }
+
+    /// Сигнатура изображения в формате индекса: одна сетка, преобразование значений
+    /// и вписывание индекса
+    pub(crate) fn signature_of(&self, image: DynamicImage) -> Result<Signature> {
+        Signature::from_image_scales(image, &[GRID_SIZE], self.transform, self.letterbox)
+    }
+
+    /// Преобразование значений сигнатур индекса: из файла, из `open_or_create_with`
+    /// или из настроек первого `scan` пустого индекса
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Вписываются ли изображения в квадрат перед уменьшением (`ComparerOptions::letterbox`);
+    /// берется оттуда же, откуда `transform`
+    pub fn is_letterbox(&self) -> bool {
+        self.letterbox
+    }
+
+    /// Добавляет запись или заменяет запись того же пути вместе с ее парами `scan`
+    pub(crate) fn insert(&mut self, entry: Entry) {
+        self.forget_pairs(&entry.path); // Пары `scan` для старого содержимого недействительны
+        match self.position(&entry.path) {
+            Some(pos) => self.entries[pos] = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Запись для `SignatureSet`: без отметки и сведений `scan`
+    pub(crate) fn reference(path: PathBuf, signature: Signature, fingerprint: Fingerprint, label: Option<String>) -> Entry {
+        Entry { path, signature, stamp: None, info: None, fingerprint: Some(fingerprint), label }
+    }
+
+    pub(crate) fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Удаляет запись о файле, возвращает `true`, если она была
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        match self.position(path.as_ref()) {
+            Some(pos) => {
+                self.entries.remove(pos);
+                self.forget_pairs(path.as_ref());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Переносит сигнатуру на новый путь после переименования файла
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> bool {
+        if from.as_ref() != to.as_ref() {
+            self.remove(to.as_ref()); // Старая запись по новому пути больше не актуальна
+        }
+        match self.position(from.as_ref()) {
+            Some(pos) => {
+                self.entries[pos].path = to.as_ref().to_path_buf();
+                for (a, b, _) in &mut self.pairs {
+                    for path in [a, b] {
+                        if path == from.as_ref() {
+                            *path = to.as_ref().to_path_buf();
+                        }
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Переносит записи всех файлов каталога `from`, на любой глубине, в каталог `to` после
+    /// переименования каталога; возвращает число перенесенных записей
+    pub fn rename_dir<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> usize {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let moved: Vec<(PathBuf, PathBuf)> = self.entries.iter().filter_map(|entry| Some((entry.path.clone(), to.join(entry.path.strip_prefix(from).ok().filter(|rest| !rest.as_os_str().is_empty())?)))).collect();
+        for (old, new) in &moved {
+            self.rename(old, new);
+        }
+        moved.len()
+    }
+
+    /// Удаляет записи всех файлов каталога `dir` на любой глубине; возвращает их число
+    pub fn remove_dir<P: AsRef<Path>>(&mut self, dir: P) -> usize {
+        let dir = dir.as_ref();
+        let removed: Vec<PathBuf> = self.entries.iter().filter(|entry| entry.path != dir && entry.path.starts_with(dir)).map(|entry| entry.path.clone()).collect();
+        for path in &removed {
+            self.remove(path);
+        }
+        removed.len()
+    }
+
+    /// Состояние каждой записи, в порядке записей; файлы проверяются параллельно
+    pub fn entry_states(&self) -> Vec<(&Path, EntryState)> {
+        self.entries.par_iter().map(|entry| (entry.path.as_path(), entry.state())).collect()
+    }
+
+    /// Число записей по состояниям и число сохраненных пар
+    pub fn stats(&self) -> IndexStats {
+        let mut stats = IndexStats { entries: self.entries.len(), pairs: self.pairs.len(), ..IndexStats::default() };
+        for (_, state) in self.entry_states() {
+            match state {
+                EntryState::Current => stats.current += 1,
+                EntryState::Modified => stats.modified += 1,
+                EntryState::Missing => stats.missing += 1,
+                EntryState::Unstamped => stats.unstamped += 1,
+            }
+        }
+        stats
+    }
+
+    /// Удаляет записи файлов, которых больше нет (`Missing`) или которые изменились
+    /// (`Modified`), вместе с их парами, и возвращает удаленные пути с причиной.
+    /// Записи без отметки остаются, пока файл есть. Файл индекса не перезаписывается,
+    /// для этого есть `save`
+    pub fn prune(&mut self) -> Vec<(PathBuf, EntryState)> {
+        let states: Vec<EntryState> = self.entry_states().into_iter().map(|(_, state)| state).collect();
+        let mut states = states.into_iter();
+        let mut removed = vec![];
+        self.entries.retain(|entry| match states.next() {
+            Some(state @ (EntryState::Missing | EntryState::Modified)) => {
+                removed.push((entry.path.clone(), state));
+                false
+            }
+            _ => true,
+        });
+        let gone: HashSet<&Path> = removed.iter().map(|(path, _)| path.as_path()).collect();
+        self.pairs.retain(|(a, b, _)| !gone.contains(a.as_path()) && !gone.contains(b.as_path()));
+        removed
+    }
+
+    /// Перезаписывает файл индекса через временный файл, как `save`. Индекс сохраняется
+    /// целиком, так что удаленные записи места в файле не занимают; перезапись переводит
+    /// файл старой версии формата в текущую и убирает оставшийся от прерванной записи
+    /// временный файл
+    pub fn compact<P: AsRef<Path>>(index_path: P) -> Result<CompactReport> {
+        let index_path = index_path.as_ref();
+        let size = |path: &Path| fs::metadata(path).map(|metadata| metadata.len()).map_err(|e| ImgAlgError::io(path, e));
+        let size_before = size(index_path)?;
+        Self::load(index_path)?.save(index_path)?;
+        Ok(CompactReport { size_before, size_after: size(index_path)? })
+    }
+
+    /// Просматривает набор файлов (обычно все изображения каталога) и находит среди них
+    /// группы дубликатов со схожестью не ниже `threshold`. Индекс после этого содержит
+    /// ровно эти файлы: записи остальных удаляются, как и файлов, которые не читаются.
+    ///
+    /// При `incremental` декодируются только новые файлы и файлы, у которых изменились
+    /// размер или время изменения (`FileStamp`), а сравниваются только пары с ними:
+    /// остальные пары берутся из прошлого просмотра. Если порог с тех пор изменился,
+    /// пары ищутся заново среди всех файлов, но сигнатуры по-прежнему берутся из индекса.
+    /// Без `incremental` все файлы декодируются и сравниваются заново. Группы в обоих
+    /// случаях одинаковы: они строятся как `ImagesComparer::duplicate_groups` по файлам,
+    /// отсортированным по пути.
+    ///
+    /// Из `options` берутся только число потоков, флаг отмены, повторы чтения, ограничение
+    /// времени декодирования (`decode_timeout`, ошибка `Timeout` у файла), преобразование
+    /// значений и вписывание в квадрат: пустой индекс их перенимает, а у непустого они должны
+    /// совпадать с индексом, иначе `InvalidOptions`. Сравнение - как у `query_file`.
+    /// После отмены возвращается `Cancelled`, а индекс остается прежним; промежуточный индекс
+    /// `set_checkpoint` при этом уже может быть записан
+    pub fn scan<P: AsRef<Path> + Sync>(&mut self, files: &[P], threshold: impl Into<ScanThreshold>, options: &ComparerOptions, incremental: bool) -> Result<ScanReport> {
+        self.scan_with(files, threshold, options, incremental, &|_| {})
+    }
+
+    /// Как `scan`, но ошибки чтения и найденные пары передаются в `on_event` по мере
+    /// получения, например чтобы выводить их, не дожидаясь конца долгого просмотра.
+    /// Ошибки приходят из потоков декодирования в произвольном порядке
+    pub fn scan_with<P: AsRef<Path> + Sync>(
+        &mut self,
+        files: &[P],
+        threshold: impl Into<ScanThreshold>,
+        options: &ComparerOptions,
+        incremental: bool,
+        on_event: &(dyn Fn(ScanEvent<'_>) + Sync),
+    ) -> Result<ScanReport> {
+        let mut files: Vec<&Path> = files.iter().map(AsRef::as_ref).collect();
+        files.sort();
+        files.dedup();
+        let mut report = ScanReport::default();
+        let pool = options.thread_pool()?;
+        // Сигнатуры индекса - в его формате: одна сетка, с преобразованием значений и вписыванием индекса
+        if self.entries.is_empty() {
+            self.transform = options.transform();
+            self.letterbox = options.is_letterbox();
+        } else if self.transform != options.transform() {
+            return Err(ImgAlgError::InvalidOptions(format!(
+                "the index holds signatures with the value transform {}, but the scan asks for {}",
+                self.transform,
+                options.transform()
+            )));
+        } else if self.letterbox != options.is_letterbox() {
+            let letterboxed = |letterbox: bool| if letterbox { "letterboxed" } else { "not letterboxed" };
+            return Err(ImgAlgError::InvalidOptions(format!(
+                "the index holds {} signatures, but the scan asks for {} ones",
+                letterboxed(self.letterbox),
+                letterboxed(options.is_letterbox())
+            )));
+        }
+        let decode_options = options.clone().multi_scale(false).ensemble(false).crop(None);
+        let owned_journal = self.journal.take();
+        let journal = owned_journal.as_ref();
+
+        // Неизмененные файлы узнаются по размеру и времени изменения
+        let known: HashMap<&Path, &Entry> = self.entries.iter().map(|entry| (entry.path.as_path(), entry)).collect();
+        let scanned: Vec<(Option<FileStamp>, Option<&Entry>)> = pool.install(|| {
+            files
+                .par_iter()
+                .map(|&path| {
+                    let stamp = FileStamp::of(path).ok();
+                    let reused = known.get(path).copied().filter(|entry| incremental && stamp.is_some() && entry.stamp == stamp && entry.info.is_some());
+                    (stamp, reused)
+                })
+                .collect()
+        });
+        let journaled: Vec<Option<(&Signature, &ImageInfo)>> = files
+            .iter()
+            .zip(&scanned)
+            .map(|(&path, (stamp, reused))| journal.filter(|_| reused.is_none()).and_then(|journal| journal.signed(path, *stamp)))
+            .collect();
+        // Сигнатуры считаются частями: после части, если пора, посчитанное сохраняется в `checkpoint`
+        let mut computed: Vec<Option<Result<(Signature, ImageInfo)>>> = Vec::with_capacity(files.len());
+        let mut last_checkpoint = Instant::now();
+        for ((chunk_files, chunk_scanned), chunk_journaled) in files.chunks(CHECKPOINT_CHUNK).zip(scanned.chunks(CHECKPOINT_CHUNK)).zip(journaled.chunks(CHECKPOINT_CHUNK)) {
+            let chunk: Vec<_> = pool.install(|| {
+                chunk_files
+                    .par_iter()
+                    .zip(chunk_scanned)
+                    .zip(chunk_journaled)
+                    .map(|((&path, (stamp, reused)), journaled)| {
+                        if reused.is_some() {
+                            on_event(ScanEvent::Signed { path, reused: true });
+                            return None;
+                        }
+                        if let Some((signature, info)) = journaled {
+                            on_event(ScanEvent::Signed { path, reused: true });
+                            return Some(Ok(((*signature).clone(), (*info).clone())));
+                        }
+                        let load = || ImagesComparer::_load_image_timed(path, &decode_options, None);
+                        let computed = options.check_cancelled().and_then(|()| ImagesComparer::_load_retrying(path, options, load));
+                        if let (Some(journal), Some(stamp), Ok((signature, info))) = (journal, stamp, &computed) {
+                            journal.record_signed(path, *stamp, signature, info);
+                        }
+                        match &computed {
+                            Ok(_) => on_event(ScanEvent::Signed { path, reused: false }),
+                            Err(ImgAlgError::Cancelled) => {}
+                            Err(e) => on_event(ScanEvent::Error(e)),
+                        }
+                        Some(computed)
+                    })
+                    .collect()
+            });
+            computed.extend(chunk);
+            if let Some(journal) = journal {
+                journal.checkpoint()?;
+            }
+            if let Some((checkpoint, interval)) = &self.checkpoint
+                && last_checkpoint.elapsed() >= *interval
+                && computed.len() < files.len()
+            {
+                self.save_checkpoint(checkpoint, &files[..computed.len()], &scanned[..computed.len()], &computed)?;
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        let mut entries = Vec::with_capacity(files.len());
+        let mut changed = vec![];
+        for (((&path, (stamp, reused)), computed), journaled) in files.iter().zip(scanned).zip(computed).zip(&journaled) {
+            let label = known.get(path).and_then(|entry| entry.label.clone());
+            let (signature, info, fingerprint) = match (reused, computed) {
+                (Some(entry), _) => {
+                    report.reused += 1;
+                    (entry.signature.clone(), entry.info.clone(), entry.fingerprint)
+                }
+                (None, Some(Ok((signature, mut info)))) => {
+                    if journaled.is_some() {
+                        report.resumed += 1;
+                    } else {
+                        report.computed += 1;
+                    }
+                    changed.push(entries.len());
+                    if incremental && known.get(path).is_some_and(|entry| entry.stamp != stamp) {
+                        report.warnings.push(Warning::StaleEntry { path: path.to_path_buf() });
+                    }
+                    report.warnings.append(&mut info.warnings);
+                    (signature, Some(info), None)
+                }
+                (None, Some(Err(ImgAlgError::Cancelled))) => return Err(ImgAlgError::Cancelled),
+                (None, Some(Err(e))) => {
+                    report.errors.push(e);
+                    continue;
+                }
+                (None, None) => unreachable!("a file is either reused or decoded"),
+            };
+            entries.push(Entry { path: path.to_path_buf(), signature, stamp, info, fingerprint, label });
+        }
+        let retried = report.warnings.iter().map(|warning| match warning {
+            Warning::Retried { retries, .. } => *retries,
+            _ => 0,
+        });
+        report.retries = retried.chain(report.errors.iter().map(ImgAlgError::retries)).sum();
+        let present: HashSet<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+        report.removed = self.entries.iter().filter(|entry| !present.contains(entry.path.as_path())).count();
+
+        // Порог по фону выводится по всем сигнатурам, в том числе взятым из индекса
+        report.threshold = match threshold.into() {
+            ScanThreshold::Fixed(threshold) => threshold,
+            ScanThreshold::Adaptive(adaptive) => {
+                let signatures: Vec<&Signature> = entries.iter().map(|entry| &entry.signature).collect();
+                let background = calibrate::background(&signatures, &ComparerOptions::default(), &adaptive.calibrate);
+                let threshold = adaptive.derive(&background).unwrap_or(SimilarityThreshold::EXACT);
+                report.background = Some(background);
+                threshold
+            }
+        };
+        let threshold = report.threshold;
+
+        // Пути к одному файлу узнаются до сравнения: схожесть такой пары - 100 без вычисления
+        let ids: Vec<Option<FileId>> = pool.install(|| entries.par_iter().map(|entry| FileId::of(&entry.path).ok()).collect());
+        let same_file = |a: usize, b: usize| ids[a].is_some() && ids[a] == ids[b];
+        let mut by_id: HashMap<&FileId, Vec<usize>> = HashMap::new();
+        for (pos, id) in ids.iter().enumerate() {
+            if let Some(id) = id {
+                by_id.entry(id).or_default().push(pos);
+            }
+        }
+        let mut same_file_pairs: Vec<(usize, usize)> = by_id.values().flat_map(|positions| positions.iter().enumerate().flat_map(|(i, &a)| positions[i + 1..].iter().map(move |&b| (a, b)))).collect();
+        same_file_pairs.sort_unstable();
+        report.same_file = same_file_pairs.iter().map(|&(a, b)| (entries[a].path.clone(), entries[b].path.clone())).collect();
+
+        // Пары между неизмененными файлами остаются с прошлого просмотра, если порог тот же
+        let positions: HashMap<&Path, usize> = entries.iter().enumerate().map(|(pos, entry)| (entry.path.as_path(), pos)).collect();
+        let mut is_changed = vec![false; entries.len()];
+        let mut pairs: Vec<Pair> = vec![];
+        if self.scan_threshold == Some(threshold) {
+            for &pos in &changed {
+                is_changed[pos] = true;
+            }
+            pairs.extend(self.pairs.iter().filter_map(|(a, b, similarity)| {
+                let (a, b) = (*positions.get(a.as_path())?, *positions.get(b.as_path())?);
+                (!is_changed[a] && !is_changed[b]).then_some((a, b, *similarity))
+            }));
+            for &(a, b, similarity) in &pairs {
+                on_event(ScanEvent::Pair { a: &entries[a].path, b: &entries[b].path, similarity, same_file: same_file(a, b) });
+            }
+        } else {
+            is_changed = vec![true; entries.len()];
+        }
+
+        // Части сравнения, сделанные до обрыва, берутся из журнала, если записи и порог те же
+        let key = comparison_key(&entries, &is_changed, threshold);
+        let done = journal.and_then(|journal| journal.compared(key));
+        if let Some(done) = done {
+            for &(a, b, similarity) in &done.pairs {
+                on_event(ScanEvent::Pair { a: &entries[a].path, b: &entries[b].path, similarity, same_file: same_file(a, b) });
+            }
+            pairs.extend(&done.pairs);
+            report.compared += done.compared;
+        }
+
+        // Новые пары: каждый измененный файл со всеми, кроме измененных перед ним
+        let rows: Vec<usize> = (0..entries.len()).filter(|&pos| is_changed[pos] && !done.is_some_and(|done| done.rows.contains(&pos))).collect();
+        for row in rows.chunks(pool.current_num_threads().max(1) * 16) {
+            options.check_cancelled()?;
+            let found: Vec<(Vec<Pair>, usize)> = pool.install(|| {
+                row.par_iter()
+                    .map(|&a| {
+                        let mut found = vec![];
+                        let mut compared = 0;
+                        for b in (0..entries.len()).filter(|&b| b != a && !(is_changed[b] && b < a)) {
+                            compared += 1;
+                            let similarity = if same_file(a, b) { 100.0 } else { signature::similarity_from_diff(entries[a].signature.raw_distance(&entries[b].signature)) };
+                            if threshold.is_met_by(similarity) {
+                                found.push((a.min(b), a.max(b), similarity));
+                            }
+                        }
+                        (found, compared)
+                    })
+                    .collect()
+            });
+            let (found_pairs, compared): (Vec<Pair>, usize) = found.into_iter().fold((vec![], 0), |(mut all, total), (found, compared)| {
+                all.extend(found);
+                (all, total + compared)
+            });
+            for &(a, b, similarity) in &found_pairs {
+                on_event(ScanEvent::Pair { a: &entries[a].path, b: &entries[b].path, similarity, same_file: same_file(a, b) });
+            }
+            if let Some(journal) = journal {
+                journal.record_compared(key, row, &found_pairs, compared);
+                journal.checkpoint()?;
+            }
+            pairs.extend(found_pairs);
+            report.compared += compared;
+        }
+        pairs.sort_by_key(|&(a, b, _)| (a, b));
+
+        // Группы по найденным парам, как `ImagesComparer::duplicate_groups`
+        let mut neighbours: Vec<Vec<(usize, f32)>> = vec![vec![]; entries.len()];
+        for &(a, b, similarity) in pairs.iter().filter(|&&(a, b, _)| self.include_same_file || !same_file(a, b)) {
+            neighbours[a].push((b, similarity));
+        }
+        let mut grouped = vec![false; entries.len()];
+        for reference in 0..entries.len() {
+            if grouped[reference] {
+                continue;
+            }
+            let mut members = vec![(reference, 100.0)];
+            for &(idx, similarity) in &neighbours[reference] {
+                if grouped[idx] {
+                    continue;
+                }
+                grouped[idx] = true;
+                // Без `include_same_file` от файла в группе остается первый путь к нему
+                if self.include_same_file || !members.iter().any(|&(other, _)| same_file(idx, other)) {
+                    members.push((idx, similarity));
+                }
+            }
+            if members.len() > 1 {
+                let group = members.iter().map(|&(idx, similarity)| {
+                    let same_file = members.iter().any(|&(other, _)| other != idx && same_file(idx, other));
+                    IndexMatch { path: entries[idx].path.clone(), similarity, same_file }
+                });
+                report.groups.push(group.collect());
+            }
+        }
+
+        self.pairs = pairs.into_iter().map(|(a, b, similarity)| (entries[a].path.clone(), entries[b].path.clone(), similarity)).collect();
+        self.entries = entries;
+        self.scan_threshold = Some(threshold);
+        if let Some(journal) = owned_journal {
+            journal.remove()?;
+        }
+        Ok(report)
+    }
+
+    /// Убирает пары `scan` с файлом, сигнатура которого изменилась или удалена
+    fn forget_pairs(&mut self, path: &Path) {
+        self.pairs.retain(|(a, b, _)| a != path && b != path);
+    }
+
+    /// Промежуточный индекс во время `scan`: прежние записи, поверх них - уже посчитанные
+    /// сигнатуры. Пар в нем нет, так что следующий просмотр найдет их заново, но сигнатуры
+    /// неизмененных файлов возьмет из индекса
+    fn save_checkpoint(&self, path: &Path, files: &[&Path], scanned: &[(Option<FileStamp>, Option<&Entry>)], computed: &[Option<Result<(Signature, ImageInfo)>>]) -> Result<()> {
+        let mut fresh: HashMap<&Path, (&Signature, Option<FileStamp>, &ImageInfo)> = HashMap::new();
+        for ((&file, (stamp, _)), computed) in files.iter().zip(scanned).zip(computed) {
+            if let Some(Ok((signature, info))) = computed {
+                fresh.insert(file, (signature, *stamp, info));
+            }
+        }
+        let mut entries: Vec<Entry> = self.entries.iter().filter(|entry| !fresh.contains_key(entry.path.as_path())).cloned().collect();
+        for entry in &self.entries {
+            if let Some((signature, stamp, info)) = fresh.remove(entry.path.as_path()) {
+                entries.push(Entry { path: entry.path.clone(), signature: signature.clone(), stamp, info: Some(info.clone()), fingerprint: None, label: entry.label.clone() });
+            }
+        }
+        for (file, (signature, stamp, info)) in fresh {
+            entries.push(Entry { path: file.to_path_buf(), signature: signature.clone(), stamp, info: Some(info.clone()), fingerprint: None, label: None });
+        }
+        let snapshot = Self { entries, transform: self.transform, letterbox: self.letterbox, command_line: self.command_line.clone(), ..Self::default() };
+        snapshot.save(path)
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.path == path)
+    }
+
+    fn query(&self, signature: &Signature, threshold: SimilarityThreshold, skip: Option<&Path>) -> Vec<IndexMatch> {
+        let mut matches = vec![];
+        for entry in &self.entries {
+            if Some(entry.path.as_path()) == skip {
+                continue; // Сам с собой не сравниваем
+            }
+            let similarity = signature::similarity_from_diff(signature.raw_distance(&entry.signature));
+            if threshold.is_met_by(similarity) {
+                matches.push(IndexMatch { path: entry.path.clone(), similarity, same_file: false });
+            }
+        }
+        // При равной схожести порядок задает путь, а не порядок добавления в индекс
+        matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.path.cmp(&b.path)));
+        matches
+    }
+}
+
+/// Ключ сравнения для журнала: пути и отметки записей по порядку, какие из них изменены,
+/// и порог. Части сравнения из журнала годятся, только если ключ тот же
+fn comparison_key(entries: &[Entry], is_changed: &[bool], threshold: SimilarityThreshold) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    feed(&threshold.value().to_bits().to_le_bytes());
+    for (entry, &changed) in entries.iter().zip(is_changed) {
+        feed(&paths::to_bytes(&entry.path));
+        let stamp = entry.stamp.unwrap_or(FileStamp { size: 0, modified: 0 });
+        feed(&[changed as u8, 0xff]);
+        feed(&stamp.size.to_le_bytes());
+        feed(&stamp.modified.to_le_bytes());
+    }
+    hash
+}
+
+/// Ошибка разбора файла индекса до привязки к его пути
+pub(crate) enum IndexReadError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl IndexReadError {
+    fn at(self, index_path: &Path) -> ImgAlgError {
+        match self {
+            Self::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => ImgAlgError::corrupt_index(index_path, "unexpected end of file"),
+            Self::Io(e) => ImgAlgError::io(index_path, e),
+            Self::Format(reason) => ImgAlgError::corrupt_index(index_path, reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for IndexReadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Сигнатура записи индекса версии `version`; с версии 4 перед сетками записано их число
+pub(crate) fn read_signature<R: Read>(reader: &mut R, version: u32, transform: Transform, letterbox: bool) -> Result<Signature, IndexReadError> {
+    if version < 4 {
+        return Ok(Signature::from_grids(vec![read_grid(reader, version, GRID_SIZE, transform)?], letterbox));
+    }
+    let grid_count = read_u32(reader)? as usize;
+    let mut grids = Vec::with_capacity(preallocation(grid_count));
+    for _ in 0..grid_count {
+        let size = read_u32(reader)?;
+        grids.push(read_grid(reader, version, size, transform)?);
+    }
+    Ok(Signature::from_grids(grids, letterbox))
+}
+
+/// Сигнатура в формате текущей версии индекса
+pub(crate) fn write_signature<W: Write>(writer: &mut W, signature: &Signature) -> std::io::Result<()> {
+    writer.write_all(&(signature.grids().len() as u32).to_le_bytes())?;
+    for grid in signature.grids() {
+        writer.write_all(&grid.size().to_le_bytes())?;
+        write_grid(writer, grid)?;
+    }
+    Ok(())
+}
+
+/// Сетка сигнатуры: ячейки, затем (с версии 2) альфа-канал и (с версии 3) насыщенность и яркость.
+/// Преобразование значений в сетке не записано, оно общее для индекса
+fn read_grid<R: Read>(reader: &mut R, version: u32, size: u32, transform: Transform) -> Result<Grid, IndexReadError> {
+    let cells_len = read_u32(reader)? as usize;
+    let mut cells = Vec::with_capacity(preallocation(cells_len));
+    for _ in 0..cells_len {
+        cells.push([read_value(reader)?, read_value(reader)?, read_value(reader)?]);
+    }
+    let mut alpha = vec![];
+    if version >= 2 {
+        let alpha_len = read_u32(reader)? as usize;
+        alpha.reserve(preallocation(alpha_len));
+        for _ in 0..alpha_len {
+            alpha.push(read_value(reader)?);
+        }
+    }
+    let mut tone = vec![];
+    if version >= 3 {
+        let tone_len = read_u32(reader)? as usize;
+        tone.reserve(preallocation(tone_len));
+        for _ in 0..tone_len {
+            tone.push([read_value(reader)?, read_value(reader)?]);
+        }
+    }
+    Ok(Grid::from_parts(size, &cells, alpha, &tone, transform))
+}
+
+/// Значение сигнатуры с проверкой диапазона
+fn read_value<R: Read>(reader: &mut R) -> Result<i32, IndexReadError> {
+    let value = read_i32(reader)?;
+    if !signature::is_valid_value(value) {
+        return Err(IndexReadError::Format("signature value out of range".to_string()));
+    }
+    Ok(value)
+}
+
+fn write_grid<W: Write>(writer: &mut W, grid: &Grid) -> std::io::Result<()> {
+    writer.write_all(&(grid.cell_count() as u32).to_le_bytes())?;
+    for value in grid.cells().flatten() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&(grid.alpha().len() as u32).to_le_bytes())?;
+    for value in grid.alpha() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&(grid.tone_count() as u32).to_le_bytes())?;
+    for value in grid.tone().flatten() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Сведения о файле: размеры, формат (расширением, пустая строка - неизвестен),
+/// размер файла и оценка качества (качество JPEG 0 - неизвестно). Индекс хранит
+/// сигнатуры целых изображений, поэтому области сравнения у них нет
+pub(crate) fn read_info<R: Read>(reader: &mut R) -> Result<ImageInfo, IndexReadError> {
+    let (width, height) = (read_u32(reader)?, read_u32(reader)?);
+    let format = image::ImageFormat::from_extension(read_string(reader)?);
+    let file_size = read_u64(reader)?;
+    let sharpness = f32::from_bits(read_u32(reader)?);
+    let jpeg_quality = Some(read_u8(reader)?).filter(|&quality| quality > 0);
+    Ok(ImageInfo { width, height, format, file_size, quality: Quality { sharpness, jpeg_quality }, crop: None, warnings: vec![] })
+}
+
+pub(crate) fn write_info<W: Write>(writer: &mut W, info: &ImageInfo) -> std::io::Result<()> {
+    writer.write_all(&info.width.to_le_bytes())?;
+    writer.write_all(&info.height.to_le_bytes())?;
+    let format = info.format_name().unwrap_or_default();
+    writer.write_all(&(format.len() as u32).to_le_bytes())?;
+    writer.write_all(format.as_bytes())?;
+    writer.write_all(&info.file_size.to_le_bytes())?;
+    writer.write_all(&info.quality.sharpness.to_bits().to_le_bytes())?;
+    writer.write_all(&[info.quality.jpeg_quality.unwrap_or(0)])
+}
+
+/// Строка UTF-8 с длиной перед ней
+pub(crate) fn read_string<R: Read>(reader: &mut R) -> Result<String, IndexReadError> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| IndexReadError::Format("corrupted string".to_string()))
+}
+
+/// Байты с длиной впереди: так записываются строки и пути (`paths::to_bytes`)
+pub(crate) fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Длина взята из файла и не проверена, поэтому байты читаются не больше, чем осталось в потоке
+pub(crate) fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, IndexReadError> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = Vec::with_capacity(preallocation(len));
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(IndexReadError::Format("length exceeds the remaining data".to_string()));
+    }
+    Ok(bytes)
+}
+
+/// Сколько элементов выделить заранее под число из файла: испорченный индекс
+/// не должен требовать гигабайты памяти до того, как кончатся данные
+fn preallocation(count: usize) -> usize {
+    count.min(MAX_PREALLOCATION)
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> std::io::Result<i32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Заголовок индекса текущей версии
+    fn header() -> Vec<u8> {
+        let mut bytes = INDEX_MAGIC.to_vec();
+        bytes.extend(INDEX_VERSION.to_le_bytes());
+        write_bytes(&mut bytes, signature::PIPELINE.as_bytes()).unwrap();
+        write_bytes(&mut bytes, b"test").unwrap();
+        bytes.extend(0u64.to_le_bytes());
+        bytes.push(0);
+        bytes
+    }
+
+    #[test]
+    fn huge_entry_count_is_a_read_error() {
+        let mut bytes = header();
+        bytes.extend(u32::MAX.to_le_bytes());
+        let result = SignatureIndex::read_entries(&mut &bytes[..]);
+        assert!(matches!(result, Err(IndexReadError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn huge_byte_length_is_a_format_error() {
+        let mut bytes = header();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend(b"short path");
+        assert!(matches!(SignatureIndex::read_entries(&mut &bytes[..]), Err(IndexReadError::Format(_))));
+    }
+
+    #[test]
+    fn huge_cell_count_is_a_read_error() {
+        let mut bytes = header();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(5u32.to_le_bytes());
+        bytes.extend(b"a.png");
+        bytes.extend(u32::MAX.to_le_bytes());
+        assert!(SignatureIndex::read_entries(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn tied_matches_are_ordered_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 0]));
+        let mut index = SignatureIndex::new();
+        for name in ["c.png", "a.png", "b.png"] {
+            let path = dir.path().join(name);
+            image.save(&path).unwrap();
+            index.check_and_insert(&path, SimilarityThreshold::DEFAULT).unwrap();
+        }
+        let query = dir.path().join("query.png");
+        image.save(&query).unwrap();
+        let matches = index.query_file(&query, SimilarityThreshold::DEFAULT).unwrap();
+        let names: Vec<_> = matches.iter().map(|m| m.path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["a.png", "b.png", "c.png"]);
+    }
+
+    /// Индекс из `tests/fixtures/index`; пути записей в нем - от корня пакета
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/index").join(name)
+    }
+
+    #[test]
+    fn future_version_is_refused() {
+        match SignatureIndex::load(fixture("future-version.idx")) {
+            Err(ImgAlgError::CorruptIndex { reason, .. }) => assert_eq!(reason, "unsupported index version 10"),
+            Err(e) => panic!("{e}"),
+            Ok(_) => panic!("a newer index must not load"),
+        }
+    }
+
+    #[test]
+    fn other_filter_is_a_mismatch_naming_the_option() {
+        match SignatureIndex::load(fixture("gaussian-filter.idx")) {
+            Err(ImgAlgError::IndexMismatch { option, stored, current, .. }) => assert_eq!((option.as_str(), stored.as_str(), current.as_str()), ("filter", "gaussian", "area")),
+            Err(e) => panic!("{e}"),
+            Ok(_) => panic!("a stale index must not load"),
+        }
+    }
+
+    #[test]
+    fn migrate_recomputes_and_keeps_entry_data() {
+        let (index, errors) = SignatureIndex::migrate(fixture("gaussian-filter.idx")).unwrap();
+        assert!(errors.is_empty());
+        let [scanned, reference] = index.entries() else { panic!("two entries expected") };
+        assert!(scanned.stamp.is_some() && scanned.info.is_some());
+        assert_eq!((scanned.fingerprint, scanned.label.as_deref()), (None, None));
+        assert_eq!(scanned.signature.similarity(&Signature::compute(&scanned.path).unwrap()).unwrap(), 100.0);
+        assert!(reference.stamp.is_none() && reference.info.is_none());
+        assert_eq!(reference.fingerprint, Some(Fingerprint::compute(&reference.path).unwrap()));
+        assert_eq!(reference.label.as_deref(), Some("blocks"));
+    }
+
+    /// Два изображения из `tests/fixtures/img_hash`
+    fn images() -> Vec<PathBuf> {
+        ["gradient.png", "diagonal.png"].map(|file| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash").join(file)).to_vec()
+    }
+
+    #[test]
+    fn value_transform_survives_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("sqrt.idx");
+        let sqrt = ComparerOptions::new().value_transform(Transform::Sqrt).unwrap();
+        let mut index = SignatureIndex::open_or_create_with(&index_path, &sqrt).unwrap();
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &sqrt, true).unwrap();
+        index.save(&index_path).unwrap();
+
+        let mut loaded = SignatureIndex::load_with(&index_path, &sqrt).unwrap();
+        assert_eq!(loaded.transform(), Transform::Sqrt);
+        let (comparer, errors) = crate::ImagesComparer::new_lossy_with(&images(), sqrt.clone());
+        assert!(errors.is_empty());
+        for entry in &loaded.entries {
+            let idx = images().iter().position(|path| *path == entry.path).unwrap();
+            assert_eq!(entry.signature.transform(), Transform::Sqrt);
+            assert_eq!(entry.signature.similarity(comparer.signature(idx).unwrap()).unwrap(), 100.0);
+        }
+        let report = loaded.scan(&images(), SimilarityThreshold::DEFAULT, &sqrt, true).unwrap();
+        assert_eq!((report.reused, report.computed), (2, 0));
+    }
+
+    #[test]
+    fn other_value_transform_is_a_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("sqrt.idx");
+        let sqrt = ComparerOptions::new().value_transform(Transform::Sqrt).unwrap();
+        let mut index = SignatureIndex::new();
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &sqrt, false).unwrap();
+        index.save(&index_path).unwrap();
+
+        match SignatureIndex::load_with(&index_path, &ComparerOptions::new()) {
+            Err(ImgAlgError::IndexMismatch { option, stored, current, .. }) => assert_eq!((option.as_str(), stored.as_str(), current.as_str()), ("values", "sqrt", "squared")),
+            Err(e) => panic!("{e}"),
+            Ok(_) => panic!("an index of sqrt signatures must not load for squared values"),
+        }
+        assert!(matches!(index.scan(&images(), SimilarityThreshold::DEFAULT, &ComparerOptions::new(), true), Err(ImgAlgError::InvalidOptions(_))));
+    }
+
+    #[test]
+    fn letterbox_survives_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("letterbox.idx");
+        let letterbox = ComparerOptions::new().letterbox(true);
+        let mut index = SignatureIndex::open_or_create_with(&index_path, &letterbox).unwrap();
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &letterbox, true).unwrap();
+        index.save(&index_path).unwrap();
+
+        let mut loaded = SignatureIndex::load_with(&index_path, &letterbox).unwrap();
+        assert!(loaded.is_letterbox());
+        let (comparer, errors) = crate::ImagesComparer::new_lossy_with(&images(), letterbox.clone());
+        assert!(errors.is_empty());
+        for entry in &loaded.entries {
+            let idx = images().iter().position(|path| *path == entry.path).unwrap();
+            assert_eq!(entry.signature.similarity(comparer.signature(idx).unwrap()).unwrap(), 100.0);
+        }
+        let report = loaded.scan(&images(), SimilarityThreshold::DEFAULT, &letterbox, true).unwrap();
+        assert_eq!((report.reused, report.computed), (2, 0));
+    }
+
+    #[test]
+    fn other_letterbox_setting_is_a_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("letterbox.idx");
+        let letterbox = ComparerOptions::new().letterbox(true);
+        let mut index = SignatureIndex::new();
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &letterbox, false).unwrap();
+        index.save(&index_path).unwrap();
+
+        match SignatureIndex::load_with(&index_path, &ComparerOptions::new()) {
+            Err(ImgAlgError::IndexMismatch { option, stored, current, .. }) => assert_eq!((option.as_str(), stored.as_str(), current.as_str()), ("filter", "area+letterbox", "area")),
+            Err(e) => panic!("{e}"),
+            Ok(_) => panic!("an index of letterboxed signatures must not load for stretched ones"),
+        }
+        assert!(matches!(index.scan(&images(), SimilarityThreshold::DEFAULT, &ComparerOptions::new(), true), Err(ImgAlgError::InvalidOptions(_))));
+    }
+
+    #[cfg(all(feature = "mmap", unix))]
+    #[test]
+    fn mapped_index_loads_like_a_buffered_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("photos.idx");
+        let mut index = SignatureIndex::new();
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &ComparerOptions::new(), false).unwrap();
+        index.save(&index_path).unwrap();
+
+        let mapped = SignatureIndex::load(&index_path).unwrap();
+        let Ok((buffered, _)) = SignatureIndex::read_entries(&mut &fs::read(&index_path).unwrap()[..]) else { panic!("the index must read from a buffer") };
+        let describe = |index: &SignatureIndex| index.entries.iter().map(|entry| (entry.path.clone(), entry.signature.to_string(), entry.stamp, entry.info.clone())).collect::<Vec<_>>();
+        assert_eq!(describe(&mapped), describe(&buffered));
+    }
+
+    #[test]
+    fn prune_removes_only_the_deleted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let copies: Vec<PathBuf> = images().iter().map(|path| dir.path().join(path.file_name().unwrap())).collect();
+        for (path, copy) in images().iter().zip(&copies) {
+            fs::copy(path, copy).unwrap();
+        }
+        let mut index = SignatureIndex::new();
+        index.scan(&copies, SimilarityThreshold::DEFAULT, &ComparerOptions::new(), false).unwrap();
+        fs::remove_file(&copies[0]).unwrap();
+        assert_eq!(index.stats(), IndexStats { entries: 2, current: 1, missing: 1, ..IndexStats::default() });
+        assert_eq!(index.prune(), [(copies[0].clone(), EntryState::Missing)]);
+        assert_eq!(index.len(), 1);
+        assert!(index.prune().is_empty());
+    }
+
+    #[test]
+    fn long_scan_leaves_a_checkpoint_with_the_first_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..CHECKPOINT_CHUNK as u32 + 4)
+            .map(|i| {
+                let path = dir.path().join(format!("{i}.png"));
+                image::RgbaImage::from_fn(8, 8, |x, y| image::Rgba([(i % 256) as u8, (x * 30) as u8, (y * 30) as u8, 255])).save(&path).unwrap();
+                path
+            })
+            .collect();
+        let checkpoint = dir.path().join("checkpoint.idx");
+        let sqrt = ComparerOptions::new().value_transform(Transform::Sqrt).unwrap();
+        let mut index = SignatureIndex::new();
+        index.set_checkpoint(Some(checkpoint.clone()), Duration::ZERO);
+        index.scan(&files, SimilarityThreshold::DEFAULT, &sqrt, false).unwrap();
+
+        // Промежуточный индекс записан после первой части и открывается с теми же настройками
+        let saved = SignatureIndex::load_with(&checkpoint, &sqrt).unwrap();
+        assert_eq!(saved.len(), CHECKPOINT_CHUNK);
+        assert_eq!(saved.transform(), Transform::Sqrt);
+    }
+
+    #[test]
+    fn loaded_index_surfaces_its_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("photos.idx");
+        let sqrt = ComparerOptions::new().value_transform(Transform::Sqrt).unwrap();
+        let mut index = SignatureIndex::new();
+        index.set_command_line(["imgalg", "scan", "photos"].map(OsString::from));
+        index.scan(&images(), SimilarityThreshold::DEFAULT, &sqrt, false).unwrap();
+        index.save(&index_path).unwrap();
+
+        let provenance = SignatureIndex::read_provenance(&index_path).unwrap();
+        assert_eq!(SignatureIndex::load(&index_path).unwrap().provenance(), Some(&provenance));
+        assert_eq!((provenance.version.as_deref(), provenance.format_version), (Some(env!("CARGO_PKG_VERSION")), INDEX_VERSION));
+        assert_eq!(provenance.pipeline, signature::pipeline(Transform::Sqrt, false));
+        assert_eq!(provenance.setting("values"), Some("sqrt"));
+        assert!(provenance.created.is_some());
+        assert_eq!(provenance.command_line, Some(["imgalg", "scan", "photos"].map(OsString::from).to_vec()));
+    }
+}
@@ -0,0 +1,257 @@
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::cmp::Ordering;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::mapping::{self, FileBytes};
+use crate::{icon, ImgAlgError, Rect, Result, Warning};
+
+/// Сторона полутоновой копии, на которой оценивается резкость
+const SHARPNESS_SIZE: u32 = 512;
+/// Оценки качества, различающиеся меньше чем на эту долю, считаются равными
+const QUALITY_TOLERANCE: f32 = 0.05;
+/// Во сколько раз одна сторона изображения может быть длиннее другой. У более вытянутых
+/// (полоса 1x2000) ячейки сетки по короткой стороне повторяют одни и те же пиксели,
+/// и сигнатура почти не зависит от содержимого
+pub const MAX_ASPECT_RATIO: u32 = 50;
+
+/// Сведения о файле изображения, собранные при загрузке
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Формат, определенный по содержимому файла
+    pub format: Option<ImageFormat>,
+    /// Размер файла в байтах
+    pub file_size: u64,
+    pub quality: Quality,
+    /// Область, по которой посчитана сигнатура; размеры выше - всего изображения
+    pub crop: Option<Rect>,
+    /// Что при загрузке пошло не так, но не помешало ее закончить. В индекс не записываются
+    pub warnings: Vec<Warning>,
+}
+
+/// Оценка качества, чтобы выбрать лучшую из копий одного изображения.
+///
+/// Это относительная эвристика, а не мера качества: значения имеет смысл сравнивать
+/// только у копий одного и того же изображения
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quality {
+    /// Дисперсия лапласиана полутоновой копии со стороной не больше 512: размытые
+    /// и растянутые копии дают меньшие значения
+    pub sharpness: f32,
+    /// Качество JPEG от 1 до 100, оцененное по таблице квантования яркости;
+    /// у других форматов `None`
+    pub jpeg_quality: Option<u8>,
+}
+
+impl Quality {
+    /// Резкость с поправкой на сжатие JPEG: блоки сильного сжатия сами по себе
+    /// добавляют перепадов, поэтому резкость умножается на долю качества
+    pub fn score(&self) -> f32 {
+        match self.jpeg_quality {
+            Some(quality) => self.sharpness * quality as f32 / 100.0,
+            None => self.sharpness,
+        }
+    }
+}
+
+impl ImageInfo {
+    /// Основное расширение формата (`png`, `jpg`, ...), если формат известен
+    pub fn format_name(&self) -> Option<&'static str> {
+        self.format.and_then(|format| format.extensions_str().first().copied())
+    }
+
+    /// Вытянуто ли изображение больше чем в `MAX_ASPECT_RATIO` раз, см. `Warning::ExtremeAspect`
+    pub fn is_extreme_aspect(&self) -> bool {
+        is_extreme_aspect(self.width, self.height)
+    }
+
+    /// Какая из копий лучше: `Greater`, если эта. Сравнивается `Quality::score`,
+    /// оценки ближе 5% друг к другу считаются равными, и тогда решает число пикселей
+    pub fn cmp_quality(&self, other: &Self) -> Ordering {
+        let (score, other_score) = (self.quality.score(), other.quality.score());
+        let pixels = |info: &Self| info.width as u64 * info.height as u64;
+        if (score - other_score).abs() <= QUALITY_TOLERANCE * score.max(other_score) {
+            pixels(self).cmp(&pixels(other))
+        } else {
+            score.total_cmp(&other_score)
+        }
+    }
+}
+
+/// Читает файл один раз и декодирует его, попутно собирая `ImageInfo`
+pub(crate) fn open(image_path: &Path) -> Result<(DynamicImage, ImageInfo)> {
+    let ((image, mut info), reread) = mapping::with_bytes(image_path, read(image_path)?, |bytes| decode(image_path, bytes))?;
+    if reread {
+        info.warnings.push(Warning::FileChanged { path: image_path.to_path_buf() });
+    }
+    Ok((image, info))
+}
+
+/// Декодирует файл без сбора сведений
+pub(crate) fn open_image(image_path: &Path) -> Result<DynamicImage> {
+    mapping::with_bytes(image_path, read(image_path)?, |bytes| {
+        let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().map_err(|e| ImgAlgError::io(image_path, e))?;
+        let image = reader.decode().map_err(|e| ImgAlgError::open(image_path, e))?;
+        check_size(Some(image_path), image.width(), image.height())?;
+        Ok(image)
+    })
+    .map(|(image, _)| image)
+}
+
+/// Содержимое файла. С функцией `zip` путь вида `архив.zip!/имя` читается из архива.
+/// Из значка ICO остается одно изображение: размера из пути `app.ico#256` или самое большое.
+/// Обычный файл с функцией `mmap` отображается в память, см. `mapping`
+fn read(image_path: &Path) -> Result<FileBytes> {
+    let (path, icon_size) = match icon::split(image_path) {
+        Some((icon, size)) => (icon, Some(size)),
+        None => (image_path.to_path_buf(), None),
+    };
+    #[cfg(feature = "zip")]
+    if let Some((archive, name)) = crate::archive::split(&path) {
+        let bytes = crate::archive::read_member(image_path, &archive, &name)?;
+        return icon::select(image_path, bytes, icon_size).map(FileBytes::Buffered);
+    }
+    let bytes = mapping::read(&path).map_err(|e| ImgAlgError::io(image_path, e))?;
+    if icon_size.is_none() && !icon::is_icon_data(&bytes) {
+        return Ok(bytes);
+    }
+    icon::select(image_path, bytes.into_vec(), icon_size).map(FileBytes::Buffered)
+}
+
+/// Декодирует уже прочитанное в память содержимое файла
+pub(crate) fn decode(image_path: &Path, bytes: &[u8]) -> Result<(DynamicImage, ImageInfo)> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| ImgAlgError::io(image_path, e))?;
+    let format = reader.format();
+    let image = reader.decode().map_err(|e| ImgAlgError::open(image_path, e))?;
+    check_size(Some(image_path), image.width(), image.height())?;
+    let jpeg_quality = (format == Some(ImageFormat::Jpeg)).then(|| jpeg_quality(bytes)).flatten();
+    let quality = Quality { sharpness: sharpness(&image), jpeg_quality };
+    let mut info = ImageInfo { width: image.width(), height: image.height(), format, file_size: bytes.len() as u64, quality, crop: None, warnings: vec![] };
+    if info.is_extreme_aspect() {
+        info.warnings.push(Warning::ExtremeAspect { path: image_path.to_path_buf(), width: info.width, height: info.height });
+    }
+    Ok((image, info))
+}
+
+/// Изображение без пикселей - ошибка `EmptyImage`: некоторые форматы допускают пустые кадры,
+/// а его сигнатура была бы одинаковой для любого содержимого
+pub(crate) fn check_size(path: Option<&Path>, width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(ImgAlgError::EmptyImage { path: path.map(Path::to_path_buf), width, height });
+    }
+    Ok(())
+}
+
+pub(crate) fn is_extreme_aspect(width: u32, height: u32) -> bool {
+    width.max(height) as u64 > MAX_ASPECT_RATIO as u64 * width.min(height) as u64
+}
+
+/// Сведения о буфере пикселей RGBA8: формата и файла нет, размер - длина буфера
+pub(crate) fn raw_rgba(buf: &[u8], width: u32, height: u32) -> ImageInfo {
+    let gray = gray_thumbnail(buf, 4, width as usize, height as usize);
+    let quality = Quality { sharpness: laplacian_variance(gray), jpeg_quality: None };
+    ImageInfo { width, height, format: None, file_size: buf.len() as u64, quality, crop: None, warnings: vec![] }
+}
+
+/// Дисперсия лапласиана `[0 1 0; 1 -4 1; 0 1 0]` по внутренним пикселям полутоновой копии.
+/// Изображения с 8 битами на канал читаются напрямую, остальные сначала переводятся в `L8`
+fn sharpness(image: &DynamicImage) -> f32 {
+    let converted;
+    let color = image.color();
+    let (bytes, channels) = if color.bytes_per_pixel() == color.channel_count() {
+        (image.as_bytes(), color.channel_count() as usize)
+    } else {
+        converted = image.to_luma8();
+        (converted.as_raw().as_slice(), 1)
+    };
+    laplacian_variance(gray_thumbnail(bytes, channels, image.width() as usize, image.height() as usize))
+}
+
+fn laplacian_variance((gray, width, height): (Vec<f64>, usize, usize)) -> f32 {
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let value = |x: usize, y: usize| gray[y * width + x];
+    let (mut sum, mut sum_squares) = (0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = value(x - 1, y) + value(x + 1, y) + value(x, y - 1) + value(x, y + 1) - 4.0 * value(x, y);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    (sum_squares / count - mean * mean) as f32
+}
+
+/// Яркость, усредненная по квадратам `step`x`step` пикселей, где `step` - наименьший,
+/// при котором сторона не больше `SHARPNESS_SIZE`, по пикселям `bytes` с `channels`
+/// 8-битными каналами. Прозрачность не учитывается
+fn gray_thumbnail(bytes: &[u8], channels: usize, width: usize, height: usize) -> (Vec<f64>, usize, usize) {
+    let step = width.max(height).div_ceil(SHARPNESS_SIZE as usize).max(1);
+    let (columns, rows) = (width.div_ceil(step), height.div_ceil(step));
+    let mut sums = vec![0u64; columns * rows];
+    let mut counts = vec![0u32; columns * rows];
+    for (y, line) in bytes.chunks_exact((width * channels).max(1)).enumerate() {
+        let row = y / step * columns;
+        for (column, block) in line.chunks(step * channels).enumerate() {
+            let luma: u32 = match channels {
+                1 | 2 => block.chunks_exact(channels).map(|pixel| pixel[0] as u32).sum(),
+                _ => block.chunks_exact(channels).map(|pixel| (77 * pixel[0] as u32 + 150 * pixel[1] as u32 + 29 * pixel[2] as u32) >> 8).sum(),
+            };
+            sums[row + column] += luma as u64;
+            counts[row + column] += (block.len() / channels) as u32;
+        }
+    }
+    let gray = sums.iter().zip(&counts).map(|(&sum, &count)| if count > 0 { sum as f64 / count as f64 } else { 0.0 }).collect();
+    (gray, columns, rows)
+}
+
+/// Стандартная таблица квантования яркости JPEG (приложение K), в порядке зигзага,
+/// как она записывается в файл
+const STANDARD_LUMINANCE: [u16; 64] = [
+    16, 11, 12, 14, 12, 10, 16, 14, 13, 14, 18, 17, 16, 19, 24, 40, 26, 24, 22, 22, 24, 49, 35, 37, 29, 40, 58, 51, 61, 60, 57, 51, 56, 55,
+    64, 72, 92, 78, 64, 68, 87, 69, 55, 56, 80, 109, 81, 87, 95, 98, 103, 104, 103, 62, 77, 113, 121, 112, 100, 120, 92, 101, 103, 99,
+];
+
+/// Качество JPEG по таблице квантования яркости (таблица 0 в сегменте DQT).
+/// Кодировщики вроде libjpeg умножают стандартную таблицу на `S / 100`, где `S = 5000 / q`
+/// при `q < 50` и `S = 200 - 2q` иначе; по среднему отношению таблиц восстанавливается `q`.
+/// У файлов с другими таблицами оценка приблизительна
+fn jpeg_quality(bytes: &[u8]) -> Option<u8> {
+    let mut pos = 2; // После SOI
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xDB {
+            let mut table = segment;
+            while let Some((&header, rest)) = table.split_first() {
+                let (precision, id) = (header >> 4, header & 0x0F);
+                let size = if precision == 0 { 64 } else { 128 };
+                let values = rest.get(..size)?;
+                if id == 0 {
+                    let scale: f64 = (0..64)
+                        .map(|i| if precision == 0 { values[i] as f64 } else { u16::from_be_bytes([values[2 * i], values[2 * i + 1]]) as f64 })
+                        .zip(STANDARD_LUMINANCE)
+                        .map(|(value, standard)| value * 100.0 / standard as f64)
+                        .sum::<f64>()
+                        / 64.0;
+                    let quality = if scale <= 100.0 { (200.0 - scale) / 2.0 } else { 5000.0 / scale };
+                    return Some(quality.round().clamp(1.0, 100.0) as u8);
+                }
+                table = &rest[size..];
+            }
+        }
+        if marker == 0xDA {
+            return None; // Начались данные, таблиц яркости не было
+        }
+        pos += 2 + len;
+    }
+    None
+}
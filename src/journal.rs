@@ -0,0 +1,255 @@
+//! Журнал долгого просмотра (`SignatureIndex::set_journal`): после каждого файла в него
+//! дописывается запись с сигнатурой, после каждой части сравнения - найденные пары, так что
+//! просмотр, оборванный отключением питания или нехваткой памяти, можно продолжить с того же
+//! места, запустив его еще раз с тем же журналом.
+//!
+//! Журнал - заголовок и записи подряд. Запись - длина, вид, содержимое и контрольная сумма:
+//! недописанная последняя запись при открытии отбрасывается. На диск журнал сбрасывается
+//! (`fsync`) после каждой части файлов и каждой части сравнения
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::index::{self, FileStamp, IndexReadError};
+use crate::signature::{self, Signature};
+use crate::{paths, ComparerOptions, ImageInfo, ImgAlgError, Result, Transform};
+
+/// Сигнатура файла журнала
+const JOURNAL_MAGIC: &[u8; 8] = b"IMGALGJL";
+const JOURNAL_VERSION: u32 = 1;
+/// Виды записей: сигнатура файла и часть сравнения
+const RECORD_SIGNED: u8 = 1;
+const RECORD_COMPARED: u8 = 2;
+
+/// Журнал просмотра одного каталога с одними настройками
+pub struct ScanJournal {
+    path: PathBuf,
+    writer: Mutex<JournalWriter>,
+    /// Сигнатуры из журнала и отметки файлов, по которым они посчитаны
+    signed: HashMap<PathBuf, (FileStamp, Signature, ImageInfo)>,
+    /// Сделанное сравнение по ключу `index::comparison_key`
+    compared: HashMap<u64, Compared>,
+    /// Преобразование значений и вписывание сигнатур журнала, из настроек просмотра
+    transform: Transform,
+    letterbox: bool,
+}
+
+struct JournalWriter {
+    file: BufWriter<File>,
+    /// Первая ошибка записи из потоков декодирования; сообщается на ближайшем `checkpoint`
+    error: Option<std::io::Error>,
+}
+
+/// Сравненные строки (позиции измененных записей) и найденные в них пары
+#[derive(Debug, Default)]
+pub(crate) struct Compared {
+    pub(crate) rows: HashSet<usize>,
+    pub(crate) pairs: Vec<(usize, usize, f32)>,
+    pub(crate) compared: usize,
+}
+
+impl ScanJournal {
+    /// Открывает журнал или создает новый. `root` - каталог просмотра, `settings` - описание
+    /// настроек вызывающего, от которых зависит результат (порог, исключения...), `options` -
+    /// настройки, с которыми будет просмотр; из них важны преобразование значений и вписывание. Журнал,
+    /// записанный для другого каталога, с другими настройками или другим вычислением сигнатур, -
+    /// ошибка `Journal`: его нужно удалить или указать другой файл
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(path: P, root: Q, settings: &str, options: &ComparerOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let root = fs::canonicalize(root.as_ref()).unwrap_or_else(|_| root.as_ref().to_path_buf());
+        let writer = Mutex::new(JournalWriter::open(path)?);
+        let mut journal = Self { path: path.to_path_buf(), writer, signed: HashMap::new(), compared: HashMap::new(), transform: options.transform(), letterbox: options.is_letterbox() };
+        let bytes = fs::read(path).map_err(|e| ImgAlgError::io(path, e))?;
+        if bytes.is_empty() {
+            journal.write_header(&root, settings)?;
+            return Ok(journal);
+        }
+        let reader = &mut &bytes[..];
+        let header = read_header(reader).map_err(|_| journal_error(path, "the file is not a scan journal".to_string()))?;
+        for (what, stored, current) in [("signatures computed as", header.pipeline, signature::pipeline(journal.transform, journal.letterbox)), ("directory", header.root, paths::to_bytes(&root).escape_ascii().to_string()), ("scan options", header.settings, settings.to_string())] {
+            if stored != current {
+                return Err(journal_error(path, format!("it was written for {what} '{stored}', but this scan uses '{current}'; delete it or use another journal")));
+            }
+        }
+        // Недописанная запись в конце отбрасывается, новые записи идут после последней целой
+        let mut valid = bytes.len() - reader.len();
+        while let Some((kind, body)) = read_record(reader) {
+            journal.apply(kind, body).map_err(|_| journal_error(path, "a record is corrupted".to_string()))?;
+            valid = bytes.len() - reader.len();
+        }
+        if valid < bytes.len() {
+            let writer = journal.writer.get_mut().expect("the journal lock is not shared yet");
+            writer.file.get_ref().set_len(valid as u64).map_err(|e| ImgAlgError::io(path, e))?;
+        }
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Сколько сигнатур можно взять из журнала
+    pub fn len(&self) -> usize {
+        self.signed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signed.is_empty()
+    }
+
+    /// Удаляет файл журнала: просмотр закончен, продолжать нечего
+    pub fn remove(self) -> Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.path).map_err(|e| ImgAlgError::io(&self.path, e))
+    }
+
+    /// Сигнатура файла из журнала, если с тех пор у файла те же размер и время изменения
+    pub(crate) fn signed(&self, path: &Path, stamp: Option<FileStamp>) -> Option<(&Signature, &ImageInfo)> {
+        let (stored, signature, info) = self.signed.get(path)?;
+        (Some(*stored) == stamp).then_some((signature, info))
+    }
+
+    /// Сравнение, уже сделанное для записей с ключом `key`
+    pub(crate) fn compared(&self, key: u64) -> Option<&Compared> {
+        self.compared.get(&key)
+    }
+
+    /// Дописывает сигнатуру файла; вызывается из потоков декодирования
+    pub(crate) fn record_signed(&self, path: &Path, stamp: FileStamp, signature: &Signature, info: &ImageInfo) {
+        let mut body = vec![];
+        let _ = index::write_bytes(&mut body, &paths::to_bytes(path)); // Запись в `Vec` не ошибается
+        body.extend(stamp.size.to_le_bytes().into_iter().chain(stamp.modified.to_le_bytes()));
+        let _ = index::write_info(&mut body, info);
+        let _ = index::write_signature(&mut body, signature);
+        self.append(RECORD_SIGNED, &body);
+    }
+
+    /// Дописывает часть сравнения: строки `rows`, найденные в них пары и число сравненных пар
+    pub(crate) fn record_compared(&self, key: u64, rows: &[usize], pairs: &[(usize, usize, f32)], compared: usize) {
+        let mut body = key.to_le_bytes().to_vec();
+        body.extend((compared as u64).to_le_bytes());
+        body.extend((rows.len() as u32).to_le_bytes());
+        body.extend(rows.iter().flat_map(|&row| (row as u32).to_le_bytes()));
+        body.extend((pairs.len() as u32).to_le_bytes());
+        for &(a, b, similarity) in pairs {
+            body.extend((a as u32).to_le_bytes().into_iter().chain((b as u32).to_le_bytes()).chain(similarity.to_bits().to_le_bytes()));
+        }
+        self.append(RECORD_COMPARED, &body);
+    }
+
+    /// Сбрасывает дописанное на диск
+    pub(crate) fn checkpoint(&self) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let result = match writer.error.take() {
+            Some(e) => Err(e),
+            None => writer.file.flush().and_then(|()| writer.file.get_ref().sync_data()),
+        };
+        result.map_err(|e| ImgAlgError::io(&self.path, e))
+    }
+
+    fn append(&self, kind: u8, body: &[u8]) {
+        let mut record = Vec::with_capacity(body.len() + 9);
+        record.extend((body.len() as u32 + 1).to_le_bytes());
+        record.push(kind);
+        record.extend_from_slice(body);
+        record.extend(checksum(kind, body).to_le_bytes());
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if writer.error.is_none()
+            && let Err(e) = writer.file.write_all(&record)
+        {
+            writer.error = Some(e);
+        }
+    }
+
+    fn write_header(&self, root: &Path, settings: &str) -> Result<()> {
+        let mut header = JOURNAL_MAGIC.to_vec();
+        header.extend(JOURNAL_VERSION.to_le_bytes());
+        for field in [signature::pipeline(self.transform, self.letterbox).as_bytes(), &paths::to_bytes(root), settings.as_bytes()] {
+            let _ = index::write_bytes(&mut header, field);
+        }
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        writer.file.write_all(&header).map_err(|e| ImgAlgError::io(&self.path, e))?;
+        drop(writer);
+        self.checkpoint()
+    }
+
+    fn apply(&mut self, kind: u8, mut body: &[u8]) -> Result<(), IndexReadError> {
+        let reader = &mut body;
+        match kind {
+            RECORD_SIGNED => {
+                let path = paths::from_bytes(index::read_bytes(reader)?);
+                let stamp = FileStamp { size: index::read_u64(reader)?, modified: index::read_u64(reader)? };
+                let info = index::read_info(reader)?;
+                let signature = index::read_signature(reader, index::INDEX_VERSION, self.transform, self.letterbox)?;
+                self.signed.insert(path, (stamp, signature, info));
+            }
+            RECORD_COMPARED => {
+                let key = index::read_u64(reader)?;
+                let compared = self.compared.entry(key).or_default();
+                compared.compared += index::read_u64(reader)? as usize;
+                for _ in 0..index::read_u32(reader)? {
+                    compared.rows.insert(index::read_u32(reader)? as usize);
+                }
+                for _ in 0..index::read_u32(reader)? {
+                    let (a, b) = (index::read_u32(reader)? as usize, index::read_u32(reader)? as usize);
+                    compared.pairs.push((a, b, f32::from_bits(index::read_u32(reader)?)));
+                }
+            }
+            _ => return Err(IndexReadError::Format(format!("unknown journal record {kind}"))),
+        }
+        Ok(())
+    }
+}
+
+impl JournalWriter {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| ImgAlgError::io(path, e))?;
+        Ok(Self { file: BufWriter::new(file), error: None })
+    }
+}
+
+struct Header {
+    pipeline: String,
+    root: String,
+    settings: String,
+}
+
+fn read_header(reader: &mut &[u8]) -> Result<Header, IndexReadError> {
+    let mut magic = [0u8; 8];
+    std::io::Read::read_exact(reader, &mut magic)?;
+    if &magic != JOURNAL_MAGIC || index::read_u32(reader)? != JOURNAL_VERSION {
+        return Err(IndexReadError::Format("not a scan journal".to_string()));
+    }
+    let pipeline = index::read_string(reader)?;
+    let root = index::read_bytes(reader)?.escape_ascii().to_string();
+    Ok(Header { pipeline, root, settings: index::read_string(reader)? })
+}
+
+/// Следующая целая запись: вид и содержимое. `None` - конец файла или недописанная запись
+fn read_record<'a>(reader: &mut &'a [u8]) -> Option<(u8, &'a [u8])> {
+    let len = u32::from_le_bytes(reader.get(..4)?.try_into().ok()?) as usize;
+    let record = reader.get(4..4 + len.checked_add(4)?)?;
+    let (&kind, rest) = record[..len].split_first()?;
+    let stored = u32::from_le_bytes(record[len..].try_into().ok()?);
+    if stored != checksum(kind, rest) {
+        return None;
+    }
+    *reader = &reader[4 + len + 4..];
+    Some((kind, rest))
+}
+
+/// FNV-1a вида и содержимого записи
+fn checksum(kind: u8, body: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for &byte in std::iter::once(&kind).chain(body) {
+        hash = (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn journal_error(path: &Path, reason: String) -> ImgAlgError {
+    ImgAlgError::Journal { path: path.to_path_buf(), reason }
+}
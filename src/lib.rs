@@ -0,0 +1,671 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use std::sync::{mpsc, Arc};
+
+pub mod animation;
+pub mod archive;
+pub mod bench;
+pub mod calibrate;
+mod bounded;
+mod cancel;
+mod copy_kind;
+mod crop;
+mod distance;
+mod downscale;
+mod error;
+mod explain;
+mod file_id;
+mod fingerprint;
+pub mod graph;
+pub mod icon;
+pub mod image;
+mod info;
+pub mod journal;
+mod mapping;
+mod matching;
+mod options;
+pub mod provenance;
+pub mod paths;
+pub mod prelude;
+pub mod index;
+#[cfg(feature = "async")]
+mod load_async;
+pub mod set;
+mod signature;
+mod threshold;
+mod tolerance;
+mod transform;
+mod verdict;
+mod warning;
+#[cfg(feature = "video")]
+pub mod video;
+
+pub use bounded::BoundedComparer;
+pub use cancel::CancelToken;
+pub use copy_kind::CopyKind;
+pub use crop::Rect;
+pub use error::{ImgAlgError, Result};
+pub use explain::CellContribution;
+pub use file_id::FileId;
+pub use fingerprint::{dedup_exact, Fingerprint, FingerprintMode};
+pub use info::{ImageInfo, Quality, MAX_ASPECT_RATIO};
+pub use matching::{match_sets, Assignment, SetMatch, SetMatching};
+pub use options::{Channel, ChannelSelect, ComparerOptions};
+pub use provenance::{algorithm_id, Provenance};
+pub use signature::{compare_signatures, Signature};
+pub use threshold::SimilarityThreshold;
+pub use tolerance::ToleranceMap;
+pub use transform::Transform;
+pub use verdict::{Cutoffs, HammingCutoffs, Verdict};
+pub use warning::Warning;
+
+/// Результат сравнения двух загруженных изображений
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairResult {
+    /// Индексы изображений в порядке загрузки, `a < b`
+    pub a: usize,
+    pub b: usize,
+    /// Накопленная разница сигнатур (с ансамблем - основных, с усреднением по площади)
+    pub distance: f64,
+    /// Процент схожести; с `ComparerOptions::ensemble` - среднее по фильтрам уменьшения
+    pub similarity: f32,
+    /// Разброс схожести по фильтрам `ComparerOptions::ensemble`: половина разности наибольшей
+    /// и наименьшей, сами они - `range`. Без ансамбля 0
+    pub spread: f32,
+    /// Каким вычислением получены числа, `algorithm_id`: сравнивать между собой можно
+    /// только результаты с одинаковым идентификатором
+    pub algorithm: Arc<str>,
+    range: (f32, f32),
+}
+
+impl PairResult {
+    /// Наименьшая и наибольшая схожесть по фильтрам ансамбля; без ансамбля обе - `similarity`.
+    /// Среднее `similarity` лежит между ними, но не обязательно посередине
+    pub fn range(&self) -> (f32, f32) {
+        self.range
+    }
+
+    /// Неуверенная пара: наибольшая схожесть по фильтрам проходит порог, а наименьшая нет,
+    /// так что решение зависит от фильтра уменьшения. Такие пары стоит проверять первыми
+    pub fn is_uncertain(&self, threshold: SimilarityThreshold) -> bool {
+        let (min, max) = self.range();
+        threshold.is_met_by(max) && !threshold.is_met_by(min)
+    }
+}
+
+/// Схожесть пары на одной сетке многомасштабной сигнатуры
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaleScore {
+    /// Сторона сетки: 8, 16 или 32
+    pub grid: u32,
+    /// Разница, приведенная к единицам сетки 16x16
+    pub distance: f64,
+    pub similarity: f32,
+}
+
+/// Загруженные сигнатуры и настройки их сравнения.
+///
+/// После загрузки сигнатуры не меняются, поэтому сравнитель можно разделять между потоками
+/// (`Send + Sync`), а `clone` дешев: сигнатуры лежат в общем `Arc`, копируются только настройки
+#[derive(Debug, Clone)]
+pub struct ImagesComparer {
+    /// Сравнивать только с первым изображением, а не все пары между собой
+    pub compare_with_first: bool,
+    /// Границы оценок для `verdict`
+    pub cutoffs: Cutoffs,
+    /// Настройки вычисления разницы. Многомасштабность задается только при загрузке
+    /// (`new_lossy_with`), ее изменение здесь на уже загруженные сигнатуры не влияет
+    pub options: ComparerOptions,
+    images: Arc<Vec<(Signature, ImageInfo)>>,
+    decoded: usize,
+}
+
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ImagesComparer>();
+};
+
+impl ImagesComparer {
+    pub fn new(images: &[&String]) -> Result<Self> {
+        let mut imgs = vec![];
+        for img in images.iter().copied() {
+            let diff_pixels = Self::_get_pixels_diff(img, &[signature::GRID_SIZE], None, Transform::Square, false, false)?;
+            imgs.push(diff_pixels);
+        }
+        let decoded = imgs.len();
+        Ok(Self { compare_with_first: false, cutoffs: Cutoffs::default(), options: ComparerOptions::default(), images: Arc::new(imgs), decoded })
+    }
+
+    /// Загружает все изображения, которые удалось прочитать, и возвращает ошибки по остальным.
+    /// Порядок загруженных изображений совпадает с порядком путей
+    pub fn new_lossy<P: AsRef<Path> + Sync>(images: &[P]) -> (Self, Vec<ImgAlgError>) {
+        Self::new_lossy_with(images, ComparerOptions::default())
+    }
+
+    /// То же, что `new_lossy`, но с настройками сравнения, в том числе влияющими на загрузку.
+    /// Файлы декодируются параллельно на `ComparerOptions::threads` потоках.
+    ///
+    /// Повторы одного файла, в том числе через символические ссылки, декодируются один раз:
+    /// сигнатура копируется для каждого упоминания. Файлы, которые не удалось загрузить,
+    /// при повторе пробуются снова.
+    ///
+    /// После отмены через `ComparerOptions::cancel_token` остальные файлы не загружаются:
+    /// последней ошибкой будет `Cancelled`, а сравнитель содержит загруженные до отмены
+    pub fn new_lossy_with<P: AsRef<Path> + Sync>(images: &[P], options: ComparerOptions) -> (Self, Vec<ImgAlgError>) {
+        let load = |path: &P| options.check_cancelled().and_then(|()| Self::_load_image(path, &options));
+        let keys: Vec<Option<PathBuf>> = images.iter().map(|path| std::fs::canonicalize(path).ok()).collect();
+
+        // Первые упоминания файлов декодируются параллельно, повторы разбираются ниже по порядку
+        let mut firsts: HashMap<&PathBuf, usize> = HashMap::new();
+        let first: Vec<bool> =
+            keys.iter().enumerate().map(|(pos, key)| key.as_ref().is_none_or(|key| *firsts.entry(key).or_insert(pos) == pos)).collect();
+        let mut decodes: Vec<Option<Result<(Signature, ImageInfo)>>> = match options.thread_pool() {
+            Ok(pool) => pool.install(|| images.par_iter().zip(&first).map(|(path, &first)| first.then(|| load(path))).collect()),
+            Err(e) => first.iter().map(|&first| first.then(|| Err(ImgAlgError::ThreadPool(pool_failure(&e))))).collect(),
+        };
+
+        let mut results: Vec<Result<(Signature, ImageInfo)>> = Vec::with_capacity(images.len());
+        let mut loaded: HashMap<PathBuf, usize> = HashMap::new();
+        let mut decoded = 0;
+        for ((path, key), decode) in images.iter().zip(keys).zip(decodes.iter_mut()) {
+            let cached = key.as_ref().and_then(|key| loaded.get(key)).and_then(|&pos| results[pos].as_ref().ok().cloned());
+            let copied = cached.is_some();
+            let result = match cached {
+                Some(image) => options.check_cancelled().map(|()| image),
+                // Повтор файла, который не загрузился в первый раз, пробуется снова
+                None => decode.take().unwrap_or_else(|| load(path)),
+            };
+            if let Err(ImgAlgError::Cancelled) = result {
+                results.push(result);
+                break;
+            }
+            if !copied {
+                decoded += 1;
+            }
+            if let (Some(key), Ok(_)) = (key, &result) {
+                loaded.entry(key).or_insert(results.len());
+            }
+            results.push(result);
+        }
+        let (mut comparer, errors) = Self::_from_results(results);
+        comparer.options = options;
+        comparer.decoded = decoded;
+        (comparer, errors)
+    }
+
+    /// Собирает сравнитель из результатов загрузки, сохраняя порядок
+    pub(crate) fn _from_results(results: Vec<Result<(Signature, ImageInfo)>>) -> (Self, Vec<ImgAlgError>) {
+        let decoded = results.len();
+        let mut imgs = vec![];
+        let mut errors = vec![];
+        for result in results {
+            match result {
+                Ok(diff_pixels) => imgs.push(diff_pixels),
+                Err(e) => errors.push(e),
+            }
+        }
+        (Self { compare_with_first: false, cutoffs: Cutoffs::default(), options: ComparerOptions::default(), images: Arc::new(imgs), decoded }, errors)
+    }
+
+    /// Количество загруженных изображений
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Сколько раз при загрузке декодировались файлы, включая неудачные попытки.
+    /// Меньше числа путей, если одни и те же файлы упоминались несколько раз
+    pub fn decoded_count(&self) -> usize {
+        self.decoded
+    }
+
+    /// Сигнатура изображения с индексом `index`
+    pub fn signature(&self, index: usize) -> Option<&Signature> {
+        self.images.get(index).map(|(signature, _)| signature)
+    }
+
+    /// Размеры, формат и размер файла изображения с индексом `index`
+    pub fn image_info(&self, index: usize) -> Option<&ImageInfo> {
+        self.images.get(index).map(|(_, info)| info)
+    }
+
+    /// Предупреждения всех загруженных изображений в порядке изображений, см. `ImageInfo::warnings`
+    pub fn warnings(&self) -> impl Iterator<Item = &Warning> + '_ {
+        self.images.iter().flat_map(|(_, info)| &info.warnings)
+    }
+
+    /// Загружает еще одно изображение и возвращает его индекс, равный числу изображений до загрузки.
+    /// Сетки сигнатуры берутся из текущих `options`. Клоны сравнителя нового изображения не видят
+    pub fn add_image<P: AsRef<Path>>(&mut self, image_path: P) -> Result<usize> {
+        let crop = self.options.crop_rect();
+        self.add_image_cropped(image_path, crop)
+    }
+
+    /// То же, что `add_image`, но сигнатура считается только по области `crop` этого
+    /// изображения вместо `ComparerOptions::crop`; `None` - по всему изображению.
+    /// Область, которая не помещается в изображение, - ошибка `CropOutOfBounds`
+    pub fn add_image_cropped<P: AsRef<Path>>(&mut self, image_path: P, crop: Option<Rect>) -> Result<usize> {
+        self.options.check_cancelled()?;
+        self.decoded += 1;
+        let image = Self::_load_image_cropped(image_path, &self.options, crop)?;
+        let images = Arc::make_mut(&mut self.images);
+        images.push(image);
+        Ok(images.len() - 1)
+    }
+
+    /// Добавляет изображение из буфера пикселей RGBA8 без кодирования, как
+    /// `Signature::from_raw_rgba`, и возвращает его индекс. Сетки берутся из текущих `options`;
+    /// область `ComparerOptions::crop` к буферу не применяется. Формата у такого изображения
+    /// нет, а размером файла считается длина буфера
+    pub fn add_raw_rgba(&mut self, buf: &[u8], width: u32, height: u32) -> Result<usize> {
+        self.options.check_cancelled()?;
+        if let Some(map) = self.options.tolerance()
+            && map.dimensions() != (width, height)
+        {
+            return Err(ImgAlgError::ToleranceMapSize { path: None, map: map.dimensions(), image: (width, height) });
+        }
+        self.options.check_letterbox()?;
+        let signature = Signature::from_raw_rgba_scales(buf, width, height, self.options.grid_sizes(), self.options.transform(), self.options.is_letterbox(), self.options.is_ensemble())?;
+        self.options.check_aspect(None, width, height)?;
+        let images = Arc::make_mut(&mut self.images);
+        images.push((signature, info::raw_rgba(buf, width, height)));
+        Ok(images.len() - 1)
+    }
+
+    /// Заменяет сигнатуру изображения `index`, загруженного из анимации `animation`, сигнатурой
+    /// ее кадра, больше всего похожего на изображение `target`, и возвращает этот кадр.
+    /// Дальнейшие сравнения, `explain` и `raw_diff` для `index` идут по этому кадру; сведения
+    /// об изображении и область `crop` остаются от загрузки, к предупреждениям добавляется
+    /// `TruncatedAnimation`, если анимация прочиталась не до конца. Клоны сравнителя замену не видят
+    pub fn select_best_frame<P: AsRef<Path>>(&mut self, index: usize, animation: P, target: usize) -> Result<animation::BestFrame> {
+        let (Some((_, info)), Some((target_signature, _))) = (self.images.get(index), self.images.get(target)) else {
+            return Err(ImgAlgError::InvalidIndex(index.max(target)));
+        };
+        let (best, signature, warning) = animation::best_frame(animation.as_ref(), info.crop, target_signature, &self.options)?;
+        let (current, info) = &mut Arc::make_mut(&mut self.images)[index];
+        *current = signature;
+        info.warnings.extend(warning);
+        Ok(best)
+    }
+
+    /// Удаляет все загруженные изображения, настройки остаются.
+    /// Выделенная память сохраняется для следующих `add_image`
+    pub fn clear(&mut self) {
+        match Arc::get_mut(&mut self.images) {
+            Some(images) => images.clear(),
+            None => self.images = Arc::default(), // Сигнатуры еще нужны клонам
+        }
+    }
+
+    /// Удаляет все изображения, кроме перечисленных.
+    ///
+    /// Оставшиеся изображения перенумеровываются подряд с нуля в прежнем порядке
+    /// (а не в порядке `indices`), следующий `add_image` вернет их количество.
+    /// Несуществующий индекс - `InvalidIndex`, сравнитель при этом не меняется
+    pub fn clear_keeping(&mut self, indices: &[usize]) -> Result<()> {
+        if let Some(&index) = indices.iter().find(|&&index| index >= self.images.len()) {
+            return Err(ImgAlgError::InvalidIndex(index));
+        }
+        match Arc::get_mut(&mut self.images) {
+            Some(images) => {
+                let mut index = 0;
+                images.retain(|_| {
+                    index += 1;
+                    indices.contains(&(index - 1))
+                });
+            }
+            None => {
+                let kept = self.images.iter().enumerate().filter(|(index, _)| indices.contains(index));
+                self.images = Arc::new(kept.map(|(_, image)| image.clone()).collect());
+            }
+        }
+        Ok(())
+    }
+
+    /// Новая функция обработки пикселей с предварительным преобразованием
+    /// Область `crop` вырезается из декодированного изображения до уменьшения (и до вписывания в квадрат)
+    /// С `ensemble` по тому же декодированному изображению считаются и сигнатуры `ComparerOptions::ensemble`
+    pub(crate) fn _get_pixels_diff<P: AsRef<Path>>(image_path: P, grid_sizes: &[u32], crop: Option<Rect>, transform: Transform, letterbox: bool, ensemble: bool) -> Result<(Signature, ImageInfo)> {
+        let (original_img, mut info) = info::open(image_path.as_ref())?;
+        let img = crop::apply(image_path.as_ref(), original_img, crop)?;
+        info.crop = crop;
+        Ok((Signature::from_image_ensemble(img, grid_sizes, transform, letterbox, ensemble)?, info))
+    }
+
+    /// Загружает все пути параллельно на потоках `options`, результаты - в порядке путей.
+    /// После отмены оставшиеся файлы дают `Cancelled`, а если потоки не запустились - `ThreadPool`
+    pub(crate) fn _load_all<P: AsRef<Path> + Sync>(paths: &[P], options: &ComparerOptions) -> Vec<Result<(Signature, ImageInfo)>> {
+        match options.thread_pool() {
+            Ok(pool) => pool.install(|| paths.par_iter().map(|path| options.check_cancelled().and_then(|()| Self::_load_image(path, options))).collect()),
+            Err(e) => paths.iter().map(|_| Err(ImgAlgError::ThreadPool(pool_failure(&e)))).collect(),
+        }
+    }
+
+    /// Загрузка с сетками, областью, преобразованием значений и ограничением времени декодирования из `options`
+    pub(crate) fn _load_image<P: AsRef<Path>>(image_path: P, options: &ComparerOptions) -> Result<(Signature, ImageInfo)> {
+        Self::_load_image_cropped(image_path, options, options.crop_rect())
+    }
+
+    fn _load_image_cropped<P: AsRef<Path>>(image_path: P, options: &ComparerOptions, crop: Option<Rect>) -> Result<(Signature, ImageInfo)> {
+        options.check_letterbox()?;
+        let (signature, info) = Self::_load_retrying(image_path.as_ref(), options, || Self::_load_image_timed(image_path.as_ref(), options, crop))?;
+        if let Some(map) = options.tolerance() {
+            let image = crop.map_or((info.width, info.height), |crop| (crop.width, crop.height));
+            if image != map.dimensions() {
+                return Err(ImgAlgError::ToleranceMapSize { path: Some(image_path.as_ref().to_path_buf()), map: map.dimensions(), image });
+            }
+        }
+        Ok((signature, info))
+    }
+
+    /// `load` с повторами `options.retries`; сколько их понадобилось - в предупреждении `Retried`.
+    /// С `options.strict_aspect` слишком вытянутое изображение - ошибка `ExtremeAspect`
+    pub(crate) fn _load_retrying(image_path: &Path, options: &ComparerOptions, load: impl FnMut() -> Result<(Signature, ImageInfo)>) -> Result<(Signature, ImageInfo)> {
+        let ((signature, mut info), retries) = options.retrying(load)?;
+        options.check_aspect(Some(image_path), info.width, info.height)?;
+        if retries > 0 {
+            info.warnings.push(Warning::Retried { path: image_path.to_path_buf(), retries });
+        }
+        Ok((signature, info))
+    }
+
+    pub(crate) fn _load_image_timed(image_path: &Path, options: &ComparerOptions, crop: Option<Rect>) -> Result<(Signature, ImageInfo)> {
+        let (grid_sizes, transform, letterbox, ensemble) = (options.grid_sizes(), options.transform(), options.is_letterbox(), options.is_ensemble());
+        let Some(timeout) = options.timeout() else {
+            return Self::_get_pixels_diff(image_path, grid_sizes, crop, transform, letterbox, ensemble);
+        };
+        let path = image_path.to_path_buf();
+        let worker_path = path.clone();
+        let (tx, rx) = mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("imgalg-decode".to_string())
+            .spawn(move || {
+                let _ = tx.send(Self::_get_pixels_diff(worker_path, grid_sizes, crop, transform, letterbox, ensemble)); // Получателя уже может не быть
+            })
+            .map_err(|e| ImgAlgError::io(&path, e))?;
+        match rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            // Поток продолжит работу в фоне, его результат никто не получит
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(ImgAlgError::Timeout { path, timeout }),
+            Err(mpsc::RecvTimeoutError::Disconnected) => match worker.join() {
+                Err(panic) => std::panic::resume_unwind(panic),
+                Ok(()) => unreachable!("the decode thread always sends its result"),
+            },
+        }
+    }
+
+    /// То же, что `_get_pixels_diff`, но для содержимого файла, уже прочитанного в память
+    #[cfg(feature = "async")]
+    pub(crate) fn _get_bytes_pixels_diff(image_path: &Path, bytes: &[u8]) -> Result<(Signature, ImageInfo)> {
+        let (original_img, info) = info::decode(image_path, bytes)?;
+        Ok((Signature::from_image(original_img)?, info))
+    }
+
+    /// Новый метод для получения процента схожести
+    pub fn similarity_percentage(&self) -> f32 {
+        self._similarity(0, 1)
+    }
+
+    /// Процент схожести двух произвольных загруженных изображений
+    pub fn similarity_percentage_between(&self, i: usize, j: usize) -> Result<f32> {
+        Ok(self.compare_pair(i, j)?.similarity)
+    }
+
+    /// Схожесть пары отдельно по каждой сетке. У обычной сигнатуры сетка одна
+    pub fn scale_scores(&self, i: usize, j: usize) -> Result<Vec<ScaleScore>> {
+        let a = &self.images.get(i).ok_or(ImgAlgError::InvalidIndex(i))?.0;
+        let b = &self.images.get(j).ok_or(ImgAlgError::InvalidIndex(j))?.0;
+        let scores = self.options.scale_distances(a, b).into_iter().map(|(grid, distance)| {
+            ScaleScore { grid, distance, similarity: self.options.similarity(distance) }
+        });
+        Ok(scores.collect())
+    }
+
+    /// Из каких ячеек сложилась разница изображений `i` и `j`, от наибольшего вклада к наименьшему.
+    /// Вклады в сумме дают `raw_diff(i, j)`; у одинаковых изображений список пуст
+    pub fn explain(&self, i: usize, j: usize) -> Result<Vec<CellContribution>> {
+        let a = &self.images.get(i).ok_or(ImgAlgError::InvalidIndex(i))?.0;
+        let b = &self.images.get(j).ok_or(ImgAlgError::InvalidIndex(j))?.0;
+        a.check_compatible(b)?;
+        Ok(explain::explain(&self.options, a, b))
+    }
+
+    /// Ненормированная разница двух загруженных изображений, см. `Signature::distance`
+    pub fn raw_diff(&self, i: usize, j: usize) -> Result<f64> {
+        Ok(self.compare_pair(i, j)?.distance)
+    }
+
+    /// Словесная оценка схожести двух загруженных изображений по границам `cutoffs`
+    pub fn verdict(&self, i: usize, j: usize) -> Result<Verdict> {
+        Ok(Verdict::from_similarity(self.similarity_percentage_between(i, j)?, &self.cutoffs))
+    }
+
+    /// Чем копии отличаются, кроме пикселей, по сведениям загрузки, см. `CopyKind::classify`.
+    /// Пары, которые не совпали, лучше не классифицировать: у разных изображений вид копии
+    /// ничего не значит
+    pub fn copy_kind(&self, i: usize, j: usize) -> Result<CopyKind> {
+        let similarity = self.similarity_percentage_between(i, j)?;
+        let (a, b) = (&self.images[i].1, &self.images[j].1);
+        Ok(CopyKind::classify(a, b, similarity))
+    }
+
+    /// Схожи ли изображения не меньше чем на `threshold` процентов
+    pub fn is_duplicate(&self, i: usize, j: usize, threshold: SimilarityThreshold) -> Result<bool> {
+        Ok(threshold.is_met_by(self.similarity_percentage_between(i, j)?))
+    }
+
+    fn _get_pair(&self, a: usize, b: usize, algorithm: &Arc<str>) -> PairResult {
+        let (distance, similarity, spread, range) = signature::measure_ensemble(&self.images[a].0, &self.images[b].0, &self.options);
+        PairResult { a, b, distance, similarity, spread, algorithm: algorithm.clone(), range }
+    }
+
+    fn _similarity(&self, a: usize, b: usize) -> f32 {
+        signature::measure_ensemble(&self.images[a].0, &self.images[b].0, &self.options).1
+    }
+
+    /// Идентификатор вычисления с текущими `options`, как в `PairResult::algorithm`
+    pub fn algorithm_id(&self) -> String {
+        algorithm_id(&self.options)
+    }
+
+    /// Сравнивает два загруженных изображения
+    pub fn compare_pair(&self, i: usize, j: usize) -> Result<PairResult> {
+        for index in [i, j] {
+            if index >= self.images.len() {
+                return Err(ImgAlgError::InvalidIndex(index));
+            }
+        }
+        Ok(self._get_pair(i.min(j), i.max(j), &Arc::from(self.algorithm_id())))
+    }
+
+    /// Сравнивает все пары изображений (или только пары с первым при `compare_with_first`)
+    /// параллельно на `options.threads` потоках, в порядке `iter_pairs`
+    pub fn compare(&self) -> Vec<PairResult> {
+        let n = self.images.len();
+        let algorithm: Arc<str> = Arc::from(self.algorithm_id());
+        let algorithm = &algorithm;
+        self.options.install(|| (0..self._first_count()).into_par_iter().flat_map_iter(|i| (i + 1..n).map(move |j| self._get_pair(i, j, algorithm))).collect())
+    }
+
+    /// Полная симметричная матрица процентов схожести, на диагонали 100.0.
+    /// Считается только верхний треугольник, нижний получается отражением
+    pub fn similarity_matrix(&self) -> Vec<Vec<f32>> {
+        let n = self.images.len();
+        let upper: Vec<Vec<f32>> = self.options.install(|| (0..n).into_par_iter().map(|i| (i + 1..n).map(|j| self._similarity(i, j)).collect()).collect());
+        let mut matrix = vec![vec![100.0; n]; n];
+        for (i, row) in upper.iter().enumerate() {
+            for (j, &similarity) in (i + 1..n).zip(row) {
+                matrix[i][j] = similarity;
+                matrix[j][i] = similarity;
+            }
+        }
+        matrix
+    }
+
+    /// Передает строки `similarity_matrix` по порядку, не собирая матрицу целиком.
+    /// Строка считается параллельно, а `on_row` вызывается в текущем потоке.
+    /// Перед каждой строкой проверяется отмена: уже переданные строки остаются у вызывающего
+    pub fn for_each_row<E: From<ImgAlgError>>(&self, mut on_row: impl FnMut(usize, &[f32]) -> std::result::Result<(), E>) -> std::result::Result<(), E> {
+        let n = self.images.len();
+        let pool = self.options.thread_pool()?;
+        let mut row = vec![100.0; n];
+        for i in 0..n {
+            self.options.check_cancelled()?;
+            pool.install(|| {
+                row.par_iter_mut().enumerate().for_each(|(j, value)| {
+                    *value = if i == j { 100.0 } else { self._similarity(i.min(j), i.max(j)) };
+                })
+            });
+            on_row(i, &row)?;
+        }
+        Ok(())
+    }
+
+    /// Средняя схожесть каждого изображения со всеми остальными, по индексам. Строки
+    /// считаются параллельно на потоках `options`, `compare_with_first` не учитывается.
+    /// У единственного изображения - 100.0
+    pub fn mean_similarities(&self) -> Vec<f32> {
+        let n = self.images.len();
+        if n < 2 {
+            return vec![100.0; n];
+        }
+        self.options.install(|| {
+            (0..n)
+                .into_par_iter()
+                .map(|i| (0..n).filter(|&j| j != i).map(|j| self._similarity(i.min(j), i.max(j))).sum::<f32>() / (n - 1) as f32)
+                .collect()
+        })
+    }
+
+    /// Изображения, которые выбиваются из набора: средняя схожесть со всеми остальными
+    /// (`mean_similarities`) ниже `threshold`. Например, кадр серийной съемки, где кто-то
+    /// моргнул. Пары (индекс, средняя схожесть) - от самой низкой, при равной - по индексу
+    pub fn outliers(&self, threshold: SimilarityThreshold) -> Vec<(usize, f32)> {
+        let mut outliers: Vec<(usize, f32)> = self.mean_similarities().into_iter().enumerate().filter(|&(_, mean)| !threshold.is_met_by(mean)).collect();
+        outliers.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        outliers
+    }
+
+    /// Ленивый аналог `compare`: пары идут в порядке (0, 1), (0, 2), ..., (1, 2), ...,
+    /// каждая разница считается только при обращении к элементу
+    pub fn iter_pairs(&self) -> impl Iterator<Item = PairResult> + '_ {
+        let algorithm: Arc<str> = Arc::from(self.algorithm_id());
+        (0..self._first_count()).flat_map(move |i| {
+            let algorithm = algorithm.clone();
+            (i + 1..self.images.len()).map(move |j| self._get_pair(i, j, &algorithm))
+        })
+    }
+
+    /// Сколько изображений бывают первыми в паре
+    fn _first_count(&self) -> usize {
+        if self.compare_with_first { self.images.len().min(1) } else { self.images.len() }
+    }
+
+    /// Только пары, схожесть которых проходит порог
+    pub fn iter_pairs_above(&self, threshold: SimilarityThreshold) -> impl Iterator<Item = PairResult> + '_ {
+        self.iter_pairs().filter(move |pair| threshold.is_met_by(pair.similarity))
+    }
+
+    /// Группы дубликатов. Каждое изображение, еще не попавшее в группу, по порядку становится
+    /// образцом, и в его группу входят все свободные изображения со схожестью с ним не ниже
+    /// `threshold`, так что схожесть внутри группы считается от первого индекса.
+    ///
+    /// Возвращаются только группы из двух и более индексов; индексы в группе по возрастанию.
+    /// При `compare_with_first` образец один - первое изображение
+    pub fn duplicate_groups(&self, threshold: SimilarityThreshold) -> Vec<Vec<usize>> {
+        let n = self.images.len();
+        let mut grouped = vec![false; n];
+        let mut groups = vec![];
+        for reference in 0..self._first_count() {
+            if grouped[reference] {
+                continue;
+            }
+            let matches: Vec<usize> = self.options.install(|| {
+                (reference + 1..n)
+                    .into_par_iter()
+                    .filter(|&idx| !grouped[idx] && threshold.is_met_by(self._similarity(reference, idx)))
+                    .collect()
+            });
+            if matches.is_empty() {
+                continue;
+            }
+            for &idx in &matches {
+                grouped[idx] = true;
+            }
+            groups.push([reference].into_iter().chain(matches).collect());
+        }
+        groups
+    }
+}
+
+/// Текст ошибки пула, чтобы `ThreadPool` досталась каждому файлу
+fn pool_failure(error: &ImgAlgError) -> String {
+    match error {
+        ImgAlgError::ThreadPool(message) => message.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+
+    /// Читатель, первые `failures` чтений которого заканчиваются `EIO`, как на сетевом диске
+    struct FlakyReader<'a, R> {
+        inner: R,
+        failures: &'a mut u32,
+    }
+
+    impl<R: Read> Read for FlakyReader<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if *self.failures > 0 {
+                *self.failures -= 1;
+                return Err(std::io::Error::from_raw_os_error(5));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    /// Загрузка через `FlakyReader` с повторами `options`
+    fn load_flaky(path: &Path, options: &ComparerOptions, mut failures: u32) -> Result<(Signature, ImageInfo)> {
+        ImagesComparer::_load_retrying(path, options, || {
+            let mut bytes = vec![];
+            FlakyReader { inner: std::fs::File::open(path).unwrap(), failures: &mut failures }.read_to_end(&mut bytes).map_err(|e| ImgAlgError::io(path, e))?;
+            let (image, info) = info::decode(path, &bytes)?;
+            Ok((Signature::from_image(image)?, info))
+        })
+    }
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash/rings.png")
+    }
+
+    #[test]
+    fn transient_read_error_is_retried() {
+        let options = ComparerOptions::new().retries(2, Duration::from_millis(1));
+        let (signature, info) = load_flaky(&fixture(), &options, 1).unwrap();
+        assert_eq!(signature, Signature::compute(fixture()).unwrap());
+        assert_eq!(info.warnings, [Warning::Retried { path: fixture(), retries: 1 }]);
+        assert!(load_flaky(&fixture(), &options, 0).unwrap().1.warnings.is_empty());
+    }
+
+    #[test]
+    fn read_error_is_recorded_once_the_retries_run_out() {
+        let error = load_flaky(&fixture(), &ComparerOptions::new(), 1).unwrap_err();
+        assert!(error.is_transient() && error.retries() == 0, "{error}");
+
+        let options = ComparerOptions::new().retries(2, Duration::from_millis(1));
+        let error = load_flaky(&fixture(), &options, 3).unwrap_err();
+        assert_eq!(error.retries(), 2, "{error}");
+        assert_eq!(error.path(), Some(fixture().as_path()));
+        assert!(error.to_string().ends_with("(still failing after 2 retries)"), "{error}");
+    }
+}
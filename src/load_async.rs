@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::{archive, icon, ComparerOptions, ImageInfo, ImagesComparer, ImgAlgError, Result, Signature};
+
+impl ImagesComparer {
+    /// Асинхронный аналог `new_lossy` с теми же путями, повторами и числом декодирований:
+    /// файлы читаются через `tokio::fs`, одновременно декодируется не больше изображений,
+    /// чем ядер процессора
+    pub async fn new_async<P: AsRef<Path>>(images: &[P]) -> (Self, Vec<ImgAlgError>) {
+        let limit = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new_async_bounded(images, limit).await
+    }
+
+    /// То же, что `new_async`, но с явным ограничением числа одновременных загрузок.
+    ///
+    /// Если future отменить (просто удалить), все еще не начатые загрузки прерываются.
+    /// Уже запущенное декодирование дорабатывает в пуле блокирующих потоков,
+    /// его результат отбрасывается
+    pub async fn new_async_bounded<P: AsRef<Path>>(images: &[P], max_concurrent: usize) -> (Self, Vec<ImgAlgError>) {
+        let semaphore = Arc::new(Semaphore::new(max_concurrent.max(1)));
+        // Повторы одного файла, как у `new_lossy_with`, узнаются по каноническому пути
+        let mut keys: Vec<Option<PathBuf>> = Vec::with_capacity(images.len());
+        for path in images {
+            keys.push(tokio::fs::canonicalize(path).await.ok());
+        }
+        let mut firsts: HashMap<&PathBuf, usize> = HashMap::new();
+        let first: Vec<bool> = keys.iter().enumerate().map(|(pos, key)| key.as_ref().is_none_or(|key| *firsts.entry(key).or_insert(pos) == pos)).collect();
+
+        // JoinSet прерывает свои задачи при удалении, так что отмена не оставляет хвостов
+        let mut tasks = JoinSet::new();
+        for (pos, path) in images.iter().enumerate().filter(|&(pos, _)| first[pos]) {
+            let path = path.as_ref().to_path_buf();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move { (pos, load(path, semaphore).await) });
+        }
+        let mut decodes: Vec<Option<Result<(Signature, ImageInfo)>>> = (0..images.len()).map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((pos, result)) => decodes[pos] = Some(result),
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => {} // Задачи прерываются только вместе с JoinSet
+            }
+        }
+
+        let mut results: Vec<Result<(Signature, ImageInfo)>> = Vec::with_capacity(images.len());
+        let mut loaded: HashMap<PathBuf, usize> = HashMap::new();
+        let mut decoded = 0;
+        for ((path, key), decode) in images.iter().zip(keys).zip(decodes) {
+            let cached = key.as_ref().and_then(|key| loaded.get(key)).and_then(|&pos| results[pos].as_ref().ok().cloned());
+            let result = match cached {
+                Some(image) => Ok(image),
+                // Повтор файла, который не загрузился в первый раз, пробуется снова
+                None => {
+                    decoded += 1;
+                    match decode {
+                        Some(result) => result,
+                        None => load(path.as_ref().to_path_buf(), semaphore.clone()).await,
+                    }
+                }
+            };
+            if let (Some(key), Ok(_)) = (key, &result) {
+                loaded.entry(key).or_insert(results.len());
+            }
+            results.push(result);
+        }
+        let (mut comparer, errors) = Self::_from_results(results);
+        comparer.decoded = decoded;
+        (comparer, errors)
+    }
+}
+
+/// Загружает изображение так же, как `new_lossy`: обычный файл читается через `tokio::fs`,
+/// из значка остается изображение размера из пути `app.ico#32`, а файл внутри архива
+/// (`архив.zip!/имя`) читается из архива в пуле блокирующих потоков
+async fn load(path: PathBuf, semaphore: Arc<Semaphore>) -> Result<(Signature, ImageInfo)> {
+    // Разрешение держим до конца декодирования, чтобы ограничить нагрузку на процессор
+    let _permit = semaphore.acquire_owned().await.map_err(|_| ImgAlgError::Cancelled)?;
+    let blocking = if archive::is_member(&path) {
+        tokio::task::spawn_blocking(move || ImagesComparer::_load_image(&path, &ComparerOptions::default())).await
+    } else {
+        let (file, icon_size) = icon::split(&path).map_or_else(|| (path.clone(), None), |(icon, size)| (icon, Some(size)));
+        let bytes = tokio::fs::read(&file).await.map_err(|e| ImgAlgError::io(&path, e))?;
+        tokio::task::spawn_blocking(move || ImagesComparer::_get_bytes_pixels_diff(&path, &icon::select(&path, bytes, icon_size)?)).await
+    };
+    match blocking {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(ImgAlgError::Cancelled), // Среда выполнения завершается
+    }
+}
@@ -1,132 +1,358 @@
-use anyhow::{bail, Context, Result};
-use image::{DynamicImage, GenericImageView, Rgba};
-use std::collections::HashMap;
-use std::env;
-
-/// Функция преобразования изображения в единый формат RGBA
-fn convert_to_rgba(sample_img: DynamicImage) -> DynamicImage {
-    match sample_img {
-        DynamicImage::ImageRgb8(_) => image::DynamicImage::ImageRgba8(sample_img.into_rgba8()),
-        DynamicImage::ImageRgba8(_) => sample_img.clone(), // Уже в RGBA
-        DynamicImage::ImageLuma8(_) => image::DynamicImage::ImageRgba8(sample_img.into_rgba8()),
-        DynamicImage::ImageLumaA8(_) => image::DynamicImage::ImageRgba8(sample_img.into_rgba8()),
-        _ => panic!("Неподдерживаемый формат изображения."),
-    }
-}
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use imgalg::{CancelToken, CellContribution, ComparerOptions, ImagesComparer, Provenance, ToleranceMap};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-pub struct ImagesComparer {
-    pub compare_with_first: bool,
-    images: Vec<(Vec<Vec<i32>>, HashMap<usize, i32>)>,
-}
+mod cli;
+
+use cli::config::Config;
+use cli::error::{CliError, CliWarning, ErrorCode, Outcome};
+use cli::output::{Output, ReportFormat};
+use cli::pairs::{PairChecks, PairColumns};
+use cli::lang::{text, tr};
+use cli::verbosity;
+use cli::report::ImageEntry;
+use cli::{Cli, Command};
 
-impl ImagesComparer {
-    pub fn new(images: &[&String]) -> Result<Self> {
-        let mut imgs = vec![];
-        for img in images.iter().copied() {
-            let diff_pixels = Self::_get_pixels_diff(img)?;
-            imgs.push((diff_pixels, Default::default()));
+fn main() {
+    // Формат нужен еще до разбора аргументов, чтобы и ошибку разбора вывести в JSON
+    let args: Vec<_> = std::env::args_os().collect();
+    let format = |value: &str| args.iter().any(|arg| *arg == *format!("--format={value}")) || args.windows(2).any(|pair| pair[0] == "--format" && pair[1] == value);
+    let ndjson = format("ndjson");
+    let json = ndjson || format("json") || args.iter().any(|arg| arg == "--json");
+    cli::error::init_lines(ndjson);
+    // Коды завершения `check` другие, в том числе при ошибке разбора его аргументов
+    let check = Cli::command().ignore_errors(true).try_get_matches_from(&args).is_ok_and(|matches| matches.subcommand_name() == Some("check"));
+    cli::error::init_check(check);
+    let mut cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // Справку и версию clap тоже возвращает как ошибку, их выводим как обычно
+        Err(e) if json && e.use_stderr() => {
+            let rendered = e.to_string();
+            let message = rendered.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+            fail(CliError::new(ErrorCode::Args, message), json, text("Error", "Ошибка"))
+        }
+        Err(e) if check && e.use_stderr() => {
+            let _ = e.print();
+            std::process::exit(cli::error::exit_code(ErrorCode::Args))
         }
-        Ok(Self { compare_with_first: false, images: imgs})
-    }
-
-    fn _get_image_type(image_path: &str) -> Result<String> {
-        let reader = image::ImageReader::open(image_path)?
-                            .with_guessed_format()?
-                            .decode()?;
-        match reader.color() {
-            image::ColorType::Rgb8 => Ok("jpg".to_string()), // JPEG поддерживает RGB
-            image::ColorType::Rgba8 => Ok("png".to_string()), // PNG поддерживает RGBA
-            image::ColorType::L8 => Ok("gray".to_string()), // Grayscale изображения
-            _ => bail!("Unsupported image format"),
+        Err(e) => e.exit(),
+    };
+    // Файл настроек нужен до выбора цвета и языка: их тоже можно задать в нем
+    let loaded = Config::load(cli.config.as_deref());
+    let config = loaded.as_ref().map(|config| config.apply(&mut cli));
+    cli::color::init(cli.color.unwrap_or_default());
+    cli::lang::init(cli.lang.unwrap_or_default());
+    cli::verbosity::init(cli.quiet, cli.verbose);
+    let started = Instant::now();
+    cli.json |= cli.format.is_some();
+
+    // Размер значка задается путем вида `app.ico#16`
+    if let Some(size) = cli.icon_size {
+        for path in cli.images.iter_mut().filter(|path| imgalg::icon::is_icon(path)) {
+            *path = imgalg::icon::size_path(&path, size);
         }
     }
 
-    /// Новая функция обработки пикселей с предварительным преобразованием
-    fn _get_pixels_diff(image_path: &str) -> Result<Vec<Vec<i32>>> {
-        let original_img = image::open(image_path).context("Failed to open the image")?;
-        let converted_img = convert_to_rgba(original_img); // Конвертируем изображение в RGBA
-        let scaled_sample = converted_img.resize_exact(16, 16, image::imageops::FilterType::Gaussian);
-        let pixels = scaled_sample.pixels().collect::<Vec<_>>();
-
-        let mut result = vec![];
-        let mut prev_color = None;
-        for y in 0..16 {
-            for x in 0..16 {
-                let pixel = *pixels.get(y * 16 + x).unwrap_or(&(0, 0, Rgba([0, 0, 0, 255]))); // Дефолтный прозрачный пиксель
-                let color = [
-                    (pixel.2[0] as i32).pow(2), // Первая составляющая (красный)
-                    (pixel.2[1] as i32).pow(2), // Вторая составляющая (зеленый)
-                    (pixel.2[2] as i32).pow(2), // Третья составляющая (синий)
-                ];
-                if Some(color) != prev_color && !(x == 0 && y == 0) {
-                    result.push(vec![
-                        color[0] - prev_color.unwrap()[0], // Преобразовываем в вектор
-                        color[1] - prev_color.unwrap()[1],
-                        color[2] - prev_color.unwrap()[2],
-                    ]);
-                }
-                prev_color = Some(color);
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => fail(CliError::new(ErrorCode::Args, cli::error::render(e)), cli.json, text("Error in the config file", "Ошибка в файле настроек")),
+    };
+    // Путь отчета проверяем до начала работы
+    let mut output = match Output::new(cli.output.as_deref()) {
+        Ok(output) => output,
+        Err(e) => fail(CliError::new(ErrorCode::Args, cli::error::render(&e)), cli.json, text("Error", "Ошибка")),
+    };
+
+    // По Ctrl-C только выставляем флаг: работа останавливается между файлами или строками,
+    // и индекс или уже готовая часть отчета успевают записаться. Второй Ctrl-C завершает сразу
+    let cancel = CancelToken::new();
+    {
+        let cancel = cancel.clone();
+        let handler = move || {
+            if cancel.is_cancelled() {
+                std::process::exit(cli::error::exit_code(ErrorCode::Cancelled));
             }
+            cancel.cancel();
+        };
+        if let Err(e) = ctrlc::set_handler(handler) {
+            fail(CliError::new(ErrorCode::Internal, format!("Failed to install the SIGINT handler: {e}")), cli.json, text("Error", "Ошибка"));
         }
-        Ok(result)
     }
 
-    fn _get_diff(&self) -> f32 {
-        let mut diff = 0.0;
-        for i in 0..std::cmp::min(self.images[0].0.len(), self.images[1].0.len()) {
-            diff += ((self.images[0].0[i][0] - self.images[1].0[i][0]) as f32 ).abs().sqrt();
-            diff += ((self.images[0].0[i][1] - self.images[1].0[i][1]) as f32 ).abs().sqrt();
-            diff += ((self.images[0].0[i][2] - self.images[1].0[i][2]) as f32 ).abs().sqrt();
+    let mut options = ComparerOptions::new();
+    if let Some(weights) = cli.weights {
+        options = options.channel_weights(weights).unwrap_or_else(|e| fail(CliError::from_lib(&e), cli.json, text("Error", "Ошибка")));
+    }
+    if let Some(channel) = cli.channel {
+        options = options.channels(channel.into());
+    }
+    options = options.ignore_hue(cli.ignore_hue).multi_scale(cli.multi_scale).decode_timeout(cli.decode_timeout).retries(cli.retries.unwrap_or_default(), Duration::from_millis(cli.retry_delay.unwrap_or_default())).cancel_token(cancel.clone()).crop(cli.crop).letterbox(cli.letterbox).strict_aspect(cli.strict_aspect).ensemble(cli.ensemble);
+    if let Some(transform) = cli.value_transform {
+        options = options.value_transform(transform).unwrap_or_else(|e| fail(CliError::from_lib(&e), cli.json, text("Error", "Ошибка")));
+    }
+    if let Some(n) = cli.ignore_worst {
+        options = options.ignore_worst(n).unwrap_or_else(|e| fail(CliError::from_lib(&e), cli.json, text("Error", "Ошибка")));
+    }
+    if let Some(path) = &cli.tolerance_map {
+        let map = ToleranceMap::open(path).unwrap_or_else(|e| fail(CliError::from_lib(&e), cli.json, text("Error in the tolerance map", "Ошибка в карте допусков")));
+        options = options.tolerance_map(Some(map));
+    }
+    if let Some(jobs) = cli.jobs {
+        options = options.threads(jobs as usize);
+    }
+    // Индекс `scan` и `watch` сравнивает сигнатуры с весами, каналами и сеткой по умолчанию:
+    // настройки сравнения пар там не действовали бы, поэтому они - ошибка, а не молча пропускаются
+    if matches!(cli.command, Some(Command::Scan(_) | Command::Watch(_))) {
+        let unsupported = [
+            ("--weights", cli.weights.is_some()),
+            ("--channel", cli.channel.is_some()),
+            ("--ignore-hue", cli.ignore_hue),
+            ("--ignore-worst", cli.ignore_worst.is_some()),
+            ("--multi-scale", cli.multi_scale),
+            ("--crop", cli.crop.is_some() || cli.crop_a.is_some() || cli.crop_b.is_some()),
+            ("--tolerance-map", cli.tolerance_map.is_some()),
+        ];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+            fail(CliError::new(ErrorCode::Args, format!("{flag} is not supported by scan and watch: the index compares signatures with the default settings")), cli.json, text("Error", "Ошибка"));
+        }
+    }
+    if cli.format == Some(ReportFormat::Ndjson) {
+        // Строками выводятся только отчеты, которые получаются по частям
+        let streamed = match &cli.command {
+            Some(command) => matches!(command, Command::Scan(_)),
+            None => cli.pairs.is_some() && cli.export_czkawka.is_none() && cli.export_graph.is_none(),
+        };
+        if !streamed {
+            fail(CliError::new(ErrorCode::Args, "--format ndjson is supported only by scan and --pairs"), cli.json, text("Error", "Ошибка"));
+        }
+        output.set_ndjson();
+    }
+    output.set_provenance(Provenance::for_options(&options).command_line(std::env::args_os()));
+    let cutoffs = cli.bands.unwrap_or_default();
+    let checks = PairChecks { threshold: cli.threshold, ignore_pairs: cli.ignore_pairs.clone(), write_ignores: cli.write_ignores.clone(), yes: cli.yes };
+    let (result, context) = match cli.command {
+        Some(Command::Watch(args)) => (cli::watch::run(&args, &config, &cancel).map(|_| Outcome::Passed), text("Error while watching", "Ошибка в режиме наблюдения")),
+        Some(Command::Fingerprint(args)) => (cli::fingerprint::run(&args, cli.json, &output).map(|_| Outcome::Passed), text("Error while computing fingerprints", "Ошибка при вычислении отпечатков")),
+        Some(Command::Bench(args)) => (cli::bench::run(&args, cli.json, &output).map(|_| Outcome::Passed), text("Error while benchmarking", "Ошибка при замере")),
+        Some(Command::Scan(args)) => (cli::scan::run(&args, &config, &options, cli.json, &output).map(|_| Outcome::Passed), text("Error while scanning", "Ошибка при просмотре")),
+        Some(Command::Match(args)) => (cli::match_sets::run(&args, &options, cli.json, &output).map(|_| Outcome::Passed), text("Error while matching", "Ошибка при сопоставлении")),
+        Some(Command::Calibrate(args)) => (cli::calibrate::run(&args, &options, cli.json, &output).map(|_| Outcome::Passed), text("Error while calibrating", "Ошибка при калибровке")),
+        Some(Command::Outliers(args)) => (cli::outliers::run(&args, &options, cli.json, &output).map(|_| Outcome::Passed), text("Error while looking for outliers", "Ошибка при поиске выбросов")),
+        Some(Command::Cache(args)) => (cli::cache::run(&args, cli.json, &output).map(|_| Outcome::Passed), text("Error in the index", "Ошибка в индексе")),
+        Some(Command::Inspect(args)) => (cli::inspect::run(&args, cli.json, &output).map(|_| Outcome::Passed), text("Error while reading the provenance", "Ошибка при чтении сведений")),
+        Some(Command::CompareSig(args)) => (cli::compare_sig::run(&args, &options, cutoffs, cli.json, &output), text("Error while comparing with the signature", "Ошибка при сравнении с сигнатурой")),
+        Some(Command::Set(args)) => (cli::set::run(&args, cli.json, &output).map(|_| Outcome::Passed), text("Error in the set", "Ошибка в наборе")),
+        Some(Command::Check(args)) => (cli::set::check(&args, &config, cli.json, &output), text("Error while checking against the set", "Ошибка при проверке по набору")),
+        Some(Command::Config(args)) => (cli::config::run(&args, &config, cli.config.as_deref()).map(|_| Outcome::Passed), text("Error", "Ошибка")),
+        None => match (&cli.pairs, cli.matrix, &cli.export_czkawka, &cli.export_graph) {
+            (_, _, _, Some(path)) => (cli::graph::run(&cli.images, &options, cli.threshold, path).map(|_| Outcome::Passed), text("Error", "Ошибка")),
+            (_, _, Some(path), _) => (cli::czkawka::run(&cli.images, &options, cli.threshold, path).map(|_| Outcome::Passed), text("Error", "Ошибка")),
+            (Some(pairs), _, _, _) => (cli::pairs::run(pairs, &options, cutoffs, &checks, PairColumns { raw: cli.raw, classify: cli.classify_matches }, cli.json, &output), text("Error", "Ошибка")),
+            (None, Some(format), None, None) => (cli::matrix::run(&cli.images, &options, cli.max_memory, format, &output).map(|_| Outcome::Passed), text("Error", "Ошибка")),
+            (None, None, None, None) => (compare(&cli, &options, &output), text("Error while creating the comparer", "Ошибка при создании компаратора")),
+        },
+    };
+    verbosity::timing(tr!("Run time: {:.2?}", "Время работы: {:.2?}", started.elapsed()));
+    match result {
+        Ok(Outcome::Passed) => {}
+        Ok(Outcome::BelowThreshold) => {
+            if !cli.json {
+                verbosity::info(text("Similarity is below the threshold", "Схожесть ниже порога"));
+            }
+            std::process::exit(ErrorCode::Threshold.exit_code());
+        }
+        Ok(Outcome::Cancelled) => {
+            if !cli.json {
+                verbosity::info(text("Interrupted, the report is incomplete", "Прервано, отчет неполный"));
+            }
+            std::process::exit(cli::error::exit_code(ErrorCode::Cancelled));
         }
-        diff
+        Ok(Outcome::Matched) => std::process::exit(cli::error::CHECK_MATCHED_EXIT),
+        Err(e) => fail(CliError::from_anyhow(e), cli.json, context),
     }
+}
 
-    /// Новый метод для получения процента схожести
-    pub fn similarity_percentage(&self) -> f32 {
-        let total_difference = self._get_diff() as f64;
-        let num_pixels = (16 * 16) as f64; 
-        let max_possible_difference_per_channel = 100.0; // Максимально возможное отличие в каждом канале
-        let channels_count = 3.0; // Три канала (RGB)
-        let max_total_difference = num_pixels * channels_count * max_possible_difference_per_channel;
-        let percentage_similarity = 100.0 - (total_difference / max_total_difference) * 100.0;
-        (percentage_similarity as f32).clamp(0.0, 100.0) // Ограничиваем диапазон от 0% до 100%
+/// Сообщает об ошибке (в `--json` - объектом в stdout) и завершает процесс с кодом ошибки
+fn fail(error: CliError, json: bool, context: &str) -> ! {
+    if json {
+        println!("{}", error.to_json());
+    } else {
+        eprintln!("{}", cli::color::Palette::stderr().error(format_args!("{} [{}]: {}", context, error.code, error)));
     }
+    std::process::exit(cli::error::exit_code(error.code));
+}
+
+/// Сколько ячеек показывает `--explain`
+const EXPLAIN_TOP: usize = 10;
 
-    pub fn compare(&mut self) {
-        let diff = self._get_diff() as i32;
-        self.images[0].1.insert(1, diff); // Храним разницу между первыми двумя изображениями
+/// Схожесть на одной сетке многомасштабной сигнатуры
+#[derive(Serialize)]
+struct ScaleReport {
+    grid: u32,
+    similarity: f32,
+}
+
+/// Ячейка из `--explain`
+#[derive(Serialize)]
+struct CellReport {
+    grid: u32,
+    x: u32,
+    y: u32,
+    a: String,
+    b: String,
+    distance: f64,
+    percent: f64,
+    /// Отброшено `--ignore-worst`
+    #[serde(skip_serializing_if = "is_zero")]
+    ignored: f64,
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+impl From<&CellContribution> for CellReport {
+    fn from(cell: &CellContribution) -> Self {
+        Self { grid: cell.grid, x: cell.x, y: cell.y, a: cli::hex_color(cell.a), b: cli::hex_color(cell.b), distance: cell.distance, percent: cell.percent, ignored: cell.ignored }
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let images = &[&args[1], &args[2]];
-
-    // Создаем объект сравнителя изображений
-    let mut comparer = match ImagesComparer::new(images) {
-        Ok(comparer) => comparer,
-        Err(e) => {
-            eprintln!("Ошибка при создании компаратора: {}", e);
-            return;
-        }
-    };
+#[derive(Serialize)]
+struct CompareReport<'a> {
+    #[serde(serialize_with = "cli::serialize_path")]
+    a: &'a Path,
+    #[serde(serialize_with = "cli::serialize_path")]
+    b: &'a Path,
+    images: Vec<ImageEntry<'a>>,
+    similarity: f32,
+    /// Разброс схожести по фильтрам уменьшения, `--ensemble`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spread: Option<f32>,
+    /// В пределах разброса схожесть бывает по обе стороны `--threshold`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    uncertain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_diff: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scales: Vec<ScaleReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explain: Option<Vec<CellReport>>,
+    verdict: &'a str,
+    /// Номер самого похожего кадра, если одно из изображений - анимация
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_frame: Option<usize>,
+    /// Начало этого кадра по задержкам кадров
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_time_ms: Option<u64>,
+    /// Вид копии для совпавшей пары, `--classify-matches`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    copy_kind: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<CliWarning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CliError>,
+}
 
+/// Сравнивает первые два изображения из `cli.images`
+fn compare(cli: &Cli, options: &ComparerOptions, output: &Output) -> Result<Outcome> {
+    let (images, threshold, raw, explain, json) = (&cli.images, cli.threshold, cli.raw, cli.explain, cli.json);
     // Проверяем наличие хотя бы двух изображений
-    if comparer.images.is_empty() {
-        eprintln!("Нет изображений для сравнения!");
-        return;
+    if images.len() < 2 {
+        return Err(CliError::new(ErrorCode::Args, "At least two images are required").into());
     }
+    let images = &images[..2];
+
+    // Создаем объект сравнителя изображений; у каждого изображения может быть своя область
+    let (mut comparer, _) = ImagesComparer::new_lossy_with::<&str>(&[], options.clone());
+    for (path, crop) in images.iter().zip([cli.crop_a, cli.crop_b]) {
+        comparer.add_image_cropped(path, crop.or(options.crop_rect())).map_err(|e| CliError::from_lib(&e))?;
+    }
+    comparer.cutoffs = cli.bands.unwrap_or_default();
+    // Неподвижное изображение сравнивается с самым похожим кадром анимации, а не с первым
+    let best_frame = match [&images[0], &images[1]].map(imgalg::animation::is_animated) {
+        [true, false] => Some(comparer.select_best_frame(0, &images[0], 1)),
+        [false, true] => Some(comparer.select_best_frame(1, &images[1], 0)),
+        _ => None,
+    };
+    let best_frame = best_frame.transpose().map_err(|e| CliError::from_lib(&e))?;
 
     // Запускаем процесс сравнения
-    comparer.compare();
+    let results = comparer.compare();
+
+    let percent_similarity = results[0].similarity;
+    let spread = options.is_ensemble().then_some(results[0].spread);
+    let uncertain = threshold.is_some_and(|threshold| results[0].is_uncertain(threshold));
+    let verdict = comparer.verdict(0, 1)?;
+    let raw_diff = raw.then(|| comparer.raw_diff(0, 1)).transpose()?;
+    let copy_kind = (cli.classify_matches && verdict.is_duplicate()).then(|| comparer.copy_kind(0, 1)).transpose()?;
+    let scales = if options.is_multi_scale() { comparer.scale_scores(0, 1)? } else { vec![] };
+    let cells = if explain { comparer.explain(0, 1)? } else { vec![] };
+    let cells = &cells[..cells.len().min(EXPLAIN_TOP)];
+    let passed = threshold.is_none_or(|threshold| threshold.is_met_by(percent_similarity));
+    let outcome = if passed { Outcome::Passed } else { Outcome::BelowThreshold };
+    let summary = tr!("Images compared: {}, similarity: {:.2}%", "Сравнено изображений: {}, процент схожести: {:.2}%", comparer.len(), percent_similarity);
+    if json {
+        let error = (!passed).then(|| CliError::new(ErrorCode::Threshold, "Similarity is below the threshold"));
+        let entries = cli::report::image_entries(&comparer, &[&images[0], &images[1]]);
+        let report = CompareReport { a: &images[0], b: &images[1], images: entries, similarity: percent_similarity, spread, uncertain, raw_diff, scales: scales.iter().map(|scale| ScaleReport { grid: scale.grid, similarity: scale.similarity }).collect(), explain: explain.then(|| cells.iter().map(CellReport::from).collect()), verdict: verdict.as_str(), best_frame: best_frame.map(|best| best.frame), frame_time_ms: best_frame.and_then(|best| best.time_ms), copy_kind: copy_kind.map(|kind| kind.as_str()), threshold: threshold.map(f32::from), warnings: comparer.warnings().map(CliWarning::from_lib).collect(), error };
+        output.emit_json(&report, &summary)?;
+        return Ok(outcome);
+    }
+    cli::error::print_warnings(comparer.warnings());
 
     // Выводим результат сравнения
-    println!("Results:");
-    for (idx, data) in comparer.images.iter().enumerate() {
-        println!("Image {}: {:?}", idx, data.1); // Выводим метаданные сравнения
+    let palette = output.palette();
+    let mut report = format!("{}\n", text("Results:", "Результаты:"));
+    for (idx, path) in images.iter().enumerate() {
+        if let Some(info) = comparer.image_info(idx) {
+            writeln!(report, "{}", tr!("Image {}: {} ({})", "Изображение {}: {} ({})", idx, path.display(), cli::report::describe(info)))?;
+        }
+    }
+    for result in &results {
+        writeln!(report, "{}", tr!("Image {} ~ Image {}: {}", "Изображение {} ~ изображение {}: {}", result.a, result.b, result.distance))?; // Выводим разницу сигнатур
     }
 
     // Выводим процент схожести
-    let percent_similarity = comparer.similarity_percentage();
-    println!("Процент схожести: {:.2}%", percent_similarity);
-}
\ No newline at end of file
+    let spread_text = spread.map(|spread| format!(" ±{spread:.2}")).unwrap_or_default();
+    writeln!(report, "{}", tr!("Similarity: {}{} ({})", "Процент схожести: {}{} ({})", palette.percent(percent_similarity), spread_text, cli::verdict_word(verdict)))?;
+    if uncertain {
+        writeln!(report, "{}", text("Uncertain: the threshold lies within the spread, check the pair by eye", "Неуверенно: порог попадает в разброс, пару стоит проверить на глаз"))?;
+    }
+    if let Some(best) = best_frame {
+        let time = best.time_ms.map(|ms| tr!(", starting at {} ms", ", начало на {} мс", ms)).unwrap_or_default();
+        writeln!(report, "{}", tr!("Most similar animation frame: {} of {}{}", "Самый похожий кадр анимации: {} из {}{}", best.frame, best.frames, time))?;
+    }
+    if let Some(kind) = copy_kind {
+        writeln!(report, "{}", tr!("Copy kind: {}", "Вид копии: {}", cli::copy_kind_word(kind)))?;
+    }
+    for scale in &scales {
+        writeln!(report, "{}", tr!("  grid {0}x{0}: {1:.2}%", "  сетка {0}x{0}: {1:.2}%", scale.grid, scale.similarity))?;
+    }
+    if let Some(raw_diff) = raw_diff {
+        writeln!(report, "{}", tr!("Signature difference: {}", "Разница сигнатур: {}", raw_diff))?;
+    }
+    if explain {
+        if cells.is_empty() {
+            writeln!(report, "{}", text("No difference: the signatures are equal", "Разницы нет: сигнатуры совпадают"))?;
+        } else {
+            writeln!(report, "{}", text("Largest contributions to the difference:", "Больше всего на разницу повлияли:"))?;
+        }
+        for cell in cells {
+            let grid = if options.is_multi_scale() { tr!("grid {0}x{0}, ", "сетка {0}x{0}, ", cell.grid) } else { String::new() };
+            let (a, b) = (cli::hex_color(cell.a), cli::hex_color(cell.b));
+            if cell.ignored > 0.0 && cell.distance == 0.0 {
+                writeln!(report, "{}", tr!("  {}cell ({},{}): A={} B={}, ignored (--ignore-worst)", "  {}ячейка ({},{}): A={} B={}, не учитывается (--ignore-worst)", grid, cell.x, cell.y, a, b))?;
+            } else {
+                writeln!(report, "{}", tr!("  {}cell ({},{}): A={} B={}, {:.1}% of the difference", "  {}ячейка ({},{}): A={} B={}, вклад {:.1}% разницы", grid, cell.x, cell.y, a, b, cell.percent))?;
+            }
+        }
+    }
+    output.emit(&report, &summary)?;
+    Ok(outcome)
+}
@@ -0,0 +1,190 @@
+//! Чтение файлов целиком: с функцией `mmap` на Unix - через отображение в память.
+//!
+//! Отображение избавляет от системных вызовов `read` и копирования в буфер: страницы
+//! подгружаются по мере обращения декодера. Оговорка: отображенный файл может поменяться,
+//! пока его читают. Если его перезапишут, декодер увидит смесь старых и новых данных,
+//! поэтому `with_bytes` после декодирования сверяет размер и время изменения файла (как
+//! `FileStamp`) и при расхождении читает файл заново обычным способом. Если файл укоротят,
+//! обращение к пропавшим страницам завершит процесс сигналом `SIGBUS`, и защиты от этого нет:
+//! не включайте `mmap` для каталогов, файлы в которых обрезают на месте. Если отобразить файл
+//! не удалось (сетевые файловые системы, специальные файлы), он читается обычным способом
+
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::{ImgAlgError, Result};
+
+/// Содержимое файла
+pub(crate) enum FileBytes {
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl FileBytes {
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        match self {
+            #[cfg(all(feature = "mmap", unix))]
+            Self::Mapped(mmap) => mmap.to_vec(),
+            Self::Buffered(bytes) => bytes,
+        }
+    }
+
+    /// Поменялся ли файл после отображения; у прочитанного в буфер - нет
+    fn is_stale(&self) -> bool {
+        match self {
+            #[cfg(all(feature = "mmap", unix))]
+            Self::Mapped(mmap) => mmap.is_stale(),
+            Self::Buffered(_) => false,
+        }
+    }
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(all(feature = "mmap", unix))]
+            Self::Mapped(mmap) => mmap,
+            Self::Buffered(bytes) => bytes,
+        }
+    }
+}
+
+/// Содержимое файла: отображение, если оно включено и удалось, иначе прочитанный буфер
+#[cfg(all(feature = "mmap", unix))]
+pub(crate) fn read(path: &Path) -> io::Result<FileBytes> {
+    match Mmap::open(path) {
+        Ok(mmap) => Ok(FileBytes::Mapped(mmap)),
+        Err(_) => fs::read(path).map(FileBytes::Buffered),
+    }
+}
+
+#[cfg(not(all(feature = "mmap", unix)))]
+pub(crate) fn read(path: &Path) -> io::Result<FileBytes> {
+    fs::read(path).map(FileBytes::Buffered)
+}
+
+/// Обрабатывает содержимое `bytes`, прочитанное `read`; если отображенный файл за это время
+/// поменялся, результат отбрасывается и файл `path` обрабатывается заново из буфера.
+/// Второе значение - пришлось ли читать заново
+pub(crate) fn with_bytes<T>(path: &Path, bytes: FileBytes, f: impl Fn(&[u8]) -> Result<T>) -> Result<(T, bool)> {
+    let result = f(&bytes);
+    if bytes.is_stale() {
+        drop(bytes);
+        return Ok((f(&fs::read(path).map_err(|e| ImgAlgError::io(path, e))?)?, true));
+    }
+    Ok((result?, false))
+}
+
+/// Файл, отображенный в память только для чтения
+#[cfg(all(feature = "mmap", unix))]
+pub(crate) struct Mmap {
+    ptr: std::ptr::NonNull<u8>,
+    len: usize,
+    /// Файл и его размер и время изменения в момент отображения
+    path: std::path::PathBuf,
+    stamp: (u64, Option<std::time::SystemTime>),
+}
+
+// Отображение только для чтения, без внутреннего изменяемого состояния
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Send for Mmap {}
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Sync for Mmap {}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Mmap {
+    fn open(path: &Path) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let file = fs::File::open(path)?;
+        let metadata = file.metadata()?;
+        let len = usize::try_from(metadata.len()).map_err(|_| io::Error::from(io::ErrorKind::FileTooLarge))?;
+        if !metadata.is_file() || len == 0 {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput)); // Пустой файл не отображается
+        }
+        // SAFETY: отображение только для чтения на весь файл; дескриптор можно закрыть сразу,
+        // отображение держит файл само. Изменение файла извне - см. описание модуля
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let ptr = std::ptr::NonNull::new(ptr.cast()).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+        Ok(Self { ptr, len, path: path.to_path_buf(), stamp: (metadata.len(), metadata.modified().ok()) })
+    }
+
+    fn is_stale(&self) -> bool {
+        fs::metadata(&self.path).map_or(true, |metadata| (metadata.len(), metadata.modified().ok()) != self.stamp)
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `len` байт по адресу `ptr` отображены и доступны для чтения до `drop`
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: адрес и длина - те, что вернул `mmap`
+        unsafe {
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mmap", unix))]
+mod tests {
+    use super::*;
+    use crate::{info, Signature};
+
+    /// PNG 40x30 с цветными полосами во временном каталоге
+    fn png(dir: &Path) -> std::path::PathBuf {
+        let path = dir.join("stripes.png");
+        image::RgbaImage::from_fn(40, 30, |x, y| image::Rgba([(x * 6) as u8, (y * 8) as u8, ((x + y) * 3) as u8, 255])).save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn mapped_file_decodes_like_a_buffered_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = png(dir.path());
+        let mapped = read(&path).unwrap();
+        assert!(matches!(mapped, FileBytes::Mapped(_)));
+        assert_eq!(&mapped[..], &fs::read(&path).unwrap()[..]);
+
+        let (image, reread) = with_bytes(&path, mapped, |bytes| info::decode(&path, bytes)).unwrap();
+        assert!(!reread);
+        let buffered = info::decode(&path, &fs::read(&path).unwrap()).unwrap();
+        assert_eq!(image, buffered);
+        let signatures = [&image.0, &buffered.0].map(|image| Signature::compute_from_image(image).unwrap());
+        assert_eq!(signatures[0].to_string(), signatures[1].to_string());
+    }
+
+    #[test]
+    fn file_changed_after_mapping_is_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = png(dir.path());
+        let mapped = read(&path).unwrap();
+        fs::write(&path, b"rewritten").unwrap();
+        let (len, reread) = with_bytes(&path, mapped, |bytes| Ok(bytes.len())).unwrap();
+        assert_eq!((len, reread), (b"rewritten".len(), true));
+    }
+
+    #[test]
+    fn empty_file_falls_back_to_a_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty");
+        fs::write(&path, b"").unwrap();
+        assert!(matches!(read(&path).unwrap(), FileBytes::Buffered(bytes) if bytes.is_empty()));
+    }
+}
@@ -0,0 +1,185 @@
+use rayon::prelude::*;
+use std::path::Path;
+
+use crate::{ComparerOptions, ImagesComparer, ImgAlgError, Signature};
+
+/// Как подбираются пары между наборами
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Assignment {
+    /// Пары по убыванию схожести, каждое изображение берется один раз. Быстро, но одна
+    /// очень похожая пара может занять изображение, нужное двум другим
+    #[default]
+    Greedy,
+    /// Венгерский алгоритм: наибольшая суммарная схожесть всех пар, `O(n²·m)`
+    Optimal,
+}
+
+/// Пара изображений из двух наборов
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SetMatch {
+    /// Позиции путей в наборах `a` и `b`
+    pub a: usize,
+    pub b: usize,
+    pub similarity: f32,
+}
+
+/// Итог `match_sets`
+#[derive(Debug, Default)]
+pub struct SetMatching {
+    /// Пары в порядке позиций в `a`
+    pub pairs: Vec<SetMatch>,
+    /// Загруженные изображения, которым не хватило пары, когда наборы разного размера
+    pub unmatched_a: Vec<usize>,
+    pub unmatched_b: Vec<usize>,
+}
+
+/// Сопоставляет каждому изображению набора `a` свое изображение набора `b`, без повторов
+/// изображений `b`. Схожесть - как у `ImagesComparer` с настройками `options`; файлы
+/// загружаются параллельно на `options.threads` потоках. Файлы, которые не удалось
+/// загрузить, в пары не входят, их ошибки возвращаются вторым значением
+pub fn match_sets<P: AsRef<Path> + Sync>(a: &[P], b: &[P], options: &ComparerOptions, assignment: Assignment) -> (SetMatching, Vec<ImgAlgError>) {
+    let mut errors = vec![];
+    let mut load = |paths: &[P]| -> Vec<(usize, Signature)> {
+        let mut loaded = vec![];
+        for (pos, load) in ImagesComparer::_load_all(paths, options).into_iter().enumerate() {
+            match load {
+                Ok((signature, _)) => loaded.push((pos, signature)),
+                Err(e) => errors.push(e),
+            }
+        }
+        loaded
+    };
+    let (a, b) = (load(a), load(b));
+
+    let matrix: Vec<Vec<f32>> = options.install(|| {
+        a.par_iter().map(|(_, a)| b.iter().map(|(_, b)| options.similarity(options.distance(a, b))).collect()).collect()
+    });
+    let assigned = match assignment {
+        Assignment::Greedy => greedy(&matrix, b.len()),
+        Assignment::Optimal => optimal(&matrix, b.len()),
+    };
+
+    let mut matching = SetMatching::default();
+    let mut used = vec![false; b.len()];
+    for (row, column) in assigned.iter().enumerate() {
+        match *column {
+            Some(column) => {
+                used[column] = true;
+                matching.pairs.push(SetMatch { a: a[row].0, b: b[column].0, similarity: matrix[row][column] });
+            }
+            None => matching.unmatched_a.push(a[row].0),
+        }
+    }
+    matching.unmatched_b = b.iter().zip(&used).filter(|(_, used)| !**used).map(|((pos, _), _)| *pos).collect();
+    (matching, errors)
+}
+
+/// Пары по убыванию схожести; при равной схожести - по позициям
+fn greedy(matrix: &[Vec<f32>], columns: usize) -> Vec<Option<usize>> {
+    let mut cells: Vec<(usize, usize)> = (0..matrix.len()).flat_map(|row| (0..columns).map(move |column| (row, column))).collect();
+    cells.sort_by(|&(ra, ca), &(rb, cb)| matrix[rb][cb].total_cmp(&matrix[ra][ca]).then((ra, ca).cmp(&(rb, cb))));
+    let mut assigned = vec![None; matrix.len()];
+    let mut used = vec![false; columns];
+    for (row, column) in cells {
+        if assigned[row].is_none() && !used[column] {
+            assigned[row] = Some(column);
+            used[column] = true;
+        }
+    }
+    assigned
+}
+
+/// Назначение с наибольшей суммой схожести: венгерский алгоритм с потенциалами для
+/// прямоугольной матрицы стоимостей `100 - схожесть`. Строк должно быть не больше столбцов,
+/// иначе матрица транспонируется
+fn optimal(matrix: &[Vec<f32>], columns: usize) -> Vec<Option<usize>> {
+    let rows = matrix.len();
+    if rows > columns {
+        let transposed: Vec<Vec<f32>> = (0..columns).map(|column| matrix.iter().map(|row| row[column]).collect()).collect();
+        let mut assigned = vec![None; rows];
+        for (column, row) in optimal(&transposed, rows).into_iter().enumerate() {
+            if let Some(row) = row {
+                assigned[row] = Some(column);
+            }
+        }
+        return assigned;
+    }
+    let cost = |row: usize, column: usize| 100.0 - matrix[row][column] as f64;
+    // Строки и столбцы нумеруются с 1, 0 - фиктивный столбец
+    let (mut u, mut v) = (vec![0.0; rows + 1], vec![0.0; columns + 1]);
+    let mut owner = vec![0usize; columns + 1];
+    let mut way = vec![0usize; columns + 1];
+    for row in 1..=rows {
+        owner[0] = row;
+        let mut column = 0;
+        let mut min = vec![f64::INFINITY; columns + 1];
+        let mut visited = vec![false; columns + 1];
+        loop {
+            visited[column] = true;
+            let current = owner[column];
+            let (mut delta, mut next) = (f64::INFINITY, 0);
+            for candidate in 1..=columns {
+                if visited[candidate] {
+                    continue;
+                }
+                let reduced = cost(current - 1, candidate - 1) - u[current] - v[candidate];
+                if reduced < min[candidate] {
+                    min[candidate] = reduced;
+                    way[candidate] = column;
+                }
+                if min[candidate] < delta {
+                    delta = min[candidate];
+                    next = candidate;
+                }
+            }
+            for candidate in 0..=columns {
+                if visited[candidate] {
+                    u[owner[candidate]] += delta;
+                    v[candidate] -= delta;
+                } else {
+                    min[candidate] -= delta;
+                }
+            }
+            column = next;
+            if owner[column] == 0 {
+                break;
+            }
+        }
+        // Чередующаяся цепочка от свободного столбца обратно к фиктивному
+        while column != 0 {
+            let previous = way[column];
+            owner[column] = owner[previous];
+            column = previous;
+        }
+    }
+    let mut assigned = vec![None; rows];
+    for column in 1..=columns {
+        if owner[column] != 0 {
+            assigned[owner[column] - 1] = Some(column - 1);
+        }
+    }
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimal_beats_greedy_on_a_contested_image() {
+        // Жадный подбор отдает первому изображению столбец 0, и второму достается 10%
+        let matrix = vec![vec![90.0, 80.0], vec![85.0, 10.0]];
+        assert_eq!(greedy(&matrix, 2), [Some(0), Some(1)]);
+        assert_eq!(optimal(&matrix, 2), [Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn leftover_rows_and_columns_stay_unassigned() {
+        let tall = vec![vec![10.0, 90.0], vec![95.0, 20.0], vec![30.0, 40.0]];
+        assert_eq!(greedy(&tall, 2), [Some(1), Some(0), None]);
+        assert_eq!(optimal(&tall, 2), [Some(1), Some(0), None]);
+        let wide = vec![vec![10.0, 20.0, 99.0]];
+        assert_eq!(optimal(&wide, 3), [Some(2)]);
+        assert!(optimal(&[], 0).is_empty());
+    }
+}
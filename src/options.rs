@@ -0,0 +1,587 @@
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::signature::{self, Grid, Plane, Signature, GRID_SIZE, MULTI_SCALE_GRIDS};
+use crate::{info, CancelToken, ImgAlgError, Rect, Result, ToleranceMap, Transform};
+
+/// Канал изображения
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    A,
+}
+
+/// Какие каналы участвуют в сравнении
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelSelect {
+    /// Цвет и прозрачность
+    All,
+    /// Только цвет, прозрачность не учитывается (как раньше)
+    #[default]
+    Rgb,
+    /// Только один канал, веса каналов при этом не используются
+    Single(Channel),
+}
+
+/// Настройки сравнения сигнатур. Значения по умолчанию дают прежние результаты
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparerOptions {
+    channel_weights: [f32; 3],
+    channels: ChannelSelect,
+    ignore_hue: bool,
+    multi_scale: bool,
+    scale_weights: [f32; 3],
+    decode_timeout: Option<Duration>,
+    retries: u32,
+    retry_delay: Duration,
+    cancel: Option<CancelToken>,
+    threads: usize,
+    pool: PoolCache,
+    crop: Option<Rect>,
+    transform: Transform,
+    ignore_worst: usize,
+    tolerance: Option<Arc<ToleranceMap>>,
+    letterbox: bool,
+    strict_aspect: bool,
+    ensemble: bool,
+}
+
+/// Пул потоков, общий у копий настроек; `threads` заводит новый
+#[derive(Clone, Default)]
+struct PoolCache(Arc<OnceLock<std::result::Result<Arc<ThreadPool>, String>>>);
+
+impl fmt::Debug for PoolCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PoolCache")
+    }
+}
+
+/// Пул выводится из `threads`, так что на равенство настроек он не влияет
+impl PartialEq for PoolCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Default for ComparerOptions {
+    fn default() -> Self {
+        Self {
+            channel_weights: [1.0; 3],
+            channels: ChannelSelect::default(),
+            ignore_hue: false,
+            multi_scale: false,
+            scale_weights: [1.0 / 3.0; 3],
+            decode_timeout: None,
+            retries: 0,
+            retry_delay: Duration::from_millis(100),
+            cancel: None,
+            threads: 0,
+            pool: PoolCache::default(),
+            crop: None,
+            transform: Transform::Square,
+            ignore_worst: 0,
+            tolerance: None,
+            letterbox: false,
+            strict_aspect: false,
+            ensemble: false,
+        }
+    }
+}
+
+impl ComparerOptions {
+    /// Наибольшее значение `ignore_worst`: одна восьмая сетки 8x8
+    pub const MAX_IGNORE_WORST: usize = 8;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Веса каналов R, G, B в разнице сигнатур.
+    ///
+    /// Веса нормируются к сумме 3, так что шкала схожести от их суммы не зависит:
+    /// `[2, 2, 2]` и `[1, 1, 1]` дают одинаковый результат
+    pub fn channel_weights(mut self, weights: [f32; 3]) -> Result<Self> {
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(ImgAlgError::InvalidOptions("channel weights must be non-negative numbers".to_string()));
+        }
+        let sum: f32 = weights.iter().sum();
+        if sum == 0.0 {
+            return Err(ImgAlgError::InvalidOptions("at least one channel weight must be positive".to_string()));
+        }
+        self.channel_weights = weights.map(|w| w * 3.0 / sum);
+        Ok(self)
+    }
+
+    /// Каналы, по которым сравниваются изображения
+    pub fn channels(mut self, channels: ChannelSelect) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Сравнивать цвет по насыщенности и яркости без учета тона, чтобы перекрашенные
+    /// копии находились как похожие. Заменяет каналы R, G, B (и их веса), альфа-канал
+    /// при `ChannelSelect::All` по-прежнему учитывается. При выборе одного канала не действует
+    pub fn ignore_hue(mut self, ignore_hue: bool) -> Self {
+        self.ignore_hue = ignore_hue;
+        self
+    }
+
+    /// Считать сигнатуры сразу на сетках 8x8, 16x16 и 32x32 и усреднять их разницы.
+    /// Влияет на загрузку изображений, поэтому передается в `ImagesComparer::new_lossy_with`
+    pub fn multi_scale(mut self, multi_scale: bool) -> Self {
+        self.multi_scale = multi_scale;
+        self
+    }
+
+    /// Веса сеток 8x8, 16x16 и 32x32 в многомасштабной разнице, по умолчанию равные.
+    /// Нормируются к сумме 1
+    pub fn scale_weights(mut self, weights: [f32; 3]) -> Result<Self> {
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(ImgAlgError::InvalidOptions("scale weights must be non-negative numbers".to_string()));
+        }
+        let sum: f32 = weights.iter().sum();
+        if sum == 0.0 {
+            return Err(ImgAlgError::InvalidOptions("at least one scale weight must be positive".to_string()));
+        }
+        self.scale_weights = weights.map(|w| w / sum);
+        Ok(self)
+    }
+
+    /// Ограничение времени декодирования одного файла. Декодирование идет в отдельном потоке;
+    /// по истечении времени файл считается ошибкой `Timeout`, а поток не останавливается
+    /// (прервать декодер нельзя), его результат просто отбрасывается
+    pub fn decode_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.decode_timeout = timeout;
+        self
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.decode_timeout
+    }
+
+    /// Сколько раз перечитывать файл после временной ошибки (`ImgAlgError::is_transient`),
+    /// например с сетевого диска, и пауза перед первым повтором; каждая следующая вдвое длиннее.
+    /// Если и последний повтор не удался - `ImgAlgError::Retried`. По умолчанию повторов нет
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Результат `load` и число повторов до него; между повторами проверяется отмена
+    pub(crate) fn retrying<T>(&self, mut load: impl FnMut() -> Result<T>) -> Result<(T, u32)> {
+        let mut retries = 0;
+        loop {
+            match load() {
+                Ok(value) => return Ok((value, retries)),
+                Err(e) if e.is_transient() && retries < self.retries => {
+                    std::thread::sleep(self.retry_delay.saturating_mul(1 << retries.min(16)));
+                    self.check_cancelled()?;
+                    retries += 1;
+                }
+                Err(e) if retries > 0 => return Err(ImgAlgError::Retried { retries, source: Box::new(e) }),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Флаг отмены: после `cancel` загрузка останавливается перед следующим файлом
+    /// (в ошибках загрузки появляется `Cancelled`, уже загруженное остается), а построчное
+    /// сравнение (`for_each_row`) - перед следующей строкой с ошибкой `Cancelled`
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// `Cancelled`, если отмена запрошена
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        self.cancel.as_ref().map_or(Ok(()), CancelToken::check)
+    }
+
+    /// Число потоков для загрузки и сравнения, 0 - по числу ядер. Работа идет в собственном
+    /// пуле `rayon` на это число потоков, глобальный пул не используется и не настраивается.
+    /// При 1 все выполняется по очереди. Результаты и их порядок от числа потоков не зависят
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self.pool = PoolCache::default();
+        self
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads
+    }
+
+    /// Пул потоков для `threads`. Строится при первом обращении и дальше общий у всех
+    /// копий настроек; если потоки запустить не удалось - `ThreadPool`
+    pub(crate) fn thread_pool(&self) -> Result<Arc<ThreadPool>> {
+        let pool = self.pool.0.get_or_init(|| {
+            let built = ThreadPoolBuilder::new().num_threads(self.threads).thread_name(|index| format!("imgalg-worker-{index}")).build();
+            built.map(Arc::new).map_err(|e| e.to_string())
+        });
+        pool.clone().map_err(ImgAlgError::ThreadPool)
+    }
+
+    /// Выполняет `op` в пуле `thread_pool`. Для операций без ошибок: если потоки запустить
+    /// не удалось, `op` выполняется в общем пуле rayon. До них пул уже строится при загрузке
+    /// изображений, которая эту ошибку возвращает
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match self.thread_pool() {
+            Ok(pool) => pool.install(op),
+            Err(_) => op(),
+        }
+    }
+
+    /// Сравнивать только область `crop` каждого изображения, например место виджета
+    /// на снимке экрана. Область вырезается из декодированного изображения до уменьшения;
+    /// если она не помещается в изображение, его загрузка - ошибка `CropOutOfBounds`.
+    /// Отдельную область для одного изображения задает `ImagesComparer::add_image_cropped`
+    pub fn crop(mut self, crop: Option<Rect>) -> Self {
+        self.crop = crop;
+        self
+    }
+
+    pub fn crop_rect(&self) -> Option<Rect> {
+        self.crop
+    }
+
+    /// Во что переводятся значения каналов перед вычислением разностей, по умолчанию
+    /// `Transform::Square`, как раньше. Как и многомасштабность, применяется при загрузке:
+    /// изменение на уже загруженные сигнатуры не влияет. Индекс `SignatureIndex` записывает
+    /// преобразование своих сигнатур, см. `SignatureIndex::load_with`. Показатель `Gamma`
+    /// должен быть положительным
+    pub fn value_transform(mut self, transform: Transform) -> Result<Self> {
+        self.transform = transform.validate()?;
+        Ok(self)
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Не учитывать в разнице пары `n` ячеек с наибольшим вкладом, чтобы битые пиксели, пылинки
+    /// на сканах и мелкие наложенные надписи не решали, дубликаты ли снимки. Ячейка - номер
+    /// разности в сигнатуре, ее вклад - сумма слагаемых всех сравниваемых каналов; у многомасштабной
+    /// сигнатуры отбрасывается по `n` ячеек каждой сетки. Оставшаяся разница умножается на
+    /// `ячеек / (ячеек - n)`, так что шкала схожести не сдвигается. Не больше `MAX_IGNORE_WORST`
+    pub fn ignore_worst(mut self, n: usize) -> Result<Self> {
+        if n > Self::MAX_IGNORE_WORST {
+            return Err(ImgAlgError::InvalidOptions(format!("at most {} worst cells can be ignored, got {n}", Self::MAX_IGNORE_WORST)));
+        }
+        self.ignore_worst = n;
+        Ok(self)
+    }
+
+    pub fn ignored_cells(&self) -> usize {
+        self.ignore_worst
+    }
+
+    /// Карта допусков: в каких местах и насколько различия не учитываются, см. `ToleranceMap`.
+    /// Размеры каждого изображения (или области `crop`) должны совпадать с размерами карты,
+    /// иначе его загрузка - ошибка `ToleranceMapSize`. Карта действует только на сигнатуры,
+    /// посчитанные из изображений: у разобранных из текста или индекса нет цветов ячеек,
+    /// по которым находятся места разностей, и они сравниваются без допусков
+    pub fn tolerance_map(mut self, map: Option<ToleranceMap>) -> Self {
+        self.tolerance = map.map(Arc::new);
+        self
+    }
+
+    pub fn tolerance(&self) -> Option<&ToleranceMap> {
+        self.tolerance.as_deref()
+    }
+
+    /// Вписывать изображение по центру в квадрат с полями его среднего цвета перед уменьшением,
+    /// чтобы изображение и его обрезка с другим соотношением сторон (3:2 и 4:5) не растягивались
+    /// по-разному. Разности с ячейками полей весят меньше. Как и преобразование значений,
+    /// применяется при загрузке; такие сигнатуры сравниваются только между собой
+    /// (`SignatureMismatch`), а с картой допусков загрузка - ошибка `InvalidOptions`
+    pub fn letterbox(mut self, letterbox: bool) -> Self {
+        self.letterbox = letterbox;
+        self
+    }
+
+    pub fn is_letterbox(&self) -> bool {
+        self.letterbox
+    }
+
+    /// Карта допусков размечена по исходному изображению, а ячейки вписанного в квадрат
+    /// изображения ей не соответствуют. Фильтры `ensemble` в квадрат не вписывают
+    pub(crate) fn check_letterbox(&self) -> Result<()> {
+        if self.letterbox && self.tolerance.is_some() {
+            return Err(ImgAlgError::InvalidOptions("letterbox cannot be combined with a tolerance map".to_string()));
+        }
+        if self.letterbox && self.ensemble {
+            return Err(ImgAlgError::InvalidOptions("letterbox cannot be combined with the filter ensemble".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Оценивать неуверенность схожести: при загрузке, по тому же декодированному изображению,
+    /// считать еще сигнатуры с уменьшением фильтрами Triangle и CatmullRom, а схожесть пары
+    /// (`PairResult`) - средним по трем уменьшениям с разбросом `PairResult::spread`. Вдвое-втрое
+    /// дороже обычной загрузки; с `letterbox` загрузка - ошибка `InvalidOptions`
+    pub fn ensemble(mut self, ensemble: bool) -> Self {
+        self.ensemble = ensemble;
+        self
+    }
+
+    pub fn is_ensemble(&self) -> bool {
+        self.ensemble
+    }
+
+    /// Не загружать изображения, вытянутые больше чем в `MAX_ASPECT_RATIO` раз: вместо
+    /// предупреждения `ExtremeAspect` их загрузка - ошибка `ExtremeAspect`. На схожесть
+    /// загруженных изображений не влияет
+    pub fn strict_aspect(mut self, strict: bool) -> Self {
+        self.strict_aspect = strict;
+        self
+    }
+
+    pub fn is_strict_aspect(&self) -> bool {
+        self.strict_aspect
+    }
+
+    /// С `strict_aspect` слишком вытянутое изображение - ошибка
+    pub(crate) fn check_aspect(&self, path: Option<&Path>, width: u32, height: u32) -> Result<()> {
+        if self.strict_aspect && info::is_extreme_aspect(width, height) {
+            return Err(ImgAlgError::ExtremeAspect { path: path.map(Path::to_path_buf), width, height });
+        }
+        Ok(())
+    }
+
+    pub fn is_multi_scale(&self) -> bool {
+        self.multi_scale
+    }
+
+    /// Описание вычисления и сравнения сигнатур с этими настройками в виде `signature::PIPELINE`:
+    /// сетки и преобразование значений подставляются, а остальные настройки, отличные
+    /// от значений по умолчанию, дописываются в конец
+    pub(crate) fn pipeline(&self) -> String {
+        let grids: Vec<String> = self.grid_sizes().iter().map(u32::to_string).collect();
+        let mut parts: Vec<String> = signature::PIPELINE
+            .split(';')
+            .map(|part| match part.split_once('=') {
+                Some(("grid", _)) => format!("grid={}", grids.join(",")),
+                Some(("filter", filter)) if self.letterbox => format!("filter={filter}+letterbox"),
+                Some(("values", _)) if self.transform != Transform::Square => format!("values={}", self.transform),
+                _ => part.to_string(),
+            })
+            .collect();
+        let list = |values: [f32; 3]| values.map(|value| value.to_string()).join(",");
+        let default = Self::default();
+        if self.channels != default.channels {
+            let channels = match self.channels {
+                ChannelSelect::All => "all",
+                ChannelSelect::Rgb => "rgb",
+                ChannelSelect::Single(Channel::R) => "r",
+                ChannelSelect::Single(Channel::G) => "g",
+                ChannelSelect::Single(Channel::B) => "b",
+                ChannelSelect::Single(Channel::A) => "a",
+            };
+            parts.push(format!("channels={channels}"));
+        }
+        if self.channel_weights != default.channel_weights {
+            parts.push(format!("weights={}", list(self.channel_weights)));
+        }
+        if self.ignore_hue {
+            parts.push("ignore_hue=true".to_string());
+        }
+        if self.multi_scale && self.scale_weights != default.scale_weights {
+            parts.push(format!("scale_weights={}", list(self.scale_weights)));
+        }
+        if let Some(crop) = self.crop {
+            parts.push(format!("crop={crop}"));
+        }
+        if self.ignore_worst > 0 {
+            parts.push(format!("ignore_worst={}", self.ignore_worst));
+        }
+        if let Some(map) = &self.tolerance {
+            let (width, height) = map.dimensions();
+            parts.push(format!("tolerance_map={width}x{height}@{:016x}", map.digest()));
+        }
+        if self.ensemble {
+            let filters: Vec<&str> = signature::ENSEMBLE_FILTERS.iter().map(|&(_, name)| name).collect();
+            parts.push(format!("ensemble=area,{}", filters.join(",")));
+        }
+        parts.join(";")
+    }
+
+    /// Стороны сеток, которые нужно посчитать при загрузке
+    pub(crate) fn grid_sizes(&self) -> &'static [u32] {
+        if self.multi_scale { &MULTI_SCALE_GRIDS } else { &[GRID_SIZE] }
+    }
+
+    /// Нормированные веса каналов
+    pub fn weights(&self) -> [f32; 3] {
+        self.channel_weights
+    }
+
+    /// Разница двух сигнатур с учетом выбранных каналов и весов.
+    /// У многомасштабных сигнатур - взвешенная сумма разниц сеток, приведенных к 16x16.
+    /// Без `ignore_worst` и карты допусков память в куче не выделяется
+    #[inline]
+    pub(crate) fn distance(&self, a: &Signature, b: &Signature) -> f64 {
+        let diff = match (a.grids(), b.grids()) {
+            ([a], [b]) => self.grid_distance(a, b),
+            (a_grids, b_grids) => {
+                let count = a_grids.len().min(b_grids.len());
+                a_grids.iter().zip(b_grids).enumerate().map(|(idx, (a, b))| self.grid_distance(a, b) * Self::grid_scale(a) * self.scale_weight(idx, count)).sum()
+            }
+        };
+        debug_assert!(diff.is_finite() && diff >= 0.0, "signature distance {diff} is out of range");
+        diff
+    }
+
+    /// Вес сетки `idx` из `count` в многомасштабной разнице; при другом числе сеток веса равны
+    fn scale_weight(&self, idx: usize, count: usize) -> f64 {
+        if count == self.scale_weights.len() { f64::from(self.scale_weights[idx]) } else { 1.0 / count as f64 }
+    }
+
+    /// Множитель приведения разницы сетки к единицам сетки 16x16
+    fn grid_scale(grid: &Grid) -> f64 {
+        (GRID_SIZE * GRID_SIZE) as f64 / (grid.size() * grid.size()) as f64
+    }
+
+    /// Множитель каждой сетки в `distance`: приведение к 16x16 и вес сетки,
+    /// у обычной сигнатуры - 1.0
+    pub(crate) fn grid_factors(&self, a: &Signature) -> Vec<f64> {
+        match a.grids() {
+            [_] => vec![1.0],
+            grids => grids.iter().enumerate().map(|(idx, grid)| Self::grid_scale(grid) * self.scale_weight(idx, grids.len())).collect(),
+        }
+    }
+
+    /// Разница по каждой сетке, приведенная к единицам сетки 16x16
+    pub(crate) fn scale_distances(&self, a: &Signature, b: &Signature) -> Vec<(u32, f64)> {
+        a.grids()
+            .iter()
+            .zip(b.grids())
+            .map(|(a, b)| (a.size(), self.grid_distance(a, b) * Self::grid_scale(a)))
+            .collect()
+    }
+
+    fn grid_distance(&self, a: &Grid, b: &Grid) -> f64 {
+        if self.ignore_worst == 0 && self.tolerance.is_none() {
+            return self.planes().map(|(plane, weight)| a.plane_distance(b, plane) * weight).sum();
+        }
+        let cells = self.cell_terms(a, b);
+        let worst = worst_cells(&cells, self.ignore_worst);
+        let kept: f64 = cells.iter().enumerate().filter(|(k, _)| !worst.contains(k)).map(|(_, term)| term).sum();
+        kept * self.kept_scale(a)
+    }
+
+    /// Вклады ячеек сетки в разницу (по номерам разностей) с весами каналов и допусками
+    fn cell_terms(&self, a: &Grid, b: &Grid) -> Vec<f64> {
+        let mut cells: Vec<f64> = vec![];
+        for (plane, weight) in self.planes() {
+            let terms = self.tolerated_terms(a, b, plane);
+            if cells.len() < terms.len() {
+                cells.resize(terms.len(), 0.0);
+            }
+            for (cell, term) in cells.iter_mut().zip(terms) {
+                *cell += term * weight;
+            }
+        }
+        cells
+    }
+
+    /// Слагаемые последовательности по номерам разностей за вычетом допусков карты
+    fn tolerated_terms(&self, a: &Grid, b: &Grid, plane: Plane) -> Vec<f64> {
+        let terms = a.entry_terms(b, plane);
+        let Some(map) = &self.tolerance else {
+            return terms;
+        };
+        let cells = a.plane_terms(b, plane);
+        if cells.len() != terms.len() {
+            return terms; // Цвета ячеек не сохранены
+        }
+        cells.into_iter().map(|(cell, term)| map.reduce(a.size(), cell, term)).collect()
+    }
+
+    /// Слагаемое `term` на ячейке `cell` сетки `size` за вычетом допуска карты, если она задана
+    pub(crate) fn tolerated(&self, size: u32, cell: usize, term: f64) -> f64 {
+        self.tolerance.as_ref().map_or(term, |map| map.reduce(size, cell, term))
+    }
+
+    /// Номера разностей, которые `ignore_worst` отбрасывает у пары сеток
+    pub(crate) fn ignored_entries(&self, a: &Grid, b: &Grid) -> Vec<usize> {
+        if self.ignore_worst == 0 {
+            return vec![];
+        }
+        worst_cells(&self.cell_terms(a, b), self.ignore_worst)
+    }
+
+    /// Множитель оставшейся разницы сетки: она приводится ко всем ячейкам. 1.0 без `ignore_worst`
+    pub(crate) fn kept_scale(&self, grid: &Grid) -> f64 {
+        let cells = (grid.size() * grid.size()) as f64;
+        cells / (cells - self.ignore_worst as f64).max(1.0)
+    }
+
+    /// Последовательности сетки, которые входят в разницу, и их веса.
+    /// Вес 1.0 не меняет слагаемое, каналы с нулевым весом не считаются.
+    /// Итератор по массиву на стеке: разница считается без выделения памяти
+    pub(crate) fn planes(&self) -> impl Iterator<Item = (Plane, f64)> + use<> {
+        let color = |channel: usize| Some((Plane::Color(channel), self.channel_weights[channel] as f64)).filter(|&(_, weight)| weight != 0.0);
+        let (saturation, value, alpha) = (Some((Plane::Saturation, 1.0)), Some((Plane::Value, 1.0)), Some((Plane::Alpha, 1.0)));
+        let planes = match self.channels {
+            ChannelSelect::Rgb if self.ignore_hue => [saturation, value, None, None],
+            ChannelSelect::All if self.ignore_hue => [saturation, value, alpha, None],
+            ChannelSelect::Rgb => [color(0), color(1), color(2), None],
+            ChannelSelect::All => [color(0), color(1), color(2), alpha],
+            ChannelSelect::Single(Channel::A) => [alpha, None, None, None],
+            ChannelSelect::Single(channel) => [Some((Plane::Color(channel as usize), 1.0)), None, None, None],
+        };
+        planes.into_iter().flatten()
+    }
+
+    /// Процент схожести для разницы, посчитанной `distance`
+    #[inline]
+    pub(crate) fn similarity(&self, diff: f64) -> f32 {
+        let channels_count = match self.channels {
+            // Без тона цвет описывают два канала: насыщенность и яркость
+            ChannelSelect::All if self.ignore_hue => 3.0,
+            ChannelSelect::Rgb if self.ignore_hue => 2.0,
+            ChannelSelect::All => 4.0,
+            ChannelSelect::Rgb => 3.0,
+            ChannelSelect::Single(_) => 1.0,
+        };
+        signature::similarity_from_channels_diff(diff, channels_count)
+    }
+}
+
+/// Номера `n` ячеек с наибольшим вкладом; при равном вкладе - с меньшим номером
+fn worst_cells(cells: &[f64], n: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..cells.len()).collect();
+    order.sort_by(|&l, &r| cells[r].total_cmp(&cells[l]).then(l.cmp(&r)));
+    order.truncate(n);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_is_built_once_and_shared_by_copies() {
+        let options = ComparerOptions::new().threads(2);
+        let pool = options.thread_pool().unwrap();
+        assert_eq!(pool.current_num_threads(), 2);
+        assert!(Arc::ptr_eq(&pool, &options.thread_pool().unwrap()));
+        assert!(Arc::ptr_eq(&pool, &options.clone().thread_pool().unwrap()));
+
+        let resized = options.clone().threads(3);
+        assert_eq!(resized.thread_pool().unwrap().current_num_threads(), 3);
+        assert_eq!(options.install(rayon::current_num_threads), 2);
+    }
+
+    #[test]
+    fn worst_cells_prefer_the_lower_index_on_ties() {
+        assert_eq!(worst_cells(&[1.0, 5.0, 0.0, 5.0, 3.0], 3), [1, 3, 4]);
+        assert!(ComparerOptions::new().ignore_worst(ComparerOptions::MAX_IGNORE_WORST).is_ok());
+        assert!(matches!(ComparerOptions::new().ignore_worst(ComparerOptions::MAX_IGNORE_WORST + 1), Err(ImgAlgError::InvalidOptions(_))));
+    }
+}
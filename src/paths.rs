@@ -0,0 +1,129 @@
+//! Пути, которые не являются строками UTF-8.
+//!
+//! На Unix имя файла - любые байты, кроме `/` и нулевого, и старые архивы с камер часто
+//! содержат имена в однобайтовых кодировках. Внутри библиотеки пути хранятся как `PathBuf`
+//! без преобразований; здесь собраны переводы пути в байты для индекса и в строку для
+//! отчетов, которые ничего не теряют. На Windows имена - последовательности UTF-16, и в UTF-8
+//! не переводятся только имена с непарными суррогатами: они пишутся с заменой символов.
+//! Длинные пути Windows (больше 260 символов) отдельной обработки не требуют: `std::fs`
+//! сама добавляет к ним префикс `\\?\`, а в `PathBuf` путь хранится без него
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Байты пути для записи в файл: на Unix - как есть
+#[cfg(unix)]
+pub fn to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
+
+/// Путь из байтов, записанных `to_bytes`
+#[cfg(unix)]
+pub fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Путь строкой для JSON и CSV. Путь в UTF-8 выводится как есть. В остальных путях байты,
+/// которые не складываются в символы UTF-8, и сам знак `%` записываются как `%XX`:
+/// `/фото/IMG_%FF.jpg`. Обратное преобразование - `decode`; путь, в котором нет `%`,
+/// всегда настоящий
+pub fn encode(path: &Path) -> Cow<'_, str> {
+    if let Some(text) = path.to_str() {
+        return Cow::Borrowed(text);
+    }
+    let mut encoded = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '%' => encoded.push_str("%25"),
+                c => encoded.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Путь из строки `encode`: если файла с таким путем нет, а в строке есть `%XX`,
+/// последовательности раскодируются в байты
+pub fn decode(text: &str) -> PathBuf {
+    if !text.contains('%') || Path::new(text).exists() {
+        return PathBuf::from(text);
+    }
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, hex) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    from_bytes(bytes)
+}
+
+/// Части пути до и после каждого вхождения разделителя `separator` из символов ASCII,
+/// слева направо. Путь может быть не в UTF-8: режутся байты, а не строка
+pub(crate) fn splits<'a>(path: &'a OsStr, separator: &'a str) -> impl Iterator<Item = (&'a OsStr, &'a OsStr)> + 'a {
+    debug_assert!(separator.is_ascii() && !separator.is_empty());
+    let bytes = path.as_encoded_bytes();
+    (0..bytes.len().saturating_sub(separator.len() - 1)).filter(move |&pos| bytes[pos..].starts_with(separator.as_bytes())).map(move |pos| {
+        // SAFETY: это байты `as_encoded_bytes`, разрезанные по границам непустой подстроки
+        // UTF-8 (разделителя), что документация `OsStr` разрешает
+        unsafe {
+            (OsStr::from_encoded_bytes_unchecked(&bytes[..pos]), OsStr::from_encoded_bytes_unchecked(&bytes[pos + separator.len()..]))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_path_is_written_as_is() {
+        assert_eq!(encode(Path::new("/фото/100%.jpg")), "/фото/100%.jpg");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_bytes_and_percent_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(b"/tmp/imgalg-missing/\xd1\x84\xff%41.jpg"));
+        let encoded = encode(path);
+        assert_eq!(encoded, "/tmp/imgalg-missing/ф%FF%2541.jpg");
+        assert_eq!(decode(&encoded), path);
+        assert_eq!(from_bytes(to_bytes(path).into_owned()), path);
+    }
+
+    #[test]
+    fn splits_cut_at_every_separator() {
+        let parts: Vec<_> = splits(OsStr::new("a.zip!/b.zip!/c.png"), "!/").collect();
+        assert_eq!(parts, [(OsStr::new("a.zip"), OsStr::new("b.zip!/c.png")), (OsStr::new("a.zip!/b.zip"), OsStr::new("c.png"))]);
+    }
+}
@@ -0,0 +1,54 @@
+//! Все, что нужно для обычного сравнения, одним импортом: `use imgalg::prelude::*;`.
+//!
+//! Состав прелюдии меняется только вместе со старшей версией: добавленное имя могло бы
+//! столкнуться с именами пользователя, импортированными через `*`.
+//!
+//! Сравнение двух изображений без прямой зависимости от `image`:
+//!
+//! ```
+//! use imgalg::prelude::*;
+//!
+//! fn main() -> Result<()> {
+//!     # let dir = tempfile::tempdir().unwrap();
+//!     # let (a, b) = (dir.path().join("a.png"), dir.path().join("b.png"));
+//!     # RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 90, 255])).save(&a).unwrap();
+//!     # RgbaImage::from_fn(128, 128, |x, y| Rgba([(x * 2) as u8, (y * 2) as u8, 90, 255])).save(&b).unwrap();
+//!     let options = ComparerOptions::new().multi_scale(true);
+//!     let (comparer, errors) = ImagesComparer::new_lossy_with(&[a, b], options);
+//!     if let Some(error) = errors.into_iter().next() {
+//!         return Err(error);
+//!     }
+//!     let pair: PairResult = comparer.compare_pair(0, 1)?;
+//!     println!("{:.2}%, {:?}", pair.similarity, comparer.verdict(0, 1)?);
+//!     assert!(SimilarityThreshold::DEFAULT.is_met_by(pair.similarity));
+//!
+//!     // Сигнатуры можно хранить самостоятельно и сравнивать без сравнителя
+//!     let image: DynamicImage = RgbaImage::from_pixel(64, 64, Rgba([200, 80, 40, 255])).into();
+//!     let signature = Signature::compute_from_image(&image)?;
+//!     let stored: Signature = signature.to_string().parse()?;
+//!     assert_eq!(compare_signatures(&signature, &stored, &ComparerOptions::default())?, 100.0);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Внутренние помощники сравнителя в API не входят:
+//!
+//! ```compile_fail
+//! imgalg::ImagesComparer::_get_pixels_diff("a.png", &[16], None, imgalg::Transform::Square, false, false);
+//! ```
+//!
+//! ```compile_fail
+//! imgalg::ImagesComparer::_get_bytes_pixels_diff(std::path::Path::new("a.png"), &[]);
+//! ```
+//!
+//! ```compile_fail
+//! let (comparer, _) = imgalg::ImagesComparer::new_lossy(&["a.png", "b.png"]);
+//! comparer._similarity(0, 1);
+//! ```
+
+pub use crate::bench::Algorithm;
+pub use crate::image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+pub use crate::{
+    compare_signatures, BoundedComparer, CancelToken, Channel, CopyKind, ChannelSelect, ComparerOptions, Fingerprint, ImageInfo, ImagesComparer,
+    ImgAlgError, PairResult, Rect, Result, Signature, SimilarityThreshold, ToleranceMap, Transform, Verdict,
+};
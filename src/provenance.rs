@@ -0,0 +1,134 @@
+use std::ffi::OsString;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::index::INDEX_VERSION;
+use crate::{signature, ComparerOptions};
+
+/// Чем и как записан файл: индекс или отчет. По нему через месяцы можно узнать,
+/// какая версия imgalg и с какими настройками его создала
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Версия imgalg; `None` у индексов, записанных до появления этих сведений
+    pub version: Option<String>,
+    /// Версия формата индекса и сигнатур (`INDEX_VERSION` в момент записи)
+    pub format_version: u32,
+    /// Описание вычисления сигнатур: `algorithm=color-diff;grid=16;filter=area;...`,
+    /// у отчетов - с настройками сравнения, отличными от значений по умолчанию
+    pub pipeline: String,
+    /// Время записи в секундах от начала эпохи Unix, если известно
+    pub created: Option<u64>,
+    /// Командная строка, если файл записан командой `imgalg`
+    pub command_line: Option<Vec<OsString>>,
+}
+
+impl Provenance {
+    /// Сведения для файла, который записывается сейчас, с сигнатурами по умолчанию (как в индексе)
+    pub fn current() -> Self {
+        Self::with_pipeline(signature::PIPELINE.to_string())
+    }
+
+    /// Сведения для отчета по сравнению с настройками `options`
+    pub fn for_options(options: &ComparerOptions) -> Self {
+        Self::with_pipeline(options.pipeline())
+    }
+
+    /// Время записи - из `SOURCE_DATE_EPOCH`, если она задана: повторный запуск дает тот же файл
+    fn with_pipeline(pipeline: String) -> Self {
+        let created = match std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.trim().parse().ok()) {
+            Some(epoch) => Some(epoch),
+            None => SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|since| since.as_secs()),
+        };
+        Self { version: Some(env!("CARGO_PKG_VERSION").to_string()), format_version: INDEX_VERSION, pipeline, created, command_line: None }
+    }
+
+    /// С командной строкой, например `std::env::args_os()`
+    pub fn command_line<I: IntoIterator<Item = OsString>>(mut self, args: I) -> Self {
+        self.command_line = Some(args.into_iter().collect());
+        self
+    }
+
+    /// Канонический идентификатор вычисления из `pipeline`, см. `algorithm_id`. У индекса
+    /// описание вычисления записано в заголовке, так что идентификатор известен и для старых файлов
+    pub fn algorithm_id(&self) -> String {
+        id_from_pipeline(&self.pipeline)
+    }
+
+    /// Значение настройки из `pipeline`, например `setting("grid")` - `Some("16")`
+    pub fn setting(&self, key: &str) -> Option<&str> {
+        self.pipeline.split(';').find_map(|part| part.split_once('=').filter(|(name, _)| *name == key).map(|(_, value)| value))
+    }
+
+    /// Время записи в UTC вида `2024-05-17T09:30:00Z`
+    pub fn created_utc(&self) -> Option<String> {
+        self.created.map(format_utc)
+    }
+}
+
+/// Канонический идентификатор алгоритма с настройками `options`, например
+/// `color-diff-v2(grid=16,filter=area,color=rgba8,values=squared)`. Он меняется вместе
+/// с любой настройкой, от которой зависят числа (сетки, вписывание, преобразование значений,
+/// каналы и веса, без тона, область, `ignore_worst`, карта допусков), и не зависит от прочих
+/// вроде числа потоков. Проценты и разницы двух результатов можно сравнивать, только если
+/// их идентификаторы равны, так что по нему внешнее хранилище может раскладывать результаты
+pub fn algorithm_id(options: &ComparerOptions) -> String {
+    id_from_pipeline(&options.pipeline())
+}
+
+/// Идентификатор из описания вычисления: имя алгоритма, версия формата сигнатур (`v1` -
+/// гауссов фильтр, `v2` - усреднение по площади) и остальные настройки в том же порядке.
+/// Запятые внутри значений (стороны сеток, веса) заменяются на `/`
+fn id_from_pipeline(pipeline: &str) -> String {
+    let mut algorithm = "unknown";
+    let mut version = "v2";
+    let mut settings = vec![];
+    for part in pipeline.split(';') {
+        match part.split_once('=') {
+            Some(("algorithm", name)) => algorithm = name,
+            Some((key, value)) => {
+                if key == "filter" && value.starts_with("gaussian") {
+                    version = "v1";
+                }
+                settings.push(format!("{key}={}", value.replace(',', "/")));
+            }
+            None => settings.push(part.to_string()),
+        }
+    }
+    format!("{algorithm}-{version}({})", settings.join(","))
+}
+
+/// Секунды от начала эпохи Unix в виде `2024-05-17T09:30:00Z`
+pub fn format_utc(secs: u64) -> String {
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // Гражданский календарь по числу дней, алгоритм Говарда Хиннанта
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", time / 3600, time / 60 % 60, time % 60)
+}
+
+/// Обратное к `format_utc`; `None`, если строка в другом виде
+pub fn parse_utc(text: &str) -> Option<u64> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let number = |part: Option<&str>| part?.parse::<i64>().ok();
+    let mut date = date.split('-');
+    let (year, month, day) = (number(date.next())?, number(date.next())?, number(date.next())?);
+    let mut time = time.split(':');
+    let (hour, minute, second) = (number(time.next())?, number(time.next())?, number(time.next())?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()
+}
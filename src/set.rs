@@ -0,0 +1,140 @@
+//! Набор эталонных изображений с метками, например список запрещенных картинок, с которым
+//! сверяется каждая загрузка. Хранится в файле индекса (`SignatureIndex`): у записи набора
+//! кроме сигнатуры есть отпечаток и необязательная метка. Любой индекс `scan` тоже можно
+//! открыть как набор, его записи без отпечатка просто сравниваются без предварительного отбора
+
+use image::DynamicImage;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::index::SignatureIndex;
+use crate::signature;
+use crate::{info, Fingerprint, Result, SimilarityThreshold};
+
+/// Наибольшее расстояние Хэмминга между отпечатками, при котором запись еще сравнивается
+/// по сигнатуре. Отпечаток грубее сигнатуры: на пробных правках (уменьшение, яркость,
+/// размытие, JPEG, обрезка до 10%) пары от 85% различались до 30 бит из 64, а у случайных
+/// пар различается около половины битов. С этой границей отбрасывается примерно половина
+/// непохожих записей; совпадение дальше нее все же возможно, для полной проверки есть
+/// `SignatureSet::prefilter(None)`
+pub const PREFILTER_DISTANCE: u32 = 32;
+
+/// Запись набора, похожая на проверяемое изображение
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceMatch {
+    pub path: PathBuf,
+    pub label: Option<String>,
+    pub similarity: f32,
+}
+
+/// Итог `SignatureSet::check`
+#[derive(Debug, Clone, Default)]
+pub struct SetCheck {
+    /// По убыванию схожести, при равной схожести - по пути
+    pub matches: Vec<ReferenceMatch>,
+    /// Сколько записей сравнено по сигнатуре
+    pub compared: usize,
+    /// Сколько записей отброшено по отпечатку, без сравнения сигнатур
+    pub skipped: usize,
+}
+
+/// Набор эталонных сигнатур с метками. Сигнатуры считаются так же, как в индексе
+/// (`Signature::compute`, с преобразованием значений из файла набора), настройки
+/// сравнения CLI на них не влияют
+pub struct SignatureSet {
+    index: SignatureIndex,
+    prefilter: Option<u32>,
+}
+
+impl Default for SignatureSet {
+    fn default() -> Self {
+        Self { index: SignatureIndex::new(), prefilter: Some(PREFILTER_DISTANCE) }
+    }
+}
+
+impl SignatureSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Загружает набор из файла индекса, см. `SignatureIndex::load`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self { index: SignatureIndex::load(path)?, ..Self::default() })
+    }
+
+    /// Сохраняет набор через временный файл, как `SignatureIndex::save`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.index.save(path)
+    }
+
+    /// Командная строка для сведений о записи, см. `SignatureIndex::set_command_line`
+    pub fn set_command_line<I: IntoIterator<Item = OsString>>(&mut self, args: I) {
+        self.index.set_command_line(args);
+    }
+
+    /// Граница предварительного отбора по отпечатку; `None` - сравнивать все записи по сигнатуре
+    pub fn prefilter(mut self, max_distance: Option<u32>) -> Self {
+        self.prefilter = max_distance;
+        self
+    }
+
+    /// Добавляет файл с меткой или заменяет запись того же пути (и ее метку)
+    pub fn add<P: AsRef<Path>>(&mut self, path: P, label: Option<&str>) -> Result<()> {
+        let image = info::open_image(path.as_ref())?;
+        self.add_image(path, &image, label)
+    }
+
+    /// Добавляет уже декодированное изображение под именем `path`, например загрузку,
+    /// у которой нет файла
+    pub fn add_image<P: AsRef<Path>>(&mut self, path: P, image: &DynamicImage, label: Option<&str>) -> Result<()> {
+        let signature = self.index.signature_of(image.clone())?;
+        let fingerprint = Fingerprint::compute_from_image(image);
+        self.index.insert(SignatureIndex::reference(path.as_ref().to_path_buf(), signature, fingerprint, label.map(str::to_string)));
+        Ok(())
+    }
+
+    /// Удаляет запись, возвращает `true`, если она была
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        self.index.remove(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Пути и метки записей в порядке добавления
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, Option<&str>)> {
+        self.index.entries().iter().map(|entry| (entry.path.as_path(), entry.label.as_deref()))
+    }
+
+    /// Записи, похожие на файл не меньше чем на `threshold` процентов
+    pub fn check<P: AsRef<Path>>(&self, path: P, threshold: SimilarityThreshold) -> Result<SetCheck> {
+        let image = info::open_image(path.as_ref())?;
+        self.check_image(&image, threshold)
+    }
+
+    /// Как `check`, для уже декодированного изображения
+    pub fn check_image(&self, image: &DynamicImage, threshold: SimilarityThreshold) -> Result<SetCheck> {
+        let signature = self.index.signature_of(image.clone())?;
+        let fingerprint = Fingerprint::compute_from_image(image);
+        let mut check = SetCheck::default();
+        for entry in self.index.entries() {
+            let far = entry.fingerprint.zip(self.prefilter).is_some_and(|(stored, max_distance)| stored.distance(&fingerprint) > max_distance);
+            if far {
+                check.skipped += 1;
+                continue;
+            }
+            check.compared += 1;
+            let similarity = signature::similarity_from_diff(signature.raw_distance(&entry.signature));
+            if threshold.is_met_by(similarity) {
+                check.matches.push(ReferenceMatch { path: entry.path.clone(), label: entry.label.clone(), similarity });
+            }
+        }
+        check.matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.path.cmp(&b.path)));
+        Ok(check)
+    }
+}
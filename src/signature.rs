@@ -0,0 +1,930 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use std::fmt;
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use crate::distance::{sqrt_diff, sqrt_diff_sum, sqrt_sum};
+use crate::{downscale, info, Transform};
+use crate::{ComparerOptions, ImgAlgError, Result};
+
+/// Описание вычисления сигнатур, которое записывается вместе с сохраненными сигнатурами.
+///
+/// Любое изменение чисел в сигнатуре (сетка по умолчанию, фильтр уменьшения, цветовое
+/// пространство, преобразование значений) должно менять эту строку и префикс текста `v2:`,
+/// иначе сигнатуры, сохраненные раньше, будут молча давать неверную схожесть
+pub(crate) const PIPELINE: &str = "algorithm=color-diff;grid=16;filter=area;color=rgba8;values=squared";
+/// Описание вычисления до перехода на собственное уменьшение: гауссов фильтр `image`,
+/// результат которого зависел от версии библиотеки
+pub(crate) const LEGACY_PIPELINE: &str = "algorithm=color-diff;grid=16;filter=gaussian;color=rgba8;values=squared";
+/// Значения в сигнатуре - разности квадратов 8-битных каналов, то есть лежат в -255²..=255².
+///
+/// Возведение в квадрат остается форматом по умолчанию: от него зависят сохраненные сигнатуры
+/// и пороги. Другие `Transform` переводят каналы в тот же диапазон `0..=255²`.
+/// Разность двух значений не больше 2·255², ее корень - не больше 361, а суммы копятся в `f64`,
+/// так что даже сетка 256x256 по четырем каналам (около 9.5e7) считается без потери точности
+pub(crate) const VALUE_LIMIT: i32 = 255 * 255;
+/// Число шестнадцатеричных цифр на одно значение (2·255² < 16⁵)
+const HEX_DIGITS: usize = 5;
+/// Настройки по умолчанию для `distance` и `similarity`: одни на все сравнения, чтобы
+/// сравнение пары не выделяло память
+static DEFAULT_OPTIONS: LazyLock<ComparerOptions> = LazyLock::new(ComparerOptions::default);
+
+/// Сторона сетки обычной сигнатуры
+pub(crate) const GRID_SIZE: u32 = 16;
+/// Стороны сеток многомасштабной сигнатуры
+pub(crate) const MULTI_SCALE_GRIDS: [u32; 3] = [8, 16, 32];
+/// Фильтры уменьшения дополнительных сигнатур `ComparerOptions::ensemble` и их имена
+/// в описании вычисления
+pub(crate) const ENSEMBLE_FILTERS: [(FilterType, &str); 2] = [(FilterType::Triangle, "triangle"), (FilterType::CatmullRom, "catmull-rom")];
+/// Во сколько раз уменьшаются разности с ячейками полей у вписанного в квадрат изображения:
+/// поля одного цвета, и их граница с содержимым почти ничего не говорит о самом изображении
+const PADDING_DAMPING: i32 = 4;
+
+/// Сигнатура изображения: разности соседних цветов уменьшенной копии 16x16
+/// (или нескольких копий 8x8, 16x16 и 32x32 для многомасштабной сигнатуры).
+///
+/// Альфа-канал хранится отдельной последовательностью разностей, так как у изображения
+/// с одинаковым цветом может меняться только прозрачность. Так же отдельно хранятся
+/// разности насыщенности и яркости (HSV без тона) для сравнения без учета тона.
+///
+/// Текстовая форма (`Display`/`FromStr`) - `v2:` (с преобразованием значений, отличным
+/// от квадрата, - `v2+sqrt:`, `v2+gamma=2.2:` и т. п., у вписанного в квадрат изображения -
+/// `v2+letterbox:` и `v2+letterbox+sqrt:`) и по пять шестнадцатеричных цифр
+/// на каждый канал каждой ячейки, затем через `:` разности альфа-канала и через еще одно `:`
+/// пары разностей насыщенности и яркости, по пять цифр на значение. Пустые секции в конце
+/// не выводятся. У многомасштабной сигнатуры сетки идут через `|` в виде `8=...|16=...|32=...`.
+/// Расстояния после разбора совпадают в точности.
+///
+/// Сигнатуры формата `v1` (уменьшение гауссовым фильтром `image`) по-прежнему разбираются,
+/// чтобы читать старые сохраненные строки, но сравниваются только между собой
+#[derive(Debug, Clone)]
+pub struct Signature {
+    grids: Vec<Grid>,
+    downscale: Downscale,
+    /// Сигнатуры того же изображения, уменьшенного фильтрами `ENSEMBLE_FILTERS`
+    /// (`ComparerOptions::ensemble`). Как и цвета ячеек, не входят ни в текстовую форму,
+    /// ни в индекс и в сравнении сигнатур на равенство не участвуют
+    ensemble: Vec<Signature>,
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Self) -> bool {
+        self.grids == other.grids && self.downscale == other.downscale
+    }
+}
+
+impl Eq for Signature {}
+
+/// Как была получена уменьшенная копия: от этого зависят все числа сигнатуры
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Downscale {
+    /// `v1`: `resize_exact` с гауссовым фильтром, только для чтения старых сигнатур
+    Gaussian,
+    /// `v2`: усреднение по площади, `downscale::area_average`
+    Area,
+    /// `v2+letterbox`: усреднение по площади после вписывания в квадрат, `downscale::letterbox_average`
+    Letterbox,
+}
+
+impl Downscale {
+    /// Версия в начале текстового представления
+    fn version(self) -> &'static str {
+        match self {
+            Self::Gaussian => "v1",
+            Self::Area | Self::Letterbox => "v2",
+        }
+    }
+
+    fn new(letterbox: bool) -> Self {
+        if letterbox { Self::Letterbox } else { Self::Area }
+    }
+}
+
+/// Процент схожести двух сигнатур с настройками сравнения `options` - то же вычисление,
+/// которым пользуются `ImagesComparer` и `BoundedComparer`, но без сравнителя: сигнатуры
+/// берутся по ссылке, например из собственного хранилища, и память в куче не выделяется
+/// (кроме `ignore_worst` и карты допусков, которым нужны вклады отдельных ячеек).
+///
+/// Совместимость проверяется по версии формата, преобразованию значений и сеткам,
+/// без обхода ячеек; несовместимые сигнатуры - `SignatureMismatch`
+#[inline]
+pub fn compare_signatures(a: &Signature, b: &Signature, options: &ComparerOptions) -> Result<f32> {
+    a.check_compatible(b)?;
+    Ok(measure(a, b, options).1)
+}
+
+/// Разница и процент схожести уже проверенных на совместимость сигнатур.
+/// Равные сигнатуры, например изображение с самим собой, - ровно 0 и 100% без подсчета
+#[inline]
+pub(crate) fn measure(a: &Signature, b: &Signature, options: &ComparerOptions) -> (f64, f32) {
+    if a == b {
+        return (0.0, 100.0);
+    }
+    let distance = options.distance(a, b);
+    (distance, options.similarity(distance))
+}
+
+/// Как `measure`, но если у обеих сигнатур есть сигнатуры `ensemble`, схожесть - среднее
+/// по всем фильтрам, третье число - разброс, половина разности наибольшей и наименьшей
+/// схожести, а четвертое - сами наименьшая и наибольшая: вокруг среднего они лежат
+/// не обязательно симметрично. Разница - по основным сигнатурам. Без ансамбля разброс 0
+pub(crate) fn measure_ensemble(a: &Signature, b: &Signature, options: &ComparerOptions) -> (f64, f32, f32, (f32, f32)) {
+    let (distance, similarity) = measure(a, b, options);
+    if a.ensemble.is_empty() || a.ensemble.len() != b.ensemble.len() {
+        return (distance, similarity, 0.0, (similarity, similarity));
+    }
+    let scores: Vec<f32> = std::iter::once(similarity).chain(a.ensemble.iter().zip(&b.ensemble).map(|(a, b)| measure(a, b, options).1)).collect();
+    let mean = scores.iter().sum::<f32>() / scores.len() as f32;
+    let (min, max) = scores.iter().fold((f32::MAX, f32::MIN), |(min, max), &score| (min.min(score), max.max(score)));
+    (distance, mean, (max - min) / 2.0, (min, max))
+}
+
+/// Разности одной уменьшенной копии изображения.
+///
+/// Каналы хранятся плоскостями в одном непрерывном массиве (сначала все R, затем G, затем B;
+/// у `tone` - насыщенность, затем яркость), чтобы разница каждого канала считалась
+/// одним проходом по подряд идущей памяти
+#[derive(Debug, Clone)]
+pub(crate) struct Grid {
+    size: u32,
+    channels: Vec<i32>,
+    alpha: Vec<i32>,
+    tone: Vec<i32>,
+    /// Во что переводились значения каналов; у сеток одной сигнатуры одинаково
+    transform: Transform,
+    /// Цвета RGBA уменьшенной копии построчно, с дробными битами `downscale::area_average_fine`:
+    /// по ним `explain` находит ячейки разностей, а `downsample_to` усредняет ячейки.
+    /// Не входят ни в текстовую форму, ни в индекс (у разобранной сигнатуры пусты)
+    /// и в сравнении сигнатур на равенство не участвуют
+    pixels: Vec<[u16; 4]>,
+}
+
+impl PartialEq for Grid {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.transform == other.transform
+            && self.channels == other.channels
+            && self.alpha == other.alpha
+            && self.tone == other.tone
+    }
+}
+
+impl Eq for Grid {}
+
+/// Последовательность разностей сетки, которая входит в разницу сигнатур
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Plane {
+    /// Цветовой канал: 0 - R, 1 - G, 2 - B. Сравнивается общая часть ячеек, как и раньше
+    Color(usize),
+    /// Альфа-канал, в тех же единицах, что и один цветовой канал. Недостающие в более короткой
+    /// последовательности разности считаются нулевыми, иначе непрозрачное изображение
+    /// совпадало бы с любым полупрозрачным
+    Alpha,
+    /// Насыщенность и яркость: тон не учитывается, поэтому перекрашенные копии совпадают.
+    /// У серых пикселей насыщенность нулевая, так что неопределенный тон шума не добавляет.
+    /// Дополняются нулями, как и альфа-канал
+    Saturation,
+    Value,
+}
+
+/// Функция преобразования изображения в единый формат RGBA
+fn convert_to_rgba(sample_img: DynamicImage) -> Result<RgbaImage> {
+    match sample_img {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) | DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => {
+            Ok(sample_img.into_rgba8())
+        }
+        _ => Err(ImgAlgError::UnsupportedColorType(sample_img.color())),
+    }
+}
+
+/// Оборачивает буфер RGBA8 в изображение, проверяя его длину
+pub(crate) fn raw_rgba(buf: &[u8], width: u32, height: u32) -> Result<ImageBuffer<Rgba<u8>, &[u8]>> {
+    let expected = width as u64 * height as u64 * 4;
+    if buf.len() as u64 != expected {
+        return Err(ImgAlgError::InvalidBuffer { len: buf.len(), width, height });
+    }
+    Ok(ImageBuffer::from_raw(width, height, buf).expect("the buffer length was checked"))
+}
+
+impl Signature {
+    /// Вычисляет сигнатуру изображения из файла
+    pub fn compute<P: AsRef<Path>>(image_path: P) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let original_img = info::open_image(image_path)?;
+        Self::from_image(original_img)
+    }
+
+    /// Вычисляет сигнатуру уже декодированного изображения
+    pub fn compute_from_image(image: &DynamicImage) -> Result<Self> {
+        Self::from_image(image.clone())
+    }
+
+    /// Вычисляет многомасштабную сигнатуру (сетки 8x8, 16x16 и 32x32) изображения из файла
+    pub fn compute_multi_scale<P: AsRef<Path>>(image_path: P) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let original_img = info::open_image(image_path)?;
+        Self::from_image_scales(original_img, &MULTI_SCALE_GRIDS, Transform::Square, false)
+    }
+
+    /// Вычисляет многомасштабную сигнатуру уже декодированного изображения
+    pub fn compute_from_image_multi_scale(image: &DynamicImage) -> Result<Self> {
+        Self::from_image_scales(image.clone(), &MULTI_SCALE_GRIDS, Transform::Square, false)
+    }
+
+    /// Вычисляет сигнатуру по пикселям RGBA8 без кодирования и декодирования: буфер
+    /// используется как есть, без копирования. Строки должны идти вплотную, длина буфера -
+    /// ровно `width * height * 4` байт; буферы с выравниванием строк (stride) не принимаются,
+    /// их нужно сначала упаковать. Сигнатура совпадает с сигнатурой тех же пикселей,
+    /// сохраненных без потерь (например, в PNG) и загруженных из файла
+    pub fn from_raw_rgba(buf: &[u8], width: u32, height: u32) -> Result<Self> {
+        Self::from_raw_rgba_scales(buf, width, height, &[GRID_SIZE], Transform::Square, false, false)
+    }
+
+    pub(crate) fn from_raw_rgba_scales(buf: &[u8], width: u32, height: u32, sizes: &[u32], transform: Transform, letterbox: bool, ensemble: bool) -> Result<Self> {
+        info::check_size(None, width, height)?;
+        Ok(Self::from_rgba(&raw_rgba(buf, width, height)?, sizes, transform, letterbox, ensemble))
+    }
+
+    pub(crate) fn from_image(original_img: DynamicImage) -> Result<Self> {
+        Self::from_image_scales(original_img, &[GRID_SIZE], Transform::Square, false)
+    }
+
+    pub(crate) fn from_image_scales(original_img: DynamicImage, sizes: &[u32], transform: Transform, letterbox: bool) -> Result<Self> {
+        Self::from_image_ensemble(original_img, sizes, transform, letterbox, false)
+    }
+
+    /// Как `from_image_scales`, а с `ensemble` - еще и сигнатуры `ENSEMBLE_FILTERS`, посчитанные
+    /// по тому же декодированному изображению
+    pub(crate) fn from_image_ensemble(original_img: DynamicImage, sizes: &[u32], transform: Transform, letterbox: bool, ensemble: bool) -> Result<Self> {
+        info::check_size(None, original_img.width(), original_img.height())?;
+        let converted_img = convert_to_rgba(original_img)?; // Конвертируем изображение в RGBA
+        Ok(Self::from_rgba(&converted_img, sizes, transform, letterbox, ensemble))
+    }
+
+    fn from_rgba<C: Deref<Target = [u8]>>(image: &ImageBuffer<Rgba<u8>, C>, sizes: &[u32], transform: Transform, letterbox: bool, ensemble: bool) -> Self {
+        let grids = sizes.iter().map(|&size| Grid::from_image(image, size, transform, letterbox)).collect();
+        // Копия уже нужного размера усреднением по площади не меняется
+        let variant = |filter: FilterType| {
+            let grids = sizes.iter().map(|&size| Grid::from_image(&imageops::resize(image, size, size, filter), size, transform, false)).collect();
+            Self { grids, downscale: Downscale::new(letterbox), ensemble: vec![] }
+        };
+        let ensemble = if ensemble { ENSEMBLE_FILTERS.iter().map(|&(filter, _)| variant(filter)).collect() } else { vec![] };
+        Self { grids, downscale: Downscale::new(letterbox), ensemble }
+    }
+
+    /// Сигнатура текущего формата из готовых сеток; `letterbox` - изображение вписывалось в квадрат
+    pub(crate) fn from_grids(grids: Vec<Grid>, letterbox: bool) -> Self {
+        Self { grids, downscale: Downscale::new(letterbox), ensemble: vec![] }
+    }
+
+    /// Есть ли у сигнатуры сигнатуры других фильтров уменьшения (`ComparerOptions::ensemble`)
+    pub fn has_ensemble(&self) -> bool {
+        !self.ensemble.is_empty()
+    }
+
+    pub(crate) fn grids(&self) -> &[Grid] {
+        &self.grids
+    }
+
+    /// Стороны сеток сигнатуры: `[16]` или `[8, 16, 32]`
+    pub fn grid_sizes(&self) -> Vec<u32> {
+        self.grids.iter().map(|grid| grid.size).collect()
+    }
+
+    pub fn is_multi_scale(&self) -> bool {
+        self.grids.len() > 1
+    }
+
+    /// Преобразование значений каналов, с которым посчитана сигнатура
+    pub fn transform(&self) -> Transform {
+        self.grids.first().map_or(Transform::Square, |grid| grid.transform)
+    }
+
+    /// Количество ячеек сигнатуры (одинаковые соседние цвета не сохраняются)
+    pub fn len(&self) -> usize {
+        self.grids.iter().map(Grid::cell_count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grids.iter().all(Grid::is_empty)
+    }
+
+    /// Примерный объем памяти, который занимает сигнатура
+    pub(crate) fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.grids.iter().map(Grid::memory_size).sum::<usize>() + self.ensemble.iter().map(Self::memory_size).sum::<usize>()
+    }
+
+    /// Накопленная разница двух сигнатур, та же, что использует `ImagesComparer`
+    /// с настройками по умолчанию.
+    ///
+    /// Единицы: сумма по ячейкам и каналам `sqrt(|a - b|)`, где `a`, `b` - разности
+    /// квадратов соседних значений канала. 0 - сигнатуры совпадают, верхняя граница
+    /// около 255 · 3 · sqrt(2 · 255²) ≈ 2.8e5. 76 800 и больше соответствует 0% схожести.
+    /// У многомасштабных сигнатур разница каждой сетки приводится к сетке 16x16.
+    /// Для формата сигнатуры `v2` значения не меняются между версиями и платформами.
+    ///
+    /// Сигнатуры с разными наборами сеток, преобразованиями значений или разных форматов
+    /// не сравниваются: `SignatureMismatch`
+    pub fn distance(&self, other: &Signature) -> Result<f64> {
+        self.check_compatible(other)?;
+        Ok(self.raw_distance(other))
+    }
+
+    /// Процент схожести двух сигнатур
+    pub fn similarity(&self, other: &Signature) -> Result<f32> {
+        compare_signatures(self, other, &DEFAULT_OPTIONS)
+    }
+
+    /// Посчитана ли сигнатура так же, как `ImagesComparer` с настройками `options` посчитал бы
+    /// ее сейчас: формат, вписывание в квадрат, преобразование значений и сетки берутся из
+    /// заголовка текстовой формы. Иначе - `InvalidSignature` с тем, что именно не совпало.
+    /// Нужна перед `compare_signatures` с сигнатурой из собственного хранилища
+    pub fn check_options(&self, options: &ComparerOptions) -> Result<()> {
+        let current = (Downscale::new(options.is_letterbox()), options.transform(), options.grid_sizes());
+        if (self.downscale, self.transform(), self.grid_sizes().as_slice()) == current {
+            return Ok(());
+        }
+        let describe = |downscale: Downscale, transform: Transform, sizes: &[u32]| {
+            let sizes: Vec<String> = sizes.iter().map(u32::to_string).collect();
+            let letterbox = if downscale == Downscale::Letterbox { "+letterbox" } else { "" };
+            format!("{}{}, values={}, grids={}", downscale.version(), letterbox, transform, sizes.join(","))
+        };
+        Err(ImgAlgError::InvalidSignature(format!(
+            "computed as {}, but the current settings compute {}",
+            describe(self.downscale, self.transform(), &self.grid_sizes()),
+            describe(current.0, current.1, current.2)
+        )))
+    }
+
+    /// Обычная сигнатура с сеткой `grid`x`grid` из более подробной, например из многомасштабной
+    /// (8, 16 и 32) для сравнения со старыми сигнатурами 16x16. Сетка нужного размера, если она
+    /// есть, берется как есть: она совпадает с сеткой обычной сигнатуры того же изображения.
+    /// Иначе блоками усредняются ячейки самой подробной сетки, сторона которой кратна `grid`.
+    /// Усредняются цвета с дробными битами, и округляются они один раз, как при вычислении
+    /// напрямую: одна ячейка может разойтись на единицу, только если среднее почти ровно
+    /// посередине между соседними уровнями.
+    ///
+    /// Цвета ячеек для усреднения есть только у посчитанных сигнатур, не у разобранных из текста
+    /// или индекса. Сигнатуры с вписыванием в квадрат усреднением не уменьшаются: поля после
+    /// него не отличить от изображения. Иначе - `InvalidSignature`
+    pub fn downsample_to(&self, grid: u32) -> Result<Signature> {
+        if let Some(native) = self.grids.iter().find(|native| native.size == grid) {
+            return Ok(Self { grids: vec![native.clone()], downscale: self.downscale, ensemble: vec![] });
+        }
+        let source = self
+            .grids
+            .iter()
+            .filter(|source| grid > 0 && source.size % grid == 0 && source.pixels.len() == (source.size * source.size) as usize)
+            .max_by_key(|source| source.size);
+        let source = match source {
+            Some(_) if self.downscale == Downscale::Letterbox => {
+                return Err(ImgAlgError::InvalidSignature("a letterboxed signature cannot be downsampled".to_string()));
+            }
+            Some(source) => source,
+            None => {
+                let sizes: Vec<String> = self.grid_sizes().iter().map(u32::to_string).collect();
+                return Err(ImgAlgError::InvalidSignature(format!("grids {} cannot be downsampled to {}x{}", sizes.join(","), grid, grid)));
+            }
+        };
+        let (n, factor) = (source.size as usize, (source.size / grid) as usize);
+        let block = (factor * factor) as u64;
+        let mut pixels = Vec::with_capacity((grid * grid * 4) as usize);
+        for y in 0..grid as usize {
+            for x in 0..grid as usize {
+                let mut sum = [0u64; 4];
+                for dy in 0..factor {
+                    let row = (y * factor + dy) * n + x * factor;
+                    for pixel in &source.pixels[row..row + factor] {
+                        for (channel, value) in sum.iter_mut().zip(pixel) {
+                            *channel += u64::from(*value);
+                        }
+                    }
+                }
+                pixels.extend(sum.map(|channel| ((channel + (block << (downscale::FINE_BITS - 1))) / (block << downscale::FINE_BITS)) as u8));
+            }
+        }
+        let image = RgbaImage::from_raw(grid, grid, pixels).expect("the buffer has grid * grid pixels");
+        Ok(Self { grids: vec![Grid::from_image(&image, grid, source.transform, false)], downscale: self.downscale, ensemble: vec![] })
+    }
+
+    #[inline]
+    pub(crate) fn check_compatible(&self, other: &Signature) -> Result<()> {
+        if self.downscale != other.downscale
+            || self.transform() != other.transform()
+            || self.grids.len() != other.grids.len()
+            || self.grids.iter().zip(&other.grids).any(|(a, b)| a.size != b.size)
+        {
+            return Err(ImgAlgError::SignatureMismatch);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn raw_distance(&self, other: &Signature) -> f64 {
+        DEFAULT_OPTIONS.distance(self, other)
+    }
+}
+
+impl Grid {
+    /// Сетка из изображения. С `letterbox` оно вписывается в квадрат (`downscale::letterbox_average`),
+    /// а разности на границе полей и между ячейками полей делятся на `PADDING_DAMPING`
+    fn from_image<C: Deref<Target = [u8]>>(converted_img: &ImageBuffer<Rgba<u8>, C>, size: u32, transform: Transform, letterbox: bool) -> Self {
+        let n = size as usize;
+        let (fine, padding) = if letterbox {
+            let (pixels, padding) = downscale::letterbox_average(converted_img, size);
+            (pixels.into_iter().map(|pixel| pixel.map(|channel| u16::from(channel) << downscale::FINE_BITS)).collect(), padding)
+        } else {
+            (downscale::area_average_fine(converted_img, size), vec![])
+        };
+        let pixels: Vec<[u8; 4]> = fine.iter().copied().map(downscale::coarse).collect();
+        let is_padding = |cell: usize| padding.get(cell).copied().unwrap_or(false);
+        let table = transform.table();
+
+        let mut result: [Vec<i32>; 3] = Default::default();
+        let mut alpha = vec![];
+        let mut tone: [Vec<i32>; 2] = Default::default();
+        let mut prev_color = None;
+        let mut prev_alpha = None;
+        let mut prev_tone = None;
+        for y in 0..n {
+            for x in 0..n {
+                let cell = y * n + x;
+                // Разность с ячейкой полей говорит о рамке, а не о содержимом
+                let damping = if is_padding(cell) || (cell > 0 && is_padding(cell - 1)) { PADDING_DAMPING } else { 1 };
+                let pixel = Rgba(*pixels.get(cell).unwrap_or(&[0, 0, 0, 255])); // Дефолтный прозрачный пиксель
+                let color = [
+                    table[pixel[0] as usize], // Первая составляющая (красный)
+                    table[pixel[1] as usize], // Вторая составляющая (зеленый)
+                    table[pixel[2] as usize], // Третья составляющая (синий)
+                ];
+                if Some(color) != prev_color && !(x == 0 && y == 0) {
+                    let prev = prev_color.unwrap();
+                    for (plane, (value, prev)) in result.iter_mut().zip(color.iter().zip(prev)) {
+                        plane.push((value - prev) / damping); // Преобразовываем в вектор
+                    }
+                }
+                prev_color = Some(color);
+
+                let opacity = table[pixel[3] as usize]; // Альфа-канал
+                if let Some(prev) = prev_alpha.filter(|prev| *prev != opacity) {
+                    alpha.push((opacity - prev) / damping);
+                }
+                prev_alpha = Some(opacity);
+
+                let [saturation, value] = saturation_value(pixel).map(|c| table[c as usize]); // Насыщенность и яркость
+                if let Some([prev_s, prev_v]) = prev_tone.filter(|prev: &[i32; 2]| *prev != [saturation, value]) {
+                    tone[0].push((saturation - prev_s) / damping);
+                    tone[1].push((value - prev_v) / damping);
+                }
+                prev_tone = Some([saturation, value]);
+            }
+        }
+        let grid = Self { size, channels: result.concat(), alpha, tone: tone.concat(), transform, pixels: fine };
+        debug_assert!(grid.values().all(is_valid_value));
+        grid
+    }
+
+    pub(crate) fn from_parts(size: u32, cells: &[[i32; 3]], alpha: Vec<i32>, tone: &[[i32; 2]], transform: Transform) -> Self {
+        let grid = Self { size, channels: planar(cells), alpha, tone: planar(tone), transform, pixels: vec![] };
+        debug_assert!(grid.values().all(is_valid_value), "signature value out of -255²..=255²");
+        grid
+    }
+
+    fn values(&self) -> impl Iterator<Item = i32> + '_ {
+        self.channels.iter().chain(&self.alpha).chain(&self.tone).copied()
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Количество ячеек (разностей соседних цветов)
+    pub(crate) fn cell_count(&self) -> usize {
+        self.channels.len() / 3
+    }
+
+    /// Ячейки в исходном порядке, по три канала
+    pub(crate) fn cells(&self) -> impl Iterator<Item = [i32; 3]> + '_ {
+        let [r, g, b] = [0, 1, 2].map(|channel| self.channel(channel));
+        r.iter().zip(g).zip(b).map(|((r, g), b)| [*r, *g, *b])
+    }
+
+    pub(crate) fn alpha(&self) -> &[i32] {
+        &self.alpha
+    }
+
+    pub(crate) fn tone_count(&self) -> usize {
+        self.tone.len() / 2
+    }
+
+    /// Пары насыщенность/яркость в исходном порядке
+    pub(crate) fn tone(&self) -> impl Iterator<Item = [i32; 2]> + '_ {
+        let (saturation, value) = self.tone.split_at(self.tone_count());
+        saturation.iter().zip(value).map(|(s, v)| [*s, *v])
+    }
+
+    /// Плоскость одного цветового канала: 0 - R, 1 - G, 2 - B
+    fn channel(&self, channel: usize) -> &[i32] {
+        let count = self.cell_count();
+        &self.channels[channel * count..(channel + 1) * count]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.alpha.is_empty() && self.tone.is_empty()
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + (self.channels.len() + self.alpha.len() + self.tone.len()) * std::mem::size_of::<i32>()
+            + self.pixels.len() * std::mem::size_of::<[u16; 4]>()
+    }
+
+    fn plane(&self, plane: Plane) -> &[i32] {
+        match plane {
+            Plane::Color(channel) => self.channel(channel),
+            Plane::Alpha => &self.alpha,
+            Plane::Saturation => &self.tone[..self.tone_count()],
+            Plane::Value => &self.tone[self.tone_count()..],
+        }
+    }
+
+    /// Разница по одной последовательности
+    pub(crate) fn plane_distance(&self, other: &Grid, plane: Plane) -> f64 {
+        match plane {
+            Plane::Color(_) => sqrt_diff_sum(self.plane(plane), other.plane(plane)),
+            _ => padded_distance(self.plane(plane), other.plane(plane)),
+        }
+    }
+
+    /// Слагаемые `plane_distance` по отдельности: номер ячейки сетки, к которой относится
+    /// разность, и ее вклад. Разности за концом более короткой последовательности относятся
+    /// к ячейкам более длинной, остальные - к ячейкам `self`.
+    /// Пусто, если цвета копии не сохранены (сигнатура разобрана из текста или индекса)
+    pub(crate) fn plane_terms(&self, other: &Grid, plane: Plane) -> Vec<(usize, f64)> {
+        let (a, b) = (self.plane(plane), other.plane(plane));
+        let (a_cells, b_cells) = (self.entry_cells(plane), other.entry_cells(plane));
+        if a_cells.len() != a.len() || b_cells.len() != b.len() {
+            return vec![];
+        }
+        let common = a.len().min(b.len());
+        let longer_cells = if a.len() > b.len() { &a_cells } else { &b_cells };
+        let cell = |k: usize| if k < common { a_cells[k] } else { longer_cells[k] };
+        self.entry_terms(other, plane).into_iter().enumerate().map(|(k, term)| (cell(k), term)).collect()
+    }
+
+    /// Слагаемые `plane_distance` по номерам разностей: сначала общая часть, затем
+    /// (кроме цветовых каналов) разности за концом более короткой последовательности
+    pub(crate) fn entry_terms(&self, other: &Grid, plane: Plane) -> Vec<f64> {
+        let (a, b) = (self.plane(plane), other.plane(plane));
+        let common = a.len().min(b.len());
+        let mut terms: Vec<f64> = (0..common).map(|k| sqrt_diff(a[k], b[k])).collect();
+        if !matches!(plane, Plane::Color(_)) {
+            let longer = if a.len() > b.len() { a } else { b };
+            terms.extend(longer[common..].iter().map(|&value| sqrt_diff(value, 0)));
+        }
+        terms
+    }
+
+    /// Номера ячеек, на которых `from_image` записала разности последовательности.
+    /// Сравниваются преобразованные значения: у крутой `Gamma` соседние уровни совпадают
+    fn entry_cells(&self, plane: Plane) -> Vec<usize> {
+        let table = self.transform.table();
+        let key = |pixel: &[u8; 4]| -> [i32; 3] {
+            match plane {
+                Plane::Color(_) => [0, 1, 2].map(|c| table[pixel[c] as usize]),
+                Plane::Alpha => [table[pixel[3] as usize], 0, 0],
+                Plane::Saturation | Plane::Value => {
+                    let [saturation, value] = saturation_value(Rgba(*pixel)).map(|c| table[c as usize]);
+                    [saturation, value, 0]
+                }
+            }
+        };
+        let mut cells = vec![];
+        for (pos, pair) in self.pixels.windows(2).enumerate() {
+            if key(&downscale::coarse(pair[0])) != key(&downscale::coarse(pair[1])) {
+                cells.push(pos + 1);
+            }
+        }
+        cells
+    }
+
+    /// Цвет RGBA ячейки уменьшенной копии, если он сохранен
+    pub(crate) fn pixel(&self, cell: usize) -> Option<[u8; 4]> {
+        self.pixels.get(cell).copied().map(downscale::coarse)
+    }
+
+}
+
+/// Раскладывает ячейки по плоскостям каналов
+fn planar<const N: usize>(cells: &[[i32; N]]) -> Vec<i32> {
+    (0..N).flat_map(|channel| cells.iter().map(move |cell| cell[channel])).collect()
+}
+
+/// Сумма `sqrt(|a - b|)`, недостающие значения более короткой последовательности считаются нулевыми
+fn padded_distance(a: &[i32], b: &[i32]) -> f64 {
+    let common = a.len().min(b.len());
+    let longer = if a.len() > b.len() { a } else { b };
+    sqrt_diff_sum(a, b) + sqrt_sum(&longer[common..])
+}
+
+/// Лежит ли значение в допустимом для сигнатуры диапазоне
+pub(crate) fn is_valid_value(value: i32) -> bool {
+    (-VALUE_LIMIT..=VALUE_LIMIT).contains(&value)
+}
+
+/// Насыщенность и яркость пикселя по модели HSV, обе в 0..=255
+fn saturation_value(pixel: Rgba<u8>) -> [i32; 2] {
+    let [r, g, b, _] = pixel.0.map(i32::from);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let saturation = if max == 0 { 0 } else { (max - min) * 255 / max };
+    [saturation, max]
+}
+
+/// Перевод разницы сигнатур в процент схожести
+pub(crate) fn similarity_from_diff(diff: f64) -> f32 {
+    similarity_from_channels_diff(diff, 3.0) // Три канала (RGB)
+}
+
+/// Наибольшая схожесть неравных по учитываемым каналам сигнатур: ближайшее к 100 меньшее `f32`
+const BELOW_IDENTICAL: f32 = f32::from_bits(100f32.to_bits() - 1);
+
+/// Перевод разницы по `channels_count` каналам в процент схожести.
+/// Всегда в [0, 100]; ровно 100 - только при нулевой разнице, даже если ненулевая
+/// разница округлилась бы до 100 в `f32`
+pub(crate) fn similarity_from_channels_diff(diff: f64, channels_count: f64) -> f32 {
+    if diff == 0.0 {
+        return 100.0;
+    }
+    let total_difference = diff;
+    let num_pixels = (16 * 16) as f64;
+    let max_possible_difference_per_channel = 100.0; // Максимально возможное отличие в каждом канале
+    let max_total_difference = num_pixels * channels_count * max_possible_difference_per_channel;
+    let percentage_similarity = 100.0 - (total_difference / max_total_difference) * 100.0;
+    if percentage_similarity.is_nan() {
+        return 0.0;
+    }
+    (percentage_similarity as f32).clamp(0.0, BELOW_IDENTICAL) // Ограничиваем диапазон от 0% до 100%
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.downscale.version())?;
+        if self.downscale == Downscale::Letterbox {
+            f.write_str("+letterbox")?;
+        }
+        if self.transform() != Transform::Square {
+            write!(f, "+{}", self.transform())?;
+        }
+        f.write_str(":")?;
+        if let [grid] = self.grids.as_slice()
+            && grid.size == GRID_SIZE
+        {
+            return grid.fmt(f);
+        }
+        for (pos, grid) in self.grids.iter().enumerate() {
+            if pos > 0 {
+                f.write_str("|")?;
+            }
+            write!(f, "{}=", grid.size)?;
+            grid.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for value in self.cells().flatten() {
+            write!(f, "{:05x}", value + VALUE_LIMIT)?;
+        }
+        if !self.alpha.is_empty() || !self.tone.is_empty() {
+            f.write_str(":")?;
+            for value in &self.alpha {
+                write!(f, "{:05x}", value + VALUE_LIMIT)?;
+            }
+        }
+        if !self.tone.is_empty() {
+            f.write_str(":")?;
+            for value in self.tone().flatten() {
+                write!(f, "{:05x}", value + VALUE_LIMIT)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Signature {
+    type Err = ImgAlgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| ImgAlgError::InvalidSignature(reason.to_string());
+        let (header, text) = s.trim().split_once(':').ok_or_else(|| invalid("missing the v2: prefix"))?;
+        let (version, rest) = header.split_once('+').map_or((header, None), |(version, rest)| (version, Some(rest)));
+        let (letterbox, rest) = match rest {
+            Some("letterbox") => (true, None),
+            Some(rest) => rest.strip_prefix("letterbox+").map_or((false, Some(rest)), |rest| (true, Some(rest))),
+            None => (false, None),
+        };
+        let transform = match rest {
+            Some(transform) => transform.parse().map_err(|_| invalid("unknown value transform"))?,
+            None => Transform::Square,
+        };
+        let downscale = match [Downscale::Area, Downscale::Gaussian].into_iter().find(|downscale| downscale.version() == version) {
+            Some(Downscale::Gaussian) if letterbox => return Err(invalid("format v1 has no letterbox")),
+            Some(_) if letterbox => Downscale::Letterbox,
+            Some(downscale) => downscale,
+            None if version.starts_with('v') && version[1..].parse::<u32>().is_ok() => {
+                return Err(ImgAlgError::InvalidSignature(format!("format {version} is not supported, expected v2 or v1")));
+            }
+            None => return Err(invalid("missing the v2: prefix")),
+        };
+        if !text.contains('=') {
+            return Ok(Self { grids: vec![parse_grid(GRID_SIZE, text, transform)?], downscale, ensemble: vec![] });
+        }
+        let mut grids = vec![];
+        for part in text.split('|') {
+            let (size, body) = part.split_once('=').ok_or_else(|| invalid("missing the grid size"))?;
+            let size = size.parse().ok().filter(|size| (1..=256).contains(size)).ok_or_else(|| invalid("invalid grid size"))?;
+            grids.push(parse_grid(size, body, transform)?);
+        }
+        Ok(Self { grids, downscale, ensemble: vec![] })
+    }
+}
+
+fn parse_grid(size: u32, text: &str, transform: Transform) -> Result<Grid> {
+    let invalid = |reason: &str| ImgAlgError::InvalidSignature(reason.to_string());
+    let mut sections = text.split(':');
+    let (hex, alpha_hex, tone_hex) = (sections.next().unwrap_or(""), sections.next().unwrap_or(""), sections.next().unwrap_or(""));
+    if sections.next().is_some() {
+        return Err(invalid("too many sections"));
+    }
+    if !hex.is_ascii() || hex.len() % (HEX_DIGITS * 3) != 0 {
+        return Err(invalid("unexpected length"));
+    }
+    if !alpha_hex.is_ascii() || alpha_hex.len() % HEX_DIGITS != 0 {
+        return Err(invalid("unexpected length of the alpha part"));
+    }
+    if !tone_hex.is_ascii() || tone_hex.len() % (HEX_DIGITS * 2) != 0 {
+        return Err(invalid("unexpected length of the saturation/value part"));
+    }
+
+    let values = parse_values(hex)?;
+    let cells: Vec<[i32; 3]> = values.chunks(3).map(|cell| [cell[0], cell[1], cell[2]]).collect();
+    let tone: Vec<[i32; 2]> = parse_values(tone_hex)?.chunks(2).map(|pair| [pair[0], pair[1]]).collect();
+    Ok(Grid::from_parts(size, &cells, parse_values(alpha_hex)?, &tone, transform))
+}
+
+/// Разбирает подряд идущие значения по `HEX_DIGITS` шестнадцатеричных цифр
+fn parse_values(hex: &str) -> Result<Vec<i32>> {
+    let invalid = |reason: &str| ImgAlgError::InvalidSignature(reason.to_string());
+    let mut values = Vec::with_capacity(hex.len() / HEX_DIGITS);
+    for digits in hex.as_bytes().chunks(HEX_DIGITS) {
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(invalid("not a hex string"));
+        }
+        let raw = digits.iter().fold(0, |acc, &d| acc * 16 + (d as char).to_digit(16).unwrap_or(0) as i32);
+        if !is_valid_value(raw - VALUE_LIMIT) {
+            return Err(invalid("value out of range"));
+        }
+        values.push(raw - VALUE_LIMIT);
+    }
+    Ok(values)
+}
+
+/// Описание вычисления сигнатур с преобразованием значений `transform`, как его записывают
+/// индекс и журнал: `PIPELINE` с подставленным `values`, а с `letterbox` - с фильтром
+/// `area+letterbox`, как в `ComparerOptions::pipeline`
+pub(crate) fn pipeline(transform: Transform, letterbox: bool) -> String {
+    let pipeline = match transform {
+        Transform::Square => PIPELINE.to_string(),
+        transform => PIPELINE.replace("values=squared", &format!("values={transform}")),
+    };
+    if letterbox { pipeline.replace("filter=area", "filter=area+letterbox") } else { pipeline }
+}
+
+/// Преобразование значений из описания `stored`; `None` - незнакомое значение `values`
+pub(crate) fn pipeline_transform(stored: &str) -> Option<Transform> {
+    match stored.split(';').find_map(|part| part.strip_prefix("values="))? {
+        "squared" => Some(Transform::Square),
+        values => values.parse().ok(),
+    }
+}
+
+/// Вписывались ли изображения в квадрат по описанию `stored`: фильтр с `+letterbox`
+pub(crate) fn pipeline_letterbox(stored: &str) -> bool {
+    stored.split(';').find_map(|part| part.strip_prefix("filter=")).is_some_and(|filter| filter.ends_with("+letterbox"))
+}
+
+/// Первая настройка, которой описание `stored` отличается от текущего `PIPELINE` с тем же
+/// преобразованием значений и вписыванием: имя, сохраненное и текущее значения
+pub(crate) fn pipeline_difference(stored: &str) -> Option<(String, String, String)> {
+    pipeline_difference_from(stored, &pipeline(pipeline_transform(stored).unwrap_or_default(), pipeline_letterbox(stored)))
+}
+
+/// Первая настройка, которой описание `stored` отличается от `current`
+pub(crate) fn pipeline_difference_from(stored: &str, current: &str) -> Option<(String, String, String)> {
+    if stored == current {
+        return None;
+    }
+    let parse = |text: &str| -> Vec<(String, String)> {
+        text.split(';')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.split_once('=').map_or((part.to_string(), String::new()), |(k, v)| (k.to_string(), v.to_string())))
+            .collect()
+    };
+    let (stored, current) = (parse(stored), parse(current));
+    for (key, value) in &current {
+        let stored_value = stored.iter().find(|(k, _)| k == key).map_or("none", |(_, v)| v.as_str());
+        if stored_value != value {
+            return Some((key.clone(), stored_value.to_string(), value.clone()));
+        }
+    }
+    let (key, value) = stored.iter().find(|entry| !current.contains(entry)).cloned().unwrap_or_default();
+    Some((key, value, "none".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Сетка `size`x`size`, все значения которой равны `value`
+    fn flat_grid(size: u32, value: i32) -> Grid {
+        let n = (size * size) as usize;
+        Grid::from_parts(size, &vec![[value; 3]; n], vec![value; n], &vec![[value; 2]; n], Transform::Square)
+    }
+
+    #[test]
+    fn all_max_against_all_min_is_finite_and_exact() {
+        let max = Signature::from_grids(vec![flat_grid(16, VALUE_LIMIT)], false);
+        let min = Signature::from_grids(vec![flat_grid(16, -VALUE_LIMIT)], false);
+        let expected = 3.0 * 256.0 * (2.0 * VALUE_LIMIT as f64).sqrt();
+        let diff = max.distance(&min).unwrap();
+        assert!((diff - expected).abs() <= expected * 1e-12, "{diff} != {expected}");
+        assert_eq!(diff, min.distance(&max).unwrap());
+        assert_eq!(max.similarity(&min).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn large_grids_and_scales_do_not_overflow() {
+        let max = Signature::from_grids([8, 16, 32, 64, 128].map(|size| flat_grid(size, VALUE_LIMIT)).into(), false);
+        let min = Signature::from_grids([8, 16, 32, 64, 128].map(|size| flat_grid(size, -VALUE_LIMIT)).into(), false);
+        let diff = max.distance(&min).unwrap();
+        assert!(diff.is_finite() && diff > 0.0);
+        assert_eq!(max.similarity(&min).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn unit_difference_survives_large_sums() {
+        // Одна единица на фоне максимальных значений не теряется при накоплении
+        let n = 128 * 128;
+        let mut cells = vec![[VALUE_LIMIT; 3]; n];
+        cells[n / 2][1] -= 1;
+        let base = Signature::from_grids(vec![flat_grid(128, VALUE_LIMIT)], false);
+        let changed = Signature::from_grids(vec![Grid::from_parts(128, &cells, vec![VALUE_LIMIT; n], &vec![[VALUE_LIMIT; 2]; n], Transform::Square)], false);
+        assert!(base.distance(&changed).unwrap() > 0.0);
+        assert!(base.similarity(&changed).unwrap() < 100.0);
+        assert_eq!(base.distance(&base).unwrap(), 0.0);
+        assert_eq!(base.similarity(&base).unwrap(), 100.0);
+    }
+
+    /// Образцы `img_hash` (плавные, блочные, шумные и кольца) и их обрезки с нечетными сторонами,
+    /// у которых ячейки не ложатся на границы пикселей
+    fn samples() -> Vec<DynamicImage> {
+        let files = ["gradient.png", "diagonal.png", "blocks.png", "stripes.png", "rings.png", "noise.png"];
+        let images = files.map(|file| crate::image::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash").join(file)).unwrap());
+        images.iter().cloned().chain(images.iter().map(|image| image.crop_imm(3, 5, 57, 43))).collect()
+    }
+
+    #[test]
+    fn grid_32_downsampled_to_16_matches_the_native_signature() {
+        for image in samples() {
+            let multi_scale = Signature::compute_from_image_multi_scale(&image).unwrap();
+            // Только сетка 32x32, иначе `downsample_to` взял бы готовую сетку 16x16
+            let grid_32 = Signature::from_grids(vec![multi_scale.grids.last().unwrap().clone()], false);
+            assert_eq!(grid_32.grid_sizes(), [32]);
+            let native = Signature::compute_from_image(&image).unwrap();
+            let reduced = grid_32.downsample_to(16).unwrap();
+            assert_eq!(reduced.grid_sizes(), [16]);
+            let similarity = reduced.similarity(&native).unwrap();
+            assert!(similarity >= 99.0, "{similarity}");
+
+            assert_eq!(multi_scale.downsample_to(16).unwrap().similarity(&native).unwrap(), 100.0);
+            assert!(matches!(grid_32.similarity(&native), Err(ImgAlgError::SignatureMismatch)));
+        }
+    }
+
+    #[test]
+    fn downsampling_needs_a_multiple_grid_and_cell_colors() {
+        let image = &samples()[0];
+        let multi_scale = Signature::compute_from_image_multi_scale(image).unwrap();
+        assert!(matches!(multi_scale.downsample_to(12), Err(ImgAlgError::InvalidSignature(_))));
+        assert!(matches!(multi_scale.downsample_to(0), Err(ImgAlgError::InvalidSignature(_))));
+        // У разобранной из текста сигнатуры цветов ячеек нет
+        let grid_32 = Signature::from_grids(vec![multi_scale.grids.last().unwrap().clone()], false);
+        let parsed: Signature = grid_32.to_string().parse().unwrap();
+        assert!(matches!(parsed.downsample_to(16), Err(ImgAlgError::InvalidSignature(_))));
+    }
+}
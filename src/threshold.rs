@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ImgAlgError, Result};
+
+/// Минимальный процент схожести, всегда в пределах 0..=100
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct SimilarityThreshold(f32);
+
+impl SimilarityThreshold {
+    /// Только изображения с одинаковыми сигнатурами
+    pub const EXACT: Self = Self(100.0);
+    /// Только практически одинаковые изображения
+    pub const STRICT: Self = Self(99.0);
+    /// Порог по умолчанию
+    pub const DEFAULT: Self = Self(95.0);
+    /// Похожие изображения, в том числе после заметных правок
+    pub const LOOSE: Self = Self(85.0);
+
+    pub fn new(value: f32) -> Result<Self> {
+        if value.is_nan() || !(0.0..=100.0).contains(&value) {
+            return Err(ImgAlgError::InvalidThreshold(format!("{value} is not between 0 and 100")));
+        }
+        Ok(Self(value))
+    }
+
+    pub fn value(self) -> f32 {
+        self.0
+    }
+
+    /// Проходит ли процент схожести порог
+    pub fn is_met_by(self, similarity: f32) -> bool {
+        similarity >= self.0
+    }
+}
+
+impl Default for SimilarityThreshold {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TryFrom<f32> for SimilarityThreshold {
+    type Error = ImgAlgError;
+
+    fn try_from(value: f32) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl From<SimilarityThreshold> for f32 {
+    fn from(threshold: SimilarityThreshold) -> Self {
+        threshold.0
+    }
+}
+
+impl FromStr for SimilarityThreshold {
+    type Err = ImgAlgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().trim_end_matches('%');
+        match s.parse::<f32>() {
+            Ok(value) => Self::new(value),
+            // "95,5" - частая ошибка при русской раскладке
+            Err(_) if s.contains(',') && s.replacen(',', ".", 1).parse::<f32>().is_ok() => Err(ImgAlgError::InvalidThreshold(
+                format!("'{s}' uses a comma, write the decimal separator as a dot: {}", s.replacen(',', ".", 1)),
+            )),
+            Err(_) => Err(ImgAlgError::InvalidThreshold(format!("'{s}' is not a number"))),
+        }
+    }
+}
+
+impl fmt::Display for SimilarityThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_percent_signs() {
+        assert_eq!("95".parse::<SimilarityThreshold>().unwrap(), SimilarityThreshold::DEFAULT);
+        assert_eq!(" 99.5% ".parse::<SimilarityThreshold>().unwrap().value(), 99.5);
+        assert_eq!("0".parse::<SimilarityThreshold>().unwrap().value(), 0.0);
+        assert_eq!("100".parse::<SimilarityThreshold>().unwrap(), SimilarityThreshold::EXACT);
+    }
+
+    #[test]
+    fn rejects_a_decimal_comma_with_a_hint() {
+        let message = "95,5".parse::<SimilarityThreshold>().unwrap_err().to_string();
+        assert!(message.contains("write the decimal separator as a dot: 95.5"), "{message}");
+    }
+
+    #[test]
+    fn rejects_out_of_range_and_garbage() {
+        for value in ["250", "-3", "100.01", "NaN", "", "ninety", "95,5,5"] {
+            assert!(value.parse::<SimilarityThreshold>().is_err(), "{value}");
+        }
+        assert!(SimilarityThreshold::try_from(f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn presets_are_ordered() {
+        use SimilarityThreshold as T;
+        assert!(T::EXACT > T::STRICT && T::STRICT > T::DEFAULT && T::DEFAULT > T::LOOSE);
+        assert!(T::DEFAULT.is_met_by(95.0) && !T::DEFAULT.is_met_by(94.99));
+    }
+}
@@ -0,0 +1,95 @@
+use image::{DynamicImage, GrayImage};
+use std::path::Path;
+
+use crate::signature::MULTI_SCALE_GRIDS;
+use crate::{downscale, info, Result};
+
+/// Наибольшее слагаемое разницы: `sqrt(2·255²)`, корень самой большой разности двух значений
+pub(crate) const MAX_TERM: f64 = 360.624_458_405_139_2;
+
+/// Карта допусков: полутоновое изображение тех же размеров, что и сравниваемые, в котором
+/// яркость пикселя задает, насколько велика допустимая разница в этом месте. Черное - допуска
+/// нет, как без карты; белое - место не сравнивается, как под маской.
+///
+/// Расчет: карта уменьшается до сетки сигнатуры тем же усреднением по площади, что
+/// и изображения, и допуск ячейки `t = яркость / 255`. Разность сигнатуры записывается между
+/// ячейкой и предыдущей по строкам, поэтому у разности допуск - больший из допусков этих двух
+/// ячеек. Каждое слагаемое разницы `sqrt(|a - b|)` уменьшается на `t · MAX_TERM`
+/// (`MAX_TERM = sqrt(2·255²) ≈ 360.6` - наибольшее возможное слагаемое) и не бывает
+/// меньше нуля: `max(0, слагаемое - t · 360.6)`. Так мелкие различия в допустимой области
+/// пропадают, а крупные учитываются частично. Ячейка слагаемого - та же, что в `explain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToleranceMap {
+    width: u32,
+    height: u32,
+    /// Для каждой стороны сетки - допуски разностей по номеру ячейки
+    grids: Vec<(u32, Vec<f64>)>,
+}
+
+impl ToleranceMap {
+    /// Читает карту из файла; цветное изображение переводится в полутоновое
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::from_image(&info::open_image(path.as_ref())?))
+    }
+
+    pub fn from_image(image: &DynamicImage) -> Self {
+        Self::from_luma(&image.to_luma8())
+    }
+
+    pub fn from_luma(image: &GrayImage) -> Self {
+        let rgba = DynamicImage::ImageLuma8(image.clone()).into_rgba8();
+        // Среди сеток многомасштабной сигнатуры есть и обычная 16x16
+        let grids = MULTI_SCALE_GRIDS
+            .into_iter()
+            .map(|size| {
+                let cells: Vec<f64> = downscale::area_average(&rgba, size).iter().map(|pixel| pixel[0] as f64 / 255.0).collect();
+                let entries = (0..cells.len()).map(|cell| if cell == 0 { cells[0] } else { cells[cell].max(cells[cell - 1]) }).collect();
+                (size, entries)
+            })
+            .collect();
+        Self { width: image.width(), height: image.height(), grids }
+    }
+
+    /// Ширина и высота карты: такими должны быть сравниваемые изображения
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Хеш FNV-1a допусков: две карты одних размеров различаются и в описании вычисления
+    pub(crate) fn digest(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (size, entries) in &self.grids {
+            for byte in size.to_le_bytes().into_iter().chain(entries.iter().flat_map(|entry| entry.to_bits().to_le_bytes())) {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Слагаемое `term` разности на ячейке `cell` сетки `size` за вычетом допуска.
+    /// Для сеток других размеров допуска нет
+    pub(crate) fn reduce(&self, size: u32, cell: usize, term: f64) -> f64 {
+        let tolerance = self.grids.iter().find(|(grid, _)| *grid == size).and_then(|(_, entries)| entries.get(cell)).copied().unwrap_or(0.0);
+        (term - tolerance * MAX_TERM).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn term_is_reduced_by_the_larger_tolerance_of_the_two_cells() {
+        // Левая половина - полный допуск, правая - четверть
+        let map = ToleranceMap::from_luma(&GrayImage::from_fn(16, 16, |x, _| image::Luma([if x < 8 { 255 } else { 64 }])));
+        assert_eq!(map.dimensions(), (16, 16));
+        let quarter = 64.0 / 255.0 * MAX_TERM;
+        assert_eq!(map.reduce(16, 3, MAX_TERM), 0.0);
+        // Разность ячейки 8 записана с ячейкой 7, у которой полный допуск
+        assert_eq!(map.reduce(16, 8, MAX_TERM), 0.0);
+        assert_eq!(map.reduce(16, 9, MAX_TERM), MAX_TERM - quarter);
+        assert_eq!(map.reduce(16, 9, quarter / 2.0), 0.0);
+        // Сетки других размеров карта не знает
+        assert_eq!(map.reduce(12, 3, 10.0), 10.0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ImgAlgError, Result};
+
+/// Как 8-битное значение канала переводится в число сигнатуры перед вычислением разностей.
+///
+/// Все преобразования переводят `0..=255` в `0..=255²`, поэтому наибольшая возможная разница
+/// и шкала процентов схожести у них общие; меняется только то, какие уровни яркости весят
+/// больше. Сигнатуры с разными преобразованиями не сравниваются: `SignatureMismatch`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Transform {
+    /// Линейно: разница двух уровней одинакова в темных и светлых участках. Подходит,
+    /// когда важны детали во всем диапазоне, например у документов и схем
+    Identity,
+    /// Квадрат значения, как раньше: светлые участки весят заметно больше темных.
+    /// С ним посчитаны сигнатуры прежних индексов, и под него подобраны пороги по умолчанию
+    #[default]
+    Square,
+    /// Корень: выделяет различия в темных участках, например у ночных снимков
+    /// или интерфейса в темной теме
+    Sqrt,
+    /// Степень `(c / 255)^γ`: при γ > 1 больше весят светлые участки, при γ < 1 - темные.
+    /// γ = 2.2 примерно переводит значения sRGB в линейную яркость
+    Gamma(f32),
+}
+
+/// `Gamma` с NaN не создается: показатель проверяется в `validate` и при разборе
+impl Eq for Transform {}
+
+impl Transform {
+    /// `InvalidOptions`, если показатель `Gamma` не положительное число
+    pub(crate) fn validate(self) -> Result<Self> {
+        match self {
+            Self::Gamma(gamma) if !gamma.is_finite() || gamma <= 0.0 => {
+                Err(ImgAlgError::InvalidOptions("the gamma exponent must be a positive number".to_string()))
+            }
+            _ => Ok(self),
+        }
+    }
+
+    /// Значения для всех 256 уровней канала
+    pub(crate) fn table(self) -> [i32; 256] {
+        let scaled = |f: fn(f64, f64) -> f64, exponent: f64| {
+            std::array::from_fn(|c| (f(c as f64 / 255.0, exponent) * 65025.0).round() as i32)
+        };
+        match self {
+            Self::Identity => std::array::from_fn(|c| c as i32 * 255),
+            Self::Square => std::array::from_fn(|c| (c as i32).pow(2)),
+            Self::Sqrt => scaled(|x, _| x.sqrt(), 0.0),
+            Self::Gamma(gamma) => scaled(f64::powf, gamma as f64),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Identity => f.write_str("identity"),
+            Self::Square => f.write_str("square"),
+            Self::Sqrt => f.write_str("sqrt"),
+            Self::Gamma(gamma) => write!(f, "gamma={gamma}"),
+        }
+    }
+}
+
+/// `identity`, `square`, `sqrt` или `gamma=2.2`
+impl FromStr for Transform {
+    type Err = ImgAlgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImgAlgError::InvalidOptions(format!("unknown value transform `{s}`, expected identity, square, sqrt or gamma=<exponent>"));
+        let transform = match s.trim() {
+            "identity" => Self::Identity,
+            "square" => Self::Square,
+            "sqrt" => Self::Sqrt,
+            other => {
+                let gamma = other.strip_prefix("gamma=").ok_or_else(invalid)?;
+                Self::Gamma(gamma.trim().parse().map_err(|_| invalid())?)
+            }
+        };
+        transform.validate()
+    }
+}
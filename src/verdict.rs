@@ -0,0 +1,172 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ImgAlgError, Result};
+
+/// Словесная оценка схожести двух изображений
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Verdict {
+    Identical,
+    NearDuplicate,
+    Similar,
+    Different,
+}
+
+impl Verdict {
+    /// Оценка по проценту схожести (`ImagesComparer`, `Signature`)
+    pub fn from_similarity(similarity: f32, cutoffs: &Cutoffs) -> Self {
+        if similarity >= cutoffs.identical {
+            Self::Identical
+        } else if similarity >= cutoffs.near_duplicate {
+            Self::NearDuplicate
+        } else if similarity >= cutoffs.similar {
+            Self::Similar
+        } else {
+            Self::Different
+        }
+    }
+
+    /// Оценка по расстоянию Хэмминга между отпечатками (`Fingerprint`)
+    pub fn from_distance(distance: u32, cutoffs: &HammingCutoffs) -> Self {
+        if distance <= cutoffs.identical {
+            Self::Identical
+        } else if distance <= cutoffs.near_duplicate {
+            Self::NearDuplicate
+        } else if distance <= cutoffs.similar {
+            Self::Similar
+        } else {
+            Self::Different
+        }
+    }
+
+    /// Машиночитаемое имя оценки
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::NearDuplicate => "near_duplicate",
+            Self::Similar => "similar",
+            Self::Different => "different",
+        }
+    }
+
+    /// Считается ли изображение дубликатом (одинаковое или почти одинаковое)
+    pub fn is_duplicate(&self) -> bool {
+        matches!(self, Self::Identical | Self::NearDuplicate)
+    }
+}
+
+/// Нижние границы процента схожести для каждой оценки.
+///
+/// По умолчанию 99.5, 97 и 90: в `identical` попадают только копии, которые можно удалять
+/// без просмотра (без изменений или пересохраненные без потерь), в `near_duplicate` -
+/// пересжатые и слегка поправленные копии для проверки человеком, в `similar` - заметные
+/// правки и другой кадр той же сцены
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cutoffs {
+    pub identical: f32,
+    pub near_duplicate: f32,
+    pub similar: f32,
+}
+
+impl Default for Cutoffs {
+    fn default() -> Self {
+        Self { identical: 99.5, near_duplicate: 97.0, similar: 90.0 }
+    }
+}
+
+impl Cutoffs {
+    /// Границы с проверкой: каждая в пределах 0..=100, и они строго убывают.
+    /// Процент, равный границе, относится к более высокой оценке
+    pub fn new(identical: f32, near_duplicate: f32, similar: f32) -> Result<Self> {
+        let cutoffs = [identical, near_duplicate, similar];
+        if let Some(value) = cutoffs.iter().find(|value| value.is_nan() || !(0.0..=100.0).contains(*value)) {
+            return Err(ImgAlgError::InvalidThreshold(format!("{value} is not between 0 and 100")));
+        }
+        if !(identical > near_duplicate && near_duplicate > similar) {
+            return Err(ImgAlgError::InvalidThreshold(format!(
+                "cutoffs {identical},{near_duplicate},{similar} must be strictly decreasing"
+            )));
+        }
+        Ok(Self { identical, near_duplicate, similar })
+    }
+}
+
+impl fmt::Display for Cutoffs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.identical, self.near_duplicate, self.similar)
+    }
+}
+
+/// Три границы через запятую: `99.5,97,90`
+impl FromStr for Cutoffs {
+    type Err = ImgAlgError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().trim_end_matches('%').parse::<f32>().map_err(|_| ImgAlgError::InvalidThreshold(format!("'{}' is not a number", value.trim()))))
+            .collect::<Result<Vec<_>>>()?;
+        match values.as_slice() {
+            &[identical, near_duplicate, similar] => Self::new(identical, near_duplicate, similar),
+            _ => Err(ImgAlgError::InvalidThreshold(format!("expected three comma-separated cutoffs, got {}", values.len()))),
+        }
+    }
+}
+
+/// Верхние границы расстояния Хэмминга между 64-битными отпечатками для каждой оценки.
+///
+/// По умолчанию - общепринятые для pHash значения: совпадение всех битов,
+/// до 5 различающихся битов для дубликатов (пересжатие, изменение размера)
+/// и до 12 для похожих изображений. Проценты здесь неприменимы: 5 бит из 64
+/// соответствовали бы 92%, что для старого алгоритма уже не дубликат
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HammingCutoffs {
+    pub identical: u32,
+    pub near_duplicate: u32,
+    pub similar: u32,
+}
+
+impl Default for HammingCutoffs {
+    fn default() -> Self {
+        Self { identical: 0, near_duplicate: 5, similar: 12 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_on_a_cutoff_takes_the_higher_band() {
+        use Verdict::*;
+        let cutoffs = Cutoffs::default();
+        let below = |value: f32| f32::from_bits(value.to_bits() - 1);
+        let scores = [100.0, 99.5, below(99.5), 97.0, below(97.0), 90.0, below(90.0), 0.0];
+        let verdicts = scores.map(|score| Verdict::from_similarity(score, &cutoffs));
+        assert_eq!(verdicts, [Identical, Identical, NearDuplicate, NearDuplicate, Similar, Similar, Different, Different]);
+    }
+
+    #[test]
+    fn cutoffs_parse_and_must_strictly_decrease() {
+        assert_eq!("99.5,97,90".parse::<Cutoffs>().unwrap(), Cutoffs::default());
+        assert_eq!(Cutoffs::default().to_string(), "99.5,97,90");
+        assert!("99.5, 97%, 90".parse::<Cutoffs>().is_ok());
+        for text in ["97,97,90", "90,97,99.5", "101,97,90", "99.5,97", "99.5,x,90", "NaN,97,90"] {
+            assert!(text.parse::<Cutoffs>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn hamming_bands_use_their_own_defaults() {
+        use Verdict::*;
+        let cutoffs = HammingCutoffs::default();
+        let verdicts = [0, 1, 5, 6, 12, 13, 64].map(|distance| Verdict::from_distance(distance, &cutoffs));
+        assert_eq!(verdicts, [Identical, NearDuplicate, NearDuplicate, Similar, Similar, Different, Different]);
+    }
+
+    #[test]
+    fn only_identical_and_near_duplicates_are_duplicates() {
+        assert!(Verdict::Identical.is_duplicate() && Verdict::NearDuplicate.is_duplicate());
+        assert!(!Verdict::Similar.is_duplicate() && !Verdict::Different.is_duplicate());
+    }
+}
@@ -0,0 +1,172 @@
+//! Сравнение видеороликов по кадрам.
+//!
+//! Кадры извлекаются программами `ffmpeg` и `ffprobe`, которые должны быть установлены
+//! и доступны через `PATH`; библиотека с ними не связывается. Без них каждый ролик
+//! дает ошибку `Video`, изображения при этом сравниваются как обычно
+
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::index::IndexMatch;
+use crate::signature::{self, Signature, GRID_SIZE};
+use crate::{ComparerOptions, ImgAlgError, Result, SimilarityThreshold, Transform};
+
+/// Сколько кадров берется из ролика по умолчанию
+pub const DEFAULT_FRAMES: usize = 8;
+
+/// Расширения роликов, которые просматриваются
+const EXTENSIONS: [&str; 5] = ["mp4", "m4v", "mov", "mkv", "webm"];
+
+/// Ролик ли это, по расширению
+pub fn is_video<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.iter().any(|known| extension.eq_ignore_ascii_case(known)))
+}
+
+/// Сигнатуры равномерно взятых кадров ролика
+#[derive(Debug, Clone)]
+pub struct VideoSignature {
+    /// Длительность по данным контейнера
+    pub duration: Duration,
+    frames: Vec<Signature>,
+}
+
+impl VideoSignature {
+    /// Берет `frames` кадров через равные промежутки, по середине каждого промежутка,
+    /// и считает сигнатуру каждого. Кадры, которые не удалось извлечь (например, у конца
+    /// ролика с неточной длительностью), пропускаются; если не извлекся ни один - ошибка
+    pub fn compute<P: AsRef<Path>>(video_path: P, frames: usize) -> Result<Self> {
+        let path = video_path.as_ref();
+        let duration = probe_duration(path)?;
+        let frames: Vec<Signature> = (0..frames.max(1))
+            .filter_map(|frame| {
+                let at = duration.mul_f64((2 * frame + 1) as f64 / (2 * frames.max(1)) as f64);
+                let image = extract_frame(path, at).ok()?;
+                Signature::from_image_scales(image, &[GRID_SIZE], Transform::Square, false).ok()
+            })
+            .collect();
+        if frames.is_empty() {
+            return Err(video_error(path, "no frames could be extracted"));
+        }
+        Ok(Self { duration, frames })
+    }
+
+    pub fn frames(&self) -> &[Signature] {
+        &self.frames
+    }
+
+    /// Схожесть роликов: для каждого кадра берется самый похожий кадр другого ролика,
+    /// схожести усредняются, и результат усредняется по обоим направлениям. Поэтому
+    /// сдвиг на несколько кадров и разное число кадров почти не влияют на оценку
+    pub fn similarity(&self, other: &VideoSignature) -> f32 {
+        (best_matches(&self.frames, &other.frames) + best_matches(&other.frames, &self.frames)) / 2.0
+    }
+}
+
+/// Средняя схожесть кадров `a` с самыми похожими кадрами `b`
+fn best_matches(a: &[Signature], b: &[Signature]) -> f32 {
+    let best = |frame: &Signature| b.iter().map(|other| signature::similarity_from_diff(frame.raw_distance(other))).fold(0.0, f32::max);
+    a.iter().map(best).sum::<f32>() / a.len() as f32
+}
+
+/// Итог `scan_videos`
+#[derive(Debug, Default)]
+pub struct VideoScan {
+    /// Ролики, которые удалось прочитать, с длительностью, в порядке путей
+    pub videos: Vec<(PathBuf, Duration)>,
+    /// Группы похожих роликов, как `ScanReport::groups`
+    pub groups: Vec<Vec<IndexMatch>>,
+    /// Ролики, которые не удалось прочитать, в том числе без `ffmpeg`
+    pub errors: Vec<ImgAlgError>,
+}
+
+/// Считает сигнатуры роликов параллельно и группирует похожие так же, как
+/// `SignatureIndex::scan` группирует изображения. Из `options` берутся число потоков
+/// и флаг отмены. Ошибка чтения ролика не прерывает просмотр, а попадает в `errors`
+pub fn scan_videos<P: AsRef<Path> + Sync>(paths: &[P], frames: usize, threshold: SimilarityThreshold, options: &ComparerOptions) -> Result<VideoScan> {
+    let mut paths: Vec<&Path> = paths.iter().map(AsRef::as_ref).collect();
+    paths.sort();
+    paths.dedup();
+    let pool = options.thread_pool()?;
+    let computed: Vec<Result<VideoSignature>> =
+        pool.install(|| paths.par_iter().map(|path| options.check_cancelled().and_then(|()| VideoSignature::compute(path, frames))).collect());
+
+    let mut scan = VideoScan::default();
+    let mut signatures = vec![];
+    for (path, result) in paths.iter().zip(computed) {
+        match result {
+            Ok(video) => {
+                scan.videos.push((path.to_path_buf(), video.duration));
+                signatures.push(video);
+            }
+            Err(ImgAlgError::Cancelled) => return Err(ImgAlgError::Cancelled),
+            Err(e) => scan.errors.push(e),
+        }
+    }
+
+    let mut grouped = vec![false; signatures.len()];
+    for reference in 0..signatures.len() {
+        if grouped[reference] {
+            continue;
+        }
+        let mut group = vec![IndexMatch { path: scan.videos[reference].0.clone(), similarity: 100.0, same_file: false }];
+        for idx in reference + 1..signatures.len() {
+            let similarity = signatures[reference].similarity(&signatures[idx]);
+            if !grouped[idx] && threshold.is_met_by(similarity) {
+                grouped[idx] = true;
+                group.push(IndexMatch { path: scan.videos[idx].0.clone(), similarity, same_file: false });
+            }
+        }
+        if group.len() > 1 {
+            scan.groups.push(group);
+        }
+    }
+    Ok(scan)
+}
+
+/// Длительность ролика по `ffprobe`
+fn probe_duration(path: &Path) -> Result<Duration> {
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"]).arg(path);
+    let output = run(path, command)?;
+    let text = String::from_utf8_lossy(&output);
+    let seconds: f64 = text.trim().parse().map_err(|_| video_error(path, format!("ffprobe reported no duration: '{}'", text.trim())))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| video_error(path, format!("invalid duration {seconds}")))
+}
+
+/// Один кадр в момент `at`, переданный из `ffmpeg` в формате PNG
+fn extract_frame(path: &Path, at: Duration) -> Result<image::DynamicImage> {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-nostdin", "-ss", &format!("{:.3}", at.as_secs_f64()), "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"]);
+    let output = run(path, command)?;
+    if output.is_empty() {
+        return Err(video_error(path, format!("no frame at {:.3}s", at.as_secs_f64())));
+    }
+    image::load_from_memory_with_format(&output, image::ImageFormat::Png).map_err(|e| video_error(path, e.to_string()))
+}
+
+/// Запускает программу и возвращает ее вывод; отсутствие программы и ненулевой код
+/// завершения - ошибка `Video` с первой строкой ее сообщения
+fn run(path: &Path, mut command: Command) -> Result<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.stdin(Stdio::null()).output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => video_error(path, format!("{program} was not found in PATH, install ffmpeg to compare videos")),
+        _ => video_error(path, format!("failed to run {program}: {e}")),
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(video_error(path, format!("{program} failed: {}", stderr.lines().next().unwrap_or("no error message"))));
+    }
+    Ok(output.stdout)
+}
+
+fn video_error(path: &Path, reason: impl Into<String>) -> ImgAlgError {
+    ImgAlgError::Video { path: path.to_path_buf(), reason: reason.into() }
+}
@@ -0,0 +1,45 @@
+use std::path::{Path, PathBuf};
+
+/// Некритичное отклонение: операция выполнена, но не совсем так, как ожидалось.
+/// Библиотека сама ничего не выводит, предупреждения возвращаются вместе с результатом:
+/// `ImageInfo::warnings`, `ImagesComparer::warnings`, `ScanReport::warnings`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Warning {
+    /// Отображенный в память файл поменялся во время декодирования и прочитан заново (`mmap`)
+    #[error("The file changed while it was being decoded and was read again")]
+    FileChanged { path: PathBuf },
+    /// У записи индекса другие размер или время изменения, чем у файла: сигнатура пересчитана
+    #[error("The index entry is out of date, the signature was recomputed")]
+    StaleEntry { path: PathBuf },
+    /// Кадр анимации не декодировался: сравнивались только кадры до него
+    #[error("Only the first {frames} frames of the animation could be read: {reason}")]
+    TruncatedAnimation { path: PathBuf, frames: usize, reason: String },
+    /// Файл прочитан не с первой попытки (`ComparerOptions::retries`)
+    #[error("The file was read after {retries} retries")]
+    Retried { path: PathBuf, retries: u32 },
+    /// Одна сторона изображения длиннее другой больше чем в `MAX_ASPECT_RATIO` раз:
+    /// сетка сигнатуры почти целиком состоит из растянутой полоски пикселей, и схожесть
+    /// с другими изображениями мало что значит. С `ComparerOptions::strict_aspect` - ошибка
+    #[error("The image is {width}x{height}: its sides differ more than {}-fold, the similarity may be meaningless", crate::info::MAX_ASPECT_RATIO)]
+    ExtremeAspect { path: PathBuf, width: u32, height: u32 },
+}
+
+impl Warning {
+    /// Файл, к которому относится предупреждение
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::FileChanged { path } | Self::StaleEntry { path } | Self::TruncatedAnimation { path, .. } | Self::Retried { path, .. } | Self::ExtremeAspect { path, .. } => path,
+        }
+    }
+
+    /// Машиночитаемое имя
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::FileChanged { .. } => "file_changed",
+            Self::StaleEntry { .. } => "stale_entry",
+            Self::TruncatedAnimation { .. } => "truncated_animation",
+            Self::Retried { .. } => "retried",
+            Self::ExtremeAspect { .. } => "extreme_aspect",
+        }
+    }
+}
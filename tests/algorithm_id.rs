@@ -0,0 +1,64 @@
+//! `algorithm_id`: идентификатор меняется с каждой настройкой, от которой зависят числа
+
+use image::{DynamicImage, GrayImage, Luma};
+use imgalg::{algorithm_id, CancelToken, Channel, ChannelSelect, ComparerOptions, ImagesComparer, Rect, ToleranceMap, Transform};
+use std::collections::HashSet;
+use std::time::Duration;
+
+const DEFAULT: &str = "color-diff-v2(grid=16,filter=area,color=rgba8,values=squared)";
+
+#[test]
+fn identifier_changes_with_every_relevant_option() {
+    let base = ComparerOptions::new;
+    let map = ToleranceMap::from_image(&DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([128]))));
+    let variants = [
+        base(),
+        base().multi_scale(true),
+        base().multi_scale(true).scale_weights([2.0, 1.0, 1.0]).unwrap(),
+        base().letterbox(true),
+        base().value_transform(Transform::Sqrt).unwrap(),
+        base().channels(ChannelSelect::All),
+        base().channels(ChannelSelect::Single(Channel::G)),
+        base().channel_weights([2.0, 1.0, 1.0]).unwrap(),
+        base().ignore_hue(true),
+        base().crop(Some(Rect::new(0, 0, 10, 10))),
+        base().crop(Some(Rect::new(1, 0, 10, 10))),
+        base().ignore_worst(2).unwrap(),
+        base().tolerance_map(Some(map)),
+        base().ensemble(true),
+    ];
+    let ids: Vec<String> = variants.iter().map(algorithm_id).collect();
+    assert_eq!(ids[0], DEFAULT);
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len(), "{ids:#?}");
+    assert!(ids.iter().all(|id| id.starts_with("color-diff-v2(") && id.ends_with(')')), "{ids:#?}");
+}
+
+#[test]
+fn identifier_ignores_how_the_work_is_done() {
+    let same = [
+        ComparerOptions::new().threads(7),
+        ComparerOptions::new().retries(3, Duration::from_millis(5)),
+        ComparerOptions::new().decode_timeout(Some(Duration::from_secs(2))),
+        ComparerOptions::new().cancel_token(CancelToken::new()),
+        ComparerOptions::new().strict_aspect(true),
+        // Веса масштабов без многомасштабной сигнатуры не используются
+        ComparerOptions::new().scale_weights([2.0, 1.0, 1.0]).unwrap(),
+        ComparerOptions::new().value_transform(Transform::Sqrt).unwrap().value_transform(Transform::Square).unwrap(),
+    ];
+    for options in &same {
+        assert_eq!(algorithm_id(options), DEFAULT);
+    }
+}
+
+#[test]
+fn results_carry_the_identifier_of_their_options() {
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash");
+    let paths = [fixtures.join("rings.png"), fixtures.join("blocks.png")];
+    for options in [ComparerOptions::new(), ComparerOptions::new().multi_scale(true).letterbox(true)] {
+        let (comparer, errors) = ImagesComparer::new_lossy_with(&paths, options.clone());
+        assert!(errors.is_empty());
+        assert_eq!(comparer.algorithm_id(), algorithm_id(&options));
+        assert_eq!(&*comparer.compare_pair(0, 1).unwrap().algorithm, algorithm_id(&options));
+        assert!(comparer.compare().iter().all(|pair| *pair.algorithm == *comparer.algorithm_id()));
+    }
+}
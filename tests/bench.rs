@@ -0,0 +1,33 @@
+//! `bench::run`: замер алгоритмов на выборке
+
+mod common;
+
+use common::{pattern, save};
+use imgalg::bench::{self, Algorithm, BenchOptions};
+use std::fs;
+
+#[test]
+fn same_seed_gives_same_distributions() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut paths: Vec<_> = (0..8).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 48, 48))).collect();
+    // Копия не дает пар с оригиналом, нечитаемый файл не участвует в парах
+    paths.push(save(dir.path(), "copy.png", &pattern(0, 48, 48)));
+    let broken = dir.path().join("broken.png");
+    fs::write(&broken, "not an image").unwrap();
+    paths.push(broken);
+
+    let options = BenchOptions { sample: 10, pairs: 50, seed: 7 };
+    let first = bench::run(&paths, &options);
+    assert_eq!(first.iter().map(|result| result.algorithm).collect::<Vec<_>>(), Algorithm::ALL);
+    for result in &first {
+        assert_eq!((result.loaded, result.failed, result.pairs), (9, 1, 50), "{:?}", result.algorithm);
+        let scores = result.scores.unwrap();
+        assert!(scores.min <= scores.median && scores.median <= scores.p95);
+        assert!(scores.p95 < 100.0, "{:?}: {scores:?}", result.algorithm);
+    }
+
+    let scores = |results: &[bench::AlgorithmBench]| results.iter().map(|result| result.scores).collect::<Vec<_>>();
+    assert_eq!(scores(&bench::run(&paths, &options)), scores(&first));
+    paths.reverse();
+    assert_eq!(scores(&bench::run(&paths, &options)), scores(&first));
+}
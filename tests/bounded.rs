@@ -0,0 +1,71 @@
+//! `BoundedComparer`: сравнение с выгрузкой сигнатур на диск
+
+mod common;
+
+use common::{blend, pattern, save};
+use imgalg::{BoundedComparer, ComparerOptions, ImagesComparer, ImgAlgError, SimilarityThreshold};
+
+/// Группы тем же способом, что `ImagesComparer::duplicate_groups`, но по строкам матрицы
+fn groups_from_rows(rows: &[Vec<f32>], threshold: SimilarityThreshold) -> Vec<Vec<usize>> {
+    let mut grouped = vec![false; rows.len()];
+    let mut groups = vec![];
+    for (reference, row) in rows.iter().enumerate() {
+        if grouped[reference] {
+            continue;
+        }
+        let matches: Vec<usize> = (reference + 1..rows.len()).filter(|&idx| !grouped[idx] && threshold.is_met_by(row[idx])).collect();
+        if matches.is_empty() {
+            continue;
+        }
+        for &idx in &matches {
+            grouped[idx] = true;
+        }
+        groups.push([reference].into_iter().chain(matches).collect());
+    }
+    groups
+}
+
+#[test]
+fn tiny_budget_keeps_duplicate_groups() {
+    let dir = tempfile::tempdir().unwrap();
+    // 40 троек «оригинал, копия, почти копия» и 80 одиночек - 200 файлов
+    let mut paths = vec![];
+    for seed in 0..40 {
+        let image = pattern(seed, 32, 32);
+        paths.push(save(dir.path(), &format!("{seed}.png"), &image));
+        paths.push(save(dir.path(), &format!("{seed}-copy.png"), &image));
+        paths.push(save(dir.path(), &format!("{seed}-near.png"), &blend(&image, &pattern(seed + 1000, 32, 32), 0.002)));
+    }
+    for seed in 2000..2080 {
+        paths.push(save(dir.path(), &format!("{seed}.png"), &pattern(seed, 32, 32)));
+    }
+    assert_eq!(paths.len(), 200);
+
+    let (bounded, errors) = BoundedComparer::load(&paths, ComparerOptions::default(), 1).unwrap();
+    assert!(errors.is_empty());
+    assert!(bounded.is_spilled());
+    assert!(bounded.blocks() > 1);
+    let mut rows = vec![];
+    bounded
+        .for_each_row(|_, row| {
+            rows.push(row.to_vec());
+            Ok::<_, ImgAlgError>(())
+        })
+        .unwrap();
+
+    let (comparer, _) = ImagesComparer::new_lossy(&paths);
+    assert_eq!(rows, comparer.similarity_matrix());
+    let groups = groups_from_rows(&rows, SimilarityThreshold::DEFAULT);
+    assert_eq!(groups, comparer.duplicate_groups(SimilarityThreshold::DEFAULT));
+    assert_eq!(groups.len(), 40, "{groups:?}");
+    assert!(groups.iter().all(|group| group.len() == 3));
+}
+
+#[test]
+fn generous_budget_stays_in_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<_> = (0..5).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 32, 32))).collect();
+    let (bounded, _) = BoundedComparer::load(&paths, ComparerOptions::default(), 1 << 30).unwrap();
+    assert!(!bounded.is_spilled());
+    assert_eq!(bounded.blocks(), 1);
+}
@@ -0,0 +1,53 @@
+//! `cache`: сведения об индексе, удаление устаревших записей и перезапись файла
+
+mod common;
+
+use common::{imgalg, json, pattern, save, stdout};
+use std::fs;
+
+#[test]
+fn prune_drops_exactly_the_deleted_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    for seed in 1..=3 {
+        save(&tree, &format!("{seed}.png"), &pattern(seed, 48, 48));
+    }
+    let index = dir.path().join("photos.idx");
+    json(imgalg().args(["scan", "--json", "--index"]).arg(&index).arg(&tree));
+    fs::remove_file(tree.join("2.png")).unwrap();
+    let cache = |action: &str| json(imgalg().args(["cache", action, "--json"]).arg(&index));
+
+    let stats = cache("stats");
+    assert_eq!((stats["entries"].as_u64(), stats["current"].as_u64(), stats["missing"].as_u64()), (Some(3), Some(2), Some(1)));
+    assert_eq!(stats["file_size"].as_u64(), Some(fs::metadata(&index).unwrap().len()));
+
+    let pruned = cache("prune");
+    assert_eq!(pruned["remaining"].as_u64(), Some(2));
+    let removed = pruned["removed"].as_array().unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0]["reason"], "missing");
+    assert!(removed[0]["path"].as_str().unwrap().ends_with("2.png"));
+    assert!(pruned["size_after"].as_u64() < pruned["size_before"].as_u64());
+    assert_eq!(cache("stats")["entries"].as_u64(), Some(2));
+
+    // Нечего удалять: записи остаются, а файл перезаписывается на месте без временных файлов
+    assert_eq!(cache("prune")["removed"].as_array().map(Vec::len), Some(0));
+    let compacted = cache("compact");
+    assert_eq!(compacted["size_after"].as_u64(), Some(fs::metadata(&index).unwrap().len()));
+    let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names.len(), 2, "{names:?}");
+}
+
+#[test]
+fn stats_text_lists_the_breakdown() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    save(&tree, "a.png", &pattern(1, 48, 48));
+    save(&tree, "b.png", &pattern(2, 48, 48));
+    let index = dir.path().join("photos.idx");
+    json(imgalg().args(["scan", "--json", "--index"]).arg(&index).arg(&tree));
+    save(&tree, "b.png", &pattern(3, 40, 40));
+    let text = stdout(&imgalg().args(["cache", "stats"]).arg(&index).output().unwrap());
+    let size = fs::metadata(&index).unwrap().len();
+    assert_eq!(text.lines().skip(1).collect::<Vec<_>>(), [format!("Entries: 2, duplicate pairs: 0, file size: {size} bytes"), "Current: 1, file changed: 1, file missing: 0, unstamped: 0".to_string()]);
+}
@@ -0,0 +1,51 @@
+//! Отмена долгого просмотра из другого потока
+
+mod common;
+
+use common::{pattern, save};
+use imgalg::{CancelToken, ComparerOptions, ImagesComparer, ImgAlgError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn rows_cancelled_from_another_thread_stop_promptly() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<_> = (0..200).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 16, 16))).collect();
+    let token = CancelToken::new();
+    let (comparer, errors) = ImagesComparer::new_lossy_with(&files, ComparerOptions::new().cancel_token(token.clone()));
+    assert!(errors.is_empty());
+    let (started_tx, started_rx) = mpsc::sync_channel(1);
+    let rows = AtomicUsize::new(0);
+
+    let canceller_token = token.clone();
+    let (result, cancelled_at) = std::thread::scope(|scope| {
+        let canceller = scope.spawn(move || {
+            started_rx.recv().unwrap();
+            canceller_token.cancel();
+            Instant::now()
+        });
+        let result = comparer.for_each_row(|_, _| -> Result<(), ImgAlgError> {
+            if rows.fetch_add(1, Ordering::SeqCst) == 0 {
+                let _ = started_tx.try_send(());
+            }
+            std::thread::sleep(Duration::from_millis(5));
+            Ok(())
+        });
+        (result, canceller.join().unwrap())
+    });
+    assert!(matches!(result, Err(ImgAlgError::Cancelled)));
+    assert!(cancelled_at.elapsed() < Duration::from_secs(2));
+    assert!(rows.load(Ordering::SeqCst) < files.len() / 2, "{} rows passed", rows.load(Ordering::SeqCst));
+}
+
+#[test]
+fn cancelled_token_stops_loading() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<_> = (0..20).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 32, 32))).collect();
+    let token = CancelToken::new();
+    token.cancel();
+    let (comparer, errors) = ImagesComparer::new_lossy_with(&files, ComparerOptions::new().cancel_token(token));
+    assert!(comparer.is_empty());
+    assert!(errors.iter().all(|e| matches!(e, ImgAlgError::Cancelled)));
+}
@@ -0,0 +1,70 @@
+//! Раскраска текстового вывода: `--color always` против обычного текста тех же результатов
+
+mod common;
+
+use common::{imgalg, pattern, save, stdout};
+use std::path::Path;
+
+/// Группа из двух одинаковых изображений, третье отдельно и файл, который не читается
+fn results(root: &Path) {
+    save(root, "a.png", &pattern(1, 48, 48));
+    save(root, "a-copy.png", &pattern(1, 48, 48));
+    save(root, "other.png", &pattern(2, 48, 48));
+    std::fs::write(root.join("broken.png"), b"not a png").unwrap();
+}
+
+const PLAIN: &str = "\
+Group 1, images: 2
+* ./a-copy.png (100.00%)
+  ./a.png (100.00%, identical)
+* - the image that is kept
+Failed to process: ./broken.png: Failed to open the image: The image format could not be determined
+Files: 4, taken from the index: 0, recomputed: 3, removed from the index: 0, pairs compared: 3, duplicate groups: 1
+By verdict: identical 1, near duplicates 0, similar 0, different 0
+";
+
+const COLORED: &str = "\
+\x1b[1mGroup 1, images: 2\x1b[0m
+* ./a-copy.png (\x1b[32m100.00%\x1b[0m)
+  ./a.png (\x1b[32m100.00%\x1b[0m, identical)
+* - the image that is kept
+\x1b[31mFailed to process: ./broken.png: Failed to open the image: The image format could not be determined\x1b[0m
+Files: 4, taken from the index: 0, recomputed: 3, removed from the index: 0, pairs compared: 3, duplicate groups: 1
+By verdict: identical 1, near duplicates 0, similar 0, different 0
+";
+
+#[test]
+fn forced_color_paints_the_same_text() {
+    let dir = tempfile::tempdir().unwrap();
+    results(dir.path());
+    let scan = |extra: &[&str]| stdout(&imgalg().current_dir(dir.path()).args(extra).args(["scan", "."]).output().unwrap());
+    assert_eq!(scan(&["--color", "always"]), COLORED);
+    // В канал без `--color` и с `NO_COLOR` - тот же текст без кодов
+    assert_eq!(scan(&[]), PLAIN);
+    assert_eq!(scan(&["--color", "never"]), PLAIN);
+    assert_eq!(imgalg().current_dir(dir.path()).env_remove("NO_COLOR").args(["scan", "."]).output().unwrap().stdout, PLAIN.as_bytes());
+}
+
+#[test]
+fn similarity_color_follows_its_band() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 48, 48));
+    let b = save(dir.path(), "b.png", &pattern(2, 48, 48));
+    let output = imgalg().args(["--color", "always"]).arg(&a).arg(&b).output().unwrap();
+    assert!(stdout(&output).contains("Similarity: \x1b[31m"), "{}", stdout(&output));
+    let output = imgalg().args(["--color", "always"]).arg(&a).arg(&a).output().unwrap();
+    assert!(stdout(&output).contains("Similarity: \x1b[32m100.00%\x1b[0m"), "{}", stdout(&output));
+}
+
+#[test]
+fn csv_keeps_plain_rows_and_colors_errors_on_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    results(dir.path());
+    let scan = |color: &str| imgalg().current_dir(dir.path()).args(["--color", color, "scan", "--csv", "."]).output().unwrap();
+    let (colored, plain) = (scan("always"), scan("never"));
+    assert_eq!(colored.stdout, plain.stdout);
+    assert!(!stdout(&colored).contains('\x1b'));
+    let error = "Failed to process: ./broken.png: Failed to open the image: The image format could not be determined";
+    assert_eq!(String::from_utf8_lossy(&colored.stderr), format!("\x1b[31m{error}\x1b[0m\n"));
+    assert_eq!(String::from_utf8_lossy(&plain.stderr), format!("{error}\n"));
+}
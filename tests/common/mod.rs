@@ -0,0 +1,113 @@
+//! Общее для тестов командной строки: запуск `imgalg` и синтетические изображения
+
+#![allow(dead_code)]
+
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// `imgalg` без файла настроек пользователя, с английским текстом и без цвета
+pub fn imgalg() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_imgalg"));
+    command.env("XDG_CONFIG_HOME", env!("CARGO_TARGET_TMPDIR")).env_remove("LANG").env_remove("LC_ALL").env("NO_COLOR", "1");
+    command
+}
+
+/// Запускает `imgalg` и разбирает stdout как JSON
+pub fn json(command: &mut Command) -> serde_json::Value {
+    let output = command.output().unwrap();
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| panic!("{e}: {}{}", stdout(&output), String::from_utf8_lossy(&output.stderr)))
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+/// Изображение из цветных блоков 8x8, одинаковое при одинаковом `seed`
+pub fn pattern(seed: u32, width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let block = (x / 8).wrapping_mul(7919) ^ (y / 8).wrapping_mul(104_729) ^ seed.wrapping_mul(2_654_435_761);
+        let mixed = block.wrapping_mul(2_246_822_519).rotate_left(13);
+        Rgba([mixed as u8, (mixed >> 8) as u8, (mixed >> 16) as u8, 255])
+    })
+}
+
+/// Плавный цветной градиент
+pub fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([(x * 255 / width.max(1)) as u8, (y * 255 / height.max(1)) as u8, ((x + y) * 127 / (width + height).max(1)) as u8, 255])
+    })
+}
+
+/// Шум амплитуды `amplitude` поверх изображения, одинаковый при одинаковом `seed`
+pub fn with_noise(image: &RgbaImage, amplitude: u8, seed: u32) -> RgbaImage {
+    let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+    let mut noisy = image.clone();
+    for pixel in noisy.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let offset = (state >> 24) as i32 % (amplitude as i32 * 2 + 1) - amplitude as i32;
+            *channel = (*channel as i32 + offset).clamp(0, 255) as u8;
+        }
+    }
+    noisy
+}
+
+/// Сохраняет изображение в `dir`, формат - по расширению имени
+pub fn save(dir: &Path, name: &str, image: &RgbaImage) -> PathBuf {
+    let path = dir.join(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    if name.ends_with(".jpg") {
+        image::DynamicImage::ImageRgba8(image.clone()).to_rgb8().save(&path).unwrap();
+    } else {
+        image.save(&path).unwrap();
+    }
+    path
+}
+
+/// Смесь двух изображений одного размера: `amount` 0 - первое, 1 - второе
+pub fn blend(a: &RgbaImage, b: &RgbaImage, amount: f32) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y).0, b.get_pixel(x, y).0);
+        Rgba(std::array::from_fn(|c| (pa[c] as f32 * (1.0 - amount) + pb[c] as f32 * amount).round() as u8))
+    })
+}
+
+/// Сохраняет изображения `image0.png`, `image1.png`, ... и загружает их в сравнитель
+pub fn comparer(dir: &Path, images: &[RgbaImage]) -> (imgalg::ImagesComparer, Vec<PathBuf>) {
+    let paths: Vec<PathBuf> = images.iter().enumerate().map(|(i, image)| save(dir, &format!("image{i}.png"), image)).collect();
+    let (comparer, errors) = imgalg::ImagesComparer::new_lossy(&paths);
+    assert!(errors.is_empty(), "{errors:?}");
+    (comparer, paths)
+}
+
+/// Несколько непохожих изображений и почти копия первого
+pub fn mixed_set(count: u32) -> Vec<RgbaImage> {
+    let mut images: Vec<RgbaImage> = (1..count).map(|seed| pattern(seed, 48, 48)).collect();
+    images.push(blend(&images[0], &pattern(100, 48, 48), 0.003));
+    images
+}
+
+/// Анимация GIF из трех кадров, обрезанная посреди последнего: первые два кадра читаются
+pub fn truncated_gif(dir: &Path, name: &str) -> PathBuf {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame};
+    let path = dir.join(name);
+    let mut bytes = vec![];
+    let frames = (0..3).map(|seed| Frame::from_parts(pattern(seed + 30, 48, 48), 0, 0, Delay::from_numer_denom_ms(40, 1)));
+    GifEncoder::new(&mut bytes).encode_frames(frames).unwrap();
+    bytes.truncate(bytes.len() * 5 / 6);
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+/// Случайное изображение для проверки свойств метрики: размер, блоки, градиент и шум
+/// зависят только от `seed`
+pub fn random_image(seed: u32) -> RgbaImage {
+    let mixed = seed.wrapping_mul(2_654_435_761).rotate_left(7);
+    let (width, height) = (24 + mixed % 80, 24 + (mixed >> 8) % 80);
+    let base = blend(&pattern(seed, width, height), &gradient(width, height), (mixed >> 16) as f32 % 11.0 / 10.0);
+    with_noise(&base, (mixed >> 20) as u8 % 24, seed)
+}
@@ -0,0 +1,213 @@
+//! Сравнение двух изображений из командной строки
+
+mod common;
+
+use common::{gradient, imgalg, json, pattern, save, stdout, truncated_gif, with_noise};
+
+#[test]
+fn json_report_includes_image_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 40, 30));
+    let b = save(dir.path(), "b.jpg", &pattern(1, 80, 60));
+    let report = json(imgalg().arg(&a).arg(&b).arg("--json"));
+    let images = report["images"].as_array().unwrap();
+    assert_eq!((images[0]["width"].as_u64(), images[0]["height"].as_u64(), images[0]["format"].as_str()), (Some(40), Some(30), Some("png")));
+    assert_eq!((images[1]["width"].as_u64(), images[1]["height"].as_u64(), images[1]["format"].as_str()), (Some(80), Some(60), Some("jpg")));
+    assert_eq!(images[1]["file_size"].as_u64(), Some(std::fs::metadata(&b).unwrap().len()));
+}
+
+#[test]
+fn alpha_channel_is_compared_only_when_selected() {
+    let dir = tempfile::tempdir().unwrap();
+    let opaque = pattern(1, 48, 48);
+    let mut masked = opaque.clone();
+    for (x, _, pixel) in masked.enumerate_pixels_mut() {
+        pixel.0[3] = if x < 24 { 255 } else { 0 };
+    }
+    let a = save(dir.path(), "opaque.png", &opaque);
+    let b = save(dir.path(), "masked.png", &masked);
+    let similarity = |extra: &[&str]| json(imgalg().arg(&a).arg(&b).arg("--json").args(extra))["similarity"].as_f64().unwrap();
+    assert_eq!(similarity(&[]), 100.0);
+    assert!(similarity(&["--channel", "a"]) < 75.0);
+    assert!(similarity(&["--channel", "all"]) < 100.0);
+    assert_eq!(similarity(&["--channel", "r"]), 100.0);
+}
+
+/// Снимок экрана 120x80: фон `seed` и одинаковый виджет 32x32 в точке (`widget_x`, 20)
+fn screenshot(seed: u32, widget_x: u32) -> image::RgbaImage {
+    let mut image = pattern(seed, 120, 80);
+    image::imageops::replace(&mut image, &pattern(50, 32, 32), widget_x as i64, 20);
+    image
+}
+
+#[test]
+fn differences_outside_the_crop_are_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &screenshot(1, 40));
+    let b = save(dir.path(), "b.png", &screenshot(2, 40));
+    let compare = |extra: &[&str]| json(imgalg().args(extra).arg(&a).arg(&b).arg("--json"));
+    assert!(compare(&[])["similarity"].as_f64().unwrap() < 90.0);
+
+    let report = compare(&["--crop", "40,20,32,32"]);
+    assert_eq!(report["similarity"].as_f64(), Some(100.0));
+    for image in report["images"].as_array().unwrap() {
+        assert_eq!(image["crop"], serde_json::json!({ "x": 40, "y": 20, "width": 32, "height": 32 }));
+    }
+
+    // Виджет на другом месте второго снимка
+    let moved = save(dir.path(), "moved.png", &screenshot(2, 72));
+    let report = json(imgalg().args(["--crop-a", "40,20,32,32", "--crop-b", "72,20,32,32"]).arg(&a).arg(&moved).arg("--json"));
+    assert_eq!(report["similarity"].as_f64(), Some(100.0));
+    assert_eq!(report["images"][1]["crop"]["x"], 72);
+}
+
+#[test]
+fn crop_outside_the_image_names_its_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &screenshot(1, 40));
+    let output = imgalg().args(["--crop", "100,20,32,32"]).arg(&a).arg(&a).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Crop rectangle 100,20,32,32 (x,y,width,height) does not fit into the 120x80 image"), "{stderr}");
+}
+
+/// Снимок с впечатанной датой: пунктир пурпурных штрихов 26x12 в левом нижнем углу
+fn with_timestamp(image: &image::RgbaImage) -> image::RgbaImage {
+    let mut stamped = image.clone();
+    for y in 242..254 {
+        for x in 4..30 {
+            if (x / 2 + y / 3) % 3 != 0 {
+                stamped.put_pixel(x, y, image::Rgba([255, 0, 255, 255]));
+            }
+        }
+    }
+    stamped
+}
+
+#[test]
+fn timestamp_matches_only_with_the_worst_cells_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let clean = with_noise(&gradient(256, 256), 6, 3);
+    let a = save(dir.path(), "clean.png", &clean);
+    let b = save(dir.path(), "stamped.png", &with_timestamp(&clean));
+    let compare = |extra: &[&str]| {
+        let output = imgalg().args(["--threshold", "99.5"]).args(extra).arg(&a).arg(&b).arg("--json").output().unwrap();
+        (output.status.success(), serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap())
+    };
+
+    let (passed, report) = compare(&[]);
+    assert!(!passed && report["similarity"].as_f64().unwrap() < 99.0, "{report}");
+    let (passed, report) = compare(&["--ignore-worst", "2", "--explain"]);
+    assert!(passed && report["similarity"].as_f64().unwrap() > 99.5, "{report}");
+    let dropped: Vec<_> = report["explain"].as_array().unwrap().iter().filter(|cell| cell["ignored"].as_f64() > Some(0.0)).map(|cell| (cell["x"].as_u64().unwrap(), cell["y"].as_u64().unwrap())).collect();
+    // Отброшены обе ячейки у даты в нижней строке сетки 16x16
+    assert_eq!(dropped, [(2, 15), (0, 15)], "{report}");
+}
+
+/// Плавный снимок 128x128 с пятном 24x24 в точке (`x`, `y`)
+fn with_patch(x: u32, y: u32) -> image::RgbaImage {
+    let mut image = with_noise(&gradient(128, 128), 6, 5);
+    image::imageops::replace(&mut image, &pattern(9, 24, 24), x as i64, y as i64);
+    image
+}
+
+#[test]
+fn tolerance_map_forgives_differences_only_where_it_is_light() {
+    let dir = tempfile::tempdir().unwrap();
+    // Полный допуск в квадрате 32x32 с углом (32, 32), точно по ячейкам сетки 16x16
+    let map = image::GrayImage::from_fn(128, 128, |x, y| image::Luma([if (32..64).contains(&x) && (32..64).contains(&y) { 255 } else { 0 }]));
+    let map_path = dir.path().join("tolerance.png");
+    map.save(&map_path).unwrap();
+    let clean = save(dir.path(), "clean.png", &with_noise(&gradient(128, 128), 6, 5));
+    let inside = save(dir.path(), "inside.png", &with_patch(36, 36));
+    let outside = save(dir.path(), "outside.png", &with_patch(76, 76));
+    let similarity = |b: &std::path::Path, extra: &[&str]| json(imgalg().args(extra).arg(&clean).arg(b).arg("--json"))["similarity"].as_f64().unwrap();
+    let with_map = ["--tolerance-map", map_path.to_str().unwrap()];
+
+    assert!(similarity(&inside, &[]) < 95.0);
+    assert_eq!(similarity(&inside, &with_map), 100.0);
+    let penalty = similarity(&outside, &with_map);
+    assert!(penalty < 95.0 && penalty == similarity(&outside, &[]), "{penalty}");
+
+    let small = dir.path().join("small.png");
+    image::GrayImage::new(64, 64).save(&small).unwrap();
+    let output = imgalg().args(["--tolerance-map", small.to_str().unwrap()]).arg(&clean).arg(&inside).arg("--json").output().unwrap();
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["error"]["code"], "E_ARGS");
+    assert_eq!(report["error"]["message"], "The tolerance map is 64x64 but the image is 128x128");
+}
+
+#[test]
+fn letterbox_aligns_a_portrait_crop_with_its_landscape_original() {
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/aspect");
+    let similarity = |extra: &[&str]| json(imgalg().args(extra).arg(fixtures.join("original-3x2.png")).arg(fixtures.join("crop-4x5.png")).arg("--json"))["similarity"].as_f64().unwrap();
+    let stretched = similarity(&[]);
+    let letterboxed = similarity(&["--letterbox"]);
+    assert!(letterboxed > stretched + 5.0, "{stretched} -> {letterboxed}");
+}
+
+/// Сохраняет JPEG с качеством `quality`
+fn save_jpeg(dir: &std::path::Path, name: &str, image: &image::RgbaImage, quality: u8) -> std::path::PathBuf {
+    let path = dir.join(name);
+    let rgb = image::DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(std::fs::File::create(&path).unwrap(), quality).encode_image(&rgb).unwrap();
+    path
+}
+
+#[test]
+fn resized_and_reencoded_copies_get_their_labels() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = gradient(256, 256);
+    let original = save_jpeg(dir.path(), "original.jpg", &photo, 90);
+    let resized = save_jpeg(dir.path(), "resized.jpg", &image::imageops::resize(&photo, 128, 128, image::imageops::FilterType::Nearest), 90);
+    let reencoded = save_jpeg(dir.path(), "q60.jpg", &photo, 60);
+    let label = |copy: &std::path::Path| {
+        // Сжатие JPEG само стоит метрике нескольких процентов, поэтому граница совпадения ниже
+        let report = json(imgalg().args(["--classify-matches", "--bands", "99.5,85,75"]).arg(&original).arg(copy).arg("--json"));
+        assert_eq!(report["verdict"], "near_duplicate", "{report}");
+        report["copy_kind"].as_str().unwrap().to_string()
+    };
+    assert_eq!(label(&resized), "resized");
+    assert_eq!(label(&reencoded), "recompressed");
+
+    // Без флага вида копии в отчете нет
+    assert!(json(imgalg().arg(&original).arg(&reencoded).arg("--json")).get("copy_kind").is_none());
+}
+
+#[test]
+fn still_of_frame_seven_matches_that_frame_of_the_gif() {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame};
+    let dir = tempfile::tempdir().unwrap();
+    let frames: Vec<image::RgbaImage> = (0..10).map(|seed| pattern(seed + 20, 48, 48)).collect();
+    let gif = dir.path().join("burst.gif");
+    let mut encoder = GifEncoder::new(std::fs::File::create(&gif).unwrap());
+    encoder.encode_frames(frames.iter().map(|frame| Frame::from_parts(frame.clone(), 0, 0, Delay::from_numer_denom_ms(40, 1)))).unwrap();
+    drop(encoder);
+    let still = save(dir.path(), "frame7.png", &frames[7]);
+
+    // Порядок аргументов не важен: анимацией может быть любой из двух файлов
+    for (a, b) in [(&still, &gif), (&gif, &still)] {
+        let report = json(imgalg().arg(a).arg(b).arg("--json"));
+        assert_eq!((report["best_frame"].as_u64(), report["frame_time_ms"].as_u64()), (Some(7), Some(280)), "{report}");
+        assert!(report["similarity"].as_f64().unwrap() > 99.9, "{report}");
+    }
+    let table = stdout(&imgalg().arg(&still).arg(&gif).output().unwrap());
+    assert!(table.contains("Most similar animation frame: 7 of 10, starting at 280 ms"), "{table}");
+}
+
+#[test]
+fn warnings_go_to_the_report_or_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = save(dir.path(), "photo.png", &pattern(2, 48, 48));
+    let gif = truncated_gif(dir.path(), "clip.gif");
+    let report = json(imgalg().arg(&photo).arg(&gif).arg("--json"));
+    assert_eq!(report["warnings"].as_array().unwrap().len(), 1, "{report}");
+    assert_eq!((report["warnings"][0]["kind"].as_str(), report["warnings"][0]["path"].as_str()), (Some("truncated_animation"), gif.to_str()));
+
+    let output = imgalg().arg(&photo).arg(&gif).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Only the first 2 frames"), "{stderr}");
+    assert!(!stdout(&output).contains("Only the first"));
+}
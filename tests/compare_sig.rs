@@ -0,0 +1,61 @@
+//! `compare-sig`: сравнение с сохраненными сигнатурами без исходных файлов
+
+mod common;
+
+use common::{gradient, imgalg, json, pattern, save, stdout, with_noise};
+use imgalg::Signature;
+
+#[test]
+fn stored_signature_scores_like_the_original_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let upload = save(dir.path(), "upload.png", &with_noise(&gradient(96, 96), 5, 1));
+    let originals = [save(dir.path(), "near.png", &with_noise(&gradient(96, 96), 5, 2)), save(dir.path(), "other.png", &pattern(4, 96, 96))];
+    let stored: Vec<String> = originals.iter().map(|path| Signature::compute(path).unwrap().to_string()).collect();
+
+    let report = json(imgalg().arg("compare-sig").arg(&upload).arg(&stored[0]).args(["--sig", &stored[1], "--json"]));
+    let matches = report["matches"].as_array().unwrap();
+    assert_eq!(matches.len(), 2);
+    for (m, original) in matches.iter().zip(&originals) {
+        let direct = json(imgalg().arg(&upload).arg(original).arg("--json"));
+        assert_eq!(m["similarity"], direct["similarity"], "{}", original.display());
+        assert_eq!(m["verdict"], direct["verdict"]);
+    }
+    assert_eq!(report["signature"].as_str(), Some(Signature::compute(&upload).unwrap().to_string().as_str()));
+
+    // Одна сигнатура ниже порога - код 1, как у сравнения файлов
+    let output = imgalg().args(["compare-sig", "--threshold", "90"]).arg(&upload).arg(&stored[0]).args(["--sig", &stored[1]]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn malformed_or_incompatible_signature_is_e_args() {
+    let dir = tempfile::tempdir().unwrap();
+    let upload = save(dir.path(), "upload.png", &pattern(1, 64, 64));
+    let multi_scale = Signature::compute_multi_scale(&upload).unwrap().to_string();
+    for signature in ["v2:not-a-signature", multi_scale.as_str()] {
+        let output = imgalg().arg("compare-sig").arg(&upload).arg(signature).arg("--json").output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{signature}");
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["error"]["code"], "E_ARGS");
+        assert!(report["error"]["message"].as_str().unwrap().starts_with("Signature 1: "), "{report}");
+    }
+}
+
+#[test]
+fn legacy_signature_is_compared_at_reduced_precision_only_when_allowed() {
+    let dir = tempfile::tempdir().unwrap();
+    let upload = save(dir.path(), "upload.png", &with_noise(&gradient(90, 70), 5, 1));
+    let legacy = Signature::compute(&upload).unwrap().to_string();
+    let run = |extra: &[&str]| imgalg().arg("--multi-scale").arg("compare-sig").args(extra).arg(&upload).arg(&legacy).arg("--json").output().unwrap();
+
+    let report: serde_json::Value = serde_json::from_slice(&run(&["--allow-downsample"]).stdout).unwrap();
+    assert_eq!(report["matches"][0]["downsampled_to"].as_u64(), Some(16), "{report}");
+    assert!(report["matches"][0]["similarity"].as_f64().unwrap() >= 99.0, "{report}");
+    let text = stdout(&imgalg().args(["--multi-scale", "compare-sig", "--allow-downsample"]).arg(&upload).arg(&legacy).output().unwrap());
+    assert!(text.contains("reduced precision: grid 16x16"), "{text}");
+
+    let refused = run(&[]);
+    assert_eq!(refused.status.code(), Some(2));
+    let error: serde_json::Value = serde_json::from_slice(&refused.stdout).unwrap();
+    assert_eq!(error["error"]["code"], "E_ARGS");
+}
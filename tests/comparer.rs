@@ -0,0 +1,407 @@
+//! Библиотечный API `ImagesComparer` на синтетических изображениях
+
+mod common;
+
+use common::{comparer, gradient, mixed_set, pattern, save, truncated_gif, with_noise};
+use image::ImageFormat;
+use imgalg::index::SignatureIndex;
+use imgalg::{ComparerOptions, ImagesComparer, SimilarityThreshold, Warning};
+
+#[test]
+fn iter_pairs_matches_compare() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut comparer, _) = comparer(dir.path(), &mixed_set(5));
+    let lazy: Vec<_> = comparer.iter_pairs().collect();
+    assert_eq!(lazy, comparer.compare());
+    assert_eq!(lazy.iter().map(|pair| (pair.a, pair.b)).collect::<Vec<_>>(), [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)]);
+
+    let above: Vec<_> = comparer.iter_pairs_above(SimilarityThreshold::DEFAULT).collect();
+    assert_eq!(above, lazy.iter().filter(|pair| pair.similarity >= 95.0).cloned().collect::<Vec<_>>());
+    assert_eq!(above.iter().map(|pair| (pair.a, pair.b)).collect::<Vec<_>>(), [(0, 4)]);
+
+    comparer.compare_with_first = true;
+    let reference: Vec<_> = comparer.iter_pairs().collect();
+    assert_eq!(reference, comparer.compare());
+    assert!(reference.iter().all(|pair| pair.a == 0) && reference.len() == 4);
+}
+
+#[test]
+fn similarity_matrix_is_symmetric_with_full_diagonal() {
+    let dir = tempfile::tempdir().unwrap();
+    let (comparer, _) = comparer(dir.path(), &mixed_set(4));
+    let matrix = comparer.similarity_matrix();
+    assert_eq!(matrix.len(), 4);
+    for (i, row) in matrix.iter().enumerate() {
+        assert_eq!(row.len(), 4);
+        assert_eq!(row[i], 100.0);
+        for (j, &similarity) in row.iter().enumerate() {
+            assert_eq!(similarity, matrix[j][i]);
+            if i < j {
+                assert_eq!(similarity, comparer.similarity_percentage_between(i, j).unwrap());
+            }
+        }
+    }
+    let mut streamed = vec![];
+    comparer.for_each_row(|_, row| -> Result<(), imgalg::ImgAlgError> {
+        streamed.push(row.to_vec());
+        Ok(())
+    }).unwrap();
+    assert_eq!(streamed, matrix);
+}
+
+#[test]
+fn image_info_comes_from_the_decoded_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = [("a.png", 40, 30, ImageFormat::Png), ("b.jpg", 64, 48, ImageFormat::Jpeg), ("c.bmp", 17, 9, ImageFormat::Bmp), ("d.gif", 20, 20, ImageFormat::Gif)];
+    let mut paths: Vec<_> = files.iter().map(|&(name, width, height, _)| save(dir.path(), name, &pattern(1, width, height))).collect();
+    // Формат определяется по содержимому, а не по расширению
+    let misnamed = dir.path().join("png-named.jpg");
+    std::fs::copy(&paths[0], &misnamed).unwrap();
+    paths.push(misnamed);
+
+    let (comparer, errors) = ImagesComparer::new_lossy(&paths);
+    assert!(errors.is_empty(), "{errors:?}");
+    for (i, &(name, width, height, format)) in files.iter().chain([&("png-named.jpg", 40, 30, ImageFormat::Png)]).enumerate() {
+        let info = comparer.image_info(i).unwrap();
+        assert_eq!((info.width, info.height, info.format), (width, height, Some(format)), "{name}");
+        assert_eq!(info.file_size, std::fs::metadata(&paths[i]).unwrap().len(), "{name}");
+    }
+    assert!(comparer.image_info(paths.len()).is_none());
+}
+
+#[test]
+fn equal_channel_weights_reproduce_default_numbers() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut comparer, _) = comparer(dir.path(), &mixed_set(4));
+    let default: Vec<_> = comparer.iter_pairs().map(|pair| (pair.distance, pair.similarity)).collect();
+    comparer.options = ComparerOptions::new().channel_weights([1.0, 1.0, 1.0]).unwrap();
+    assert_eq!(comparer.iter_pairs().map(|pair| (pair.distance, pair.similarity)).collect::<Vec<_>>(), default);
+    // Веса нормируются: имеет значение только их соотношение
+    comparer.options = ComparerOptions::new().channel_weights([2.0, 2.0, 2.0]).unwrap();
+    assert_eq!(comparer.iter_pairs().map(|pair| (pair.distance, pair.similarity)).collect::<Vec<_>>(), default);
+}
+
+#[test]
+fn zero_weight_hides_a_channel() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = pattern(1, 48, 48);
+    let mut red_shifted = original.clone();
+    for pixel in red_shifted.pixels_mut() {
+        pixel.0[0] = 255 - pixel.0[0];
+    }
+    let (mut comparer, _) = comparer(dir.path(), &[original, red_shifted]);
+    assert!(comparer.similarity_percentage() < 90.0);
+    comparer.options = ComparerOptions::new().channel_weights([0.0, 1.0, 1.0]).unwrap();
+    assert_eq!(comparer.similarity_percentage(), 100.0);
+    comparer.options = ComparerOptions::new().channel_weights([1.0, 0.0, 0.0]).unwrap();
+    assert!(comparer.similarity_percentage() < 90.0);
+}
+
+#[test]
+fn invalid_channel_weights_are_rejected() {
+    assert!(ComparerOptions::new().channel_weights([0.0, 0.0, 0.0]).is_err());
+    assert!(ComparerOptions::new().channel_weights([-1.0, 1.0, 1.0]).is_err());
+    assert!(ComparerOptions::new().channel_weights([f32::NAN, 1.0, 1.0]).is_err());
+}
+
+#[test]
+fn hue_rotated_copy_matches_only_when_hue_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = pattern(3, 48, 48);
+    // Поворот тона на 120° - циклическая перестановка каналов R -> G -> B
+    let mut rotated = original.clone();
+    for pixel in rotated.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        pixel.0 = [b, r, g, a];
+    }
+    let (mut comparer, _) = comparer(dir.path(), &[original, rotated]);
+    let default = comparer.similarity_percentage();
+    comparer.options = ComparerOptions::new().ignore_hue(true);
+    let hue_invariant = comparer.similarity_percentage();
+    assert!(default < 60.0, "{default}");
+    assert!(hue_invariant > 99.0, "{hue_invariant}");
+}
+
+#[test]
+fn near_gray_tint_matters_less_without_hue() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut gray = pattern(4, 48, 48);
+    for pixel in gray.pixels_mut() {
+        let value = 64 + pixel.0[0] / 4 * 3;
+        pixel.0 = [value, value, value, 255];
+    }
+    // Едва заметный оттенок: у почти серых пикселей тон меняется произвольно
+    let mut tinted = gray.clone();
+    for (x, y, pixel) in tinted.enumerate_pixels_mut() {
+        let channel = ((x / 8 + y / 8) % 3) as usize;
+        pixel.0[channel] = pixel.0[channel].saturating_add(2);
+    }
+    let (mut comparer, _) = comparer(dir.path(), &[gray, tinted]);
+    let default = comparer.similarity_percentage();
+    comparer.options = ComparerOptions::new().ignore_hue(true);
+    let hue_invariant = comparer.similarity_percentage();
+    assert!(hue_invariant > 90.0 && hue_invariant > default + 20.0, "{default} vs {hue_invariant}");
+}
+
+#[test]
+fn fine_grid_flags_a_small_edit_the_coarse_one_smooths_away() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = pattern(1, 96, 96);
+    let mut edited = original.clone();
+    for x in 40..43 {
+        for y in 40..43 {
+            edited.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+        }
+    }
+    let paths = [save(dir.path(), "original.png", &original), save(dir.path(), "edited.png", &edited)];
+    let (comparer, _) = ImagesComparer::new_lossy_with(&paths, ComparerOptions::new().multi_scale(true));
+    let scores = comparer.scale_scores(0, 1).unwrap();
+    let score = |grid: u32| scores.iter().find(|score| score.grid == grid).unwrap().similarity;
+    let combined = comparer.similarity_percentage();
+    assert!(score(16) > 99.0 && score(32) < 80.0, "{scores:?}");
+    assert!(score(32) < combined && combined < score(16), "{combined} {scores:?}");
+}
+
+#[test]
+fn shared_comparer_answers_from_many_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    let (comparer, _) = comparer(dir.path(), &mixed_set(6));
+    let n = comparer.len();
+    let expected: Vec<f32> = (0..n * n).map(|k| comparer.similarity_percentage_between(k / n, k % n).unwrap()).collect();
+
+    let results: Vec<Vec<f32>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let shared = &comparer;
+                let handle = comparer.clone();
+                // Половина запросов - через общую ссылку, половина - через дешевый клон
+                scope.spawn(move || {
+                    (0..n * n)
+                        .map(|k| {
+                            let comparer = if k % 2 == 0 { shared } else { &handle };
+                            comparer.similarity_percentage_between(k / n, k % n).unwrap()
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    for result in results {
+        assert_eq!(result, expected);
+    }
+}
+
+#[test]
+fn batches_against_one_retained_reference() {
+    let dir = tempfile::tempdir().unwrap();
+    let reference = save(dir.path(), "reference.png", &pattern(1, 64, 64));
+    let (mut comparer, errors) = ImagesComparer::new_lossy(&[&reference]);
+    assert!(errors.is_empty());
+    comparer.compare_with_first = true;
+
+    for batch in 0..3u32 {
+        let copy = save(dir.path(), &format!("copy{batch}.png"), &pattern(1, 64, 64));
+        let other = save(dir.path(), &format!("other{batch}.png"), &pattern(10 + batch, 64, 64));
+        assert_eq!(comparer.add_image(&copy).unwrap(), 1);
+        assert_eq!(comparer.add_image(&other).unwrap(), 2);
+        let pairs = comparer.compare();
+        assert_eq!(pairs.iter().map(|pair| (pair.a, pair.b)).collect::<Vec<_>>(), [(0, 1), (0, 2)]);
+        assert_eq!(pairs[0].similarity, 100.0);
+        assert!(pairs[1].similarity < 50.0);
+        comparer.clear_keeping(&[0]).unwrap();
+        assert_eq!(comparer.len(), 1);
+    }
+}
+
+#[test]
+fn partial_clear_renumbers_in_loading_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut comparer, _) = comparer(dir.path(), &mixed_set(5));
+    let before: Vec<f32> = [(1, 3), (3, 4), (1, 4)].iter().map(|&(i, j)| comparer.similarity_percentage_between(i, j).unwrap()).collect();
+    // Порядок в `indices` не важен: остаются 1, 3, 4 и получают номера 0, 1, 2
+    comparer.clear_keeping(&[4, 1, 3]).unwrap();
+    assert_eq!(comparer.len(), 3);
+    let after: Vec<f32> = [(0, 1), (1, 2), (0, 2)].iter().map(|&(i, j)| comparer.similarity_percentage_between(i, j).unwrap()).collect();
+    assert_eq!(after, before);
+    assert!(comparer.clear_keeping(&[3]).is_err());
+    assert_eq!(comparer.len(), 3);
+
+    let clone = comparer.clone();
+    comparer.clear();
+    assert_eq!(comparer.len(), 0);
+    assert_eq!(clone.len(), 3);
+}
+
+#[test]
+fn explain_splits_the_distance_by_cell() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = pattern(1, 64, 64);
+    let mut edited = original.clone();
+    for (x, y, pixel) in edited.enumerate_pixels_mut() {
+        if (32..40).contains(&x) && (16..24).contains(&y) {
+            *pixel = image::Rgba([255, 0, 255, 255]);
+        }
+    }
+    let (comparer, _) = comparer(dir.path(), &[original.clone(), edited, original]);
+    assert!(comparer.explain(0, 2).unwrap().is_empty());
+
+    let cells = comparer.explain(0, 1).unwrap();
+    let total: f64 = cells.iter().map(|cell| cell.distance).sum();
+    assert!((total - comparer.raw_diff(0, 1).unwrap()).abs() < 1e-6 * total);
+    assert!((cells.iter().map(|cell| cell.percent).sum::<f64>() - 100.0).abs() < 1e-9);
+    assert!(cells.windows(2).all(|pair| pair[0].distance >= pair[1].distance));
+    // Блок 8x8 на месте ячеек 8..10 по x и 4..6 по y сетки 16x16
+    let top = cells[0];
+    assert_eq!(top.grid, 16);
+    assert!((8..=10).contains(&top.x) && (4..=6).contains(&top.y), "{top:?}");
+    assert!(cells.iter().any(|cell| cell.b == [255, 0, 255, 255]));
+}
+
+#[test]
+fn raw_rgba_matches_the_png_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    // Нечетные размеры и полупрозрачные пиксели
+    let mut image = common::with_noise(&pattern(4, 37, 23), 30, 1);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        pixel.0[3] = ((x * 7 + y * 3) % 256) as u8;
+    }
+    let path = save(dir.path(), "raw.png", &image);
+
+    let raw = imgalg::Signature::from_raw_rgba(image.as_raw(), 37, 23).unwrap();
+    let decoded = imgalg::Signature::compute(&path).unwrap();
+    assert_eq!(raw.to_string(), decoded.to_string());
+    assert_eq!(raw.distance(&decoded).unwrap(), 0.0);
+
+    let (mut comparer, errors) = ImagesComparer::new_lossy_with(&[&path], ComparerOptions::new().multi_scale(true));
+    assert!(errors.is_empty(), "{errors:?}");
+    let index = comparer.add_raw_rgba(image.as_raw(), 37, 23).unwrap();
+    assert_eq!(comparer.signature(index).unwrap().to_string(), comparer.signature(0).unwrap().to_string());
+    assert_eq!(comparer.raw_diff(0, index).unwrap(), 0.0);
+}
+
+#[test]
+fn raw_rgba_rejects_padded_rows() {
+    let image = pattern(1, 10, 4);
+    let mut padded = vec![];
+    for row in image.as_raw().chunks(40) {
+        padded.extend_from_slice(row);
+        padded.extend_from_slice(&[0; 8]);
+    }
+    let error = imgalg::Signature::from_raw_rgba(&padded, 10, 4).unwrap_err();
+    assert!(matches!(error, imgalg::ImgAlgError::InvalidBuffer { len: 192, width: 10, height: 4 }), "{error}");
+}
+
+/// Пять почти одинаковых кадров серии и один совсем другой на четвертом месте
+fn burst() -> Vec<image::RgbaImage> {
+    let mut frames: Vec<_> = (0..5).map(|seed| with_noise(&gradient(96, 96), 4, seed)).collect();
+    frames.insert(3, pattern(9, 96, 96));
+    frames
+}
+
+#[test]
+fn outliers_flag_only_the_odd_frame() {
+    let dir = tempfile::tempdir().unwrap();
+    let (comparer, _) = comparer(dir.path(), &burst());
+    let means = comparer.mean_similarities();
+    // Среднее, а не медиана: совсем другой кадр тянет вниз среднее каждого из остальных
+    for (i, mean) in means.iter().enumerate().filter(|&(i, _)| i != 3) {
+        let others: Vec<f32> = (0..6).filter(|&j| j != i).map(|j| comparer.compare_pair(i.min(j), i.max(j)).unwrap().similarity).collect();
+        assert!((mean - others.iter().sum::<f32>() / 5.0).abs() < 1e-3, "{i}: {mean}");
+    }
+    let outliers = comparer.outliers(SimilarityThreshold::new(60.0).unwrap());
+    assert_eq!(outliers.iter().map(|&(i, _)| i).collect::<Vec<_>>(), [3], "{means:?}");
+    assert_eq!(outliers[0].1, means[3]);
+}
+
+#[test]
+fn warnings_are_returned_with_the_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = save(dir.path(), "photo.png", &pattern(2, 64, 64));
+    let gif = truncated_gif(dir.path(), "clip.gif");
+    let (mut comparer, errors) = ImagesComparer::new_lossy(&[&photo, &gif]);
+    assert!(errors.is_empty(), "{errors:?}");
+    assert!(comparer.warnings().next().is_none());
+    comparer.select_best_frame(1, &gif, 0).unwrap();
+    let warnings: Vec<Warning> = comparer.warnings().cloned().collect();
+    assert!(matches!(&warnings[..], [Warning::TruncatedAnimation { path, frames: 2, .. }] if *path == gif), "{warnings:?}");
+    assert_eq!(comparer.image_info(1).unwrap().warnings.len(), 1);
+    assert!(comparer.image_info(0).unwrap().warnings.is_empty());
+
+    // Запись индекса устарела: файл переписан после прошлого просмотра
+    let mut index = SignatureIndex::new();
+    let paths = [&photo];
+    assert!(index.scan(&paths, SimilarityThreshold::DEFAULT, &ComparerOptions::new(), true).unwrap().warnings.is_empty());
+    save(dir.path(), "photo.png", &pattern(2, 80, 80));
+    let report = index.scan(&paths, SimilarityThreshold::DEFAULT, &ComparerOptions::new(), true).unwrap();
+    assert_eq!(report.warnings, [Warning::StaleEntry { path: photo.clone() }]);
+    assert_eq!(report.warnings[0].as_str(), "stale_entry");
+    assert_ne!(report.warnings[0].as_str(), warnings[0].as_str());
+}
+
+/// PGM 10x0: в отличие от PNG, формат допускает изображение без строк
+fn zero_height(dir: &std::path::Path) -> std::path::PathBuf {
+    let path = dir.join("empty.pgm");
+    std::fs::write(&path, b"P5 10 0 255\n").unwrap();
+    path
+}
+
+#[test]
+fn degenerate_images_are_reported_not_panicked_on() {
+    let dir = tempfile::tempdir().unwrap();
+    let strip = save(dir.path(), "strip.png", &pattern(1, 1, 500));
+    let empty = zero_height(dir.path());
+
+    let signature = imgalg::Signature::compute(&strip).unwrap();
+    assert_eq!(signature.similarity(&signature).unwrap(), 100.0);
+    let (comparer, errors) = ImagesComparer::new_lossy(&[&strip, &empty]);
+    assert_eq!(comparer.len(), 1);
+    assert!(comparer.image_info(0).unwrap().is_extreme_aspect());
+    assert_eq!(comparer.image_info(0).unwrap().warnings, [Warning::ExtremeAspect { path: strip.clone(), width: 1, height: 500 }]);
+    assert!(matches!(&errors[..], [imgalg::ImgAlgError::EmptyImage { width: 10, height: 0, path: Some(path) }] if *path == empty), "{errors:?}");
+
+    let (strict, errors) = ImagesComparer::new_lossy_with(&[&strip], ComparerOptions::new().strict_aspect(true));
+    assert!(strict.is_empty());
+    assert!(matches!(&errors[..], [imgalg::ImgAlgError::ExtremeAspect { width: 1, height: 500, .. }]), "{errors:?}");
+
+    for (width, height) in [(0, 0), (10, 0), (0, 7)] {
+        let error = imgalg::Signature::from_raw_rgba(&[], width, height).unwrap_err();
+        assert!(matches!(error, imgalg::ImgAlgError::EmptyImage { path: None, .. }), "{width}x{height}: {error}");
+    }
+}
+
+#[test]
+fn ensemble_reports_the_mean_and_flags_a_borderline_pair() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = pattern(4, 96, 96);
+    let images = [original.clone(), original.clone(), with_noise(&original, 40, 7)];
+    let paths: Vec<_> = images.iter().enumerate().map(|(i, image)| save(dir.path(), &format!("image{i}.png"), image)).collect();
+    let (ensemble, errors) = ImagesComparer::new_lossy_with(&paths, ComparerOptions::new().ensemble(true));
+    assert!(errors.is_empty(), "{errors:?}");
+
+    // У одинаковых изображений все фильтры дают 100, разброса нет
+    let identical = ensemble.compare_pair(0, 1).unwrap();
+    assert_eq!((identical.similarity, identical.spread, identical.range()), (100.0, 0.0, (100.0, 100.0)));
+    assert!(!identical.is_uncertain(SimilarityThreshold::EXACT));
+
+    // У зашумленной копии оценки трех фильтров расходятся: разброс - половина размаха,
+    // схожесть - их среднее, и оценка основного фильтра - одна из трех
+    let noisy = ensemble.compare_pair(0, 2).unwrap();
+    let (min, max) = noisy.range();
+    assert!(min < max, "{noisy:?}");
+    assert!((noisy.spread - (max - min) / 2.0).abs() < 1e-4);
+    let middle = 3.0 * noisy.similarity - min - max;
+    assert!((min - 1e-3..=max + 1e-3).contains(&middle), "{middle} not in {min}..={max}");
+    let (plain, _) = ImagesComparer::new_lossy(&paths);
+    let main = plain.compare_pair(0, 2).unwrap().similarity;
+    assert!([min, middle, max].iter().any(|score| (score - main).abs() < 1e-3), "{main} is not one of {min}, {middle}, {max}");
+
+    // Неуверенность - по крайним оценкам: порог, который проходит только наибольшая, - неуверенный,
+    // тот, что проходит и наименьшая или не проходит ни одна, - нет
+    let uncertain = |value: f32| noisy.is_uncertain(SimilarityThreshold::new(value).unwrap());
+    assert!(uncertain(noisy.similarity));
+    assert!(uncertain(max));
+    assert!(!uncertain(max + 0.01));
+    assert!(uncertain(min + 0.01));
+    assert!(!uncertain(min));
+}
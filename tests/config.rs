@@ -0,0 +1,32 @@
+//! `config --show`: итоговые настройки из флагов, файла и встроенных значений
+
+mod common;
+
+use common::imgalg;
+use std::fs;
+
+#[test]
+fn show_merges_flags_file_and_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "threshold = 93\njobs = 4\nflush_interval = 10\n").unwrap();
+    let output = imgalg().args(["--jobs", "2", "config", "--show", "--config"]).arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let shown = String::from_utf8(output.stdout).unwrap();
+    assert!(shown.contains("jobs = 2\n"), "{shown}");
+    assert!(shown.contains("threshold = 93.0\n"), "{shown}");
+    assert!(shown.contains("flush_interval = 10\n"), "{shown}");
+    assert!(shown.contains("retry_delay = 100\n"), "{shown}");
+}
+
+#[test]
+fn malformed_file_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "treshold = 93\n").unwrap();
+    let output = imgalg().args(["config", "--show", "--config"]).arg(&path).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown field `treshold`"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
@@ -0,0 +1,20 @@
+//! `--export-czkawka`: список групп в формате czkawka
+
+mod common;
+
+use common::imgalg;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn export_matches_the_golden_file() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let fixtures = Path::new("tests/fixtures/czkawka");
+    let dir = tempfile::tempdir().unwrap();
+    let exported = dir.path().join("czkawka.txt");
+    // Пути относительные, как их передал бы пользователь из корня пакета
+    let images = ["a.png", "b.png", "c.png", "photos/a-copy.png", "photos/b-near.png"].map(|name| fixtures.join(name));
+    let status = imgalg().current_dir(root).arg("--export-czkawka").arg(&exported).args(&images).status().unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(&exported).unwrap(), fs::read_to_string(root.join(fixtures).join("expected.txt")).unwrap());
+}
@@ -0,0 +1,94 @@
+//! Коды ошибок и коды завершения в `--json`
+
+mod common;
+
+use common::{imgalg, pattern, save, with_noise};
+use std::fs;
+use std::path::Path;
+
+/// Код ошибки из JSON и код завершения
+fn failure(a: &Path, b: &Path, extra: &[&str]) -> (String, Option<i32>) {
+    let output = imgalg().arg(a).arg(b).arg("--json").args(extra).output().unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    (report["error"]["code"].as_str().unwrap_or_default().to_string(), output.status.code())
+}
+
+#[test]
+fn nonexistent_path_is_e_io() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 32, 32));
+    let (code, exit) = failure(&a, &dir.path().join("missing.png"), &[]);
+    assert_eq!((code.as_str(), exit), ("E_IO", Some(3)));
+}
+
+#[test]
+fn text_file_is_e_unsupported() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 32, 32));
+    let text = dir.path().join("notes.txt");
+    fs::write(&text, "not an image\n").unwrap();
+    let (code, exit) = failure(&a, &text, &[]);
+    assert_eq!((code.as_str(), exit), ("E_UNSUPPORTED", Some(5)));
+}
+
+#[test]
+fn corrupted_image_is_e_decode() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 32, 32));
+    let mut bytes = fs::read(&a).unwrap();
+    // Сигнатура PNG цела, испорчены данные после нее
+    for byte in &mut bytes[16..40] {
+        *byte ^= 0x5a;
+    }
+    let corrupted = dir.path().join("corrupted.png");
+    fs::write(&corrupted, &bytes).unwrap();
+    let (code, exit) = failure(&a, &corrupted, &[]);
+    assert_eq!((code.as_str(), exit), ("E_DECODE", Some(4)));
+}
+
+#[test]
+fn below_threshold_exits_with_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 64, 64));
+    let b = save(dir.path(), "b.png", &with_noise(&pattern(1, 64, 64), 60, 7));
+    let output = imgalg().arg(&a).arg(&b).args(["--threshold", "99.9", "--json"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn invalid_threshold_is_rejected_before_decoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.png");
+    for threshold in ["250", "95,5"] {
+        let (code, exit) = failure(&missing, &missing, &["--threshold", threshold]);
+        assert_eq!((code.as_str(), exit), ("E_ARGS", Some(2)), "{threshold}");
+    }
+}
+
+/// Большое, но правильное изображение: декодируется заметно дольше миллисекунды
+fn huge_image(dir: &Path) -> std::path::PathBuf {
+    save(dir, "huge.bmp", &image::RgbaImage::from_pixel(3000, 3000, image::Rgba([90, 120, 150, 255])))
+}
+
+#[test]
+fn slow_decode_is_e_timeout() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 32, 32));
+    let (code, exit) = failure(&a, &huge_image(dir.path()), &["--decode-timeout", "0.05"]);
+    assert_eq!((code.as_str(), exit), ("E_TIMEOUT", Some(6)));
+}
+
+#[test]
+fn scan_skips_a_timed_out_file_and_continues() {
+    let dir = tempfile::tempdir().unwrap();
+    save(dir.path(), "a.png", &pattern(1, 32, 32));
+    save(dir.path(), "b.png", &pattern(1, 32, 32));
+    huge_image(dir.path());
+    let output = imgalg().args(["--decode-timeout", "0.05", "scan", "--json"]).arg(dir.path()).output().unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1, "{report}");
+    assert_eq!(errors[0]["code"], "E_TIMEOUT");
+    assert!(errors[0]["path"].as_str().unwrap().ends_with("huge.bmp"));
+    assert_eq!(report["groups"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,81 @@
+//! `--export-graph`: пары дубликатов графом DOT и GraphML
+
+mod common;
+
+use common::{blend, gradient, imgalg, pattern, save, with_noise};
+use std::collections::HashSet;
+use std::fs;
+
+/// Цепочка A ≈ B ≈ C, в которой A ≉ C при пороге 75%, и несвязанное D
+fn chain(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let (a, c) = (gradient(64, 64), with_noise(&gradient(64, 64), 40, 2));
+    vec![save(dir, "a.png", &a), save(dir, "b {mid} 'x'.png", &blend(&a, &c, 0.5)), save(dir, "c.png", &c), save(dir, "d.png", &pattern(5, 64, 64))]
+}
+
+/// Строки в кавычках DOT по порядку, с раскрытыми `\"` и `\\`; вне кавычек - текст между ними
+fn split_quoted(line: &str) -> (Vec<String>, String) {
+    let (mut quoted, mut bare, mut current, mut inside, mut chars) = (vec![], String::new(), String::new(), false, line.chars());
+    while let Some(c) = chars.next() {
+        match (inside, c) {
+            (false, '"') => inside = true,
+            (false, c) => bare.push(c),
+            (true, '"') => {
+                quoted.push(std::mem::take(&mut current));
+                inside = false;
+            }
+            (true, '\\') => match chars.next() {
+                Some('n') => current.push('\n'),
+                Some(escaped) => current.push(escaped),
+                None => panic!("dangling escape in {line}"),
+            },
+            (true, c) => current.push(c),
+        }
+    }
+    assert!(!inside, "unterminated string in {line}");
+    (quoted, bare)
+}
+
+#[test]
+fn dot_graph_has_one_edge_per_pair_above_the_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    let images = chain(dir.path());
+    let out = dir.path().join("pairs.dot");
+    let status = imgalg().args(["--threshold", "75", "--export-graph"]).arg(&out).args(&images).status().unwrap();
+    assert!(status.success());
+
+    let dot = fs::read_to_string(&out).unwrap();
+    let lines: Vec<&str> = dot.lines().collect();
+    assert_eq!((lines[0], lines[1], *lines.last().unwrap()), ("graph duplicates {", "  node [shape=box];", "}"));
+    let (mut nodes, mut edges) = (HashSet::new(), vec![]);
+    for line in &lines[2..lines.len() - 1] {
+        let (quoted, bare) = split_quoted(line);
+        let statement = bare.trim().strip_suffix(';').unwrap_or_else(|| panic!("{line}"));
+        let (head, attributes) = statement.split_once(" [").unwrap();
+        assert!(attributes.ends_with(']'), "{line}");
+        match head.split_once(" -- ") {
+            Some((a, b)) => {
+                assert!(quoted[0].ends_with('%'), "{line}");
+                edges.push((a.to_string(), b.to_string()));
+            }
+            None => {
+                assert!(nodes.insert(head.to_string()), "{line}");
+                let (label, tooltip) = (&quoted[0], &quoted[1]);
+                let path = images.iter().find(|path| path.to_str() == Some(tooltip.as_str())).unwrap_or_else(|| panic!("{line}"));
+                assert_eq!(*label, format!("{}\n64x64", path.file_name().unwrap().to_str().unwrap()));
+            }
+        }
+    }
+    assert_eq!(edges, [("n0".to_string(), "n1".to_string()), ("n1".to_string(), "n2".to_string())]);
+    assert_eq!(nodes, HashSet::from(["n0", "n1", "n2"].map(String::from)));
+}
+
+#[test]
+fn graphml_extension_writes_graphml() {
+    let dir = tempfile::tempdir().unwrap();
+    let images = chain(dir.path());
+    let out = dir.path().join("pairs.graphml");
+    assert!(imgalg().args(["--threshold", "75", "--export-graph"]).arg(&out).args(&images).status().unwrap().success());
+    let graphml = fs::read_to_string(&out).unwrap();
+    assert_eq!((graphml.matches("<node ").count(), graphml.matches("<edge ").count()), (3, 2));
+    assert!(graphml.contains("<data key=\"label\">b {mid} &apos;x&apos;.png</data>"), "{graphml}");
+}
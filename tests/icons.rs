@@ -0,0 +1,54 @@
+//! Значки `.ico` из `tests/fixtures/icons`: у `app.ico` и `other.ico` одинаковые изображения
+//! 16 и 32 пикселя и разные - 256 пикселей
+
+mod common;
+
+use common::{imgalg, json};
+use std::path::{Path, PathBuf};
+
+fn icon(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/icons").join(name)
+}
+
+fn similarity(extra: &[&str]) -> f64 {
+    json(imgalg().args(extra).arg(icon("app.ico")).arg(icon("other.ico")).arg("--json"))["similarity"].as_f64().unwrap()
+}
+
+#[test]
+fn largest_size_is_compared_by_default() {
+    let report = json(imgalg().arg(icon("app.ico")).arg(icon("other.ico")).arg("--json"));
+    assert!(report["similarity"].as_f64().unwrap() < 50.0, "{report}");
+    assert_eq!((report["images"][0]["width"].as_u64(), report["images"][0]["height"].as_u64()), (Some(256), Some(256)));
+}
+
+#[test]
+fn icon_size_selects_the_embedded_image() {
+    assert_eq!(similarity(&["--icon-size", "16"]), 100.0);
+    assert_eq!(similarity(&["--icon-size", "32"]), 100.0);
+    assert!(similarity(&["--icon-size", "256"]) < 50.0);
+}
+
+#[test]
+fn missing_size_lists_the_available_ones() {
+    let output = imgalg().args(["--icon-size", "48"]).arg(icon("app.ico")).arg(icon("other.ico")).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The icon has no 48px image, available sizes: 16, 32, 256"), "{stderr}");
+}
+
+#[test]
+fn split_icons_scans_each_size_as_an_item() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["app.ico", "other.ico"] {
+        std::fs::copy(icon(name), dir.path().join(name)).unwrap();
+    }
+    let report = json(imgalg().args(["scan", "--json", "--split-icons"]).arg(dir.path()));
+    assert_eq!(report["files"], 6);
+    let groups: Vec<Vec<String>> = report["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|group| group.as_array().unwrap().iter().map(|entry| Path::new(entry["path"].as_str().unwrap()).file_name().unwrap().to_string_lossy().into_owned()).collect())
+        .collect();
+    assert_eq!(groups, [["app.ico#16", "other.ico#16"], ["app.ico#32", "other.ico#32"]]);
+}
@@ -0,0 +1,68 @@
+//! Совместимость `FingerprintMode::ImgHash` с отпечатками, посчитанными `img_hash`
+
+mod common;
+
+use common::imgalg;
+use imgalg::{Fingerprint, FingerprintMode};
+use std::path::{Path, PathBuf};
+
+/// Сколько битов может разойтись из-за другого декодера и округления при уменьшении
+const MAX_DISTANCE: u32 = 2;
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash")
+}
+
+/// Таблица `имя base64` из `hashes.txt`
+fn known_hashes() -> Vec<(String, String)> {
+    let table = std::fs::read_to_string(fixtures().join("hashes.txt")).unwrap();
+    let rows: Vec<_> = table
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            let (name, hash) = line.split_once(' ').unwrap();
+            (name.to_string(), hash.trim().to_string())
+        })
+        .collect();
+    assert!(rows.len() >= 5);
+    rows
+}
+
+#[test]
+fn matches_known_img_hash_outputs() {
+    for (name, hash) in known_hashes() {
+        let expected = Fingerprint::from_img_hash_base64(&hash).unwrap();
+        let computed = Fingerprint::compute_with(fixtures().join(&name), FingerprintMode::ImgHash).unwrap();
+        let distance = computed.distance(&expected);
+        assert!(distance <= MAX_DISTANCE, "{name}: {} vs {hash}, {distance} bits apart", computed.to_img_hash_base64());
+    }
+}
+
+#[test]
+fn base64_round_trips() {
+    for (_, hash) in known_hashes() {
+        assert_eq!(Fingerprint::from_img_hash_base64(&hash).unwrap().to_img_hash_base64(), hash);
+    }
+    assert!(Fingerprint::from_img_hash_base64("not base64!").is_err());
+    assert!(Fingerprint::from_img_hash_base64("AAAA").is_err());
+}
+
+#[test]
+fn distances_match_img_hash_bit_order() {
+    // img_hash кладет первый бит в младший разряд первого байта: `AQ==...` - один бит
+    let one_bit = Fingerprint::from_img_hash_base64("AQAAAAAAAAA=").unwrap();
+    assert_eq!(one_bit.to_u64(), 1 << 63);
+    assert_eq!(one_bit.distance(&Fingerprint::from_img_hash_base64("AAAAAAAAAAA=").unwrap()), 1);
+}
+
+#[test]
+fn cli_prints_img_hash_base64() {
+    let output = imgalg().arg("fingerprint").arg("--img-hash").args(known_hashes().iter().map(|(name, _)| fixtures().join(name))).output().unwrap();
+    assert!(output.status.success());
+    let printed = String::from_utf8(output.stdout).unwrap();
+    for (name, hash) in known_hashes() {
+        let line = printed.lines().find(|line| line.ends_with(&name)).unwrap();
+        let computed = Fingerprint::from_img_hash_base64(line.split_whitespace().next().unwrap()).unwrap();
+        assert!(computed.distance(&Fingerprint::from_img_hash_base64(&hash).unwrap()) <= MAX_DISTANCE, "{line}");
+    }
+}
@@ -0,0 +1,55 @@
+//! `inspect`: сведения о том, чем и как записан индекс или отчет
+
+mod common;
+
+use common::{imgalg, json, pattern, save};
+use std::fs;
+
+#[test]
+fn inspected_index_shows_what_was_configured() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    save(&tree, "a.png", &pattern(1, 48, 48));
+    let index = dir.path().join("photos.idx");
+    let args = ["--value-transform", "sqrt", "scan", "--index", index.to_str().unwrap(), tree.to_str().unwrap()];
+    assert!(imgalg().env("SOURCE_DATE_EPOCH", "1700000000").args(args).output().unwrap().status.success());
+
+    let inspected = json(imgalg().args(["inspect", "--json"]).arg(&index));
+    assert_eq!(inspected["kind"], "index");
+    let provenance = &inspected["provenance"];
+    assert_eq!(provenance["version"].as_str(), Some(env!("CARGO_PKG_VERSION")));
+    assert_eq!(provenance["format_version"].as_u64(), Some(9));
+    assert_eq!(provenance["pipeline"], "algorithm=color-diff;grid=16;filter=area;color=rgba8;values=sqrt");
+    assert_eq!(provenance["algorithm_id"], "color-diff-v2(grid=16,filter=area,color=rgba8,values=sqrt)");
+    assert_eq!((provenance["grid"].as_str(), provenance["filter"].as_str(), provenance["color"].as_str()), (Some("16"), Some("area"), Some("rgba8")));
+    assert_eq!(provenance["created"], "2023-11-14T22:13:20Z");
+    let command_line: Vec<&str> = provenance["command_line"].as_array().unwrap().iter().map(|arg| arg.as_str().unwrap()).collect();
+    assert_eq!(command_line[1..], args);
+}
+
+#[test]
+fn inspected_report_matches_its_provenance_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 48, 48));
+    let b = save(dir.path(), "b.png", &pattern(2, 48, 48));
+    let output = imgalg().env("SOURCE_DATE_EPOCH", "1700000000").args(["--multi-scale"]).arg(&a).arg(&b).arg("--json").output().unwrap();
+    let report_path = dir.path().join("report.json");
+    fs::write(&report_path, &output.stdout).unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let inspected = json(imgalg().args(["inspect", "--json"]).arg(&report_path));
+    assert_eq!(inspected["kind"], "report");
+    assert_eq!(inspected["provenance"], report["provenance"]);
+    assert_eq!(inspected["provenance"]["grid"], "8,16,32");
+}
+
+#[test]
+fn other_files_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "not a report").unwrap();
+    let output = imgalg().args(["inspect", "--json"]).arg(&path).output().unwrap();
+    assert!(!output.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(error["error"]["message"], format!("{} is neither an index nor a JSON report", path.display()));
+}
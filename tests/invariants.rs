@@ -0,0 +1,58 @@
+//! Свойства метрики на случайном наборе изображений: пределы, симметрия, 100% только у равных
+//! сигнатур, убывание схожести с ростом шума
+
+mod common;
+
+use common::random_image;
+use imgalg::image::DynamicImage;
+use imgalg::{compare_signatures, ComparerOptions, Signature};
+
+fn signature(image: &image::RgbaImage, options: &ComparerOptions) -> Signature {
+    let image = DynamicImage::ImageRgba8(image.clone());
+    if options.is_multi_scale() { Signature::compute_from_image_multi_scale(&image) } else { Signature::compute_from_image(&image) }.unwrap()
+}
+
+#[test]
+fn similarity_is_bounded_symmetric_and_exact_for_equal_signatures() {
+    for options in [ComparerOptions::new(), ComparerOptions::new().multi_scale(true)] {
+        let corpus: Vec<Signature> = (0..24).map(|seed| signature(&random_image(seed), &options)).collect();
+        for (i, a) in corpus.iter().enumerate() {
+            assert_eq!(compare_signatures(a, a, &options).unwrap(), 100.0);
+            assert_eq!(compare_signatures(a, &a.to_string().parse().unwrap(), &options).unwrap(), 100.0);
+            for (j, b) in corpus.iter().enumerate().skip(i + 1) {
+                let (ab, ba) = (compare_signatures(a, b, &options).unwrap(), compare_signatures(b, a, &options).unwrap());
+                assert_eq!(ab.to_bits(), ba.to_bits(), "{i}, {j}");
+                assert!((0.0..=100.0).contains(&ab), "{i}, {j}: {ab}");
+                assert_eq!(ab == 100.0, a == b, "{i}, {j}: {ab}");
+            }
+        }
+    }
+}
+
+/// Один и тот же шум с размахом `amplitude`: от амплитуды зависит только масштаб отклонений,
+/// иначе шум большей амплитуды мог бы случайно лечь удачнее
+fn scaled_noise(image: &image::RgbaImage, amplitude: f32, seed: u32) -> image::RgbaImage {
+    let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+    let mut noisy = image.clone();
+    for pixel in noisy.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let unit = (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+            *channel = (*channel as f32 + unit * amplitude).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    noisy
+}
+
+#[test]
+fn similarity_falls_as_the_noise_grows() {
+    let options = ComparerOptions::new();
+    for seed in 0..12 {
+        let original = random_image(seed);
+        let target = signature(&original, &options);
+        let scores: Vec<f32> = [0.0, 4.0, 16.0, 48.0, 96.0].into_iter().map(|amplitude| compare_signatures(&target, &signature(&scaled_noise(&original, amplitude, seed + 100), &options), &options).unwrap()).collect();
+        assert_eq!(scores[0], 100.0);
+        assert!(scores.windows(2).all(|pair| pair[1] <= pair[0]), "{seed}: {scores:?}");
+        assert!(scores[4] < scores[1], "{seed}: {scores:?}");
+    }
+}
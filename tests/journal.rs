@@ -0,0 +1,75 @@
+//! Продолжение прерванного просмотра по журналу
+
+mod common;
+
+use common::{pattern, save};
+use imgalg::index::{ScanEvent, SignatureIndex};
+use imgalg::journal::ScanJournal;
+use imgalg::{CancelToken, ComparerOptions, ImgAlgError, SimilarityThreshold};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const SETTINGS: &str = "test";
+
+/// Пути файлов, сигнатуры которых посчитаны декодированием, а не взяты из журнала
+fn decoded(on: &Mutex<BTreeSet<PathBuf>>) -> impl Fn(ScanEvent<'_>) + Sync + '_ {
+    move |event| {
+        if let ScanEvent::Signed { path, reused: false } = event {
+            on.lock().unwrap().insert(path.to_path_buf());
+        }
+    }
+}
+
+#[test]
+fn resumed_scan_decodes_only_the_remaining_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let images = dir.path().join("images");
+    std::fs::create_dir(&images).unwrap();
+    let files: Vec<_> = (0..12).map(|seed| save(&images, &format!("{seed:02}.png"), &pattern(seed, 48, 48))).collect();
+    let journal_path = dir.path().join("scan.journal");
+
+    // Первый просмотр обрывается после пяти файлов: отмена из обработчика событий
+    let token = CancelToken::new();
+    let options = ComparerOptions::new().threads(1).cancel_token(token.clone());
+    let first = Mutex::new(BTreeSet::new());
+    let on_event = |event: ScanEvent<'_>| {
+        decoded(&first)(event);
+        if first.lock().unwrap().len() == 5 {
+            token.cancel();
+        }
+    };
+    let mut index = SignatureIndex::new();
+    index.set_journal(Some(ScanJournal::open(&journal_path, &images, SETTINGS, &options).unwrap()));
+    let result = index.scan_with(&files, SimilarityThreshold::DEFAULT, &options, false, &on_event);
+    assert!(matches!(result, Err(ImgAlgError::Cancelled)));
+    assert!(journal_path.exists());
+    let first = first.into_inner().unwrap();
+    assert_eq!(first.len(), 5);
+
+    // Второй просмотр с тем же журналом декодирует только остальные файлы
+    let options = ComparerOptions::new().threads(1);
+    let journal = ScanJournal::open(&journal_path, &images, SETTINGS, &options).unwrap();
+    assert_eq!(journal.len(), 5);
+    let second = Mutex::new(BTreeSet::new());
+    let mut index = SignatureIndex::new();
+    index.set_journal(Some(journal));
+    let report = index.scan_with(&files, SimilarityThreshold::DEFAULT, &options, false, &decoded(&second)).unwrap();
+    let second = second.into_inner().unwrap();
+    let remaining: BTreeSet<_> = files.iter().filter(|path| !first.contains(*path)).cloned().collect();
+    assert_eq!(second, remaining);
+    assert_eq!(report.resumed, 5);
+    assert_eq!(report.computed, 7);
+    assert_eq!(index.len(), 12);
+    assert!(!journal_path.exists());
+}
+
+#[test]
+fn journal_of_other_settings_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal_path = dir.path().join("scan.journal");
+    let options = ComparerOptions::new();
+    drop(ScanJournal::open(&journal_path, dir.path(), SETTINGS, &options).unwrap());
+    let error = ScanJournal::open(&journal_path, dir.path(), "other", &options).err().unwrap();
+    assert!(error.to_string().contains("delete it or use another journal"), "{error}");
+}
@@ -0,0 +1,63 @@
+//! `--lang`: один и тот же отчет на обоих языках, машиночитаемый вывод от языка не зависит
+
+mod common;
+
+use common::imgalg;
+use std::path::Path;
+
+const EN: &str = "\
+Results:
+Image 0: blocks.png (64x64, png, 412 bytes)
+Image 1: rings.png (80x80, png, 11191 bytes)
+Image 0 ~ Image 1: 25021.147834440613
+Similarity: 67.42% (different)
+";
+
+const RU: &str = "\
+Результаты:
+Изображение 0: blocks.png (64x64, png, 412 байт)
+Изображение 1: rings.png (80x80, png, 11191 байт)
+Изображение 0 ~ изображение 1: 25021.147834440613
+Процент схожести: 67.42% (разные)
+";
+
+/// Сравнение образцов `img_hash` по относительным путям, чтобы вывод не зависел от каталога
+fn compare(args: &[&str]) -> std::process::Command {
+    let mut command = imgalg();
+    command.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash")).args(args).args(["blocks.png", "rings.png"]);
+    command
+}
+
+fn stdout(command: &mut std::process::Command) -> String {
+    String::from_utf8(command.output().unwrap().stdout).unwrap()
+}
+
+#[test]
+fn comparison_is_rendered_in_both_languages() {
+    assert_eq!(stdout(&mut compare(&["--lang", "en"])), EN);
+    assert_eq!(stdout(&mut compare(&["--lang", "ru"])), RU);
+    assert_eq!(stdout(&mut compare(&[])), EN);
+    assert_eq!(stdout(compare(&["--lang", "auto"]).env("LANG", "ru_RU.UTF-8")), RU);
+    assert_eq!(stdout(compare(&["--lang", "auto"]).env("LANG", "de_DE.UTF-8")), EN);
+}
+
+#[test]
+fn machine_formats_do_not_depend_on_the_language() {
+    let report = |lang: &str| {
+        let mut report: serde_json::Value = serde_json::from_str(&stdout(compare(&["--lang", lang, "--json"]).env("SOURCE_DATE_EPOCH", "1700000000"))).unwrap();
+        report["provenance"].as_object_mut().unwrap().remove("command_line");
+        report
+    };
+    assert_eq!(report("ru"), report("en"));
+
+    // Ошибки: код и сообщение библиотеки по-английски, переводится только подпись CLI
+    let missing = |lang: &str| imgalg().args(["--lang", lang, "--json", "missing.png", "other.png"]).output().unwrap();
+    let (en, ru) = (missing("en"), missing("ru"));
+    assert_eq!(en.stdout, ru.stdout);
+    let error: serde_json::Value = serde_json::from_slice(&ru.stdout).unwrap();
+    assert_eq!(error["error"]["code"], "E_IO");
+    let text = |lang: &str| String::from_utf8(imgalg().args(["--lang", lang, "missing.png", "other.png"]).output().unwrap().stderr).unwrap();
+    let library = "[E_IO]: missing.png: Failed to open the image: ";
+    assert!(text("ru").starts_with(&format!("Ошибка при создании компаратора {library}")), "{}", text("ru"));
+    assert!(text("en").starts_with(&format!("Error while creating the comparer {library}")), "{}", text("en"));
+}
@@ -0,0 +1,53 @@
+//! `new_async` загружает то же, что `new_lossy`: значки, файлы архивов, повторы и ссылки
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::{pattern, save};
+use imgalg::ImagesComparer;
+use std::path::{Path, PathBuf};
+
+fn icons() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/icons")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_loading_matches_lossy_loading() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 48, 48));
+    let b = save(dir.path(), "b.png", &pattern(2, 48, 48));
+    let missing = dir.path().join("missing.png");
+    let mut paths = vec![a.clone(), b, icons().join("app.ico#16"), icons().join("app.ico#32"), icons().join("app.ico#7"), missing.clone(), a.clone(), missing];
+    #[cfg(unix)]
+    {
+        let link = dir.path().join("link.png");
+        std::os::unix::fs::symlink(&a, &link).unwrap();
+        paths.push(link);
+    }
+    #[cfg(feature = "zip")]
+    {
+        use std::fs;
+        use std::io::Write;
+        let mut archive = zip::ZipWriter::new(fs::File::create(dir.path().join("album.zip")).unwrap());
+        archive.start_file("copy.png", zip::write::SimpleFileOptions::default()).unwrap();
+        archive.write_all(&fs::read(&a).unwrap()).unwrap();
+        archive.finish().unwrap();
+        paths.push(imgalg::archive::member_path(dir.path().join("album.zip"), "copy.png"));
+    }
+
+    let (lossy, lossy_errors) = ImagesComparer::new_lossy(&paths);
+    let (loaded, errors) = ImagesComparer::new_async(&paths).await;
+    assert_eq!(loaded.len(), lossy.len());
+    for idx in 0..lossy.len() {
+        assert_eq!(loaded.signature(idx), lossy.signature(idx), "image {idx}");
+        assert_eq!(loaded.image_info(idx).map(|info| (info.width, info.height, info.file_size)), lossy.image_info(idx).map(|info| (info.width, info.height, info.file_size)), "image {idx}");
+    }
+    let messages = |errors: &[imgalg::ImgAlgError]| errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(messages(&errors), messages(&lossy_errors));
+    // Отсутствующий значок размера 7 и два упоминания отсутствующего файла
+    assert_eq!(errors.len(), 3, "{errors:?}");
+    // Повтор `a.png` и ссылка на него не декодируются, отсутствующий файл пробуется дважды,
+    // каждый размер значка декодируется отдельно
+    assert_eq!(loaded.decoded_count(), lossy.decoded_count());
+    assert_eq!(loaded.decoded_count(), 7 + usize::from(cfg!(feature = "zip")));
+}
@@ -0,0 +1,61 @@
+//! `match_sets` и `imgalg match` на перемешанных наборах
+
+mod common;
+
+use common::{blend, imgalg, json, pattern, save};
+use imgalg::{match_sets, Assignment, ComparerOptions};
+use std::path::{Path, PathBuf};
+
+/// Шесть снимков в `a` и их почти копии в `b` под перемешанными именами. Седьмые
+/// изображения наборов не похожи друг на друга, восьмое в `b` - лишнее. Возвращает пути
+/// `a`, `b` по порядку имен и ожидаемую пару из `b` для каждого снимка
+fn shuffled_sets(root: &Path) -> (Vec<PathBuf>, Vec<PathBuf>, Vec<usize>) {
+    const ORDER: [usize; 6] = [4, 0, 5, 2, 1, 3];
+    let mut a = vec![];
+    let mut b = vec![None; 8];
+    for (seed, &slot) in ORDER.iter().enumerate() {
+        let photo = pattern(seed as u32 + 1, 48, 48);
+        a.push(save(&root.join("a"), &format!("photo{seed}.png"), &photo));
+        b[slot] = Some(save(&root.join("b"), &format!("export{slot}.png"), &blend(&photo, &pattern(100, 48, 48), 0.003)));
+    }
+    a.push(save(&root.join("a"), "photo6.png", &pattern(20, 48, 48)));
+    b[6] = Some(save(&root.join("b"), "export6.png", &pattern(21, 48, 48)));
+    b[7] = Some(save(&root.join("b"), "export7.png", &pattern(22, 48, 48)));
+    (a, b.into_iter().map(Option::unwrap).collect(), ORDER.to_vec())
+}
+
+#[test]
+fn shuffled_sets_recover_the_bijection() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, expected) = shuffled_sets(dir.path());
+    for assignment in [Assignment::Greedy, Assignment::Optimal] {
+        let (matching, errors) = match_sets(&a, &b, &ComparerOptions::new(), assignment);
+        assert!(errors.is_empty(), "{errors:?}");
+        let pairs: Vec<(usize, usize)> = matching.pairs.iter().map(|pair| (pair.a, pair.b)).collect();
+        let mut wanted: Vec<(usize, usize)> = expected.iter().copied().enumerate().collect();
+        wanted.push((6, pairs.last().unwrap().1));
+        assert_eq!(pairs, wanted, "{assignment:?}");
+        assert!(matching.pairs[..6].iter().all(|pair| pair.similarity > 95.0), "{:?}", matching.pairs);
+        assert!(matching.unmatched_a.is_empty());
+        assert_eq!(matching.unmatched_b.len(), 1);
+    }
+}
+
+#[test]
+fn match_command_reports_leftovers_and_the_floor() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, _, expected) = shuffled_sets(dir.path());
+    let report = json(imgalg().args(["match", "--json", "--exact", "--min-similarity", "90"]).arg(dir.path().join("a")).arg(dir.path().join("b")));
+    let name = |value: &serde_json::Value| Path::new(value.as_str().unwrap()).file_name().unwrap().to_string_lossy().into_owned();
+    let pairs = report["pairs"].as_array().unwrap();
+    assert_eq!(pairs.len(), 7, "{report}");
+    for (pair, slot) in pairs.iter().zip(&expected) {
+        assert_eq!(name(&pair["b"]), format!("export{slot}.png"), "{pair}");
+        assert_eq!(pair["below_min_similarity"], false);
+    }
+    assert_eq!(name(&pairs[6]["a"]), "photo6.png");
+    assert_eq!(pairs[6]["below_min_similarity"], true);
+    assert_eq!(report["below_min_similarity"], 1);
+    assert_eq!(report["unmatched_a"].as_array().unwrap().len(), 0);
+    assert_eq!(report["unmatched_b"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,29 @@
+//! `--matrix json|csv`
+
+mod common;
+
+use common::{imgalg, json, mixed_set, save};
+
+#[test]
+fn json_and_csv_layouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<_> = mixed_set(4).iter().enumerate().map(|(i, image)| save(dir.path(), &format!("{i}.png"), image)).collect();
+
+    let report = json(imgalg().args(&paths).args(["--matrix", "json"]));
+    let matrix = report["matrix"].as_array().unwrap();
+    assert_eq!(report["paths"].as_array().unwrap().len(), 4);
+    for (i, row) in matrix.iter().enumerate() {
+        assert_eq!(row[i], 100.0);
+        for j in 0..4 {
+            assert_eq!(row[j], matrix[j][i]);
+        }
+    }
+
+    let output = imgalg().args(&paths).args(["--matrix", "csv"]).output().unwrap();
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with(&format!("path,{},", paths[0].display())), "{}", lines[0]);
+    assert!(lines[1].starts_with(&paths[0].display().to_string()));
+    assert_eq!(lines[1].split(',').nth(1), Some("100"));
+}
@@ -0,0 +1,21 @@
+//! `outliers`: поиск кадров, которые выбиваются из серии
+
+mod common;
+
+use common::{gradient, imgalg, json, pattern, save, with_noise};
+
+#[test]
+fn odd_frame_is_printed_with_its_average() {
+    let dir = tempfile::tempdir().unwrap();
+    for seed in 0..5 {
+        save(dir.path(), &format!("frame{seed}.png"), &with_noise(&gradient(96, 96), 4, seed));
+    }
+    let odd = save(dir.path(), "frame3-car.png", &pattern(9, 96, 96));
+
+    let report = json(imgalg().args(["outliers", "--threshold", "60"]).arg(dir.path()).arg("--json"));
+    assert_eq!(report["loaded"].as_u64(), Some(6));
+    let outliers = report["outliers"].as_array().unwrap();
+    assert_eq!(outliers.len(), 1, "{report}");
+    assert_eq!(outliers[0]["path"].as_str(), odd.to_str());
+    assert!(outliers[0]["average"].as_f64().unwrap() < 10.0, "{report}");
+}
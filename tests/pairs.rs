@@ -0,0 +1,99 @@
+//! `--pairs`: сравнение пар из файла
+
+mod common;
+
+use common::{imgalg, json, pattern, save};
+use std::fs;
+
+#[test]
+fn repeated_path_is_decoded_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 64, 64));
+    let others: Vec<_> = (2..5).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 64, 64))).collect();
+    let list: String = others.iter().map(|other| format!("{}\t{}\n", a.display(), other.display())).collect();
+    let pairs = dir.path().join("pairs.txt");
+    fs::write(&pairs, list).unwrap();
+
+    let report = json(imgalg().arg("--pairs").arg(&pairs).arg("--json"));
+    assert_eq!(report["stats"]["pairs"], 3);
+    assert_eq!(report["stats"]["decoded"], 4);
+    assert_eq!(report["stats"]["decodes_saved"], 2);
+}
+
+#[test]
+fn rows_keep_input_order_and_failed_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 64, 64));
+    let b = save(dir.path(), "b.png", &pattern(2, 64, 64));
+    let missing = dir.path().join("missing.png");
+    let pairs = dir.path().join("pairs.csv");
+    fs::write(&pairs, format!("a,b\n{0},{1}\n{0},{2}\n{1},{0}\n", a.display(), b.display(), missing.display())).unwrap();
+
+    let report = json(imgalg().arg("--pairs").arg(&pairs).arg("--json"));
+    let rows = report["pairs"].as_array().unwrap();
+    assert_eq!(rows.iter().map(|row| row["line"].as_u64().unwrap()).collect::<Vec<_>>(), [2, 3, 4]);
+    assert_eq!(rows[1]["error"]["code"], "E_IO");
+    assert_eq!(rows[0]["similarity"], rows[2]["similarity"]);
+    assert_eq!(report["stats"]["failed"], 1);
+}
+
+#[test]
+fn path_listed_five_times_decodes_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 64, 64));
+    let others: Vec<_> = (2..7).map(|seed| save(dir.path(), &format!("{seed}.png"), &pattern(seed, 64, 64))).collect();
+    let list: String = others.iter().map(|other| format!("{}\t{}\n", a.display(), other.display())).collect();
+    let pairs = dir.path().join("pairs.txt");
+    fs::write(&pairs, list).unwrap();
+
+    let report = json(imgalg().arg("--pairs").arg(&pairs).arg("--json"));
+    assert_eq!(report["stats"]["pairs"], 5);
+    assert_eq!(report["stats"]["decoded"], 6);
+    assert_eq!(report["stats"]["decodes_saved"], 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_shares_the_target_signature() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 64, 64));
+    let b = save(dir.path(), "b.png", &pattern(2, 64, 64));
+    let link = dir.path().join("link.png");
+    std::os::unix::fs::symlink(&a, &link).unwrap();
+    let pairs = dir.path().join("pairs.txt");
+    fs::write(&pairs, format!("{0}\t{1}\n{2}\t{1}\n", a.display(), b.display(), link.display())).unwrap();
+
+    let report = json(imgalg().arg("--pairs").arg(&pairs).arg("--json"));
+    assert_eq!(report["stats"]["decoded"], 2);
+    assert_eq!(report["stats"]["decodes_saved"], 2);
+    let rows = report["pairs"].as_array().unwrap();
+    assert_eq!(rows[0]["similarity"], rows[1]["similarity"]);
+}
+
+#[test]
+fn failing_pair_is_ignored_on_the_second_run() {
+    let dir = tempfile::tempdir().unwrap();
+    save(dir.path(), "a.png", &pattern(1, 64, 64));
+    save(dir.path(), "a-copy.png", &pattern(1, 64, 64));
+    save(dir.path(), "b.png", &pattern(2, 64, 64));
+    fs::write(dir.path().join("pairs.txt"), "a.png\ta-copy.png\na.png\tb.png\n").unwrap();
+    let run = |extra: &[&str]| imgalg().current_dir(dir.path()).args(["--pairs", "pairs.txt", "--threshold", "90", "--json"]).args(extra).output().unwrap();
+
+    let first = run(&["--write-ignores", "accepted.txt", "--yes"]);
+    assert_eq!(first.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&first.stdout).unwrap();
+    assert_eq!((report["stats"]["below_threshold"].as_u64(), report["stats"]["ignored"].as_u64()), (Some(1), Some(0)));
+    let accepted = fs::read_to_string(dir.path().join("accepted.txt")).unwrap();
+    assert_eq!(accepted.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).count(), 1, "{accepted}");
+
+    // Пара узнается и с `./` перед путями, и в обратном порядке
+    fs::write(dir.path().join("pairs.txt"), "./a.png\ta-copy.png\n./b.png\t./a.png\n").unwrap();
+    let second = run(&["--ignore-pairs", "accepted.txt"]);
+    assert_eq!(second.status.code(), Some(0), "{}", String::from_utf8_lossy(&second.stdout));
+    let report: serde_json::Value = serde_json::from_slice(&second.stdout).unwrap();
+    assert_eq!((report["stats"]["below_threshold"].as_u64(), report["stats"]["ignored"].as_u64()), (Some(0), Some(1)));
+    let row = &report["pairs"][1];
+    assert_eq!((row["ignored"].as_bool(), row["passed"].as_bool()), (Some(true), Some(false)), "{row}");
+    assert!(row["similarity"].as_f64().unwrap() < 90.0);
+    assert!(report["pairs"][0].get("ignored").is_none());
+}
@@ -0,0 +1,49 @@
+//! Имена файлов не в UTF-8: просмотр, сравнение и отчеты без потери байтов
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{imgalg, json, pattern, save, stdout};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+/// Имя старого импорта с камеры в однобайтовой кодировке, да еще со знаком `%`
+const NAME: &[u8] = b"IMG_\xff%.png";
+const ENCODED: &str = "IMG_%FF%25.png";
+
+#[test]
+fn non_utf8_name_is_scanned_and_reported_round_trippably() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    pattern(1, 48, 48).save(root.join(OsStr::from_bytes(NAME))).unwrap();
+    save(root, "copy.png", &pattern(1, 48, 48));
+
+    let report = json(imgalg().current_dir(root).args(["scan", "--json", "."]));
+    assert_eq!(report["files"].as_u64(), Some(2));
+    let group = report["groups"][0].as_array().unwrap();
+    let encoded = group[0]["path"].as_str().unwrap();
+    assert_eq!(encoded, format!("./{ENCODED}"));
+    assert_eq!(imgalg::paths::decode(encoded).file_name().unwrap().as_bytes(), NAME);
+    assert!(root.join(imgalg::paths::decode(encoded)).exists());
+
+    let csv = stdout(&imgalg().current_dir(root).args(["scan", "--csv", "."]).output().unwrap());
+    assert!(csv.lines().any(|line| line == format!("1,./{ENCODED},100,,true")), "{csv}");
+
+    // Индекс хранит байты имени: повторный просмотр узнает файл
+    let index = root.join("photos.idx");
+    let scan = || json(imgalg().current_dir(root).args(["scan", "--json", "--incremental", "--index"]).arg(&index).arg("."));
+    scan();
+    assert_eq!(scan()["reused"].as_u64(), Some(2));
+}
+
+#[test]
+fn non_utf8_name_is_compared() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    pattern(1, 48, 48).save(root.join(OsStr::from_bytes(NAME))).unwrap();
+    save(root, "copy.png", &pattern(1, 48, 48));
+    let report = json(imgalg().current_dir(root).arg(OsStr::from_bytes(NAME)).arg("copy.png").arg("--json"));
+    assert_eq!((report["a"].as_str(), report["similarity"].as_f64()), (Some(ENCODED), Some(100.0)));
+    assert_eq!(report["images"][0]["path"].as_str(), Some(ENCODED));
+}
@@ -0,0 +1,40 @@
+//! Ненормированная разница: значения закреплены и меняются только с версией алгоритма
+
+mod common;
+
+use common::{blend, comparer, gradient, imgalg, json, pattern, save};
+use imgalg::Fingerprint;
+use std::path::Path;
+
+#[test]
+fn signature_raw_diff_is_pinned() {
+    let dir = tempfile::tempdir().unwrap();
+    let (comparer, _) = comparer(dir.path(), &[pattern(1, 48, 48), blend(&pattern(1, 48, 48), &pattern(2, 48, 48), 0.01), pattern(2, 48, 48), gradient(48, 48)]);
+    assert_eq!(comparer.algorithm_id(), "color-diff-v2(grid=16,filter=area,color=rgba8,values=squared)");
+    let pinned = [((0, 1), 5148.349895574009), ((0, 2), 56417.701294306426), ((0, 3), 49263.15623905127), ((2, 3), 48946.37364083323)];
+    for ((i, j), raw) in pinned {
+        assert_eq!(comparer.raw_diff(i, j).unwrap(), raw, "{i} ~ {j}");
+        assert_eq!(comparer.raw_diff(j, i).unwrap(), raw);
+    }
+    assert_eq!(comparer.raw_diff(0, 0).unwrap(), 0.0);
+}
+
+#[test]
+fn fingerprint_raw_diff_is_hamming_distance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash");
+    let fingerprint = |name: &str| Fingerprint::compute(dir.join(name)).unwrap();
+    assert_eq!(fingerprint("rings.png").distance(&fingerprint("rings.jpg")), 0);
+    assert_eq!(fingerprint("rings.png").distance(&fingerprint("noise.png")), 36);
+    assert_eq!(fingerprint("blocks.png").distance(&fingerprint("diagonal.png")), 32);
+}
+
+#[test]
+fn cli_prints_raw_diff_with_full_precision() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = save(dir.path(), "a.png", &pattern(1, 48, 48));
+    let b = save(dir.path(), "b.png", &blend(&pattern(1, 48, 48), &pattern(2, 48, 48), 0.01));
+    let report = json(imgalg().arg(&a).arg(&b).args(["--raw", "--json"]));
+    assert_eq!(report["raw_diff"].as_f64(), Some(5148.349895574009));
+    let output = imgalg().arg(&a).arg(&b).arg("--raw").output().unwrap();
+    assert!(String::from_utf8(output.stdout).unwrap().contains("Signature difference: 5148.349895574009\n"));
+}
@@ -0,0 +1,479 @@
+//! `scan`: поиск групп дубликатов в каталоге
+
+mod common;
+
+use common::{blend, gradient, imgalg, json, pattern, save, stdout, with_noise};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn parallel_json_is_identical_between_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    tied_dir(root);
+
+    let run = || stdout(&imgalg().env("SOURCE_DATE_EPOCH", "1700000000").args(["--jobs", "4", "scan", "--json"]).arg(root).output().unwrap());
+    let first = run();
+    assert!(first.contains("copy0.png"), "{first}");
+    for _ in 0..3 {
+        assert_eq!(run(), first);
+    }
+    let report: serde_json::Value = serde_json::from_str(&first).unwrap();
+    let groups = report["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+    let paths = |group: &serde_json::Value| group.as_array().unwrap().iter().map(|entry| entry["path"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+    for group in groups {
+        let paths = paths(group);
+        assert!(paths.is_sorted(), "{paths:?}");
+    }
+    assert!(paths(&groups[0])[0] < paths(&groups[1])[0]);
+    assert_eq!(report["provenance"]["created"], "2023-11-14T22:13:20Z");
+}
+
+/// Вложенные каталоги с копиями и «почти копиями»: у одинаковых копий равные схожести,
+/// и их порядок не должен зависеть от потоков
+fn tied_dir(root: &Path) {
+    for sub in ["b", "a/deep", "c"] {
+        fs::create_dir_all(root.join(sub)).unwrap();
+    }
+    for (idx, sub) in ["b", "a/deep", "c", "a", ""].iter().enumerate() {
+        save(&root.join(sub), &format!("copy{idx}.png"), &pattern(1, 64, 64));
+        save(&root.join(sub), &format!("near{idx}.png"), &blend(&pattern(2, 64, 64), &pattern(3, 64, 64), 0.002));
+        save(&root.join(sub), &format!("other{idx}.png"), &pattern(10 + idx as u32, 64, 64));
+    }
+}
+
+#[test]
+fn one_and_four_jobs_give_identical_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    tied_dir(root);
+
+    // Отличается только записанная командная строка с самим `--jobs`
+    let run = |jobs: &str| {
+        let mut report = json(imgalg().env("SOURCE_DATE_EPOCH", "1700000000").args(["--jobs", jobs, "scan", "--json"]).arg(root));
+        report["provenance"].as_object_mut().unwrap().remove("command_line");
+        report
+    };
+    let single = run("1");
+    assert_eq!(single["groups"].as_array().unwrap().len(), 2);
+    assert_eq!(run("4"), single);
+}
+
+#[test]
+fn ndjson_lines_rebuild_the_batch_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    tied_dir(root);
+    fs::write(root.join("broken.png"), b"not a png").unwrap();
+    let run = |format: &[&str]| imgalg().env("SOURCE_DATE_EPOCH", "1700000000").args(["--jobs", "4"]).args(format).arg("scan").arg(root).output().unwrap();
+    let mut batch: serde_json::Value = serde_json::from_slice(&run(&["--format", "json"]).stdout).unwrap();
+
+    // Каждая строка - отдельный объект JSON; пары и ошибки идут в порядке потоков
+    let (mut rebuilt, mut pairs) = (serde_json::Map::new(), vec![]);
+    let (mut groups, mut errors) = (vec![], vec![]);
+    let output = stdout(&run(&["--format", "ndjson"]));
+    for line in output.lines() {
+        let mut object = serde_json::from_str::<serde_json::Value>(line).unwrap_or_else(|e| panic!("{e}: {line}")).as_object().unwrap().clone();
+        match object.remove("type").unwrap().as_str().unwrap() {
+            "pair" => pairs.push(object),
+            "group" => groups.push(object.remove("entries").unwrap()),
+            "error" => errors.push(serde_json::Value::Object(object)),
+            "summary" => rebuilt = object,
+            other => panic!("unexpected line type {other}"),
+        }
+    }
+    assert_eq!(output.lines().last().map(|line| line.starts_with(r#"{"type":"summary""#)), Some(true));
+    rebuilt.insert("groups".to_string(), groups.into());
+    rebuilt.insert("errors".to_string(), errors.into());
+    let mut rebuilt = serde_json::Value::Object(rebuilt);
+    for report in [&mut batch, &mut rebuilt] {
+        report["provenance"].as_object_mut().unwrap().remove("command_line");
+    }
+    assert_eq!(rebuilt, batch);
+
+    // Каждая группа - копии, похожие друг на друга, так что пар столько же, сколько внутри групп
+    let grouped: usize = batch["groups"].as_array().unwrap().iter().map(|group| group.as_array().unwrap().len()).map(|n| n * (n - 1) / 2).sum();
+    assert_eq!(pairs.len(), grouped);
+    assert!(pairs.iter().all(|pair| pair["band"].is_string()), "{pairs:?}");
+}
+
+/// Двенадцать несвязанных изображений и копия первого: шумные градиенты похожи друг на друга
+/// сильнее, чем цветные блоки
+fn corpus(root: &Path, image: impl Fn(u32) -> image::RgbaImage) {
+    for i in 0..12 {
+        save(root, &format!("{i:02}.png"), &image(i));
+    }
+    save(root, "00-copy.png", &image(0));
+}
+
+#[test]
+fn adaptive_threshold_follows_the_background_of_each_corpus() {
+    let dir = tempfile::tempdir().unwrap();
+    let (photos, screens) = (dir.path().join("photos"), dir.path().join("screens"));
+    corpus(&photos, |i| with_noise(&gradient(64, 64), 30, i));
+    corpus(&screens, |i| pattern(i + 1, 64, 64));
+    let scan = |root: &Path, samples: &str| json(imgalg().args(["scan", "--json", "--adaptive-threshold", "95", "--margin", "10", "--samples", samples, "--seed", "7"]).arg(root));
+
+    let mut thresholds = vec![];
+    for root in [&photos, &screens] {
+        let report = scan(root, "1000");
+        assert_eq!((report["adaptive"]["pairs"].as_u64(), report["adaptive"]["seed"].as_u64()), (Some(78), Some(7)));
+        let threshold = report["threshold"].as_f64().unwrap();
+        assert_eq!(threshold, report["adaptive"]["background"].as_f64().unwrap() + 10.0);
+        // Найдена только копия
+        let groups = report["groups"].as_array().unwrap();
+        assert_eq!(groups.len(), 1, "{report}");
+        let names: Vec<&str> = groups[0].as_array().unwrap().iter().map(|entry| entry["path"].as_str().unwrap().rsplit(['/', '\\']).next().unwrap()).collect();
+        assert_eq!(names, ["00-copy.png", "00.png"]);
+        thresholds.push(threshold);
+        // С тем же `--seed` выборка пар та же
+        assert_eq!(scan(root, "40")["threshold"], scan(root, "40")["threshold"]);
+    }
+    assert!(thresholds[0] > thresholds[1] + 20.0, "{thresholds:?}");
+}
+
+#[test]
+fn incremental_rescan_decodes_only_the_added_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    fs::create_dir_all(tree.join("2023")).unwrap();
+    save(&tree, "a.png", &pattern(1, 48, 48));
+    save(&tree.join("2023"), "b.png", &pattern(2, 48, 48));
+    save(&tree.join("2023"), "c.png", &pattern(3, 48, 48));
+    let index = dir.path().join("photos.idx");
+    let run = || json(imgalg().args(["scan", "--json", "--incremental", "--index"]).arg(&index).arg(&tree));
+
+    let first = run();
+    assert_eq!((first["reused"].as_u64(), first["recomputed"].as_u64()), (Some(0), Some(3)));
+    save(&tree.join("2023"), "a-copy.png", &pattern(1, 48, 48));
+    let second = run();
+    assert_eq!((second["files"].as_u64(), second["reused"].as_u64(), second["recomputed"].as_u64()), (Some(4), Some(3), Some(1)));
+    assert_eq!(second["groups"].as_array().unwrap().len(), 1, "{second}");
+}
+
+#[test]
+fn index_of_another_value_transform_is_recomputed() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    save(&tree, "a.png", &pattern(1, 48, 48));
+    save(&tree, "b.png", &pattern(2, 48, 48));
+    let index = dir.path().join("photos.idx");
+    let run = |transform: &str| json(imgalg().args(["--value-transform", transform, "scan", "--json", "--incremental", "--index"]).arg(&index).arg(&tree));
+
+    assert_eq!(run("sqrt")["recomputed"].as_u64(), Some(2));
+    let same = run("sqrt");
+    assert_eq!((same["reused"].as_u64(), same["recomputed"].as_u64()), (Some(2), Some(0)));
+    let other = run("square");
+    assert_eq!((other["reused"].as_u64(), other["recomputed"].as_u64()), (Some(0), Some(2)));
+}
+
+#[test]
+fn index_of_another_letterbox_setting_is_recomputed() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    save(&tree, "a.png", &pattern(1, 60, 40));
+    save(&tree, "b.png", &pattern(2, 40, 50));
+    let index = dir.path().join("photos.idx");
+    let run = |extra: &[&str]| json(imgalg().args(extra).args(["scan", "--json", "--incremental", "--index"]).arg(&index).arg(&tree));
+
+    assert_eq!(run(&["--letterbox"])["recomputed"].as_u64(), Some(2));
+    let same = run(&["--letterbox"]);
+    assert_eq!((same["reused"].as_u64(), same["recomputed"].as_u64()), (Some(2), Some(0)));
+    let stretched = run(&[]);
+    assert_eq!((stretched["reused"].as_u64(), stretched["recomputed"].as_u64()), (Some(0), Some(2)));
+}
+
+#[cfg(unix)]
+#[test]
+fn linked_directory_loop_is_read_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("album")).unwrap();
+    save(root, "a.png", &pattern(1, 48, 48));
+    save(&root.join("album"), "b.png", &pattern(2, 48, 48));
+    // Ссылка на корень из подкаталога и вторая ссылка на тот же подкаталог
+    std::os::unix::fs::symlink(root, root.join("album/up")).unwrap();
+    std::os::unix::fs::symlink(root.join("album"), root.join("again")).unwrap();
+
+    let report = json(imgalg().args(["scan", "--json"]).arg(root));
+    assert_eq!(report["files"].as_u64(), Some(2), "{report}");
+}
+
+#[test]
+fn duplicates_split_across_folders_are_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    // Одна копия из 2019 попала в 2020, еще одна пара - внутри 2020
+    save(root, "2019/a.png", &pattern(1, 48, 48));
+    save(root, "2019/deep/c.png", &pattern(3, 48, 48));
+    save(root, "2020/a-copy.png", &pattern(1, 48, 48));
+    save(root, "2020/b.png", &pattern(2, 48, 48));
+    save(root, "2020/b-copy.png", &pattern(2, 48, 48));
+    save(root, "loose.png", &pattern(4, 48, 48));
+
+    let report = json(imgalg().args(["scan", "--json"]).arg(root));
+    let summary = &report["summary"];
+    assert_eq!(summary["cross_directory_pairs"], 1, "{summary}");
+    let rows: Vec<(&str, u64, u64, u64)> = summary["directories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| (row["directory"].as_str().unwrap(), row["images"].as_u64().unwrap(), row["duplicates"].as_u64().unwrap(), row["cross_directory_pairs"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(rows, [(".", 1, 0, 0), ("2019", 2, 1, 1), ("2020", 3, 3, 1)]);
+    // Освобождается место копий: остаются первые изображения групп, обе копии - в 2020
+    let reclaimable: Vec<u64> = summary["directories"].as_array().unwrap().iter().map(|row| row["reclaimable_bytes"].as_u64().unwrap()).collect();
+    let size = |name: &str| fs::metadata(root.join(name)).unwrap().len();
+    assert_eq!(reclaimable, [0, 0, size("2020/a-copy.png") + size("2020/b-copy.png")]);
+
+    let text = stdout(&imgalg().args(["scan", "--summary"]).arg(root).output().unwrap());
+    assert!(text.contains("Duplicate pairs across subdirectories: 1"), "{text}");
+}
+
+#[test]
+fn original_is_kept_over_its_blurred_upscale() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    // Мелкий шум поверх градиента: размытие убирает его, а средние цвета ячеек остаются
+    let original = with_noise(&gradient(256, 256), 12, 7);
+    let upscaled = image::imageops::resize(&original, 512, 512, image::imageops::FilterType::Triangle);
+    // Увеличенная размытая копия больше по размеру и идет в группе первой
+    save(root, "a-upscaled.png", &image::imageops::blur(&upscaled, 1.5));
+    save(root, "b-original.png", &original);
+
+    let kept = |policy: &str| {
+        let report = json(imgalg().args(["scan", "--json", "--keep", policy]).arg(root));
+        let group = report["groups"].as_array().unwrap().first().unwrap_or_else(|| panic!("{report}")).as_array().unwrap().clone();
+        let kept: Vec<&str> = group.iter().filter(|entry| entry["keep"] == true).map(|entry| entry["path"].as_str().unwrap()).collect();
+        assert_eq!(kept.len(), 1, "{group:?}");
+        Path::new(kept[0]).file_name().unwrap().to_str().unwrap().to_string()
+    };
+    assert_eq!(kept("first"), "a-upscaled.png");
+    assert_eq!(kept("best-quality"), "b-original.png");
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn archive_copies_count_their_unpacked_size_as_reclaimable() {
+    use std::io::Write;
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let original = save(root, "a.png", &pattern(1, 48, 48));
+    let bytes = fs::read(&original).unwrap();
+    let mut archive = zip::ZipWriter::new(fs::File::create(root.join("album.zip")).unwrap());
+    archive.start_file("copy.png", zip::write::SimpleFileOptions::default()).unwrap();
+    archive.write_all(&bytes).unwrap();
+    archive.finish().unwrap();
+
+    let report = json(imgalg().args(["scan", "--json"]).arg(root));
+    assert_eq!(report["groups"].as_array().unwrap().len(), 1, "{report}");
+    let rows: Vec<(&str, u64)> = report["summary"]["directories"].as_array().unwrap().iter().map(|row| (row["directory"].as_str().unwrap(), row["reclaimable_bytes"].as_u64().unwrap())).collect();
+    assert_eq!(rows.iter().map(|(_, bytes)| bytes).sum::<u64>(), bytes.len() as u64, "{rows:?}");
+}
+
+#[test]
+fn pair_comparison_options_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    save(dir.path(), "a.png", &pattern(1, 32, 32));
+    let map = save(dir.path(), "map.png", &pattern(2, 32, 32));
+    let map = map.to_str().unwrap();
+    for flags in [&["--weights", "1,2,1"][..], &["--channel", "r"], &["--ignore-hue"], &["--ignore-worst", "2"], &["--multi-scale"], &["--crop", "0,0,8,8"], &["--tolerance-map", map]] {
+        for command in [&["scan"][..], &["watch", "--index", "index.bin"]] {
+            let output = imgalg().current_dir(dir.path()).args(flags).arg("--json").args(command).arg(".").output().unwrap();
+            assert_eq!(output.status.code(), Some(2), "{flags:?} {command:?}");
+            let error: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+            assert_eq!(error["error"]["code"], "E_ARGS", "{flags:?} {command:?}");
+            assert!(error["error"]["message"].as_str().unwrap().starts_with(&format!("{} is not supported by scan and watch", flags[0])), "{error}");
+        }
+    }
+}
+
+/// Каталог с парой копий и парой «почти копия», схожесть которой возвращается
+fn banded_dir(root: &Path) -> f32 {
+    save(root, "a.png", &pattern(1, 64, 48));
+    save(root, "a-copy.png", &pattern(1, 64, 48));
+    let b = save(root, "b.png", &pattern(2, 48, 48));
+    let near = save(root, "b-near.png", &blend(&pattern(2, 48, 48), &pattern(3, 48, 48), 0.004));
+    let report = json(imgalg().arg(&b).arg(&near).arg("--json"));
+    report["similarity"].as_f64().unwrap() as f32
+}
+
+/// Оценка почти копии в группах `scan --json` с границами `bands`
+fn near_copy_band(root: &Path, bands: &str) -> String {
+    let report = json(imgalg().args(["--bands", bands, "--threshold", "50", "scan", "--json"]).arg(root));
+    let entry = report["groups"].as_array().unwrap().iter().flat_map(|group| group.as_array().unwrap()).find(|entry| entry["path"].as_str().unwrap().ends_with("b.png")).unwrap();
+    entry["band"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn score_on_a_band_boundary_takes_the_higher_band() {
+    let dir = tempfile::tempdir().unwrap();
+    let score = banded_dir(dir.path());
+    let above = f32::from_bits(score.to_bits() + 1);
+    assert_eq!(near_copy_band(dir.path(), &format!("{score},90,50")), "identical");
+    assert_eq!(near_copy_band(dir.path(), &format!("{above},{score},50")), "near_duplicate");
+    assert_eq!(near_copy_band(dir.path(), &format!("99.9,{above},{score}")), "similar");
+    assert_eq!(near_copy_band(dir.path(), &format!("99.9,99,{above}")), "different");
+}
+
+#[test]
+fn bands_appear_in_every_scan_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let score = banded_dir(dir.path());
+    assert!((90.0..97.0).contains(&score), "{score}");
+
+    let report = json(imgalg().args(["scan", "--json"]).arg(dir.path()));
+    assert_eq!(report["cutoffs"], serde_json::json!({"identical": 99.5, "near_duplicate": 97.0, "similar": 90.0}));
+    assert_eq!(report["bands"], serde_json::json!({"identical": 1, "near_duplicate": 0, "similar": 1, "different": 0}));
+    for group in report["groups"].as_array().unwrap() {
+        assert!(group[0].get("band").is_none());
+    }
+
+    let text = stdout(&imgalg().arg("scan").arg(dir.path()).output().unwrap());
+    assert!(text.contains("a.png (100.00%, identical)"), "{text}");
+    assert!(text.contains(&format!("b.png ({score:.2}%, similar)")), "{text}");
+    assert!(text.ends_with("By verdict: identical 1, near duplicates 0, similar 1, different 0\n"), "{text}");
+
+    let csv = stdout(&imgalg().args(["scan", "--csv"]).arg(dir.path()).output().unwrap());
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows[0], ["group", "path", "similarity", "band", "keep"]);
+    let bands: Vec<(&str, &str)> = rows[1..].iter().map(|row| (row[0], row[3])).collect();
+    assert_eq!(bands, [("1", ""), ("1", "identical"), ("2", ""), ("2", "similar")]);
+}
+
+#[test]
+fn excluded_directories_are_never_decoded() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path();
+    save(tree, "a.png", &pattern(1, 48, 48));
+    save(tree, "b.png", &pattern(2, 48, 48));
+    // Миниатюры совпадают с оригиналами, а битый файл при чтении дал бы ошибку
+    for junk in ["@eaDir", ".thumbnails", "cache"] {
+        save(&tree.join(junk), "a-thumb.png", &pattern(1, 48, 48));
+        save(&tree.join(junk), "b-thumb.png", &pattern(2, 48, 48));
+        fs::write(tree.join(junk).join("broken.png"), b"not a png").unwrap();
+    }
+    fs::write(tree.join(".broken.png"), b"not a png").unwrap();
+    let run = |extra: &[&str]| json(imgalg().args(["scan", "--json"]).args(extra).arg(tree));
+
+    let report = run(&["--exclude", "cache/"]);
+    assert_eq!((report["files"].as_u64(), report["recomputed"].as_u64()), (Some(2), Some(2)), "{report}");
+    assert_eq!(report["errors"].as_array().map_or(0, Vec::len), 0, "{report}");
+    assert_eq!(report["groups"].as_array().unwrap().len(), 0, "{report}");
+    assert_eq!(report["excluded"], serde_json::json!({ "files": 1, "directories": 3 }));
+
+    let everything = run(&["--no-default-excludes", "--hidden"]);
+    assert_eq!(everything["files"].as_u64(), Some(12), "{everything}");
+    assert_eq!(everything["errors"].as_array().unwrap().len(), 4, "{everything}");
+    assert_eq!(everything["excluded"], serde_json::json!({ "files": 0, "directories": 0 }));
+}
+
+#[test]
+fn degenerate_images_do_not_abort_the_scan() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path();
+    save(tree, "a.png", &pattern(1, 48, 48));
+    save(tree, "a-copy.png", &pattern(1, 48, 48));
+    let strip = save(tree, "strip.png", &pattern(2, 1, 500));
+    fs::write(tree.join("empty.pgm"), b"P5 10 0 255\n").unwrap();
+    let run = |extra: &[&str]| imgalg().args(extra).args(["scan", "--json"]).arg(tree).output().unwrap();
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["groups"].as_array().unwrap().len(), 1, "{report}");
+    let warnings = report["warnings"].as_array().unwrap();
+    assert_eq!((warnings.len(), warnings[0]["kind"].as_str(), warnings[0]["path"].as_str()), (1, Some("extreme_aspect"), strip.to_str()), "{report}");
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!((errors.len(), errors[0]["code"].as_str()), (1, Some("E_DECODE")), "{report}");
+    assert_eq!(errors[0]["message"].as_str().map(|message| message.ends_with("The image is 10x0 and has no pixels")), Some(true), "{report}");
+
+    // С `--strict-aspect` полоска - ошибка файла, а не повод прервать просмотр
+    let strict: serde_json::Value = serde_json::from_slice(&run(&["--strict-aspect"]).stdout).unwrap();
+    assert_eq!(strict["groups"].as_array().unwrap().len(), 1, "{strict}");
+    let codes: Vec<&str> = strict["errors"].as_array().unwrap().iter().filter_map(|error| error["code"].as_str()).collect();
+    assert_eq!(codes.len(), 2, "{strict}");
+    assert!(codes.contains(&"E_UNSUPPORTED"), "{strict}");
+}
+
+#[test]
+fn contact_sheets_are_written_per_group_with_thumbnail_strips() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("images");
+    fs::create_dir(&root).unwrap();
+    // Три широкие копии, девять квадратных (на лист - восемь) и одно изображение без пары
+    for idx in 0..3 {
+        save(&root, &format!("wide{idx}.png"), &pattern(1, 200, 100));
+    }
+    for idx in 0..9 {
+        save(&root, &format!("square{idx}.png"), &pattern(2, 100, 100));
+    }
+    save(&root, "single.png", &pattern(3, 100, 100));
+    let sheets = dir.path().join("sheets");
+
+    let report = json(imgalg().args(["scan", "--json", "--contact-sheets"]).arg(&sheets).arg(&root));
+    assert_eq!(report["groups"].as_array().unwrap().len(), 2);
+    let listed: Vec<_> = report["contact_sheets"].as_array().unwrap().iter().map(|sheet| (sheet["group"].as_u64().unwrap(), sheet["file"].as_str().unwrap().to_string())).collect();
+    assert_eq!(listed, [(1, "group_0001.jpg".to_string()), (2, "group_0002.jpg".to_string())]);
+    let mut written: Vec<_> = fs::read_dir(&sheets).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    written.sort();
+    assert_eq!(written, ["group_0001.jpg", "group_0002.jpg"]);
+
+    // Миниатюры высотой 160 с промежутками по 4 пикселя: ширина листа - по числу и пропорциям изображений
+    let mut widths: Vec<_> = written
+        .iter()
+        .map(|name| {
+            let (width, height) = image::image_dimensions(sheets.join(name)).unwrap();
+            assert_eq!(height, 168, "{name}");
+            width
+        })
+        .collect();
+    widths.sort();
+    assert_eq!(widths, [4 + 3 * (320 + 4), 4 + 8 * (160 + 4)]);
+}
+
+#[test]
+fn csv_names_the_contact_sheet_of_each_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("images");
+    fs::create_dir(&root).unwrap();
+    for idx in 0..2 {
+        save(&root, &format!("copy{idx}.png"), &pattern(1, 64, 64));
+    }
+    let csv = stdout(&imgalg().args(["scan", "--csv", "--contact-sheets"]).arg(dir.path().join("sheets")).arg(&root).output().unwrap());
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows[0], ["group", "path", "similarity", "band", "keep", "contact_sheet"]);
+    assert_eq!(rows.len(), 3, "{csv}");
+    assert!(rows[1..].iter().all(|row| row[5] == "group_0001.jpg"), "{csv}");
+    assert!(dir.path().join("sheets/group_0001.jpg").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_is_flagged_as_the_same_file_and_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let original = save(root, "a.png", &pattern(1, 64, 64));
+    save(root, "b-copy.png", &pattern(1, 64, 64));
+    std::os::unix::fs::symlink(&original, root.join("c-link.png")).unwrap();
+    let paths = |group: &serde_json::Value| group.as_array().unwrap().iter().map(|entry| entry["path"].as_str().unwrap().rsplit('/').next().unwrap().to_string()).collect::<Vec<_>>();
+
+    // По умолчанию ссылка - не дубликат: пара перечислена отдельно, в группе файл один раз
+    let report = json(imgalg().args(["scan", "--json"]).arg(root));
+    let same_file = report["same_file"].as_array().unwrap();
+    assert_eq!(same_file.len(), 1, "{report}");
+    let pair: Vec<_> = ["a", "b"].iter().map(|key| same_file[0][key].as_str().unwrap().rsplit('/').next().unwrap()).collect();
+    assert_eq!(pair, ["a.png", "c-link.png"]);
+    let groups = report["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(paths(&groups[0]), ["a.png", "b-copy.png"]);
+
+    // С `--include-same-file` ссылка в группе, помечена и остается вместе с файлом, на который ведет
+    let report = json(imgalg().args(["scan", "--json", "--include-same-file"]).arg(root));
+    let group = &report["groups"][0];
+    assert_eq!(paths(group), ["a.png", "b-copy.png", "c-link.png"]);
+    let flags = |key: &str| group.as_array().unwrap().iter().map(|entry| entry[key].as_bool().unwrap_or(false)).collect::<Vec<_>>();
+    assert_eq!(flags("same_file"), [true, false, true]);
+    assert_eq!(flags("keep"), [true, false, true]);
+}
@@ -0,0 +1,68 @@
+//! `set` и `check`: набор эталонных изображений с метками и проверка по нему
+
+mod common;
+
+use common::{gradient, imgalg, json, pattern, save, with_noise};
+
+#[test]
+fn upload_is_checked_against_a_labelled_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let banned = save(dir.path(), "banned.png", &gradient(96, 96));
+    let other = save(dir.path(), "other.png", &pattern(7, 96, 96));
+    let set = dir.path().join("blocklist.idx");
+    assert!(imgalg().args(["set", "create"]).arg(&set).status().unwrap().success());
+    assert!(!imgalg().args(["set", "create"]).arg(&set).status().unwrap().success(), "an existing set must not be overwritten");
+    let added = json(imgalg().args(["--json", "set", "add", "--label", "spam"]).arg(&set).arg(&banned));
+    assert_eq!(added["total"], 1);
+    json(imgalg().args(["--json", "set", "add"]).arg(&set).arg(&other));
+    let listed = json(imgalg().args(["--json", "set", "list"]).arg(&set));
+    assert_eq!(listed["entries"][0]["label"], "spam", "{listed}");
+    assert!(listed["entries"][1].get("label").is_none(), "{listed}");
+
+    // Совпадение - исход 2, а не ошибка, метка выводится вместе с ним
+    let upload = save(dir.path(), "upload.png", &with_noise(&gradient(96, 96), 3, 1));
+    let output = imgalg().args(["--json", "check", "--threshold", "90", "--set"]).arg(&set).arg(&upload).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["matched"], true);
+    assert_eq!((report["matches"][0]["path"].as_str(), report["matches"][0]["label"].as_str()), (banned.to_str(), Some("spam")), "{report}");
+    let counted = report["compared"].as_u64().unwrap() + report["skipped"].as_u64().unwrap();
+    assert_eq!(counted, 2);
+
+    let unrelated = save(dir.path(), "unrelated.png", &pattern(20, 96, 96));
+    let output = imgalg().args(["--json", "check", "--threshold", "99", "--no-prefilter", "--set"]).arg(&set).arg(&unrelated).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((report["matched"].as_bool(), report["compared"].as_u64(), report["skipped"].as_u64()), (Some(false), Some(2), Some(0)), "{report}");
+
+    let removed = json(imgalg().args(["--json", "set", "remove"]).arg(&set).arg(&other).arg(dir.path().join("never-added.png")));
+    assert_eq!(removed["total"], 1);
+    assert_eq!(removed["missing"].as_array().unwrap().len(), 1, "{removed}");
+}
+
+#[test]
+fn check_errors_exit_with_1() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = save(dir.path(), "image.png", &gradient(32, 32));
+    let missing_set = imgalg().args(["check", "--set"]).arg(dir.path().join("missing.idx")).arg(&image).output().unwrap().status;
+    assert_eq!(missing_set.code(), Some(1));
+    // Без `--set` ошибка аргументов, но и она не должна выглядеть как совпадение (2)
+    assert_eq!(imgalg().arg("check").arg(&image).output().unwrap().status.code(), Some(1));
+}
+
+#[test]
+fn labels_survive_a_scan_of_the_same_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = dir.path().join("photos");
+    std::fs::create_dir(&tree).unwrap();
+    let photo = save(&tree, "photo.png", &gradient(64, 64));
+    let set = dir.path().join("set.idx");
+    imgalg().args(["set", "create"]).arg(&set).status().unwrap();
+    json(imgalg().args(["--json", "set", "add", "--label", "kept"]).arg(&set).arg(&photo));
+
+    save(&tree, "photo.png", &pattern(3, 64, 64));
+    json(imgalg().args(["scan", "--json", "--index"]).arg(&set).arg(&tree));
+    let listed = json(imgalg().args(["--json", "set", "list"]).arg(&set));
+    assert_eq!(listed["entries"].as_array().unwrap().len(), 1, "{listed}");
+    assert_eq!(listed["entries"][0]["label"], "kept", "{listed}");
+}
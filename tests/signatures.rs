@@ -0,0 +1,67 @@
+//! Неизменность сигнатур: сигнатуры файлов `tests/fixtures/img_hash` сверяются до байта
+//! с записанными в `tests/fixtures/signatures`. Расхождение означает, что числа изменились
+//! и сохраненные индексы стали неверны: нужна новая версия формата сигнатуры
+
+use imgalg::{ComparerOptions, ImagesComparer, Signature, Transform};
+use std::fs;
+use std::path::Path;
+
+const FIXTURES: [&str; 6] = ["noise.png", "gradient.png", "blocks.png", "rings.png", "stripes.png", "diagonal.png"];
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// Строки `имя<TAB>сигнатура` из записанного файла
+fn golden(name: &str) -> Vec<(String, String)> {
+    let text = fs::read_to_string(fixture("signatures").join(name)).unwrap();
+    text.lines().map(|line| line.split_once('\t').map(|(file, signature)| (file.to_string(), signature.to_string())).unwrap()).collect()
+}
+
+#[test]
+fn signatures_match_the_golden_file() {
+    let golden = golden("grid16.txt");
+    assert_eq!(golden.iter().map(|(file, _)| file.as_str()).collect::<Vec<_>>(), FIXTURES);
+    for (file, expected) in &golden {
+        let signature = Signature::compute(fixture("img_hash").join(file)).unwrap();
+        assert_eq!(&signature.to_string(), expected, "{file}");
+        assert_eq!(expected.parse::<Signature>().unwrap().distance(&signature).unwrap(), 0.0);
+    }
+}
+
+#[test]
+fn multi_scale_signature_matches_the_golden_file() {
+    for (file, expected) in golden("multi-scale.txt") {
+        let signature = Signature::compute_multi_scale(fixture("img_hash").join(&file)).unwrap();
+        assert_eq!(signature.to_string(), expected, "{file}");
+    }
+}
+
+#[test]
+fn comparer_signatures_are_the_same_bytes() {
+    let paths = FIXTURES.map(|file| fixture("img_hash").join(file));
+    let (comparer, errors) = ImagesComparer::new_lossy_with(&paths, ComparerOptions::new().threads(3));
+    assert!(errors.is_empty());
+    for ((file, expected), idx) in golden("grid16.txt").into_iter().zip(0..) {
+        assert_eq!(comparer.signature(idx).unwrap().to_string(), expected, "{file}");
+    }
+}
+
+#[test]
+fn each_value_transform_has_pinned_scores() {
+    let paths = ["gradient.png", "diagonal.png"].map(|file| fixture("img_hash").join(file));
+    for (transform, header, similarity) in [
+        (Transform::Identity, "v2+identity:", 56.109955),
+        (Transform::Square, "v2:", 55.177124),
+        (Transform::Sqrt, "v2+sqrt:", 60.79496),
+        (Transform::Gamma(2.2), "v2+gamma=2.2:", 55.202507),
+    ] {
+        let options = ComparerOptions::new().value_transform(transform).unwrap();
+        let (comparer, errors) = ImagesComparer::new_lossy_with(&paths, options);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(comparer.compare()[0].similarity, similarity, "{transform}");
+        let signature = comparer.signature(0).unwrap();
+        assert_eq!(signature.transform(), transform);
+        assert!(signature.to_string().starts_with(header), "{transform}");
+    }
+}
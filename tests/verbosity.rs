@@ -0,0 +1,55 @@
+//! `-q`, `-v`, `-vv`: в stdout только результат при любой подробности
+
+mod common;
+
+use common::imgalg;
+use std::path::Path;
+
+/// Образцы `img_hash` во временном каталоге, чтобы индекс писался не в репозиторий
+fn samples() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for entry in std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/img_hash")).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.path().join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn verbose_json_stdout_is_only_the_document() {
+    let dir = samples();
+    let commands: [&[&str]; 8] = [
+        &["blocks.png", "rings.png"],
+        &["--matrix", "blocks.png", "rings.png", "stripes.png"],
+        &["scan", "--index", "samples.idx", "."],
+        &["cache", "stats", "samples.idx"],
+        &["outliers", "."],
+        &["match", ".", "."],
+        &["calibrate", "."],
+        &["fingerprint", "blocks.png"],
+    ];
+    for verbose in ["-v", "-vv"] {
+        for args in commands {
+            let output = imgalg().current_dir(dir.path()).args([verbose, "--json"]).args(args).output().unwrap();
+            // Весь stdout разбирается как один документ: лишняя строка до или после него - ошибка
+            let parsed = serde_json::from_slice::<serde_json::Value>(&output.stdout);
+            assert!(parsed.is_ok_and(|report| report.is_object()), "{verbose} {args:?}: {}", String::from_utf8_lossy(&output.stdout));
+        }
+    }
+
+    let output = imgalg().current_dir(dir.path()).args(["-vv", "--json", "scan", "."]).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().filter(|line| line.starts_with("Processed: ")).count(), 7, "{stderr}");
+    assert!(stderr.contains("Run time: "), "{stderr}");
+}
+
+#[test]
+fn quiet_prints_only_the_result() {
+    let dir = samples();
+    let output = imgalg().current_dir(dir.path()).args(["-q", "scan", "."]).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    let quiet = imgalg().current_dir(dir.path()).args(["-q", "--json", "scan", "."]).output().unwrap();
+    assert!(serde_json::from_slice::<serde_json::Value>(&quiet.stdout).is_ok());
+    assert!(quiet.stderr.is_empty());
+}
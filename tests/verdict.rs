@@ -0,0 +1,45 @@
+//! Оценки `Verdict` по границам по умолчанию: по изображению на каждую
+
+mod common;
+
+use common::{blend, imgalg, pattern, save};
+use imgalg::{ImagesComparer, SimilarityThreshold, Verdict};
+use std::path::{Path, PathBuf};
+
+/// Исходное изображение и его копии, попадающие в каждую оценку
+fn fixtures(dir: &Path) -> (PathBuf, [(PathBuf, Verdict); 4]) {
+    let original = pattern(1, 64, 64);
+    let other = pattern(2, 64, 64);
+    let a = save(dir, "original.png", &original);
+    let copies = [
+        (save(dir, "copy.png", &original), Verdict::Identical),
+        (save(dir, "near.png", &blend(&original, &other, 0.003)), Verdict::NearDuplicate),
+        (save(dir, "similar.png", &blend(&original, &other, 0.012)), Verdict::Similar),
+        (save(dir, "different.png", &other), Verdict::Different),
+    ];
+    (a, copies)
+}
+
+#[test]
+fn each_band_has_a_fixture() {
+    let dir = tempfile::tempdir().unwrap();
+    let (original, copies) = fixtures(dir.path());
+    let mut paths = vec![original];
+    paths.extend(copies.iter().map(|(path, _)| path.clone()));
+    let (comparer, errors) = ImagesComparer::new_lossy(&paths);
+    assert!(errors.is_empty());
+    for (j, (path, expected)) in copies.iter().enumerate() {
+        let similarity = comparer.similarity_percentage_between(0, j + 1).unwrap();
+        assert_eq!(comparer.verdict(0, j + 1).unwrap(), *expected, "{}: {similarity}", path.display());
+        assert_eq!(comparer.is_duplicate(0, j + 1, SimilarityThreshold::LOOSE).unwrap(), expected.is_duplicate() || *expected == Verdict::Similar);
+    }
+}
+
+#[test]
+fn cli_prints_the_verdict_word() {
+    let dir = tempfile::tempdir().unwrap();
+    let (original, copies) = fixtures(dir.path());
+    let output = imgalg().arg(&original).arg(&copies[1].0).output().unwrap();
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("(near duplicates)"), "{text}");
+}
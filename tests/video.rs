@@ -0,0 +1,68 @@
+//! Ролики в `scan`: при установленном `ffmpeg` - сравнение по кадрам, без него - ошибка
+//! каждого ролика, не прерывающая просмотр
+
+#![cfg(feature = "video")]
+
+mod common;
+
+use common::{imgalg, json, pattern, save};
+use imgalg::video::VideoSignature;
+use std::path::Path;
+use std::process::Command;
+
+/// Есть ли `ffmpeg` и `ffprobe`; без них тесты с настоящими роликами пропускаются
+fn has_ffmpeg() -> bool {
+    ["ffmpeg", "ffprobe"].iter().all(|program| Command::new(program).arg("-version").output().is_ok_and(|output| output.status.success()))
+}
+
+/// Записывает ролик `path` из входа `input` с параметрами кодека `codec`
+fn encode(path: &Path, input: &[&str], codec: &[&str]) {
+    let status = Command::new("ffmpeg").args(["-v", "error", "-y"]).args(input).args(codec).arg(path).status().unwrap();
+    assert!(status.success(), "ffmpeg failed for {}", path.display());
+}
+
+#[test]
+fn clip_matches_its_reencoded_copy() {
+    if !has_ffmpeg() {
+        eprintln!("ffmpeg is not installed, skipped");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let (clip, copy, other) = (dir.path().join("clip.mp4"), dir.path().join("copy.mkv"), dir.path().join("other.webm"));
+    let source = ["-f", "lavfi", "-i", "testsrc=duration=3:size=160x120:rate=10"];
+    encode(&clip, &source, &["-pix_fmt", "yuv420p", "-crf", "18"]);
+    // Та же картинка с гораздо меньшим битрейтом и в другом контейнере
+    encode(&copy, &["-i", clip.to_str().unwrap()], &["-pix_fmt", "yuv420p", "-b:v", "60k"]);
+    encode(&other, &["-f", "lavfi", "-i", "mandelbrot=size=160x120:rate=10", "-t", "3"], &[]);
+
+    let signature = |path: &Path| VideoSignature::compute(path, 6).unwrap();
+    let (clip, copy, other) = (signature(&clip), signature(&copy), signature(&other));
+    assert!((clip.duration.as_secs_f64() - 3.0).abs() < 0.2, "{:?}", clip.duration);
+    assert_eq!(clip.frames().len(), 6);
+    let similarity = clip.similarity(&copy);
+    assert!(similarity >= 95.0, "{similarity}");
+    assert!(clip.similarity(&other) < similarity - 10.0);
+
+    let report = json(imgalg().args(["scan", "--json", "--frames", "6"]).arg(dir.path()));
+    assert_eq!(report["videos"], 3, "{report}");
+    let groups = report["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 1, "{report}");
+    let names: Vec<&str> = groups[0].as_array().unwrap().iter().map(|entry| entry["path"].as_str().unwrap().rsplit('/').next().unwrap()).collect();
+    assert_eq!(names, ["clip.mp4", "copy.mkv"]);
+    assert!(groups[0][0]["duration"].as_f64().is_some());
+}
+
+#[test]
+fn missing_ffmpeg_fails_only_the_videos() {
+    let dir = tempfile::tempdir().unwrap();
+    save(dir.path(), "a.png", &pattern(1, 48, 48));
+    save(dir.path(), "a-copy.png", &pattern(1, 48, 48));
+    std::fs::write(dir.path().join("clip.mp4"), b"not really a video").unwrap();
+    let empty = tempfile::tempdir().unwrap();
+
+    let report = json(imgalg().env("PATH", empty.path()).args(["scan", "--json"]).arg(dir.path()));
+    assert_eq!(report["groups"].as_array().unwrap().len(), 1, "{report}");
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1, "{report}");
+    assert!(errors[0].to_string().contains("ffprobe was not found in PATH"), "{}", errors[0]);
+}